@@ -0,0 +1,196 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::{error, fmt::Display, time::Duration};
+
+/// An error response from the Web API.
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    /// E.g. `NO_ACTIVE_DEVICE` or `PREMIUM_REQUIRED`; only player errors have one.
+    pub reason: Option<String>,
+    /// Seconds to wait before retrying, when rate limited.
+    pub retry_after: Option<u64>,
+}
+
+/// The documented error body: `{"error": {"status", "message", "reason"}}`.
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ErrorObject,
+}
+
+#[derive(Deserialize)]
+struct ErrorObject {
+    #[serde(default)]
+    message: String,
+    reason: Option<String>,
+}
+
+/// Max length of a non-JSON body (e.g. an HTML error page) in the message.
+const MAX_RAW_BODY_CHARS: usize = 200;
+
+impl ApiError {
+    /// Parses an error response body. Bodies that aren't in the documented
+    /// shape, e.g. HTML from a proxy, are kept as the message as is.
+    pub fn from_response(status: StatusCode, retry_after: Option<u64>, body: &str) -> ApiError {
+        let (message, reason) = match serde_json::from_str::<ErrorBody>(body) {
+            Ok(body) => (body.error.message, body.error.reason),
+            Err(_) => (body.trim().chars().take(MAX_RAW_BODY_CHARS).collect(), None),
+        };
+
+        ApiError {
+            status,
+            message,
+            reason,
+            retry_after,
+        }
+    }
+
+    pub fn is_no_active_device(&self) -> bool {
+        self.reason.as_deref() == Some("NO_ACTIVE_DEVICE")
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.reason.as_deref(), self.status) {
+            (Some("NO_ACTIVE_DEVICE"), _) => write!(
+                f,
+                "No active device found. Start Spotify on a device or pick one with --device."
+            ),
+            (Some("PREMIUM_REQUIRED"), _) => {
+                write!(f, "This needs Spotify Premium.")
+            }
+            (_, StatusCode::TOO_MANY_REQUESTS) => match self.retry_after {
+                Some(secs) => write!(f, "Rate limited by Spotify. Try again in {secs} seconds."),
+                None => write!(f, "Rate limited by Spotify. Try again later."),
+            },
+            _ if self.message.is_empty() => write!(f, "Spotify API error: {}", self.status),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// `main` prints returned errors with Debug, so show the message there too.
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+impl error::Error for ApiError {}
+
+/// A request that got no response in time; see `http::init`.
+pub struct TimeoutError {
+    pub url: String,
+    pub timeout: Duration,
+}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "No response from {} in {:.1} seconds. Try again, or allow more time with --timeout.",
+            self.url,
+            self.timeout.as_secs_f64()
+        )
+    }
+}
+
+impl std::fmt::Debug for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+impl error::Error for TimeoutError {}
+
+/// Broad classes of failures, for scripts wrapping the CLI. Each has its own
+/// exit code; 2 is left to clap for usage errors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    Other,
+    Auth,
+    RateLimited,
+    NotFound,
+    NoActiveDevice,
+    PremiumRequired,
+    Network,
+    Api,
+    Timeout,
+}
+
+impl ErrorKind {
+    pub fn of(e: &(dyn error::Error + 'static)) -> ErrorKind {
+        if let Some(e) = e.downcast_ref::<ApiError>() {
+            return match (e.reason.as_deref(), e.status) {
+                (Some("NO_ACTIVE_DEVICE"), _) => ErrorKind::NoActiveDevice,
+                (Some("PREMIUM_REQUIRED"), _) => ErrorKind::PremiumRequired,
+                (_, StatusCode::UNAUTHORIZED) => ErrorKind::Auth,
+                (_, StatusCode::TOO_MANY_REQUESTS) => ErrorKind::RateLimited,
+                (_, StatusCode::NOT_FOUND) => ErrorKind::NotFound,
+                _ => ErrorKind::Api,
+            };
+        }
+        if e.downcast_ref::<TimeoutError>().is_some() {
+            return ErrorKind::Timeout;
+        }
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return match e.is_timeout() {
+                true => ErrorKind::Timeout,
+                false => ErrorKind::Network,
+            };
+        }
+
+        ErrorKind::Other
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::Auth => "auth",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::NoActiveDevice => "no_active_device",
+            ErrorKind::PremiumRequired => "premium_required",
+            ErrorKind::Network => "network",
+            ErrorKind::Api => "api",
+            ErrorKind::Timeout => "timeout",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Auth => 3,
+            ErrorKind::RateLimited => 4,
+            ErrorKind::NotFound => 5,
+            ErrorKind::NoActiveDevice => 6,
+            ErrorKind::PremiumRequired => 7,
+            ErrorKind::Network => 8,
+            ErrorKind::Api => 9,
+            ErrorKind::Timeout => 10,
+        }
+    }
+}
+
+/// The error as `{"error": {"kind", "message", "status"}}`; `status` is only
+/// set for API errors.
+pub fn error_json(e: &(dyn error::Error + 'static)) -> serde_json::Value {
+    let api_error = e.downcast_ref::<ApiError>();
+    let mut error = json!({
+        "kind": ErrorKind::of(e).name(),
+        "message": e.to_string(),
+    });
+    if let Some(api_error) = api_error {
+        error["status"] = json!(api_error.status.as_u16());
+        if let Some(reason) = &api_error.reason {
+            error["reason"] = json!(reason);
+        }
+        if let Some(secs) = api_error.retry_after {
+            error["retry_after"] = json!(secs);
+        }
+    }
+
+    json!({ "error": error })
+}
@@ -0,0 +1,226 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use clap::ValueEnum;
+use image::{imageops::FilterType, RgbImage};
+use std::{
+    env, error,
+    fmt::Write as _,
+    io::{self, IsTerminal, Write},
+};
+
+/// How to draw images in the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ArtProtocol {
+    /// Pick based on the terminal; nothing is drawn if stdout isn't a terminal
+    Auto,
+    /// Kitty graphics protocol (kitty, Ghostty)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm)
+    Iterm,
+    Sixel,
+    /// Colored unicode half blocks, works in any terminal with true color
+    Blocks,
+}
+
+/// Width of the image in terminal cells.
+const ART_WIDTH_CELLS: u32 = 32;
+
+/// Assumed width of a terminal cell in pixels, for sixel which draws in
+/// pixels.
+const SIXEL_CELL_PIXELS: u32 = 8;
+
+impl ArtProtocol {
+    /// Resolves `Auto` from the env variables terminals set. `None` if
+    /// nothing should be drawn.
+    fn detect(self) -> Option<ArtProtocol> {
+        if self != ArtProtocol::Auto {
+            return Some(self);
+        }
+        if !io::stdout().is_terminal() {
+            return None;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+        let protocol = if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term_program == "ghostty"
+        {
+            ArtProtocol::Kitty
+        } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+            ArtProtocol::Iterm
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            ArtProtocol::Sixel
+        } else {
+            ArtProtocol::Blocks
+        };
+
+        Some(protocol)
+    }
+
+    /// Width in pixels the image should at least have to look sharp.
+    pub fn wanted_width(self) -> u32 {
+        match self {
+            ArtProtocol::Blocks => ART_WIDTH_CELLS,
+            _ => ART_WIDTH_CELLS * SIXEL_CELL_PIXELS,
+        }
+    }
+}
+
+/// Draws an image (e.g. a JPEG album cover) to stdout.
+pub fn print(image_bytes: &[u8], protocol: ArtProtocol) -> Result<(), Box<dyn error::Error>> {
+    let Some(protocol) = protocol.detect() else {
+        return Ok(());
+    };
+
+    let image = image::load_from_memory(image_bytes)
+        .map_err(|e| format!("Failed to decode the album art: {e}"))?;
+    let width_cells = term_size::dimensions()
+        .map(|(w, _)| (w as u32).min(ART_WIDTH_CELLS))
+        .unwrap_or(ART_WIDTH_CELLS);
+    // Cells are roughly twice as tall as wide.
+    let height_cells = (width_cells * image.height() / image.width().max(1)).div_ceil(2);
+
+    let out = match protocol {
+        ArtProtocol::Kitty => {
+            let rgb = image.to_rgb8();
+            kitty(&rgb, width_cells, height_cells)
+        }
+        ArtProtocol::Iterm => format!(
+            "\x1b]1337;File=inline=1;width={width_cells};height={height_cells};preserveAspectRatio=1:{}\x07\n",
+            BASE64_STANDARD.encode(image_bytes)
+        ),
+        ArtProtocol::Sixel => {
+            let width = width_cells * SIXEL_CELL_PIXELS;
+            let height = width * image.height() / image.width().max(1);
+            sixel(&image.resize_exact(width, height, FilterType::Triangle).to_rgb8())
+        }
+        ArtProtocol::Blocks | ArtProtocol::Auto => {
+            let height = height_cells * 2;
+            half_blocks(
+                &image
+                    .resize_exact(width_cells, height, FilterType::Triangle)
+                    .to_rgb8(),
+            )
+        }
+    };
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(out.as_bytes())?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Kitty graphics protocol with raw RGB data, sent in chunks of at most 4096
+/// bytes as the protocol requires.
+fn kitty(image: &RgbImage, width_cells: u32, height_cells: u32) -> String {
+    let data = BASE64_STANDARD.encode(image.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},c={width_cells},r={height_cells},m={more};{chunk}\x1b\\",
+                image.width(),
+                image.height()
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    out.push('\n');
+
+    out
+}
+
+/// Two pixels per cell: the upper one as the foreground color of `▀` and the
+/// lower one as the background.
+fn half_blocks(image: &RgbImage) -> String {
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            let _ = write!(out, "\x1b[38;2;{r};{g};{b}m");
+            if y + 1 < image.height() {
+                let [r, g, b] = image.get_pixel(x, y + 1).0;
+                let _ = write!(out, "\x1b[48;2;{r};{g};{b}m");
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+
+    out
+}
+
+/// Sixel with the colors quantized to a 6x6x6 color cube.
+fn sixel(image: &RgbImage) -> String {
+    let level = |c: u8| (c as u32 * 5 + 127) / 255;
+    let color_index = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        (level(r) * 36 + level(g) * 6 + level(b)) as usize
+    };
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", image.width(), image.height());
+    for i in 0..216u32 {
+        let percent = |l: u32| l * 100 / 5;
+        let _ = write!(
+            out,
+            "#{i};2;{};{};{}",
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        );
+    }
+
+    for band in (0..image.height()).step_by(6) {
+        let rows = (image.height() - band).min(6);
+        let mut used = [false; 216];
+        for y in band..band + rows {
+            for x in 0..image.width() {
+                used[color_index(x, y)] = true;
+            }
+        }
+
+        for (color, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            let _ = write!(out, "#{color}");
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..image.width() {
+                let bits = (0..rows)
+                    .filter(|dy| color_index(x, band + dy) == color)
+                    .fold(0u8, |bits, dy| bits | (1 << dy));
+                let c = (63 + bits) as char;
+                run = match run {
+                    Some((prev, n)) if prev == c => Some((prev, n + 1)),
+                    Some((prev, n)) => {
+                        push_sixel_run(&mut out, prev, n);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((c, n)) = run {
+                push_sixel_run(&mut out, c, n);
+            }
+            // Back to the start of the band for the next color.
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+
+    out
+}
+
+fn push_sixel_run(out: &mut String, c: char, n: usize) {
+    match n {
+        1..=3 => out.extend(std::iter::repeat_n(c, n)),
+        _ => {
+            let _ = write!(out, "!{n}{c}");
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::{desktop, http, i18n::tr, logging, prompt};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use rand::distributions::{Alphanumeric, DistString};
 use reqwest::{
@@ -7,17 +8,112 @@ use reqwest::{
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    error, fs,
+    env, error, fs,
     io::{self, Read, Write},
     str::FromStr,
-    time::SystemTime,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
+const DEFAULT_ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+
+/// Url of the accounts service endpoint at `path`. The base can be
+/// overridden with SPOTIFY_CLI_ACCOUNTS_URL, e.g. to use a mock server.
+fn accounts_url(path: &str) -> String {
+    let base =
+        env::var("SPOTIFY_CLI_ACCOUNTS_URL").unwrap_or_else(|_| DEFAULT_ACCOUNTS_URL.to_string());
+    format!("{}{path}", base.trim_end_matches('/'))
+}
+
 #[derive(Deserialize, Debug)]
 struct AuthenticationResponse {
     access_token: String,
     expires_in: u64,
     refresh_token: Option<String>,
+    scope: Option<String>,
+}
+
+/// OAuth scopes used by the CLI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    UserReadPlaybackState,
+    UserReadCurrentlyPlaying,
+    UserModifyPlaybackState,
+    PlaylistReadPrivate,
+    PlaylistReadCollaborative,
+    PlaylistModifyPrivate,
+    PlaylistModifyPublic,
+    UserLibraryRead,
+    UserLibraryModify,
+    UserTopRead,
+    UserReadRecentlyPlayed,
+    UserReadPlaybackPosition,
+    UgcImageUpload,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 13] = [
+        Scope::UserReadPlaybackState,
+        Scope::UserReadCurrentlyPlaying,
+        Scope::UserModifyPlaybackState,
+        Scope::PlaylistReadPrivate,
+        Scope::PlaylistReadCollaborative,
+        Scope::PlaylistModifyPrivate,
+        Scope::PlaylistModifyPublic,
+        Scope::UserLibraryRead,
+        Scope::UserLibraryModify,
+        Scope::UserTopRead,
+        Scope::UserReadRecentlyPlayed,
+        Scope::UserReadPlaybackPosition,
+        Scope::UgcImageUpload,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::UserReadPlaybackState => "user-read-playback-state",
+            Scope::UserReadCurrentlyPlaying => "user-read-currently-playing",
+            Scope::UserModifyPlaybackState => "user-modify-playback-state",
+            Scope::PlaylistReadPrivate => "playlist-read-private",
+            Scope::PlaylistReadCollaborative => "playlist-read-collaborative",
+            Scope::PlaylistModifyPrivate => "playlist-modify-private",
+            Scope::PlaylistModifyPublic => "playlist-modify-public",
+            Scope::UserLibraryRead => "user-library-read",
+            Scope::UserLibraryModify => "user-library-modify",
+            Scope::UserTopRead => "user-top-read",
+            Scope::UserReadRecentlyPlayed => "user-read-recently-played",
+            Scope::UserReadPlaybackPosition => "user-read-playback-position",
+            Scope::UgcImageUpload => "ugc-image-upload",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|s| s.as_str() == scope)
+    }
+}
+
+/// Scopes requested when authorizing for the first time. Commands needing
+/// anything else ask for it when they're first used.
+pub const DEFAULT_SCOPES: [Scope; 5] = [
+    Scope::UserReadPlaybackState,
+    Scope::UserReadCurrentlyPlaying,
+    Scope::UserModifyPlaybackState,
+    Scope::PlaylistReadPrivate,
+    Scope::PlaylistModifyPrivate,
+];
+
+fn parse_scopes(scopes: &str) -> Vec<Scope> {
+    scopes.split_whitespace().filter_map(Scope::parse).collect()
+}
+
+fn join_scopes(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 pub struct SpotifyAuth {
@@ -26,14 +122,51 @@ pub struct SpotifyAuth {
     access_token: Option<String>,
     valid_until: Option<u64>,
     refresh_token: Option<String>,
+    scopes: Vec<Scope>,
     filepath: Option<String>,
+    redirect_ports: Vec<u16>,
+    redirect_uri: Option<String>,
+    manual: bool,
+    qr: bool,
+    open_browser: bool,
+    redirect_timeout: Duration,
 }
 
+/// Ports tried in order for the redirect server when no redirect uri is set.
+/// Allowed redirect URIs need to be specified in Spotify's app dashboard.
+/// Thus we can't use actually random ports. To allow multiple port choices,
+/// we need to list http://localhost:5555, http://localhost:5556, ... in
+/// the app dashboard.
+pub const DEFAULT_REDIRECT_PORTS: [u16; 5] = [5555, 5556, 5557, 5558, 5559];
+
+/// The redirect ports from the env variable `SPOTIFY_CLI_REDIRECT_PORTS`, or
+/// else the configured ones, or else the defaults.
+pub fn redirect_ports(configured: Option<&[u16]>) -> Result<Vec<u16>, Box<dyn error::Error>> {
+    match env::var("SPOTIFY_CLI_REDIRECT_PORTS") {
+        Ok(ports) => Ok(ports
+            .split(',')
+            .map(|port| port.trim().parse::<u16>())
+            .collect::<Result<Vec<u16>, _>>()
+            .map_err(|_| "The env variable SPOTIFY_CLI_REDIRECT_PORTS must be a comma separated list of ports.")?),
+        Err(_) => Ok(configured.unwrap_or(&DEFAULT_REDIRECT_PORTS).to_vec()),
+    }
+}
+
+/// How long to wait for the redirect before asking whether to keep waiting.
+pub const DEFAULT_REDIRECT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often the redirect server checks whether waiting was cancelled.
+const REDIRECT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
 #[derive(Deserialize, Debug, Serialize)]
 struct TokenFile {
     access_token: Option<String>,
     valid_until: Option<u64>,
     refresh_token: Option<String>,
+    /// Space separated granted scopes. Missing from token files written by
+    /// older versions, which always requested `DEFAULT_SCOPES`.
+    #[serde(default)]
+    scopes: Option<String>,
 }
 
 impl SpotifyAuth {
@@ -51,7 +184,14 @@ impl SpotifyAuth {
             access_token: None,
             valid_until: None,
             refresh_token: None,
+            scopes: Vec::new(),
             filepath: None,
+            redirect_ports: DEFAULT_REDIRECT_PORTS.to_vec(),
+            redirect_uri: None,
+            manual: false,
+            qr: false,
+            open_browser: true,
+            redirect_timeout: DEFAULT_REDIRECT_TIMEOUT,
         })
     }
 
@@ -65,6 +205,47 @@ impl SpotifyAuth {
         Ok(())
     }
 
+    /// Sets the ports to try for the redirect server, in order. The redirect
+    /// uri is then `http://localhost:<port>`.
+    pub fn set_redirect_ports(&mut self, ports: Vec<u16>) {
+        self.redirect_ports = ports;
+    }
+
+    /// Sets a fully custom redirect uri, e.g. `http://127.0.0.1:8888/callback`.
+    /// It has to match the one registered in the app dashboard exactly.
+    /// Overrides the redirect ports.
+    pub fn set_redirect_uri(&mut self, uri: &str) {
+        self.redirect_uri = Some(uri.to_owned());
+    }
+
+    /// In the manual flow no local server is started to catch the redirect;
+    /// the user pastes the redirect url or code instead. Meant for headless
+    /// machines where the browser runs on another machine.
+    pub fn set_manual(&mut self, manual: bool) {
+        self.manual = manual;
+    }
+
+    /// Whether to open the authorization url in the browser automatically.
+    pub fn set_open_browser(&mut self, open_browser: bool) {
+        self.open_browser = open_browser;
+    }
+
+    /// Sets how long to wait for the redirect before asking whether to keep
+    /// waiting.
+    pub fn set_redirect_timeout(&mut self, timeout: Duration) {
+        self.redirect_timeout = timeout;
+    }
+
+    /// Also prints the authorization url as a QR code, to authorize on a
+    /// phone. The phone can't reach the local redirect server, so this
+    /// implies the manual flow.
+    pub fn set_qr(&mut self, qr: bool) {
+        self.qr = qr;
+        if qr {
+            self.manual = true;
+        }
+    }
+
     /// Reads credentials from a file.
     ///
     /// NOTE: fails if file does not already exist. Use `with_file` if you're
@@ -92,28 +273,126 @@ impl SpotifyAuth {
         token_file.read_to_string(&mut token_file_str)?;
         let tokens: TokenFile = serde_json::from_str(&token_file_str)?;
 
+        self.set_tokens(tokens);
+
+        Ok(())
+    }
+
+    fn set_tokens(&mut self, tokens: TokenFile) {
+        self.scopes = match (&tokens.scopes, &tokens.refresh_token) {
+            (Some(scopes), _) => parse_scopes(scopes),
+            (None, Some(_)) => DEFAULT_SCOPES.to_vec(),
+            (None, None) => Vec::new(),
+        };
         self.access_token = tokens.access_token;
         self.valid_until = tokens.valid_until;
         self.refresh_token = tokens.refresh_token;
+    }
 
-        Ok(())
+    fn token_file(&self) -> TokenFile {
+        TokenFile {
+            access_token: self.access_token.clone(),
+            valid_until: self.valid_until,
+            refresh_token: self.refresh_token.clone(),
+            scopes: Some(join_scopes(&self.scopes)),
+        }
     }
 
+    /// Writes the tokens to the file. The file is replaced atomically so that
+    /// another invocation reading it never sees a half written file.
     fn save(&self) -> Result<(), Box<dyn error::Error>> {
         if let Some(ref filepath) = self.filepath {
-            let tokens = TokenFile {
-                access_token: self.access_token.clone(),
-                valid_until: self.valid_until,
-                refresh_token: self.refresh_token.clone(),
-            };
+            let tokens = self.token_file();
             let token_str = serde_json::to_string(&tokens)?;
-            let mut token_file = fs::File::create(filepath)?;
+            let tmp_path = format!("{filepath}.tmp-{}", std::process::id());
+            let mut token_file = fs::File::create(&tmp_path)?;
             write!(token_file, "{token_str}")?;
+            token_file.sync_all()?;
+            fs::rename(&tmp_path, filepath)?;
         }
 
         Ok(())
     }
 
+    /// Takes an exclusive lock on `<token file>.lock`. Released when the
+    /// returned file is dropped. Held while refreshing so that concurrent
+    /// invocations (e.g. a status bar poller and a manual command) don't both
+    /// spend the same refresh token.
+    fn lock_token_file(&self) -> Result<Option<fs::File>, Box<dyn error::Error>> {
+        let Some(ref filepath) = self.filepath else {
+            return Ok(None);
+        };
+        let lock_file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(format!("{filepath}.lock"))?;
+        lock_file.lock()?;
+
+        Ok(Some(lock_file))
+    }
+
+    /// Picks up tokens another invocation has saved to the file in the
+    /// meantime. Returns true if they include a fresh access token, in which
+    /// case there's no need to refresh.
+    fn reload_newer_tokens(&mut self) -> Result<bool, Box<dyn error::Error>> {
+        let Some(ref filepath) = self.filepath else {
+            return Ok(false);
+        };
+        let Ok(token_file_str) = fs::read_to_string(filepath) else {
+            return Ok(false);
+        };
+        let tokens: TokenFile = serde_json::from_str(&token_file_str)?;
+        if tokens.refresh_token.is_none() {
+            return Ok(false);
+        }
+
+        let used_access_token = self.access_token.clone();
+        self.set_tokens(tokens);
+
+        let curr_time = current_time_secs_from_epoch()?;
+        let fresh = self
+            .valid_until
+            .is_some_and(|valid_until| curr_time < valid_until.saturating_sub(120));
+        Ok(self.access_token != used_access_token && fresh)
+    }
+
+    /// Runs the auth flow even if there already are tokens, requesting the
+    /// default scopes and any scopes granted before.
+    pub async fn login(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let mut wanted = DEFAULT_SCOPES.to_vec();
+        wanted.extend(self.scopes.iter().filter(|s| !DEFAULT_SCOPES.contains(s)));
+        self.run_auth_flow(&wanted).await
+    }
+
+    /// Writes the current tokens to another file, e.g. to be imported on
+    /// another machine with `import_tokens`.
+    pub fn export_tokens(&self, filepath: &str) -> Result<(), Box<dyn error::Error>> {
+        if self.refresh_token.is_none() {
+            return Err("No tokens to export; authorize first.".into());
+        }
+
+        let tokens = self.token_file();
+        fs::write(filepath, serde_json::to_string(&tokens)?)?;
+
+        Ok(())
+    }
+
+    /// Reads tokens from a file written by `export_tokens` and saves them to
+    /// this object's file.
+    pub fn import_tokens(&mut self, filepath: &str) -> Result<(), Box<dyn error::Error>> {
+        let token_file_str = fs::read_to_string(filepath)
+            .map_err(|_| format!("Failed to read file {}", filepath))?;
+        let tokens: TokenFile = serde_json::from_str(&token_file_str)
+            .map_err(|e| format!("{filepath} is not a valid token file: {e}"))?;
+        if tokens.refresh_token.is_none() {
+            return Err(format!("{filepath} has no refresh token.").into());
+        }
+
+        self.set_tokens(tokens);
+        self.save()
+    }
+
     /// Resets the tokens.
     ///
     /// NOTE: if the credentials are saved to a file, this method also
@@ -122,6 +401,7 @@ impl SpotifyAuth {
         self.access_token = None;
         self.valid_until = None;
         self.refresh_token = None;
+        self.scopes = Vec::new();
 
         self.save()?;
 
@@ -151,76 +431,213 @@ impl SpotifyAuth {
                 }
             }
             (None, None, None) => {
-                let (authorization_code, redirect_port) = self.authorize()?;
-                let (access_token, refresh_token, valid_until) = self
-                    .authenticate(&authorization_code, redirect_port)
-                    .await?;
-                self.access_token = Some(access_token.clone());
-                self.valid_until = Some(valid_until);
-                self.refresh_token = Some(refresh_token);
+                self.run_auth_flow(&DEFAULT_SCOPES).await?;
+                self.access_token
+                    .clone()
+                    .ok_or("Broken auth state: access token is missing after authorizing.".into())
+            }
+            _ => Err("Broken auth state: some of the token fields are missing but not all.".into()),
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// When the access token expires, in seconds since the epoch.
+    pub fn valid_until(&self) -> Option<u64> {
+        self.valid_until
+    }
+
+    pub fn has_refresh_token(&self) -> bool {
+        self.refresh_token.is_some()
+    }
+
+    /// Makes sure all of the given scopes have been granted. If not, runs the
+    /// auth flow again requesting the union of the granted and needed scopes.
+    pub async fn require_scopes(&mut self, scopes: &[Scope]) -> Result<(), Box<dyn error::Error>> {
+        if scopes.is_empty() {
+            return Ok(());
+        }
+
+        // Not authorized at all yet; the first request will run the flow
+        // and the missing scopes get requested then.
+        if self.refresh_token.is_none() {
+            let mut wanted = DEFAULT_SCOPES.to_vec();
+            wanted.extend(scopes.iter().filter(|s| !DEFAULT_SCOPES.contains(s)));
+            return self.run_auth_flow(&wanted).await;
+        }
+
+        let missing: Vec<Scope> = scopes
+            .iter()
+            .filter(|s| !self.scopes.contains(s))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        println!(
+            "{}",
+            tr!(
+                "This command needs permissions that have not been granted yet: {scopes}",
+                scopes = join_scopes(&missing)
+            )
+        );
+        println!("{}", tr!("Re-authorizing to request them."));
+
+        let mut wanted = self.scopes.clone();
+        wanted.extend(missing);
+        self.run_auth_flow(&wanted).await
+    }
 
-                self.save()?;
+    async fn run_auth_flow(&mut self, scopes: &[Scope]) -> Result<(), Box<dyn error::Error>> {
+        let (authorization_code, redirect_uri) = self.authorize(scopes).await?;
+        let (access_token, refresh_token, valid_until, granted) = self
+            .authenticate(&authorization_code, &redirect_uri)
+            .await?;
+        self.access_token = Some(access_token);
+        self.valid_until = Some(valid_until);
+        self.refresh_token = Some(refresh_token);
+        self.scopes = granted.unwrap_or(scopes.to_vec());
+
+        self.save()
+    }
 
-                Ok(access_token)
+    /// Picks the redirect uri to use and the local port to listen to it on.
+    fn redirect_target(&self) -> Result<(String, u16), Box<dyn error::Error>> {
+        match &self.redirect_uri {
+            Some(uri) => {
+                let port = Url::parse(uri)?
+                    .port_or_known_default()
+                    .ok_or(format!("Can't tell the port of the redirect uri {uri}"))?;
+                Ok((uri.clone(), port))
+            }
+            // No local server is started in the manual flow so it doesn't
+            // matter whether the port is free.
+            None if self.manual => {
+                let port = *self
+                    .redirect_ports
+                    .first()
+                    .ok_or("No redirect ports configured.")?;
+                Ok((format!("http://localhost:{port}"), port))
+            }
+            None => {
+                let port = get_free_port(&self.redirect_ports)?;
+                Ok((format!("http://localhost:{port}"), port))
             }
-            _ => Err("Broken auth state: some of the token fields are missing but not all.".into()),
         }
     }
 
-    fn authorize(&self) -> Result<(String, u16), Box<dyn error::Error>> {
+    async fn authorize(&self, scopes: &[Scope]) -> Result<(String, String), Box<dyn error::Error>> {
         let state = generate_random_state();
 
-        let redirect_port = get_free_port()?;
+        let (redirect_uri, redirect_port) = self.redirect_target()?;
         let url = Url::parse_with_params(
-            "https://accounts.spotify.com/authorize",
+            &accounts_url("/authorize"),
             &[
                 ("client_id", &self.client_id),
                 ("response_type", &"code".to_string()),
-                (
-                    "redirect_uri",
-                    &format!("http://localhost:{}", redirect_port),
-                ),
+                ("redirect_uri", &redirect_uri),
                 ("state", &state),
-                (
-                    "scope",
-                    &"user-read-playback-state user-read-currently-playing user-modify-playback-state playlist-read-private playlist-modify-private"
-                        .to_string(),
-                ),
+                ("scope", &join_scopes(scopes)),
             ],
         )?;
 
-        println!("Go to this url for the auth flow: {}", url.as_str());
-
-        let redirected_to = match tiny_http::Server::http(format!("127.0.0.1:{redirect_port}")) {
-            Ok(server) => {
-                let request = server.recv()?;
-                let request_url = request.url().to_string();
-                request.respond(tiny_http::Response::from_string(
-                    "Succesfully received the redirected url. You can now close this tab."
-                        .to_string(),
-                ))?;
-                format!("http://localhost:{redirect_port}{request_url}")
+        println!(
+            "{}",
+            tr!(
+                "Go to this url for the auth flow: {url}",
+                url = url.as_str()
+            )
+        );
+        if self.qr {
+            println!("{}", qr_code(url.as_str())?);
+        }
+
+        let server = match self.manual {
+            true => None,
+            false => match tiny_http::Server::http(format!("127.0.0.1:{redirect_port}")) {
+                Ok(server) => {
+                    // The url is printed above in case this doesn't work.
+                    if self.open_browser {
+                        if let Err(e) = desktop::open(url.as_str()) {
+                            tracing::debug!("Couldn't open the browser: {e}");
+                        }
+                    }
+                    Some(Arc::new(server))
+                }
+                Err(e) => {
+                    println!(
+                        "{}\n",
+                        tr!(
+                            "Failed to start a server to listen to the redirect:\n{error}",
+                            error = e
+                        )
+                    );
+                    None
+                }
+            },
+        };
+
+        let redirected_to = match server {
+            Some(server) => {
+                let redirect_path = Url::parse(&redirect_uri)?.path().to_string();
+                let request_url = loop {
+                    let waited = wait_for_redirect(
+                        server.clone(),
+                        &redirect_path,
+                        &state,
+                        self.redirect_timeout,
+                    )
+                    .await?;
+                    if let Some(request_url) = waited {
+                        break request_url;
+                    }
+
+                    if !prompt::confirm(
+                        tr!("Timed out waiting for the redirect. Keep waiting?"),
+                        true,
+                    )? {
+                        return Err(tr!("Timed out waiting for the authorization redirect.").into());
+                    }
+                };
+                Url::parse(&redirect_uri)?.join(&request_url)?.to_string()
             }
-            Err(e) => {
-                println!("Failed to start a server to listen to the redirect:\n{e}\n");
-                println!("Instead, write the entire url you were redirected to here:");
+            None => {
+                println!("{}", tr!("After authorizing, your browser is redirected to a page that likely fails to load."));
+                println!("{}", tr!("Paste the entire url you were redirected to here (or just the value of its code parameter):"));
                 let mut user_provided_url = String::new();
-                io::stdin().read_line(&mut user_provided_url)?;
+                prompt::read_line(&mut user_provided_url)?;
                 user_provided_url.trim().to_string()
             }
         };
 
-        #[cfg(debug_assertions)]
-        println!("\nRedirected to: {redirected_to}");
+        tracing::debug!(
+            redirected_to = %logging::redact_url_str(&redirected_to),
+            "Got the authorization redirect"
+        );
+
+        // Only the code was given; there's no state to verify.
+        if !redirected_to.contains("code=") && !redirected_to.contains("error=") {
+            if redirected_to.is_empty() {
+                return Err(tr!("No redirect url or code given.").into());
+            }
+            println!(
+                "{}",
+                tr!("Note: can't verify the state parameter when only the code is given.")
+            );
+            return Ok((redirected_to, redirect_uri));
+        }
 
         let redirected_url = Url::from_str(&redirected_to)?;
 
         let query_params: HashMap<String, String> =
             redirected_url.query_pairs().into_owned().collect();
 
-        #[cfg(debug_assertions)]
-        println!("\nQuery params in the redirected url: {query_params:?}");
-
+        if let Some(error) = query_params.get("error") {
+            return Err(format!("Authorization failed: {error}").into());
+        }
         let token = query_params
             .get("code")
             .ok_or("The query param code is missing from redirect url.")?
@@ -229,26 +646,21 @@ impl SpotifyAuth {
             .get("state")
             .ok_or("The query param state is missing from redirect url.")?;
 
-        #[cfg(debug_assertions)]
-        println!("\nGenerated state: {state}");
-        #[cfg(debug_assertions)]
-        println!("User provided state: {redirect_state}\n");
-        #[cfg(debug_assertions)]
-        println!("\nToken: {token}\n");
+        tracing::trace!(%state, %redirect_state, "Checking the state parameter");
 
         if &state != redirect_state {
             Err("Invalid state! Something fishy might be going on.".into())
         } else {
-            Ok((token, redirect_port))
+            Ok((token, redirect_uri))
         }
     }
 
     async fn authenticate(
         &self,
         authorization_code: &str,
-        redirect_port: u16,
-    ) -> Result<(String, String, u64), Box<dyn error::Error>> {
-        let url = Url::parse("https://accounts.spotify.com/api/token")?;
+        redirect_uri: &str,
+    ) -> Result<(String, String, u64, Option<Vec<Scope>>), Box<dyn error::Error>> {
+        let url = Url::parse(&accounts_url("/api/token"))?;
 
         let mut headers = HeaderMap::new();
         let encoded_id_and_secret =
@@ -259,35 +671,33 @@ impl SpotifyAuth {
             HeaderValue::from_str(&authorization_header)?,
         );
 
-        let redirect_uri = format!("http://localhost:{}", redirect_port);
         let form = [
             ("grant_type", "authorization_code"),
             ("code", authorization_code),
-            ("redirect_uri", redirect_uri.as_str()),
+            ("redirect_uri", redirect_uri),
         ];
 
-        #[cfg(debug_assertions)]
-        println!("Authentication request url: {}", url.as_str());
-        #[cfg(debug_assertions)]
-        println!("Headers: {:?}", headers);
-        #[cfg(debug_assertions)]
-        println!("Form: {:?}\n", form);
+        tracing::debug!(url = %url, form = ?logging::redact_form(&form), "Requesting tokens");
 
         let curr_time = current_time_secs_from_epoch()?;
-        let client = reqwest::Client::new();
+        let client = http::client();
         let res = client.post(url).headers(headers).form(&form).send().await?;
 
         match res.status() {
             StatusCode::OK => {
                 let auth_response: AuthenticationResponse = res.json().await?;
 
-                #[cfg(debug_assertions)]
-                println!("Authentication response:\n{:?}\n", auth_response);
+                tracing::debug!(
+                    expires_in = auth_response.expires_in,
+                    scope = ?auth_response.scope,
+                    "Got tokens"
+                );
 
                 Ok((
                     auth_response.access_token,
                     auth_response.refresh_token.unwrap(),
                     curr_time + auth_response.expires_in,
+                    auth_response.scope.as_deref().map(parse_scopes),
                 ))
             }
             _ => Err(res.text().await?.into()),
@@ -303,7 +713,12 @@ impl SpotifyAuth {
     /// within a longer duration than the 2 minutes set as the refresh limit
     /// in the method `get_access_token`.
     pub async fn refresh_token(&mut self) -> Result<(), Box<dyn error::Error>> {
-        let url = Url::parse("https://accounts.spotify.com/api/token")?;
+        let _lock = self.lock_token_file()?;
+        if self.reload_newer_tokens()? {
+            return Ok(());
+        }
+
+        let url = Url::parse(&accounts_url("/api/token"))?;
 
         let mut headers = HeaderMap::new();
         let encoded_id_and_secret =
@@ -320,29 +735,31 @@ impl SpotifyAuth {
                 ("refresh_token", refresh_token.as_str()),
             ];
 
-            #[cfg(debug_assertions)]
-            println!("Refreshing token request url: {}", url.as_str());
-            #[cfg(debug_assertions)]
-            println!("Headers: {:?}", headers);
-            #[cfg(debug_assertions)]
-            println!("Form: {:?}\n", form);
+            tracing::debug!(url = %url, form = ?logging::redact_form(&form), "Refreshing the access token");
 
             let curr_time = current_time_secs_from_epoch()?;
-            let client = reqwest::Client::new();
+            let client = http::client();
             let res = client.post(url).headers(headers).form(&form).send().await?;
 
             match res.status() {
                 StatusCode::OK => {
                     let auth_response: AuthenticationResponse = res.json().await?;
 
-                    #[cfg(debug_assertions)]
-                    println!("Refreshing token response:\n{:?}\n", auth_response);
+                    tracing::debug!(
+                        expires_in = auth_response.expires_in,
+                        new_refresh_token = auth_response.refresh_token.is_some(),
+                        scope = ?auth_response.scope,
+                        "Refreshed the access token"
+                    );
 
                     self.access_token = Some(auth_response.access_token);
                     if let Some(refresh_token) = auth_response.refresh_token {
                         self.refresh_token = Some(refresh_token);
                     }
                     self.valid_until = Some(curr_time + auth_response.expires_in);
+                    if let Some(scope) = auth_response.scope {
+                        self.scopes = parse_scopes(&scope);
+                    }
 
                     self.save()?;
 
@@ -356,28 +773,231 @@ impl SpotifyAuth {
     }
 }
 
-fn current_time_secs_from_epoch() -> Result<u64, Box<dyn error::Error>> {
+/// Where the API calls get their access tokens. `SpotifyAuth` runs the OAuth
+/// flow and refreshes expiring tokens; `StaticToken` just hands out a token
+/// obtained elsewhere. The handlers only depend on this trait.
+pub trait TokenProvider {
+    /// A token valid for at least the next request.
+    async fn get_access_token(&mut self) -> Result<String, Box<dyn error::Error>>;
+
+    /// Called when the API rejects the token.
+    async fn refresh_token(&mut self) -> Result<(), Box<dyn error::Error>>;
+
+    fn has_scope(&self, scope: Scope) -> bool;
+
+    /// Makes sure the scopes are granted, re-authorizing if possible.
+    async fn require_scopes(&mut self, scopes: &[Scope]) -> Result<(), Box<dyn error::Error>>;
+
+    /// The OAuth state, for the commands managing it (`auth login` etc.).
+    fn spotify_auth(&mut self) -> Option<&mut SpotifyAuth> {
+        None
+    }
+}
+
+impl TokenProvider for SpotifyAuth {
+    async fn get_access_token(&mut self) -> Result<String, Box<dyn error::Error>> {
+        SpotifyAuth::get_access_token(self).await
+    }
+
+    async fn refresh_token(&mut self) -> Result<(), Box<dyn error::Error>> {
+        SpotifyAuth::refresh_token(self).await
+    }
+
+    fn has_scope(&self, scope: Scope) -> bool {
+        SpotifyAuth::has_scope(self, scope)
+    }
+
+    async fn require_scopes(&mut self, scopes: &[Scope]) -> Result<(), Box<dyn error::Error>> {
+        SpotifyAuth::require_scopes(self, scopes).await
+    }
+
+    fn spotify_auth(&mut self) -> Option<&mut SpotifyAuth> {
+        Some(self)
+    }
+}
+
+/// A fixed access token, e.g. from SPOTIFY_CLI_ACCESS_TOKEN. It can't be
+/// refreshed and its scopes are unknown, so all scopes are assumed granted
+/// and missing ones show up as API errors.
+pub struct StaticToken {
+    access_token: String,
+}
+
+impl StaticToken {
+    pub fn new(access_token: &str) -> StaticToken {
+        StaticToken {
+            access_token: access_token.to_owned(),
+        }
+    }
+}
+
+impl TokenProvider for StaticToken {
+    async fn get_access_token(&mut self) -> Result<String, Box<dyn error::Error>> {
+        Ok(self.access_token.clone())
+    }
+
+    async fn refresh_token(&mut self) -> Result<(), Box<dyn error::Error>> {
+        Err("The access token was rejected and a fixed token can't be refreshed.".into())
+    }
+
+    fn has_scope(&self, _scope: Scope) -> bool {
+        true
+    }
+
+    async fn require_scopes(&mut self, _scopes: &[Scope]) -> Result<(), Box<dyn error::Error>> {
+        Ok(())
+    }
+}
+
+/// Page where users can remove the app's access to their account. Spotify
+/// has no API for revoking tokens.
+pub const ACCOUNT_APPS_URL: &str = "https://www.spotify.com/account/apps/";
+
+/// Deletes the token file and its lock file. Returns whether there was a
+/// token file to delete.
+pub fn delete_token_file(filepath: &str) -> Result<bool, Box<dyn error::Error>> {
+    let existed = match fs::remove_file(filepath) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(format!("Failed to delete {filepath}: {e}").into()),
+    };
+    match fs::remove_file(format!("{filepath}.lock")) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(format!("Failed to delete {filepath}.lock: {e}").into())
+        }
+        _ => {}
+    }
+
+    Ok(existed)
+}
+
+pub fn current_time_secs_from_epoch() -> Result<u64, Box<dyn error::Error>> {
     let secs = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs();
     Ok(secs)
 }
 
+/// A small page for the browser tab the redirect lands in. Scripts may only
+/// close tabs they opened, so the text also asks to close it.
+fn redirect_page(status: u16, message: &str) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>spotify-cli</title></head>\
+         <body style=\"font-family: sans-serif; text-align: center; margin-top: 4em\">\
+         <h2>spotify-cli</h2><p>{message}</p>\
+         <script>setTimeout(() => window.close(), 1000)</script></body></html>"
+    );
+    let content_type = tiny_http::Header::from_bytes("Content-Type", "text/html; charset=utf-8")
+        .expect("the header is valid");
+
+    tiny_http::Response::from_string(html)
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+/// Waits for the browser to be redirected to the local server and returns
+/// the requested url, or `None` on timeout. Requests to other paths, e.g.
+/// `/favicon.ico`, get a 404 and requests with the wrong state a 400; neither
+/// is taken as the redirect, so a stray or forged request can't end the flow.
+/// Ctrl-C stops waiting and frees the port.
+async fn wait_for_redirect(
+    server: Arc<tiny_http::Server>,
+    redirect_path: &str,
+    state: &str,
+    timeout: Duration,
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let deadline = Instant::now() + timeout;
+    let redirect_path = redirect_path.to_string();
+    let state = state.to_string();
+
+    let waiting = cancelled.clone();
+    let mut handle = tokio::task::spawn_blocking(move || -> Result<Option<String>, String> {
+        while !waiting.load(Ordering::Relaxed) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            let request = server
+                .recv_timeout(remaining.min(REDIRECT_POLL_INTERVAL))
+                .map_err(|e| e.to_string())?;
+            let Some(request) = request else {
+                continue;
+            };
+
+            let request_url = request.url().to_string();
+            let path = request_url.split('?').next().unwrap_or_default();
+            if path != redirect_path {
+                tracing::debug!(%path, "Ignoring a request to another path");
+                let _ = request.respond(redirect_page(404, "Not found."));
+                continue;
+            }
+
+            // Only the path and query are sent; any base works for parsing.
+            let query_params: HashMap<String, String> = Url::parse("http://localhost")
+                .and_then(|base| base.join(&request_url))
+                .map(|url| url.query_pairs().into_owned().collect())
+                .unwrap_or_default();
+            if query_params.get("state") != Some(&state) {
+                tracing::warn!("Ignoring a redirect with a wrong state");
+                let _ = request.respond(redirect_page(
+                    400,
+                    "Invalid state. This doesn't look like the redirect spotify-cli is waiting for.",
+                ));
+                continue;
+            }
+
+            if let Some(error) = query_params.get("error") {
+                let _ = request.respond(redirect_page(
+                    400,
+                    "Authorization failed. See the terminal for details.",
+                ));
+                return Err(format!("Authorization failed: {error}"));
+            }
+
+            request
+                .respond(redirect_page(
+                    200,
+                    "Authorized! You can now close this tab and go back to the terminal.",
+                ))
+                .map_err(|e| e.to_string())?;
+            return Ok(Some(request_url));
+        }
+
+        Ok(None)
+    });
+
+    tokio::select! {
+        waited = &mut handle => Ok(waited??),
+        _ = tokio::signal::ctrl_c() => {
+            cancelled.store(true, Ordering::Relaxed);
+            let _ = handle.await;
+            Err("Authorization cancelled.".into())
+        }
+    }
+}
+
+/// Renders the text as a QR code of half-height blocks for the terminal.
+fn qr_code(text: &str) -> Result<String, Box<dyn error::Error>> {
+    let code = qrcode::QrCode::new(text)?;
+
+    // Dark on light, whatever the terminal's colors are.
+    Ok(code
+        .render::<qrcode::render::unicode::Dense1x2>()
+        .dark_color(qrcode::render::unicode::Dense1x2::Light)
+        .light_color(qrcode::render::unicode::Dense1x2::Dark)
+        .build())
+}
+
 fn generate_random_state() -> String {
     Alphanumeric.sample_string(&mut rand::thread_rng(), 16)
 }
 
-fn get_free_port() -> Result<u16, Box<dyn error::Error>> {
-    // Allowed redirect URIs need to be specified in Spotify's app dashboard.
-    // Thus we can't use actually random ports. To allow multiple port choices,
-    // we need to list http://localhost:5555, http://localhost:5556, ... in
-    // the app dashboard.
-    // TODO: get a list of ports from an env var or something? Hardcoding is nasty.
-    let possible_ports = [5555, 5556, 5557, 5558, 5559];
+pub fn get_free_port(possible_ports: &[u16]) -> Result<u16, Box<dyn error::Error>> {
     for port in possible_ports {
-        if portpicker::is_free(port) {
-            return Ok(port);
+        if portpicker::is_free(*port) {
+            return Ok(*port);
         }
     }
-    Err("All ports unavailable.".into())
+    Err(format!("All ports unavailable: {possible_ports:?}").into())
 }
@@ -0,0 +1,162 @@
+use super::{
+    auth::current_time_secs_from_epoch,
+    paths::{self, DefaultFile},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, env, error, fs};
+
+/// Small on-disk cache for data that is needed fast and can be slightly stale,
+/// e.g. the now playing summary for shell prompts.
+#[derive(Deserialize, Debug, Default, Serialize)]
+pub struct CacheFile {
+    #[serde(default)]
+    pub now_playing: Option<NowPlaying>,
+    #[serde(default)]
+    pub devices: Option<Devices>,
+    /// Genres by artist id.
+    #[serde(default)]
+    pub artist_genres: HashMap<String, ArtistGenres>,
+    /// Track uris of recently played playlists and albums by context uri,
+    /// for finding the position of the current track.
+    #[serde(default)]
+    pub context_tracks: HashMap<String, ContextTracks>,
+    /// The API calls of the last day, oldest first, for `quota`.
+    #[serde(default)]
+    pub api_calls: Vec<ApiCall>,
+    #[serde(default)]
+    pub genre_seeds: Option<GenreSeeds>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct NowPlaying {
+    pub fetched_at: u64,
+    pub title: String,
+    pub artists: String,
+    pub is_playing: bool,
+}
+
+impl NowPlaying {
+    pub fn age_secs(&self) -> u64 {
+        current_time_secs_from_epoch()
+            .unwrap_or(self.fetched_at)
+            .saturating_sub(self.fetched_at)
+    }
+}
+
+/// Device names and ids, so that `--device <name>` doesn't need to list the
+/// devices on every invocation.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Devices {
+    pub fetched_at: u64,
+    pub devices: Vec<CachedDevice>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct CachedDevice {
+    pub id: String,
+    pub name: String,
+}
+
+impl Devices {
+    pub fn age_secs(&self) -> u64 {
+        current_time_secs_from_epoch()
+            .unwrap_or(self.fetched_at)
+            .saturating_sub(self.fetched_at)
+    }
+}
+
+/// An artist's genres. They rarely change, so these are kept for a while.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct ArtistGenres {
+    pub fetched_at: u64,
+    pub genres: Vec<String>,
+}
+
+impl ArtistGenres {
+    pub fn age_secs(&self) -> u64 {
+        current_time_secs_from_epoch()
+            .unwrap_or(self.fetched_at)
+            .saturating_sub(self.fetched_at)
+    }
+}
+
+/// The genres recommendations can be seeded with. The list hardly ever
+/// changes and the endpoint is being phased out, so it's kept for long.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct GenreSeeds {
+    pub fetched_at: u64,
+    pub genres: Vec<String>,
+}
+
+impl GenreSeeds {
+    pub fn age_secs(&self) -> u64 {
+        current_time_secs_from_epoch()
+            .unwrap_or(self.fetched_at)
+            .saturating_sub(self.fetched_at)
+    }
+}
+
+/// The tracks of a playlist or album. A playlist's are valid as long as its
+/// snapshot id is the same; an album's don't change.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct ContextTracks {
+    pub fetched_at: u64,
+    pub snapshot_id: Option<String>,
+    pub uris: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct ApiCall {
+    /// Seconds from the epoch.
+    pub at: u64,
+    /// E.g. `GET /playlists/{id}/tracks`.
+    pub endpoint: String,
+    pub status: u16,
+    /// Seconds Spotify asked to wait when it rate limited the call.
+    pub retry_after: Option<u64>,
+}
+
+/// How many contexts' tracks are kept; big playlists have thousands.
+const MAX_CONTEXTS: usize = 5;
+
+impl CacheFile {
+    /// Stores the tracks of a context, dropping the oldest ones over
+    /// `MAX_CONTEXTS`.
+    pub fn insert_context_tracks(&mut self, uri: &str, tracks: ContextTracks) {
+        self.context_tracks.insert(uri.to_string(), tracks);
+        while self.context_tracks.len() > MAX_CONTEXTS {
+            let oldest = self
+                .context_tracks
+                .iter()
+                .min_by_key(|(_, tracks)| tracks.fetched_at)
+                .map(|(uri, _)| uri.clone());
+            match oldest {
+                Some(uri) => self.context_tracks.remove(&uri),
+                None => break,
+            };
+        }
+    }
+}
+
+fn cache_path() -> Result<String, Box<dyn error::Error>> {
+    match env::var("SPOTIFY_CLI_CACHE_FILE") {
+        Ok(filepath) => Ok(filepath),
+        Err(_) => paths::default_path(DefaultFile::Cache),
+    }
+}
+
+/// Loads the cache. A missing or unreadable cache is treated as empty.
+pub fn load() -> CacheFile {
+    cache_path()
+        .ok()
+        .and_then(|filepath| fs::read_to_string(filepath).ok())
+        .and_then(|cache_str| serde_json::from_str(&cache_str).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(cache: &CacheFile) -> Result<(), Box<dyn error::Error>> {
+    let cache_str = serde_json::to_string(cache)?;
+    fs::write(cache_path()?, cache_str)?;
+
+    Ok(())
+}
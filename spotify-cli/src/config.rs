@@ -0,0 +1,258 @@
+use super::paths::{self, DefaultFile};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, error, fs};
+
+/// User configuration read from a TOML file. Every field is optional so an
+/// empty or missing file is a valid config.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Short names for uris, used as `@name` in place of a uri.
+    #[serde(default)]
+    pub alias: BTreeMap<String, String>,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// What to run when no command is given, e.g. `show` or `queue 10`,
+    /// instead of printing the help.
+    pub default_command: Option<String>,
+    /// Short names for device names or ids, used as `--device @name`.
+    #[serde(default)]
+    pub device: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub lyrics: LyricsConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub playback: PlaybackConfig,
+    #[serde(default)]
+    pub recommendations: RecommendationsConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+}
+
+/// Settings for the OAuth flow. The env variables `SPOTIFY_CLI_REDIRECT_PORTS`
+/// (comma separated) and `SPOTIFY_CLI_REDIRECT_URI` override these.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Ports to try in order for the `http://localhost:<port>` redirect uri.
+    pub redirect_ports: Option<Vec<u16>>,
+    /// Fully custom redirect uri, e.g. `http://127.0.0.1:8888/callback`.
+    pub redirect_uri: Option<String>,
+    /// Seconds to wait for the redirect before asking whether to keep waiting.
+    pub redirect_timeout_secs: Option<u64>,
+    /// Open the authorization url in the browser; defaults to true.
+    pub open_browser: Option<bool>,
+}
+
+/// Shell commands to run on playback events. See `hooks` for the env
+/// variables passed to the commands.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    pub track_change: Option<String>,
+    pub pause: Option<String>,
+    pub resume: Option<String>,
+    pub device_change: Option<String>,
+    pub device_lost: Option<String>,
+}
+
+/// Pausing playback by `watch` when a device disappears from the devices
+/// list, e.g. a phone leaving the WiFi. Off unless enabled.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WatchdogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Names, ids or `@aliases` of the devices to watch. Defaults to the
+    /// device that's playing.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Transfer playback here instead of pausing, if it's available.
+    pub fallback_device: Option<String>,
+}
+
+/// Recording of the listening history by `watch`. Off unless enabled.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// SQLite file to record into. Defaults to
+    /// `$XDG_DATA_HOME/spotify-cli/history.sqlite`.
+    pub file: Option<String>,
+}
+
+pub fn config_path() -> Result<String, Box<dyn error::Error>> {
+    match env::var("SPOTIFY_CLI_CONFIG_FILE") {
+        Ok(filepath) => Ok(filepath),
+        Err(_) => paths::default_path(DefaultFile::Config),
+    }
+}
+
+/// Loads the config. A missing file gives the default config, but a file
+/// that fails to parse is an error so typos don't get silently ignored.
+pub fn load() -> Result<Config, Box<dyn error::Error>> {
+    let filepath = config_path()?;
+    if !fs::exists(&filepath)? {
+        return Ok(Config::default());
+    }
+
+    let config_str = fs::read_to_string(&filepath)
+        .map_err(|_| format!("Failed to read config file {}", filepath))?;
+    let config: Config = toml::from_str(&config_str)
+        .map_err(|e| format!("Failed to parse config file {}:\n{e}", filepath))?;
+
+    Ok(config)
+}
+
+/// Which config table aliases go in.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum AliasKind {
+    /// A uri/url, or anything else given in place of one, e.g. a playlist name
+    Uri,
+    /// A device name or id
+    Device,
+}
+
+impl AliasKind {
+    fn table(self) -> &'static str {
+        match self {
+            AliasKind::Uri => "alias",
+            AliasKind::Device => "device",
+        }
+    }
+}
+
+/// Reads the config file for editing, keeping its comments and formatting.
+fn load_document(filepath: &str) -> Result<toml_edit::DocumentMut, Box<dyn error::Error>> {
+    let config_str = match fs::exists(filepath)? {
+        true => fs::read_to_string(filepath)
+            .map_err(|_| format!("Failed to read config file {}", filepath))?,
+        false => String::new(),
+    };
+    config_str
+        .parse()
+        .map_err(|e| format!("Failed to parse config file {}:\n{e}", filepath).into())
+}
+
+/// Adds or replaces an alias in the config file.
+pub fn set_alias(kind: AliasKind, name: &str, value: &str) -> Result<(), Box<dyn error::Error>> {
+    let filepath = config_path()?;
+    let mut document = load_document(&filepath)?;
+    let table = document
+        .entry(kind.table())
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or(format!("{} in the config isn't a table.", kind.table()))?;
+    table.insert(name, toml_edit::value(value));
+
+    fs::write(&filepath, document.to_string())?;
+
+    Ok(())
+}
+
+/// Removes an alias from the config file. Returns whether there was one.
+pub fn remove_alias(kind: AliasKind, name: &str) -> Result<bool, Box<dyn error::Error>> {
+    let filepath = config_path()?;
+    let mut document = load_document(&filepath)?;
+    let removed = document
+        .get_mut(kind.table())
+        .and_then(|table| table.as_table_like_mut())
+        .and_then(|table| table.remove(name))
+        .is_some();
+    if removed {
+        fs::write(&filepath, document.to_string())?;
+    }
+
+    Ok(removed)
+}
+
+/// Where to get lyrics from. Defaults to LRCLIB.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LyricsConfig {
+    /// Base url of an LRCLIB compatible server.
+    pub lrclib_url: Option<String>,
+    /// Url template of a custom endpoint, e.g.
+    /// `https://example.com/lyrics?artist={artist}&title={title}`.
+    pub url_template: Option<String>,
+}
+
+/// Settings for all requests, to the Web API as well as the accounts service
+/// and lyrics. The env variables `SPOTIFY_CLI_PROXY` and `SPOTIFY_CLI_CA_FILE`
+/// override these.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Proxy for all requests, e.g. `http://proxy.example.com:8080`. Without
+    /// it the usual `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` are respected.
+    pub proxy: Option<String>,
+    /// PEM file with extra CA certificates to trust, e.g. for a TLS
+    /// intercepting proxy.
+    pub ca_file: Option<String>,
+    /// Seconds to wait for a connection. Defaults to 10.
+    pub connect_timeout_secs: Option<f64>,
+    /// Seconds to wait for a whole request. Defaults to 30; `--timeout`
+    /// overrides this.
+    pub timeout_secs: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PlaybackConfig {
+    /// How many seconds into a track `previous` restarts it instead of going
+    /// back. Defaults to 3.
+    pub previous_restart_secs: Option<u64>,
+    /// Tracks at least this many minutes long have their position
+    /// remembered when paused, like episodes. Defaults to 20.
+    pub remember_position_mins: Option<u64>,
+    /// Country code of the catalog to use, e.g. `FI`, instead of the one of
+    /// the account. `--market` overrides this.
+    pub market: Option<String>,
+}
+
+/// Where `rec generate` gets recommendations from.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RecommendationsConfig {
+    #[serde(default)]
+    pub backend: RecommendationBackendKind,
+    /// Leave explicit tracks out of generated and saved recommendations.
+    #[serde(default)]
+    pub exclude_explicit: bool,
+}
+
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecommendationBackendKind {
+    /// The recommendations endpoint, falling back to sampling if the app
+    /// doesn't have access to it.
+    #[default]
+    Auto,
+    /// Only the recommendations endpoint.
+    Spotify,
+    /// Top tracks of the seed artists and related artists.
+    Sampling,
+}
+
+/// Styles of colored output, e.g. `bold bright-green` or raw ANSI codes
+/// like `1;92`. See `output` for the names.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// The current track in track lists.
+    pub highlight: Option<String>,
+    /// The paused marker.
+    pub paused: Option<String>,
+    /// Playlist, album etc. titles.
+    pub header: Option<String>,
+    /// Tracks that can't be played in the market.
+    pub unavailable: Option<String>,
+}
@@ -0,0 +1,205 @@
+use std::{
+    env, error, fs,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// Clipboard tools to try, in order.
+fn clipboard_commands() -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        vec![("pbcopy", &[])]
+    } else if cfg!(windows) {
+        vec![("clip", &[])]
+    } else {
+        let mut commands: Vec<(&'static str, &'static [&'static str])> = vec![
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ];
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            commands.insert(0, ("wl-copy", &[]));
+        }
+        commands
+    }
+}
+
+/// Copies the text to the system clipboard with whichever clipboard tool is
+/// installed.
+pub fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn error::Error>> {
+    for (program, args) in clipboard_commands() {
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to run {program}: {e}").into()),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+
+    Err("Couldn't copy to the clipboard; install wl-clipboard, xclip or xsel.".into())
+}
+
+/// Whether this is Linux running under WSL, where the Windows side has the
+/// browser.
+fn is_wsl() -> bool {
+    cfg!(target_os = "linux")
+        && (env::var_os("WSL_DISTRO_NAME").is_some()
+            || fs::read_to_string("/proc/sys/kernel/osrelease")
+                .is_ok_and(|release| release.to_lowercase().contains("microsoft")))
+}
+
+/// Commands opening the target, to try in order.
+fn open_commands(target: &str) -> Vec<Command> {
+    let command = |program: &str, args: &[&str]| {
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    };
+
+    if cfg!(target_os = "macos") {
+        vec![command("open", &[target])]
+    } else if cfg!(windows) {
+        // Unlike `cmd /C start`, this doesn't split urls at `&`.
+        vec![command(
+            "rundll32",
+            &["url.dll,FileProtocolHandler", target],
+        )]
+    } else if is_wsl() {
+        let script = format!("Start-Process '{}'", target.replace('\'', "''"));
+        vec![
+            command("wslview", &[target]),
+            command("powershell.exe", &["-NoProfile", "-Command", &script]),
+        ]
+    } else {
+        vec![command("xdg-open", &[target])]
+    }
+}
+
+/// Opens a url (or a `spotify:` uri) with the default browser or app.
+pub fn open(target: &str) -> Result<(), Box<dyn error::Error>> {
+    for mut command in open_commands(target) {
+        let status = match command.stdout(Stdio::null()).stderr(Stdio::null()).status() {
+            Ok(status) => status,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to open {target}: {e}").into()),
+        };
+        if status.success() {
+            return Ok(());
+        }
+    }
+
+    Err(format!("Failed to open {target}.").into())
+}
+
+/// Name of the desktop entry registered for `spotify:` links.
+const URI_HANDLER_DESKTOP_FILE: &str = "spotify-cli-uri-handler.desktop";
+
+/// Registers `command` (the program followed by its arguments) as the handler
+/// of `spotify:` links for the current user; the link is appended as the last
+/// argument. Returns where it was registered.
+pub fn register_uri_handler(command: &[String]) -> Result<String, Box<dyn error::Error>> {
+    if cfg!(windows) {
+        register_uri_handler_windows(command)
+    } else if cfg!(target_os = "macos") {
+        Err("Registering a handler for spotify: links needs an app bundle on macOS; that's not supported.".into())
+    } else {
+        register_uri_handler_xdg(command)
+    }
+}
+
+/// Quotes an argument for the `Exec` key of a desktop entry.
+fn desktop_entry_quote(arg: &str) -> String {
+    if arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./=@".contains(c))
+    {
+        return arg.to_string();
+    }
+    let escaped: String = arg
+        .chars()
+        .flat_map(|c| match c {
+            '"' | '`' | '$' | '\\' => vec!['\\', c],
+            '%' => vec!['%', '%'],
+            c => vec![c],
+        })
+        .collect();
+    format!("\"{escaped}\"")
+}
+
+fn register_uri_handler_xdg(command: &[String]) -> Result<String, Box<dyn error::Error>> {
+    let dir = dirs::data_dir()
+        .ok_or("Can't get data directory?")?
+        .join("applications");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(URI_HANDLER_DESKTOP_FILE);
+
+    let exec: Vec<String> = command.iter().map(|arg| desktop_entry_quote(arg)).collect();
+    let entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=spotify-cli\n\
+         Comment=Play spotify: links with spotify-cli\n\
+         Exec={} %u\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         MimeType=x-scheme-handler/spotify;\n",
+        exec.join(" ")
+    );
+    fs::write(&path, entry)?;
+
+    let status = Command::new("xdg-mime")
+        .args([
+            "default",
+            URI_HANDLER_DESKTOP_FILE,
+            "x-scheme-handler/spotify",
+        ])
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(path.display().to_string()),
+        Ok(_) => Err(format!(
+            "Wrote {} but xdg-mime failed to make it the default handler.",
+            path.display()
+        )
+        .into()),
+        Err(_) => Err(format!(
+            "Wrote {} but couldn't run xdg-mime to make it the default handler; install xdg-utils.",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+fn register_uri_handler_windows(command: &[String]) -> Result<String, Box<dyn error::Error>> {
+    const KEY: &str = r"HKCU\Software\Classes\spotify";
+    let quoted: Vec<String> = command.iter().map(|arg| format!("\"{arg}\"")).collect();
+    let open_command = format!("{} \"%1\"", quoted.join(" "));
+    let open_key = format!(r"{KEY}\shell\open\command");
+    let entries: [(&str, &[&str]); 3] = [
+        (KEY, &["/ve", "/d", "URL:Spotify"]),
+        (KEY, &["/v", "URL Protocol", "/d", ""]),
+        (&open_key, &["/ve", "/d", &open_command]),
+    ];
+    for (key, args) in entries {
+        let status = Command::new("reg")
+            .args(["add", key])
+            .args(args)
+            .arg("/f")
+            .stdout(Stdio::null())
+            .status()?;
+        if !status.success() {
+            return Err(format!("Failed to write the registry key {key}.").into());
+        }
+    }
+
+    Ok(KEY.to_string())
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// Parses durations like `30m`, `1h30m`, `90s`, or `45` (minutes).
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let bad = || format!("Bad duration: {duration}. Use e.g. 30m, 1h30m or 90s.");
+    if let Ok(minutes) = duration.parse::<u64>() {
+        return match minutes.checked_mul(60) {
+            Some(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+            _ => Err(bad()),
+        };
+    }
+
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+    for c in duration.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' | 's' if !number.is_empty() => {
+                let value: u64 = number.parse().map_err(|_| bad())?;
+                let unit = match c {
+                    'h' => 60 * 60,
+                    'm' => 60,
+                    _ => 1,
+                };
+                secs = value
+                    .checked_mul(unit)
+                    .and_then(|value| secs.checked_add(value))
+                    .ok_or_else(bad)?;
+                number.clear();
+            }
+            _ => return Err(bad()),
+        }
+    }
+    if !number.is_empty() || secs == 0 {
+        return Err(bad());
+    }
+
+    Ok(Duration::from_secs(secs))
+}
+
+/// Parses a number of seconds like `10`, `2.5` or `500ms`.
+pub fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    let bad = || format!("Bad number of seconds: {seconds}. Use e.g. 10, 2.5 or 500ms.");
+    let duration = match seconds.strip_suffix("ms") {
+        Some(ms) => Duration::from_millis(ms.parse().map_err(|_| bad())?),
+        None => {
+            let secs: f64 = seconds
+                .strip_suffix('s')
+                .unwrap_or(seconds)
+                .parse()
+                .map_err(|_| bad())?;
+            Duration::try_from_secs_f64(secs).map_err(|_| bad())?
+        }
+    };
+    match duration.is_zero() {
+        true => Err(bad()),
+        false => Ok(duration),
+    }
+}
+
+/// Formats milliseconds as `m:ss`, or `h:mm:ss` for durations over an hour.
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
@@ -1,23 +1,95 @@
-use super::auth::SpotifyAuth;
+use super::{
+    api::{self, ApiError},
+    art::{self, ArtProtocol},
+    auth::{
+        self, current_time_secs_from_epoch, Scope, SpotifyAuth, TokenProvider, ACCOUNT_APPS_URL,
+    },
+    cache::{self, ArtistGenres, NowPlaying},
+    config::{self, AliasKind, Config, RecommendationBackendKind},
+    desktop,
+    duration::format_duration,
+    genres::FALLBACK_GENRE_SEEDS,
+    history::{self, History, Play},
+    hooks::{self, Event},
+    http,
+    i18n::tr,
+    logging,
+    lyrics::{Lrclib, Lyrics, LyricsProvider, LyricsQuery, UrlTemplate},
+    models::{
+        self, Artist, Context, Device, Episode, Playlist, SavedShow, Show, Song, SpotifyId,
+        TrackItem, TracksLink, User,
+    },
+    output::{self, Table, TableRow},
+    picker,
+    progress::{self, Progress},
+    prompt, schedule, snapshots,
+    state::{self, BlockedTrack, LastDevice, SavedPosition},
+    text, usage,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::NaiveTime;
+use rand::{seq::SliceRandom, Rng};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
     StatusCode,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, env, error, fmt::Display, io, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    env, error,
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal},
+    sync::OnceLock,
+    time::Duration,
+};
 
-fn get_max_print_width() -> usize {
-    let width = term_size::dimensions().unwrap_or((80, 0)).0;
+const DEFAULT_API_BASE_URL: &str = "https://api.spotify.com/v1";
 
-    if width >= 80 {
-        width
-    } else {
-        width * ((80 / width) + 1)
+static API_BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Overrides the base url of the Web API, e.g. to point the CLI at a mock
+/// server. Has to be called before any requests are made.
+pub fn set_api_base_url(base_url: &str) {
+    let _ = API_BASE_URL.set(base_url.trim_end_matches('/').to_string());
+}
+
+fn api_base_url() -> &'static str {
+    API_BASE_URL.get_or_init(|| DEFAULT_API_BASE_URL.to_string())
+}
+
+fn api_url(path: &str) -> String {
+    format!("{}{path}", api_base_url())
+}
+
+static MARKET: OnceLock<String> = OnceLock::new();
+
+/// Uses the catalog of the given market (an ISO 3166-1 alpha-2 country code)
+/// instead of the one of the account. Has to be called before any requests
+/// are made.
+pub fn set_market(market: &str) -> Result<(), Box<dyn error::Error>> {
+    if market.len() != 2 || !market.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(
+            format!("Bad market {market}; give a two letter country code, e.g. FI.").into(),
+        );
     }
+    let _ = MARKET.set(market.to_uppercase());
+
+    Ok(())
+}
+
+/// The `market` query parameter of catalog requests. Tracks are relinked to
+/// versions playable in this market, and ones that aren't get `is_playable:
+/// false`.
+fn market() -> &'static str {
+    MARKET
+        .get()
+        .map(|market| market.as_str())
+        .unwrap_or("from_token")
 }
 
-async fn auth_header(auth: &mut SpotifyAuth) -> Result<HeaderMap, Box<dyn error::Error>> {
+async fn auth_header(auth: &mut impl TokenProvider) -> Result<HeaderMap, Box<dyn error::Error>> {
     let access_token = auth.get_access_token().await?;
     let authorization_value = format!("Bearer {}", access_token);
     let mut headers = HeaderMap::new();
@@ -28,67 +100,111 @@ async fn auth_header(auth: &mut SpotifyAuth) -> Result<HeaderMap, Box<dyn error:
     Ok(headers)
 }
 
-#[derive(Deserialize, Debug)]
-struct Album {
-    name: String,
-    // artists: Vec<Artist>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Artist {
-    name: String,
-    id: String,
-}
+/// The longest `Retry-After` that is waited out instead of failing.
+const MAX_RATE_LIMIT_WAIT_SECS: u64 = 5;
+
+/// How many times a rate limited request is retried.
+const RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Sends the request with the user's access token.
+///
+/// The token is refreshed ahead of its expiry, but that doesn't help when
+/// the clocks disagree or the token was revoked. So if the API still answers
+/// 401, the token is refreshed once and the request retried. Short rate
+/// limits, e.g. in the middle of adding thousands of tracks, are waited out
+/// and the request retried a few times; longer ones fail right away.
+async fn send(
+    auth: &mut impl TokenProvider,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, Box<dyn error::Error>> {
+    let Some(retry) = request.try_clone() else {
+        return execute(request.headers(auth_header(auth).await?)).await;
+    };
+    let mut res = execute(request.headers(auth_header(auth).await?)).await?;
 
-impl Display for Artist {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
+    if res.status() == StatusCode::UNAUTHORIZED {
+        if let Some(request) = retry.try_clone() {
+            tracing::debug!("Got 401, refreshing the token and retrying");
+            auth.refresh_token().await?;
+            res = execute(request.headers(auth_header(auth).await?)).await?;
+        }
     }
-}
 
-#[derive(Deserialize, Debug)]
-struct Song {
-    album: Option<Album>,
-    name: String,
-    id: String,
-    uri: String,
-    artists: Vec<Artist>,
-    is_playable: Option<bool>,
+    let retry = retry.headers(auth_header(auth).await?);
+    wait_out_rate_limit(res, retry).await
 }
 
-impl Display for Song {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let artists_str = if !self.artists.is_empty() {
-            let tmp = self
-                .artists
-                .iter()
-                .fold("".to_string(), |acc, x| acc + ", " + &x.name);
-            tmp.strip_prefix(", ").unwrap().to_string()
-        } else {
-            "unknown artist".to_string()
+/// Retries an already authorized request while the API answers with a short
+/// rate limit, see `send`. Doesn't need the token, so spawned tasks can use
+/// it too.
+async fn wait_out_rate_limit(
+    mut res: reqwest::Response,
+    retry: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, Box<dyn error::Error>> {
+    for attempt in 1..=RATE_LIMIT_RETRIES {
+        let after_secs = match retry_after(res.headers()) {
+            Some(secs)
+                if res.status() == StatusCode::TOO_MANY_REQUESTS
+                    && secs <= MAX_RATE_LIMIT_WAIT_SECS =>
+            {
+                secs
+            }
+            _ => break,
         };
-
-        match &self.album {
-            Some(album) => write!(
-                f,
-                "{} - {} [from the album: {}]",
-                self.name, artists_str, album.name
-            ),
-            None => write!(f, "{} - {}", self.name, artists_str),
-        }
+        let Some(request) = retry.try_clone() else {
+            break;
+        };
+        tracing::debug!(after_secs, attempt, "Rate limited, waiting and retrying");
+        progress::report(&progress::Event::Retrying {
+            after_secs,
+            attempt,
+        });
+        tokio::time::sleep(Duration::from_secs(after_secs)).await;
+        res = execute(request).await?;
     }
-}
 
-#[derive(Deserialize, Debug)]
-struct Device {
-    name: String,
-    r#type: String,
+    Ok(res)
 }
 
-impl Display for Device {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({})", self.name, self.r#type)
+/// Sends the request, logging it and the response status.
+async fn execute(
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, Box<dyn error::Error>> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().clone();
+    let url = logging::redact_url(request.url());
+    if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+        tracing::trace!(%method, %url, body = %logging::redact_json(&String::from_utf8_lossy(body)), "Request");
+    } else {
+        tracing::debug!(%method, %url, "Request");
     }
+
+    let start = std::time::Instant::now();
+    let res = client
+        .execute(request)
+        .await
+        .map_err(|e| match e.is_timeout() {
+            true => Box::new(api::TimeoutError {
+                url: url.to_string(),
+                timeout: http::timeout(),
+            }) as Box<dyn error::Error>,
+            false => e.into(),
+        })?;
+    tracing::debug!(%method, %url, status = %res.status(), elapsed_ms = start.elapsed().as_millis() as u64, "Response");
+    usage::record(
+        method.as_str(),
+        res.url(),
+        res.status(),
+        retry_after(res.headers()),
+    );
+
+    Ok(res)
+}
+
+#[derive(Deserialize, Debug)]
+struct DevicesResponse {
+    devices: Vec<Device>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -98,13 +214,9 @@ struct PlayerResponse {
     song: Song,
     is_playing: bool,
     context: Option<Context>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Context {
-    r#type: String,
-    href: String,
-    uri: String,
+    progress_ms: Option<u64>,
+    #[serde(default)]
+    shuffle_state: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -112,65 +224,24 @@ struct PlaylistDescription {
     name: String,
     description: Option<String>,
     tracks: Option<PlaylistTracks>,
+    #[serde(default)]
+    snapshot_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 struct PlaylistResponse {
-    #[allow(dead_code)]
     next: Option<String>,
     items: Vec<Playlist>,
-}
-
-impl Display for PlaylistResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let n = self.items.len();
-        for playlist in self.items.iter().take(n - 1) {
-            writeln!(f, "{playlist}\n")?;
-        }
-        if let Some(last) = self.items.last() {
-            write!(f, "{}", last)
-        } else {
-            Ok(())
-        }
-    }
-}
-
-#[derive(Deserialize, Debug)]
-struct Playlist {
-    description: Option<String>,
-    uri: String,
-    name: String,
-    tracks: TracksLink,
-    public: Option<bool>,
-}
-
-impl Display for Playlist {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)?;
-
-        if let Some(true) = &self.public {
-            write!(f, " (public)")?;
-        } else if let Some(false) = &self.public {
-            write!(f, " (private)")?;
-        }
-
-        if let Some(desc) = &self.description {
-            write!(f, ": {}", desc)?;
-        }
-
-        write!(f, "({} tracks) uri: {}", self.tracks.total, self.uri)
-    }
-}
-
-#[derive(Deserialize, Debug)]
-struct TracksLink {
-    total: u16,
+    #[serde(default)]
+    total: Option<usize>,
 }
 
 #[derive(Deserialize, Debug)]
 struct PlaylistTracks {
     next: Option<String>,
     items: Vec<TrackItem>,
+    #[serde(default)]
+    total: Option<usize>,
 }
 
 impl Display for PlaylistTracks {
@@ -188,118 +259,322 @@ impl Display for PlaylistTracks {
 }
 
 impl PlaylistTracks {
+    /// Prints the tracks prefixed with their positions in the playlist, i.e.
+    /// the indices usable with `jump`. Unplayable tracks are dimmed and show
+    /// why they can't be played.
+    ///
+    /// With `highlight`, the matching track is highlighted and `max_lines` or
+    /// `around` limit the output to the tracks around it.
     pub async fn print_tracks(
         self,
-        auth: &mut SpotifyAuth,
+        auth: &mut impl TokenProvider,
         highlight: Option<&str>,
         max_lines: Option<u16>,
+        around: Option<u16>,
     ) -> Result<(), Box<dyn error::Error>> {
         if let Some(0) = max_lines {
             return Ok(());
         }
 
-        let tracks: Vec<Song> = self.get_tracks(auth).await?;
+        let tracks: Vec<(usize, Song)> = self
+            .fetch_all_items(auth, true)
+            .await?
+            .into_iter()
+            .enumerate()
+            .map(|(ind, item)| (ind, item.track))
+            .collect();
 
-        let mut first_ind = 0;
-        let mut last_ind = tracks.len() as i32;
-        let mut highlight_ind = None;
+        print_track_lines(&tracks, highlight, max_lines, around)
+    }
 
-        if let Some(name) = highlight {
-            for (ind, track) in tracks.iter().enumerate() {
-                if track.name == name {
-                    highlight_ind = Some(ind);
-                    if let Some(max_lines) = max_lines {
-                        first_ind = ind as i32 - ((max_lines as i32 - 1) / 2);
-                        last_ind = ind as i32 + (max_lines as i32 / 2);
-                    }
-                    break;
-                }
-            }
-            if highlight_ind.is_none() {
-                return Err("Could not find the song to highlight.".into());
+    pub async fn get_tracks(
+        self,
+        auth: &mut impl TokenProvider,
+    ) -> Result<Vec<Song>, Box<dyn error::Error>> {
+        let tracks: Vec<Song> = self
+            .get_items(auth)
+            .await?
+            .into_iter()
+            .map(|track| track.track)
+            .collect();
+
+        Ok(tracks)
+    }
+
+    /// Like `get_tracks` but keeps the playlist item metadata, e.g. `added_at`.
+    pub async fn get_items(
+        self,
+        auth: &mut impl TokenProvider,
+    ) -> Result<Vec<TrackItem>, Box<dyn error::Error>> {
+        let items: Vec<TrackItem> = self
+            .get_all_items(auth)
+            .await?
+            .into_iter()
+            .filter(|item| item.track.is_playable != Some(false))
+            .collect();
+
+        Ok(items)
+    }
+
+    /// Fetches every item in the playlist, including unplayable ones. Use this
+    /// when the positions of the items matter, e.g. when reordering.
+    pub async fn get_all_items(
+        self,
+        auth: &mut impl TokenProvider,
+    ) -> Result<Vec<TrackItem>, Box<dyn error::Error>> {
+        self.fetch_all_items(auth, false).await
+    }
+
+    /// Fetches the remaining pages, showing progress for big playlists. When
+    /// the total is known, the pages are fetched `CONCURRENT_PAGES` at a time.
+    ///
+    /// If `interruptible`, Ctrl-C stops fetching and the items fetched so far
+    /// are returned. Only for displaying; partial results must never be used
+    /// to modify a playlist.
+    async fn fetch_all_items(
+        self,
+        auth: &mut impl TokenProvider,
+        interruptible: bool,
+    ) -> Result<Vec<TrackItem>, Box<dyn error::Error>> {
+        let mut items: Vec<TrackItem> = self.items;
+        let Some(next) = self.next else {
+            return Ok(items);
+        };
+
+        let mut progress = Progress::new("Fetching tracks", progress::Kind::Pages, self.total);
+        let mut interrupted = std::pin::pin!(async {
+            match interruptible {
+                true => tokio::signal::ctrl_c().await,
+                false => std::future::pending().await,
             }
-        } else if let Some(max_lines) = max_lines {
-            last_ind = (max_lines - 1) as i32;
-        }
+        });
+
+        let Some(urls) = self.total.and_then(|total| page_urls(&next, total)) else {
+            // Can't tell where the pages are, so follow the next links.
+            let mut next = Some(next);
+            while let Some(url) = next {
+                let mut playlist_tracks = tokio::select! {
+                    page = get_playlist_tracks_page(auth, &url) => page?,
+                    _ = &mut interrupted => {
+                        drop(progress);
+                        eprintln!("{}", tr!("Interrupted; showing the first {count} tracks.", count = items.len()));
+                        return Ok(items);
+                    }
+                };
+
+                items.append(&mut playlist_tracks.items);
+                progress.update(items.len());
 
-        let max_print_width = get_max_print_width();
-        let n = tracks.len();
-        for (ind, track) in tracks.iter().take(n - 1).enumerate() {
-            if (ind as i32) < first_ind || (ind as i32) > last_ind {
-                continue;
+                next = playlist_tracks.next;
             }
 
-            let mut line = format!("#{ind} {}", track);
-            if line.chars().count() > max_print_width {
-                line = line.chars().take(max_print_width - 4).collect();
-                line += " ...";
+            return Ok(items);
+        };
+
+        let mut headers = auth_header(auth).await?;
+        let mut refreshed = false;
+        let client = http::client();
+        let mut pages: Vec<Option<Vec<TrackItem>>> = urls.iter().map(|_| None).collect();
+        let mut queued = urls.iter().enumerate();
+        let mut tasks = tokio::task::JoinSet::new();
+        let mut fetched = items.len();
+        let mut was_interrupted = false;
+        loop {
+            while tasks.len() < CONCURRENT_PAGES {
+                let Some((ind, url)) = queued.next() else {
+                    break;
+                };
+                let request = client.get(url).headers(headers.clone());
+                tasks.spawn(async move { (ind, fetch_tracks_page(request).await) });
             }
 
-            if highlight_ind.is_some() && ind == highlight_ind.unwrap() {
-                println!("\x1b[93m{line}\x1b[0m");
-            } else {
-                println!("{line}");
+            let joined = tokio::select! {
+                joined = tasks.join_next() => joined,
+                _ = &mut interrupted => {
+                    tasks.abort_all();
+                    was_interrupted = true;
+                    break;
+                }
+            };
+            let Some(joined) = joined else {
+                break;
+            };
+
+            // Failed pages are fetched again below, one by one. A rejected
+            // token is refreshed once so that the rest of the pages use the
+            // new one.
+            match joined? {
+                (ind, Ok(page)) => {
+                    fetched += page.items.len();
+                    progress.update(fetched);
+                    pages[ind] = Some(page.items);
+                }
+                (_, Err(Some(StatusCode::UNAUTHORIZED))) if !refreshed => {
+                    tracing::debug!("Got 401 fetching a page, refreshing the token");
+                    auth.refresh_token().await?;
+                    headers = auth_header(auth).await?;
+                    refreshed = true;
+                }
+                (_, Err(_)) => {}
             }
         }
-        if let Some(last) = tracks.last() {
-            let mut line = format!("#{} {}", n - 1, last);
-            if line.chars().count() > max_print_width {
-                line = line.chars().take(max_print_width - 4).collect();
-                line += " ...";
-            }
 
-            if ((n - 1) as i32) < first_ind || ((n - 1) as i32) > last_ind {
-            } else if highlight.is_some() && last.name == highlight.unwrap() {
-                println!("\x1b[93m{line}\x1b[0m");
-            } else {
-                println!("{line}");
+        for (ind, page) in pages.into_iter().enumerate() {
+            match page {
+                Some(mut page) => items.append(&mut page),
+                None if was_interrupted => {
+                    drop(progress);
+                    eprintln!(
+                        "{}",
+                        tr!(
+                            "Interrupted; showing the first {count} tracks.",
+                            count = items.len()
+                        )
+                    );
+                    return Ok(items);
+                }
+                None => items.append(&mut get_playlist_tracks_page(auth, &urls[ind]).await?.items),
             }
         }
 
-        Ok(())
+        Ok(items)
     }
+}
 
-    pub async fn get_tracks(
-        self,
-        auth: &mut SpotifyAuth,
-    ) -> Result<Vec<Song>, Box<dyn error::Error>> {
-        let mut tracks: Vec<Song> = self
-            .items
-            .into_iter()
-            .map(|track| track.track)
-            .filter(|track| track.is_playable != Some(false))
-            .collect();
+/// Max number of pages of a playlist fetched at the same time.
+const CONCURRENT_PAGES: usize = 4;
 
-        let mut next = self.next.clone();
-        while let Some(url) = next {
-            let headers = auth_header(auth).await?;
-            let client = reqwest::Client::new();
+/// Urls of all the pages from `next` on, derived from its `offset` and `limit`.
+fn page_urls(next: &str, total: usize) -> Option<Vec<String>> {
+    let url = reqwest::Url::parse(next).ok()?;
+    let param = |name: &str| -> Option<usize> {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .and_then(|(_, value)| value.parse().ok())
+    };
+    let offset = param("offset")?;
+    let limit = param("limit").filter(|limit| *limit > 0)?;
+
+    let urls = (offset..total)
+        .step_by(limit)
+        .map(|offset| {
+            let mut page_url = url.clone();
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(key, value)| match key.as_ref() {
+                    "offset" => (key.into_owned(), offset.to_string()),
+                    _ => (key.into_owned(), value.into_owned()),
+                })
+                .collect();
+            page_url.query_pairs_mut().clear().extend_pairs(pairs);
+            page_url.to_string()
+        })
+        .collect();
 
-            let res = client.get(url).headers(headers).send().await?;
+    Some(urls)
+}
 
-            let response_text = check_for_error_and_return_text(res).await?;
-            let playlist_tracks: PlaylistTracks =
-                serde_json::from_str(&response_text).map_err(|_| response_text)?;
+/// Fetches a page with an already authorized request, waiting out short
+/// rate limits like `send`. Fails with the status of the response, if there
+/// was one, so this can run in a spawned task; the caller refreshes the token
+/// on a 401 and fetches the failed pages again through `send`.
+async fn fetch_tracks_page(
+    request: reqwest::RequestBuilder,
+) -> Result<PlaylistTracks, Option<StatusCode>> {
+    let retry = request.try_clone().ok_or(None)?;
+    let res = execute(request).await.map_err(|_| None)?;
+    let res = wait_out_rate_limit(res, retry).await.map_err(|_| None)?;
+    let status = res.status();
+
+    parse_response(res).await.map_err(|_| Some(status))
+}
 
-            let mut more_tracks: Vec<Song> = playlist_tracks
-                .items
-                .into_iter()
-                .map(|track| track.track)
-                .filter(|track| track.is_playable != Some(false))
-                .collect();
+async fn get_playlist_tracks_page(
+    auth: &mut impl TokenProvider,
+    url: &str,
+) -> Result<PlaylistTracks, Box<dyn error::Error>> {
+    let client = http::client();
+    let playlist_tracks: PlaylistTracks = api_request(auth, client.get(url)).await?;
+
+    Ok(playlist_tracks)
+}
+
+/// Prints `(index, track)` pairs, see `PlaylistTracks::print_tracks`.
+fn print_track_lines(
+    tracks: &[(usize, Song)],
+    highlight: Option<&str>,
+    max_lines: Option<u16>,
+    around: Option<u16>,
+) -> Result<(), Box<dyn error::Error>> {
+    if let Some(0) = max_lines {
+        return Ok(());
+    }
 
-            tracks.append(&mut more_tracks);
+    let mut first_line = 0;
+    let mut last_line = tracks.len() as i32;
+    let mut highlight_line = None;
 
-            next = playlist_tracks.next;
+    if let Some(name) = highlight {
+        highlight_line = tracks.iter().position(|(_, track)| track.name == name);
+        let Some(line) = highlight_line else {
+            return Err("Could not find the song to highlight.".into());
+        };
+
+        if let Some(around) = around {
+            first_line = line as i32 - around as i32;
+            last_line = line as i32 + around as i32;
+        } else if let Some(max_lines) = max_lines {
+            first_line = line as i32 - ((max_lines as i32 - 1) / 2);
+            last_line = line as i32 + (max_lines as i32 / 2);
+        }
+    } else if let Some(max_lines) = max_lines {
+        last_line = (max_lines - 1) as i32;
+    }
+
+    let width = output::line_width();
+    let headers: Vec<&str> = std::iter::once("#").chain(Song::headers()).collect();
+    let mut table = Table::new(&headers);
+    for (line_ind, (ind, track)) in tracks.iter().enumerate() {
+        if (line_ind as i32) < first_line || (line_ind as i32) > last_line {
+            continue;
         }
 
-        Ok(tracks)
+        let unavailable = track.unavailable_reason();
+        let note = match &unavailable {
+            Some(reason) => format!(" ({reason})"),
+            None if track.linked_from.is_some() => " (relinked)".to_string(),
+            None => String::new(),
+        };
+
+        if output::tables() {
+            let mut cells = vec![ind.to_string()];
+            cells.extend(track.cells());
+            cells[1] += &note;
+            if highlight_line == Some(line_ind) {
+                table.add_styled_row(cells, output::highlight);
+            } else if unavailable.is_some() {
+                table.add_styled_row(cells, output::unavailable);
+            } else {
+                table.add_row(cells);
+            }
+            continue;
+        }
+
+        let line = fit_line(&format!("#{ind} "), track, &note, width);
+
+        if highlight_line == Some(line_ind) {
+            println!("{}", output::highlight(&line));
+        } else if unavailable.is_some() {
+            println!("{}", output::unavailable(&line));
+        } else {
+            println!("{line}");
+        }
+    }
+    if output::tables() {
+        table.print();
     }
-}
 
-#[derive(Deserialize, Debug)]
-struct TrackItem {
-    track: Song,
+    Ok(())
 }
 
 #[derive(Deserialize, Debug)]
@@ -310,11 +585,6 @@ struct PlayerQueueResponse {
     queued: Vec<Song>,
 }
 
-#[derive(Deserialize, Debug)]
-struct User {
-    id: String,
-}
-
 #[derive(Deserialize, Debug)]
 struct PlaylistCreateResponse {
     id: String,
@@ -357,710 +627,7062 @@ struct RecommendationParameters {
     seed_tracks: Vec<String>,
 }
 
+/// Max number of seed artists, genres and tracks in total.
+const MAX_RECOMMENDATION_SEEDS: usize = 5;
+
+impl RecommendationParameters {
+    fn seeds(&self) -> usize {
+        self.seed_artists.len() + self.genres.len() + self.seed_tracks.len()
+    }
+}
+
 impl Display for RecommendationParameters {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Limit:   {:?}", self.limit)?;
         writeln!(f, "Artists: {:?}", self.artists)?;
-        #[cfg(debug_assertions)]
-        writeln!(f, "A ids:   {:?}", self.seed_artists)?;
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            writeln!(f, "A ids:   {:?}", self.seed_artists)?;
+        }
         writeln!(f, "Genres:  {:?}", self.genres)?;
         writeln!(f, "Tracks:  {:?}", self.tracks)?;
-        #[cfg(debug_assertions)]
-        writeln!(f, "T ids:   {:?}", self.seed_tracks)?;
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            writeln!(f, "T ids:   {:?}", self.seed_tracks)?;
+        }
 
         Ok(())
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct TopItemsResponse<T> {
+    items: Vec<T>,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum TimeRange {
+    /// Roughly the last 4 weeks
+    Short,
+    /// Roughly the last 6 months
+    Medium,
+    /// Roughly the last year
+    Long,
+}
+
+impl TimeRange {
+    fn as_query(&self) -> &'static str {
+        match self {
+            TimeRange::Short => "short_term",
+            TimeRange::Medium => "medium_term",
+            TimeRange::Long => "long_term",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum TopType {
+    Tracks,
+    Artists,
+}
+
 #[derive(Deserialize, Debug)]
 struct GenresResponse {
     genres: Vec<String>,
 }
 
-async fn get_player(auth: &mut SpotifyAuth) -> Result<PlayerResponse, Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player".to_string();
+async fn get_player(
+    auth: &mut impl TokenProvider,
+) -> Result<PlayerResponse, Box<dyn error::Error>> {
+    let url = api_url("/me/player");
 
-    let headers = auth_header(auth).await?;
-    let client = reqwest::Client::new();
+    let client = http::client();
 
-    let res = client.get(url).headers(headers.clone()).send().await?;
+    let res = send(auth, client.get(url)).await?;
 
     if res.status() == StatusCode::NO_CONTENT {
         return Err("No active devices.".into());
     }
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let player_response: PlayerResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let player_response: PlayerResponse = parse_response(res).await?;
 
     Ok(player_response)
 }
 
+/// Fetches the playlist at an `href` from an API response. The href points
+/// to the real API, so it's rebased onto `--base-url` if one was given.
 async fn get_playlist_from_href(
-    auth: &mut SpotifyAuth,
+    auth: &mut impl TokenProvider,
     href: &str,
 ) -> Result<PlaylistDescription, Box<dyn error::Error>> {
-    let headers = auth_header(auth).await?;
-    let client = reqwest::Client::new();
-
-    let res = client
-        .get(href)
-        .headers(headers)
-        .query(&[("market", "from_token")])
-        .send()
-        .await?;
+    let url = match href.strip_prefix(DEFAULT_API_BASE_URL) {
+        Some(path) => api_url(path),
+        None => href.to_string(),
+    };
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let playlist_description: PlaylistDescription =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let client = http::client();
+    let request = client.get(url).query(&[("market", market())]);
+    let playlist_description: PlaylistDescription = api_request(auth, request).await?;
 
     Ok(playlist_description)
 }
 
 async fn get_playlist_from_id(
-    auth: &mut SpotifyAuth,
+    auth: &mut impl TokenProvider,
     id: &str,
 ) -> Result<PlaylistDescription, Box<dyn error::Error>> {
-    let url = format!("https://api.spotify.com/v1/playlists/{id}");
-
-    let headers = auth_header(auth).await?;
-
-    let client = reqwest::Client::new();
-    let res = client
-        .get(url)
-        .headers(headers)
-        .query(&[("market", "from_token")])
-        .send()
-        .await?;
+    let url = api_url(&format!("/playlists/{id}"));
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let playlist_description: PlaylistDescription =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let client = http::client();
+    let request = client.get(url).query(&[("market", market())]);
+    let playlist_description: PlaylistDescription = api_request(auth, request).await?;
 
     Ok(playlist_description)
 }
 
-pub async fn playback_show(
-    auth: &mut SpotifyAuth,
-    show_playlist: bool,
-) -> Result<(), Box<dyn error::Error>> {
-    let player_response = get_player(auth).await?;
+/// Parses the playlist id out of a playlist uri (`spotify:playlist:<id>`),
+/// an open.spotify.com url, or accepts a bare id as is.
+fn playlist_id_from_uri(uri: &str) -> Result<String, Box<dyn error::Error>> {
+    if !uri.is_empty() && !uri.contains(':') && !uri.contains('/') {
+        return Ok(uri.to_string());
+    }
 
-    println!("Current song: {}", player_response.song);
-    if !player_response.is_playing {
-        println!("(paused)");
+    match SpotifyId::parse(uri) {
+        Ok(id) if id.kind == "playlist" => Ok(id.id),
+        _ => Err(format!("Can't parse a playlist id from: {uri}").into()),
     }
-    println!("Running on:   {}", player_response.device);
+}
 
-    if show_playlist && player_response.context.is_some() {
-        let ctx = player_response.context.unwrap();
+/// Resolves a playlist given either as a uri/url/id or by the name of one
+/// of the current user's playlists.
+async fn resolve_playlist_id(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
+) -> Result<String, Box<dyn error::Error>> {
+    if uri_or_name.starts_with("spotify:") || uri_or_name.contains("open.spotify.com/") {
+        return playlist_id_from_uri(uri_or_name);
+    }
 
-        let playlist_description = get_playlist_from_href(auth, &ctx.href).await?;
+    let playlists = get_my_playlists(auth).await?;
+    let name = uri_or_name.to_lowercase();
+    if let Some(playlist) = playlists.iter().find(|p| p.name.to_lowercase() == name) {
+        return playlist_id_from_uri(&playlist.uri);
+    }
 
-        println!(
-            "Playing from: {} ({})",
-            playlist_description.name, ctx.r#type
-        );
+    if uri_or_name.len() == 22 && uri_or_name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Ok(uri_or_name.to_string());
+    }
 
-        if let Some(desc) = playlist_description.description {
-            if !desc.is_empty() {
-                println!(" - {}", desc);
-            }
+    // No exact match; let the user pick from the playlists, starting from the
+    // given name.
+    if picker::enabled()? && !playlists.is_empty() {
+        let names: Vec<String> = playlists.iter().map(|p| p.name.clone()).collect();
+        let prompt = tr!("No playlist named {name}; pick one:", name = uri_or_name);
+        if let Some(ind) = picker::pick(&prompt, &names, uri_or_name)? {
+            return playlist_id_from_uri(&playlists[ind].uri);
         }
-    };
+    }
 
-    Ok(())
+    Err(format!("Could not find a playlist named {uri_or_name}.").into())
+}
+
+#[derive(Deserialize, Debug)]
+struct PlaylistTotal {
+    tracks: TracksLink,
 }
 
-pub async fn playback_pause(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player/pause".to_string();
+async fn get_playlist_total(
+    auth: &mut impl TokenProvider,
+    id: &str,
+) -> Result<u16, Box<dyn error::Error>> {
+    let url = api_url(&format!("/playlists/{id}"));
 
-    let headers = auth_header(auth).await?;
+    let client = http::client();
+    let request = client.get(url).query(&[("fields", "tracks.total")]);
+    let playlist_total: PlaylistTotal = api_request(auth, request).await?;
 
-    let client = reqwest::Client::new();
-    let res = client
-        .put(url)
-        .headers(headers)
-        .header("content-length", 0)
-        .send()
-        .await?;
+    Ok(playlist_total.tracks.total)
+}
+
+async fn set_shuffle(
+    auth: &mut impl TokenProvider,
+    state: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/shuffle");
 
-    let _response_text = check_for_error_and_return_text(res).await?;
+    let client = http::client();
+    let request = client
+        .put(url)
+        .query(&[("state", state)])
+        .header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
 
-    #[cfg(debug_assertions)]
-    println!("{_response_text}");
+    check_for_error_and_return_text(res).await?;
 
     Ok(())
 }
 
-pub async fn playback_play(
-    auth: &mut SpotifyAuth,
-    uri: Option<&str>,
+/// Plays a playlist from `index`, or from a random track if `random`.
+/// `shuffle` also turns shuffle on first.
+pub async fn playlist_play(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
     index: Option<u16>,
+    random: bool,
+    shuffle: bool,
 ) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player/play".to_string();
-
-    let headers = auth_header(auth).await?;
-
-    let client = reqwest::Client::new();
-    let mut res_builder = client.put(url).headers(headers);
-    let mut map = serde_json::Map::new();
-    if let Some(uri) = uri {
-        map.insert(
-            "context_uri".to_string(),
-            serde_json::Value::String(uri.to_owned()),
-        );
-    }
-    if let Some(offset) = index {
-        let mut tmp = serde_json::Map::new();
-        tmp.insert(
-            "position".to_string(),
-            serde_json::Value::Number(offset.into()),
-        );
-        map.insert("offset".to_string(), serde_json::Value::Object(tmp));
-
-        if uri.is_none() {
-            let player_response = get_player(auth).await?;
-            match player_response.context {
-                Some(ctx) => {
-                    if ctx.r#type != "playlist" {
-                        return Err("Not playing from a playlist; can't jump to an index.".into());
-                    }
-                    map.insert(
-                        "context_uri".to_string(),
-                        serde_json::Value::String(ctx.uri.to_owned()),
-                    );
-                }
-                None => return Err("Not playing from a playlist; can't jump to an index.".into()),
+    let uri = match uri_or_name.starts_with("spotify:") {
+        true => uri_or_name.to_string(),
+        false => format!(
+            "spotify:playlist:{}",
+            resolve_playlist_id(auth, uri_or_name).await?
+        ),
+    };
+    let uri = uri.as_str();
+    let index = match random || shuffle {
+        true => {
+            let total = get_playlist_total(auth, &playlist_id_from_uri(uri)?).await?;
+            if total == 0 {
+                return Err("The playlist is empty.".into());
             }
+            Some(rand::thread_rng().gen_range(0..total))
         }
+        false => index,
+    };
+    if shuffle {
+        set_shuffle(auth, true).await?;
     }
 
-    if map.is_empty() {
-        res_builder = res_builder.header("content-length", 0);
-    } else {
-        res_builder = res_builder.json(&map);
+    let before = playback_snapshot(auth).await;
+    playback_play(auth, Some(uri), None, index).await?;
+    show_playback_change(auth, &before, ExpectedChange::Context(uri)).await
+}
+
+/// Fetches all of the current user's playlists, following pagination.
+async fn get_my_playlists(
+    auth: &mut impl TokenProvider,
+) -> Result<Vec<Playlist>, Box<dyn error::Error>> {
+    let mut playlists = Vec::new();
+    let mut progress: Option<Progress> = None;
+
+    let mut next = Some(api_url("/me/playlists?limit=50"));
+    while let Some(url) = next {
+        let client = http::client();
+
+        let mut playlist_response: PlaylistResponse = api_request(auth, client.get(url)).await?;
+
+        playlists.append(&mut playlist_response.items);
+        progress
+            .get_or_insert_with(|| {
+                Progress::new(
+                    "Fetching playlists",
+                    progress::Kind::Pages,
+                    playlist_response.total,
+                )
+            })
+            .update(playlists.len());
+
+        next = playlist_response.next;
     }
-    let res = res_builder.send().await?;
 
-    let _response_text = check_for_error_and_return_text(res).await?;
+    Ok(playlists)
+}
+
+async fn get_playlist_tracks(
+    auth: &mut impl TokenProvider,
+    uri: &str,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let id = playlist_id_from_uri(uri)?;
+    let playlist_description = get_playlist_from_id(auth, &id).await?;
 
-    #[cfg(debug_assertions)]
-    println!("{_response_text}");
+    match playlist_description.tracks {
+        Some(tracks) => tracks.get_tracks(auth).await,
+        None => Ok(Vec::new()),
+    }
+}
 
-    Ok(())
+/// The open.spotify.com url of a `spotify:<type>:<id>` uri.
+fn spotify_url(uri: &str) -> Option<String> {
+    // Liked songs, e.g. spotify:user:<user>:collection
+    if uri.starts_with("spotify:") && uri.ends_with(":collection") {
+        return Some("https://open.spotify.com/collection/tracks".to_string());
+    }
+    match uri.starts_with("spotify:") {
+        true => SpotifyId::parse(uri).ok().map(|id| id.url()),
+        false => None,
+    }
 }
 
-pub async fn playback_next(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player/next".to_string();
+/// Uri of the current track, or of the current context (playlist, album, ...)
+/// if `context`.
+async fn current_uri(
+    auth: &mut impl TokenProvider,
+    context: bool,
+) -> Result<String, Box<dyn error::Error>> {
+    let player_response = get_player(auth).await?;
+    let uri = match context {
+        true => {
+            player_response
+                .context
+                .ok_or("Not playing from a playlist, album or artist currently.")?
+                .uri
+        }
+        false => player_response.song.uri,
+    };
 
-    let headers = auth_header(auth).await?;
+    Ok(uri)
+}
 
-    let client = reqwest::Client::new();
-    let res = client
-        .post(url)
-        .headers(headers)
-        .header("content-length", 0)
-        .send()
-        .await?;
+async fn current_url(
+    auth: &mut impl TokenProvider,
+    context: bool,
+) -> Result<String, Box<dyn error::Error>> {
+    let uri = current_uri(auth, context).await?;
 
-    let _response_text = check_for_error_and_return_text(res).await?;
+    Ok(spotify_url(&uri).ok_or_else(|| format!("No url for {uri}"))?)
+}
 
-    #[cfg(debug_assertions)]
-    println!("{_response_text}");
+pub async fn playback_show_url(
+    auth: &mut impl TokenProvider,
+    context: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    println!("{}", current_url(auth, context).await?);
 
     Ok(())
 }
 
-pub async fn playback_previous(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player/previous".to_string();
+/// Copies the url of the current track or context to the clipboard.
+pub async fn copy_url(
+    auth: &mut impl TokenProvider,
+    context: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = current_url(auth, context).await?;
+    desktop::copy_to_clipboard(&url)?;
+    output::info!("{}", tr!("Copied {url}", url = url));
 
-    let headers = auth_header(auth).await?;
+    Ok(())
+}
 
-    let client = reqwest::Client::new();
-    let res = client
-        .post(url)
-        .headers(headers)
-        .header("content-length", 0)
-        .send()
-        .await?;
+/// Opens the uri in the Spotify app if `app`, otherwise its url in the browser.
+fn open_uri(uri: &str, app: bool) -> Result<(), Box<dyn error::Error>> {
+    let target = match app {
+        true => uri.to_string(),
+        false => spotify_url(uri).ok_or_else(|| format!("No url for {uri}"))?,
+    };
+    desktop::open(&target)?;
+    output::info!("{}", tr!("Opened {target}", target = target));
+
+    Ok(())
+}
 
-    let _response_text = check_for_error_and_return_text(res).await?;
+/// Opens the current track, or the current context if `context`.
+pub async fn open_current(
+    auth: &mut impl TokenProvider,
+    context: bool,
+    app: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let uri = current_uri(auth, context).await?;
 
-    #[cfg(debug_assertions)]
-    println!("{_response_text}");
+    open_uri(&uri, app)
+}
 
-    Ok(())
+/// Opens a playlist given by name, uri or url.
+pub async fn open_playlist(
+    auth: &mut impl TokenProvider,
+    playlist: &str,
+    app: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, playlist).await?;
+
+    open_uri(&format!("spotify:playlist:{id}"), app)
 }
 
-pub async fn playback_restart(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player/seek".to_string();
+/// Prints the current playback. With `context_progress`, also where the
+/// track is in the playlist or album.
+pub async fn playback_show(
+    auth: &mut impl TokenProvider,
+    show_playlist: bool,
+    context_progress: bool,
+    art: Option<ArtProtocol>,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_response = get_player(auth).await?;
 
-    let headers = auth_header(auth).await?;
+    if let Some(protocol) = art {
+        print_album_art(&player_response.song, protocol).await?;
+    }
 
-    let client = reqwest::Client::new();
-    let res = client
-        .put(url)
-        .query(&[("position_ms", 0)])
-        .headers(headers)
-        .header("content-length", 0)
+    print_player(auth, player_response, show_playlist, context_progress).await
+}
+
+/// Downloads the album cover of the song and draws it in the terminal.
+async fn print_album_art(song: &Song, protocol: ArtProtocol) -> Result<(), Box<dyn error::Error>> {
+    let images = song
+        .album
+        .as_ref()
+        .map(|album| album.images.as_slice())
+        .unwrap_or_default();
+    // The smallest image that is still big enough, or the biggest one.
+    let wanted_width = protocol.wanted_width();
+    let image = images
+        .iter()
+        .filter(|image| image.width.is_none_or(|width| width >= wanted_width))
+        .min_by_key(|image| image.width.unwrap_or(u32::MAX))
+        .or_else(|| images.iter().max_by_key(|image| image.width))
+        .ok_or("The current track has no album art.")?;
+
+    let res = http::client()
+        .get(&image.url)
         .send()
-        .await?;
+        .await
+        .map_err(|e| format!("Failed to download the album art: {e}"))?;
+    if !res.status().is_success() {
+        return Err(format!("Failed to download the album art: {}", res.status()).into());
+    }
+    let bytes = res.bytes().await?;
+
+    art::print(&bytes, protocol)
+}
 
-    let _response_text = check_for_error_and_return_text(res).await?;
+async fn print_player(
+    auth: &mut impl TokenProvider,
+    player_response: PlayerResponse,
+    show_playlist: bool,
+    context_progress: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut cache = cache::load();
+    cache.now_playing = Some(NowPlaying::from(&player_response));
+    let _ = cache::save(&cache);
+
+    let saved = saved_tracks(auth, &[player_response.song.id.as_str()]).await;
+    println!(
+        "{}",
+        fit_line(
+            &tr!("Current song: {saved}", saved = saved_marker(saved[0])),
+            &player_response.song,
+            "",
+            output::line_width()
+        )
+    );
+    if !player_response.is_playing {
+        println!("{}", output::paused(tr!("(paused)")));
+    }
+    println!(
+        "{}",
+        tr!("Running on:   {device}", device = player_response.device)
+    );
+
+    if let (true, Some(ctx)) = (show_playlist, &player_response.context) {
+        let mut playlist_description = get_playlist_from_href(auth, &ctx.href).await?;
+
+        println!(
+            "{}",
+            tr!(
+                "Playing from: {name} ({type})",
+                name = playlist_description.name,
+                type = ctx.r#type
+            )
+        );
+
+        if let Some(desc) = playlist_description.description.take() {
+            if !desc.is_empty() {
+                println!(" - {}", desc);
+            }
+        }
 
-    #[cfg(debug_assertions)]
-    println!("{_response_text}");
+        if context_progress {
+            let name = playlist_description.name.clone();
+            let uris = context_track_uris(auth, ctx, Some(playlist_description)).await?;
+            match position_in_context(&uris, &player_response.song) {
+                Some(position) => println!(
+                    "{}",
+                    tr!(
+                        "Position:     track {position} of {count} in {name}",
+                        position = position + 1,
+                        count = uris.len(),
+                        name = name
+                    )
+                ),
+                None => println!("{}", tr!("Position:     not found in {name}", name = name)),
+            }
+        }
+    };
 
     Ok(())
 }
 
-pub async fn queue_show(
-    auth: &mut SpotifyAuth,
-    number: usize,
-) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/player/queue".to_string();
+/// The track uris of the playlist or album being played from, in order. A
+/// playlist is only fetched in full when its snapshot id differs from the
+/// cached one, and albums are fetched once. `playlist` is the playlist if
+/// it was already fetched.
+async fn context_track_uris(
+    auth: &mut impl TokenProvider,
+    ctx: &Context,
+    playlist: Option<PlaylistDescription>,
+) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let mut cache = cache::load();
+    let cached = cache.context_tracks.get(&ctx.uri);
+
+    let (snapshot_id, uris) = match ctx.r#type.as_str() {
+        "playlist" => {
+            let playlist = match playlist {
+                Some(playlist) => playlist,
+                None => get_playlist_from_href(auth, &ctx.href).await?,
+            };
+            if let Some(cached) = cached.filter(|cached| {
+                playlist.snapshot_id.is_some() && cached.snapshot_id == playlist.snapshot_id
+            }) {
+                return Ok(cached.uris.clone());
+            }
+
+            let uris: Vec<String> = playlist
+                .tracks
+                .ok_or("Not actually playing from a playlist currently.")?
+                .get_all_items(auth)
+                .await?
+                .into_iter()
+                .map(|item| item.track.uri)
+                .collect();
+            (playlist.snapshot_id, uris)
+        }
+        "album" => {
+            if let Some(cached) = cached {
+                return Ok(cached.uris.clone());
+            }
+
+            let context_id = ctx.uri.rsplit(':').next().unwrap_or_default();
+            let uris = get_album(auth, context_id)
+                .await?
+                .tracks
+                .items
+                .into_iter()
+                .map(|track| track.uri)
+                .collect();
+            (None, uris)
+        }
+        other => return Err(format!("Not playing from a playlist or album but a {other}.").into()),
+    };
 
-    let headers = auth_header(auth).await?;
+    cache.insert_context_tracks(
+        &ctx.uri,
+        cache::ContextTracks {
+            fetched_at: current_time_secs_from_epoch()?,
+            snapshot_id,
+            uris: uris.clone(),
+        },
+    );
+    let _ = cache::save(&cache);
 
-    let client = reqwest::Client::new();
-    let res = client.get(url).headers(headers).send().await?;
+    Ok(uris)
+}
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let player_queue_response: PlayerQueueResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+/// The position of the track in the context's track uris; a relinked track
+/// is in the context under its original uri. If the track is in a playlist
+/// more than once, its first position is used.
+fn position_in_context(uris: &[String], track: &Song) -> Option<usize> {
+    uris.iter().position(|uri| {
+        *uri == track.uri
+            || track
+                .linked_from
+                .as_ref()
+                .is_some_and(|linked| linked.uri == *uri)
+    })
+}
 
-    if player_queue_response.current.is_none() {
-        return Err("Not playing anything currently.".into());
+/// Max number of ids in one `/me/tracks/contains` request.
+const CONTAINS_BATCH_SIZE: usize = 50;
+
+/// Which of the tracks are saved in the library, in the same order. This is
+/// only for marking tracks in output, so without the library scope, or if
+/// the check fails, none are reported as saved.
+async fn saved_tracks(auth: &mut impl TokenProvider, ids: &[&str]) -> Vec<bool> {
+    if !auth.has_scope(Scope::UserLibraryRead) {
+        return vec![false; ids.len()];
     }
 
-    let max_print_width = get_max_print_width();
-    let current = player_queue_response.current.unwrap();
-    let mut line = format!("Currently playing: {}", current);
-    if line.chars().count() > max_print_width {
-        line = line.chars().take(max_print_width - 4).collect();
-        line += " ...";
-    }
-    println!("{line}");
-    println!("In queue:");
-    if number > 1 {
-        for (ind, song) in player_queue_response
-            .queued
-            .iter()
-            .take(number - 1)
-            .enumerate()
-        {
-            let mut line = format!("#{} {}", ind + 1, song);
-            if line.chars().count() > max_print_width {
-                line = line.chars().take(max_print_width - 4).collect();
-                line += " ...";
+    let mut saved = Vec::with_capacity(ids.len());
+    for batch in ids.chunks(CONTAINS_BATCH_SIZE) {
+        let url = api_url("/me/tracks/contains");
+        let client = http::client();
+        let request = client.get(url).query(&[("ids", batch.join(","))]);
+        match api_request::<Vec<bool>>(auth, request).await {
+            Ok(batch_saved) if batch_saved.len() == batch.len() => saved.extend(batch_saved),
+            Ok(_) => {
+                tracing::debug!("Wrong number of results checking saved tracks");
+                return vec![false; ids.len()];
+            }
+            Err(e) => {
+                tracing::debug!("Failed to check saved tracks: {e}");
+                return vec![false; ids.len()];
             }
-            println!("{line}");
         }
     }
 
-    Ok(())
+    saved
 }
 
-pub async fn playlist_list(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me/playlists".to_string();
+/// Prefix for tracks saved in the library.
+fn saved_marker(saved: bool) -> &'static str {
+    match saved {
+        true => "♥ ",
+        false => "",
+    }
+}
 
-    let headers = auth_header(auth).await?;
+/// The parts of the player state needed to tell whether a playback command
+/// has taken effect yet.
+pub struct PlaybackSnapshot {
+    song_uri: Option<String>,
+    context_uri: Option<String>,
+}
 
-    // TODO: pagination. Do I _actually_ care? When would I ever have >50 playlists created&liked?
-    // Could actually just implement this in the Display impl since `playlist_response` is not even
-    // returned; it's just printed.
-    let client = reqwest::Client::new();
-    let res = client
-        .get(url)
-        .headers(headers)
-        .query(&[("limit", 50)])
-        .send()
-        .await?;
+/// What a playback command is expected to change.
+pub enum ExpectedChange<'a> {
+    /// A different track starts, normally within the same context.
+    Track,
+    /// Playback moves to the given context.
+    Context(&'a str),
+    /// The given track starts playing.
+    Song(&'a str),
+}
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let playlist_response: PlaylistResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+/// Takes a snapshot of the player state before a playback command. Having no
+/// active playback is not an error here.
+pub async fn playback_snapshot(auth: &mut impl TokenProvider) -> PlaybackSnapshot {
+    snapshot_of(get_player(auth).await.ok().as_ref())
+}
+
+fn snapshot_of(player_response: Option<&PlayerResponse>) -> PlaybackSnapshot {
+    PlaybackSnapshot {
+        song_uri: player_response.map(|p| p.song.uri.clone()),
+        context_uri: player_response.and_then(|p| p.context.as_ref().map(|ctx| ctx.uri.clone())),
+    }
+}
 
-    println!("{playlist_response}");
+/// Polls the player until the expected change is visible and then shows the
+/// playback. The API keeps returning the previous state for a moment after a
+/// command so a single fixed sleep either wastes time or shows stale info.
+///
+/// Also reports if the playback ended up somewhere unexpected, e.g. the
+/// playlist ended and Spotify switched to autoplay/radio.
+pub async fn show_playback_change(
+    auth: &mut impl TokenProvider,
+    before: &PlaybackSnapshot,
+    expected: ExpectedChange<'_>,
+) -> Result<(), Box<dyn error::Error>> {
+    if output::quiet() {
+        return Ok(());
+    }
 
-    Ok(())
+    let mut latest = None;
+    let mut changed = false;
+
+    for _ in 0..8 {
+        tokio::time::sleep(Duration::from_millis(250u64)).await;
+
+        let player_response = match get_player(auth).await {
+            Ok(player_response) => player_response,
+            Err(_) => continue,
+        };
+
+        let song_uri = Some(&player_response.song.uri);
+        let context_uri = player_response.context.as_ref().map(|ctx| &ctx.uri);
+        changed = match expected {
+            ExpectedChange::Track => song_uri != before.song_uri.as_ref(),
+            ExpectedChange::Context(uri) => context_uri.map(|c| c.as_str()) == Some(uri),
+            ExpectedChange::Song(uri) => player_response.song.uri == uri,
+        };
+        latest = Some(player_response);
+
+        if changed {
+            break;
+        }
+    }
+
+    let Some(player_response) = latest else {
+        return playback_show(auth, false, false, None).await;
+    };
+
+    if !changed {
+        println!(
+            "{}",
+            tr!("Playback didn't change as expected; showing the latest state.")
+        );
+    } else if let ExpectedChange::Track = expected {
+        let context_uri = player_response.context.as_ref().map(|ctx| &ctx.uri);
+        if before.context_uri.is_some() && context_uri != before.context_uri.as_ref() {
+            match context_uri {
+                Some(uri) => println!(
+                    "{}",
+                    tr!(
+                        "The previous context ended; now playing from {uri}.",
+                        uri = uri
+                    )
+                ),
+                None => println!("{}", tr!("The previous context ended.")),
+            }
+        }
+    }
+
+    print_player(auth, player_response, false, false).await
 }
 
-pub async fn playlist_current(
-    auth: &mut SpotifyAuth,
-    max_lines: Option<u16>,
+impl From<&PlayerResponse> for NowPlaying {
+    fn from(player_response: &PlayerResponse) -> Self {
+        let artists: Vec<&str> = player_response
+            .song
+            .artists
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        NowPlaying {
+            fetched_at: current_time_secs_from_epoch().unwrap_or_default(),
+            title: player_response.song.name.clone(),
+            artists: artists.join(", "),
+            is_playing: player_response.is_playing,
+        }
+    }
+}
+
+fn print_prompt_segment(now_playing: &NowPlaying) {
+    if now_playing.is_playing {
+        println!("{} – {}", now_playing.artists, now_playing.title);
+    } else {
+        println!(
+            "{}",
+            tr!(
+                "{artists} – {title} (paused)",
+                artists = now_playing.artists,
+                title = now_playing.title
+            )
+        );
+    }
+}
+
+/// Prints a one-line now playing summary meant to be embedded in a shell
+/// prompt. Uses the cached summary if it's younger than `max_age_secs` and
+/// otherwise fetches the player state within `budget`, falling back to the
+/// stale cache. Never prompts and never fails loudly.
+pub async fn prompt_segment(
+    token_path: &str,
+    max_age_secs: u64,
+    budget: Duration,
 ) -> Result<(), Box<dyn error::Error>> {
-    let player_response = get_player(auth).await?;
+    let mut cache = cache::load();
 
-    let current_song = player_response.song.name;
+    if let Some(now_playing) = &cache.now_playing {
+        if now_playing.age_secs() <= max_age_secs {
+            print_prompt_segment(now_playing);
+            return Ok(());
+        }
+    }
 
-    match player_response.context {
-        Some(ctx) => {
-            let playlist_description = get_playlist_from_href(auth, &ctx.href).await?;
+    let fetched = tokio::time::timeout(budget, async {
+        let client_id = env::var("SPOTIFY_CLI_CLIENT_ID").ok()?;
+        let client_secret = env::var("SPOTIFY_CLI_CLIENT_SECRET").ok()?;
+        let mut auth = SpotifyAuth::from_file(&client_id, &client_secret, token_path).ok()?;
+        get_player(&mut auth).await.ok()
+    })
+    .await;
+
+    match fetched {
+        Ok(Some(player_response)) => {
+            let now_playing = NowPlaying::from(&player_response);
+            print_prompt_segment(&now_playing);
+            cache.now_playing = Some(now_playing);
+            let _ = cache::save(&cache);
+        }
+        _ => {
+            if let Some(now_playing) = &cache.now_playing {
+                print_prompt_segment(now_playing);
+            }
+        }
+    }
 
-            println!("{}", playlist_description.name);
+    Ok(())
+}
 
-            if let Some(desc) = playlist_description.description {
-                if !desc.is_empty() {
-                    println!(" - {}", desc);
-                }
+/// Renders a template like `{artist} - {title}` with the player state.
+/// Literal braces can be written as `{{` and `}}`.
+fn render_player_template(
+    template: &str,
+    player_response: &PlayerResponse,
+) -> Result<String, Box<dyn error::Error>> {
+    let mut out = String::new();
+
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
             }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err("Unclosed { in the format template.".into()),
+                    }
+                }
 
-            if let Some(tracks) = playlist_description.tracks {
-                println!();
-                tracks
-                    .print_tracks(auth, Some(&current_song), max_lines)
-                    .await?;
-            } else {
-                println!("\nNot actually playing from a playlist currently.")
+                let song = &player_response.song;
+                let value = match placeholder.as_str() {
+                    "artist" => {
+                        let artists: Vec<&str> =
+                            song.artists.iter().map(|a| a.name.as_str()).collect();
+                        artists.join(", ")
+                    }
+                    "title" => song.name.clone(),
+                    "album" => song
+                        .album
+                        .as_ref()
+                        .map(|a| a.name.clone())
+                        .unwrap_or_default(),
+                    "device" => player_response.device.name.clone(),
+                    "state" => match player_response.is_playing {
+                        true => "playing".to_string(),
+                        false => "paused".to_string(),
+                    },
+                    "progress" => format_duration(player_response.progress_ms.unwrap_or(0)),
+                    "duration" => format_duration(song.duration_ms),
+                    "url" => spotify_url(&song.uri).unwrap_or_default(),
+                    _ => {
+                        return Err(format!(
+                            "Unknown placeholder {{{placeholder}}} in the format template. Available: artist, title, album, device, state, progress, duration, url"
+                        )
+                        .into())
+                    }
+                };
+                out += &value;
             }
+            c => out.push(c),
         }
-        None => println!("Not playing from a playlist currently."),
     }
 
+    Ok(out)
+}
+
+/// Prints the current playback on one line using a user given template.
+pub async fn playback_show_formatted(
+    auth: &mut impl TokenProvider,
+    template: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_response = get_player(auth).await?;
+
+    println!("{}", render_player_template(template, &player_response)?);
+
     Ok(())
 }
 
-fn get_managed_playlist_id() -> Result<String, Box<dyn error::Error>> {
-    env::var("SPOTIFY_CLI_MANAGED_PLAYLIST_ID")
-        .map_err(|_| "The env variable SPOTIFY_CLI_MANAGED_PLAYLIST_ID is not set. If a managed playlist has not been created yet, run 'recommendation init'; if it has been created then set the env variable with the id of the playlist.".into())
+fn hook_vars(player_response: &PlayerResponse) -> Vec<(&'static str, String)> {
+    let artists: Vec<&str> = player_response
+        .song
+        .artists
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect();
+    let state = match player_response.is_playing {
+        true => "playing",
+        false => "paused",
+    };
+
+    vec![
+        ("SPOTIFY_TRACK", player_response.song.name.clone()),
+        ("SPOTIFY_ARTIST", artists.join(", ")),
+        (
+            "SPOTIFY_ALBUM",
+            player_response
+                .song
+                .album
+                .as_ref()
+                .map(|a| a.name.clone())
+                .unwrap_or_default(),
+        ),
+        ("SPOTIFY_URI", player_response.song.uri.clone()),
+        ("SPOTIFY_DEVICE", player_response.device.name.clone()),
+        ("SPOTIFY_STATE", state.to_string()),
+    ]
 }
 
-pub async fn recommendation_show(
-    auth: &mut SpotifyAuth,
-    max_lines: Option<u16>,
+/// Events that happened between two consecutive player states.
+fn player_events(previous: &PlayerResponse, current: &PlayerResponse) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    if previous.device.id != current.device.id || previous.device.name != current.device.name {
+        events.push(Event::DeviceChange);
+    }
+    if previous.song.uri != current.song.uri {
+        events.push(Event::TrackChange);
+    }
+    match (previous.is_playing, current.is_playing) {
+        (true, false) => events.push(Event::Pause),
+        (false, true) => events.push(Event::Resume),
+        _ => {}
+    }
+
+    events
+}
+
+/// Polls the player and runs the hook commands configured for each event.
+/// Runs until interrupted.
+pub async fn watch(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    interval: Duration,
 ) -> Result<(), Box<dyn error::Error>> {
-    let managed_list = get_managed_playlist_id()?;
+    let hooks = &config.hooks;
+    let hook_for = |event: Event| match event {
+        Event::TrackChange => hooks.track_change.as_deref(),
+        Event::Pause => hooks.pause.as_deref(),
+        Event::Resume => hooks.resume.as_deref(),
+        Event::DeviceChange => hooks.device_change.as_deref(),
+        Event::DeviceLost => hooks.device_lost.as_deref(),
+    };
 
-    let playlist_description = get_playlist_from_id(auth, &managed_list).await?;
+    let device_alias = |name: &String| match name.strip_prefix('@') {
+        Some(alias) => config.device.get(alias).unwrap_or(name).clone(),
+        None => name.clone(),
+    };
+    let watchdog = &config.watchdog;
+    let watched_devices: Vec<String> = watchdog.devices.iter().map(device_alias).collect();
+    let fallback_device = watchdog.fallback_device.as_ref().map(device_alias);
+    // The devices listed on the previous poll, to notice ones that disappear.
+    let mut previous_devices: Option<Vec<Device>> = None;
+
+    let history = match config.history.enabled {
+        true => Some(History::open(&history::history_path(&config.history)?)?),
+        false => None,
+    };
+    // The id and track uri of the play last recorded into the history.
+    let mut recorded: Option<(i64, String)> = None;
+
+    let mut previous: Option<PlayerResponse> = None;
+    loop {
+        // Checked before polling the player so that the player from the
+        // previous poll tells what was playing on a lost device.
+        if watchdog.enabled {
+            match get_devices(auth).await {
+                Ok(devices) => {
+                    let watched = match (&previous, watched_devices.is_empty()) {
+                        (Some(player), true) => vec![player
+                            .device
+                            .id
+                            .clone()
+                            .unwrap_or(player.device.name.clone())],
+                        _ => watched_devices.clone(),
+                    };
+                    let lost = match &previous_devices {
+                        Some(previous_devices) => {
+                            lost_devices(&watched, previous_devices, &devices)
+                        }
+                        None => Vec::new(),
+                    };
+                    for device in lost {
+                        let action = on_device_lost(
+                            auth,
+                            &device,
+                            fallback_device.as_deref(),
+                            &devices,
+                            previous.as_ref(),
+                        )
+                        .await;
+                        tracing::debug!(device = %device.name, action, "Device lost");
+
+                        if let Some(command) = hook_for(Event::DeviceLost) {
+                            let mut vars = previous.as_ref().map(hook_vars).unwrap_or_default();
+                            vars.push(("SPOTIFY_LOST_DEVICE", device.name.clone()));
+                            vars.push(("SPOTIFY_WATCHDOG_ACTION", action.to_string()));
+                            hooks::run(command, Event::DeviceLost, &vars);
+                        }
+                    }
+                    previous_devices = Some(devices);
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        }
 
-    println!("{}", playlist_description.name);
+        match get_player(auth).await {
+            Ok(current) => {
+                let events = match &previous {
+                    Some(previous) => player_events(previous, &current),
+                    // Treat the first observed track as a change so hooks
+                    // see the state the watcher starts in.
+                    None => vec![Event::TrackChange],
+                };
 
-    if let Some(desc) = playlist_description.description {
-        if !desc.is_empty() {
-            println!(" - {}", desc);
+                if events.contains(&Event::TrackChange) {
+                    skip_if_blocked(auth, &current).await;
+                }
+
+                for event in events {
+                    tracing::debug!(event = event.name(), "Playback event");
+
+                    if let Some(command) = hook_for(event) {
+                        hooks::run(command, event, &hook_vars(&current));
+                    }
+                }
+
+                if let Some(history) = &history {
+                    if let Err(e) = record_history(history, &mut recorded, &current) {
+                        eprintln!(
+                            "{}",
+                            tr!("Failed to record the listening history: {e}", e = e)
+                        );
+                    }
+                }
+
+                previous = Some(current);
+            }
+            Err(e) => eprintln!("{e}"),
         }
+
+        tokio::time::sleep(interval).await;
     }
+}
 
-    if let Some(tracks) = playlist_description.tracks {
-        println!();
-        tracks.print_tracks(auth, None, max_lines).await?;
+/// The watched devices, given as names or ids, that were listed before but
+/// aren't anymore.
+fn lost_devices(watched: &[String], previous: &[Device], current: &[Device]) -> Vec<Device> {
+    let is_watched = |device: &Device| {
+        watched.iter().any(|name_or_id| {
+            device.id.as_deref() == Some(name_or_id) || device.name.eq_ignore_ascii_case(name_or_id)
+        })
+    };
+    let is_listed = |device: &Device| {
+        current.iter().any(|d| match (&d.id, &device.id) {
+            (Some(id), Some(previous_id)) => id == previous_id,
+            _ => d.name == device.name,
+        })
+    };
+
+    previous
+        .iter()
+        .filter(|device| is_watched(device) && !is_listed(device))
+        .cloned()
+        .collect()
+}
+
+/// Pauses playback, or moves it to the fallback device if that's available,
+/// when a watched device disappears while playing. Errors are only printed
+/// so that watching keeps going. Returns what was done for the hook:
+/// `paused`, `transferred`, `none` or `failed`.
+async fn on_device_lost(
+    auth: &mut impl TokenProvider,
+    lost: &Device,
+    fallback_device: Option<&str>,
+    devices: &[Device],
+    player: Option<&PlayerResponse>,
+) -> &'static str {
+    if !player.is_some_and(|player| player.is_playing) {
+        output::info!("{}", tr!("{name} disappeared.", name = lost.name));
+        return "none";
+    }
+
+    let fallback = fallback_device
+        .and_then(|name_or_id| find_device(devices, name_or_id))
+        .and_then(|device| Some((device.id.clone()?, device.name.as_str())));
+    match fallback {
+        Some((id, name)) => match transfer_playback(auth, &id).await {
+            Ok(()) => {
+                output::info!(
+                    "{}",
+                    tr!(
+                        "{lost} disappeared; moved playback to {name}.",
+                        lost = lost.name,
+                        name = name
+                    )
+                );
+                "transferred"
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    tr!("Failed to move playback to {name}: {e}", name = name, e = e)
+                );
+                "failed"
+            }
+        },
+        None => match playback_pause(auth).await {
+            Ok(()) => {
+                output::info!(
+                    "{}",
+                    tr!("{name} disappeared; paused playback.", name = lost.name)
+                );
+                "paused"
+            }
+            Err(e) => {
+                eprintln!("{}", tr!("Failed to pause playback: {e}", e = e));
+                "failed"
+            }
+        },
+    }
+}
+
+/// Records a new play when a track starts playing, and otherwise keeps the
+/// listened time of the recorded play up to date.
+fn record_history(
+    history: &History,
+    recorded: &mut Option<(i64, String)>,
+    player_response: &PlayerResponse,
+) -> Result<(), Box<dyn error::Error>> {
+    let song = &player_response.song;
+    let progress_ms = player_response.progress_ms.unwrap_or_default();
+
+    match recorded {
+        Some((id, uri)) if *uri == song.uri => history.set_listened(*id, progress_ms)?,
+        _ if player_response.is_playing => {
+            let artists: Vec<&str> = song.artists.iter().map(|a| a.name.as_str()).collect();
+            let id = history.record(&Play {
+                // The watcher may have started in the middle of the track.
+                played_at: current_time_secs_from_epoch()?.saturating_sub(progress_ms / 1000),
+                track_uri: &song.uri,
+                track: &song.name,
+                artists: &artists,
+                album: song.album.as_ref().map(|album| album.name.as_str()),
+                context_uri: player_response.context.as_ref().map(|c| c.uri.as_str()),
+                device: &player_response.device.name,
+            })?;
+            history.set_listened(id, progress_ms)?;
+            *recorded = Some((id, song.uri.clone()));
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Skips the track if it's on the blocklist. Errors are only printed so that
+/// watching keeps going.
+async fn skip_if_blocked(auth: &mut impl TokenProvider, player_response: &PlayerResponse) {
+    // Reloaded on every track change so that `block add` takes effect without
+    // restarting the watcher.
+    let blocked = match state::load() {
+        Ok(state) => state.is_blocked(&player_response.song.uri),
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    if blocked {
+        match playback_next(auth).await {
+            Ok(()) => output::info!(
+                "{}",
+                tr!(
+                    "Skipped blocked track: {track}",
+                    track = track_label(&player_response.song)
+                )
+            ),
+            Err(e) => eprintln!("{}", tr!("Failed to skip blocked track: {e}", e = e)),
+        }
+    }
+}
+
+/// `track - artists`
+fn track_label(song: &Song) -> String {
+    let artists: Vec<&str> = song.artists.iter().map(|a| a.name.as_str()).collect();
+    format!("{} - {}", song.name, artists.join(", "))
+}
+
+/// Fetches a track. Without a market set with `--market` the track isn't
+/// relinked and includes the markets it's available in.
+async fn get_track(auth: &mut impl TokenProvider, id: &str) -> Result<Song, Box<dyn error::Error>> {
+    let url = api_url(&format!("/tracks/{id}"));
+
+    let client = http::client();
+    let mut request = client.get(url);
+    if let Some(market) = MARKET.get() {
+        request = request.query(&[("market", market)]);
+    }
+    let song: Song = api_request(auth, request).await?;
+
+    Ok(song)
+}
+
+/// Prints the details of a track given as a uri/url, `current`, or searched
+/// for by name.
+pub async fn track_info(
+    auth: &mut impl TokenProvider,
+    track: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let uri = match track {
+        "current" => get_player(auth).await?.song.uri,
+        _ => match track_uri_from_input(track) {
+            Some(uri) => uri,
+            None => search_track_uri(auth, track, None)
+                .await?
+                .ok_or(format!("Didn't find a track called {track}."))?,
+        },
+    };
+    let id = uri.rsplit(':').next().unwrap_or_default();
+    let song = get_track(auth, id).await?;
+
+    let artists: Vec<&str> = song.artists.iter().map(|a| a.name.as_str()).collect();
+    println!("{}", tr!("Title:      {name}", name = song.name));
+    println!(
+        "{}",
+        tr!("Artists:    {artists}", artists = artists.join(", "))
+    );
+    if let Some(album) = &song.album {
+        match &album.release_date {
+            Some(release_date) => println!(
+                "{}",
+                tr!(
+                    "Album:      {name} ({release_date})",
+                    name = album.name,
+                    release_date = release_date
+                )
+            ),
+            None => println!("{}", tr!("Album:      {name}", name = album.name)),
+        }
+    }
+    match (song.track_number, song.disc_number) {
+        (Some(track_number), Some(disc_number)) if disc_number > 1 => {
+            println!(
+                "{}",
+                tr!(
+                    "Track:      {track_number} (disc {disc_number})",
+                    track_number = track_number,
+                    disc_number = disc_number
+                )
+            )
+        }
+        (Some(track_number), _) => println!(
+            "{}",
+            tr!("Track:      {track_number}", track_number = track_number)
+        ),
+        (None, _) => {}
+    }
+    println!(
+        "{}",
+        tr!(
+            "Duration:   {duration}",
+            duration = format_duration(song.duration_ms)
+        )
+    );
+    if let Some(popularity) = song.popularity {
+        println!(
+            "{}",
+            tr!("Popularity: {popularity}/100", popularity = popularity)
+        );
+    }
+    println!(
+        "{}",
+        match song.explicit {
+            true => tr!("Explicit:   yes"),
+            false => tr!("Explicit:   no"),
+        }
+    );
+    if let Some(markets) = &song.available_markets {
+        println!("{}", tr!("Markets:    {count}", count = markets.len()));
+    }
+    if let Some(reason) = song.unavailable_reason() {
+        println!("{}", tr!("Playable:   no, {reason}", reason = reason));
+    }
+    if let Some(linked_from) = &song.linked_from {
+        println!("{}", tr!("Relinked:   from {uri}", uri = linked_from.uri));
+    }
+    if let Some(isrc) = song.external_ids.as_ref().and_then(|ids| ids.isrc.as_ref()) {
+        println!("{}", tr!("ISRC:       {isrc}", isrc = isrc));
+    }
+    if let Some(url) = spotify_url(&song.uri) {
+        println!("{}", tr!("URL:        {url}", url = url));
+    }
+
+    Ok(())
+}
+
+/// Adds a track given as a uri/url, or `current`, to the blocklist. Blocking
+/// the current track also skips it.
+pub async fn block_add(
+    auth: &mut impl TokenProvider,
+    track: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let (song, is_current) = match track {
+        "current" => (get_player(auth).await?.song, true),
+        _ => {
+            let uri =
+                track_uri_from_input(track).ok_or(format!("Not a track uri or url: {track}"))?;
+            let id = uri.trim_start_matches("spotify:track:");
+            (get_track(auth, id).await?, false)
+        }
+    };
+
+    let mut state = state::load()?;
+    let label = track_label(&song);
+    if state.is_blocked(&song.uri) {
+        output::info!("{}", tr!("Already blocked: {label}", label = label));
+    } else {
+        state.blocklist.push(BlockedTrack {
+            uri: song.uri.clone(),
+            name: label.clone(),
+        });
+        state::save(&state)?;
+        output::info!("{}", tr!("Blocked: {label}", label = label));
+    }
+
+    if is_current {
+        playback_next(auth).await?;
+    }
+
+    Ok(())
+}
+
+pub fn block_list() -> Result<(), Box<dyn error::Error>> {
+    let state = state::load()?;
+    if state.blocklist.is_empty() {
+        println!("{}", tr!("The blocklist is empty."));
+    }
+    for (i, track) in state.blocklist.iter().enumerate() {
+        println!("{}. {} ({})", i + 1, track.name, track.uri);
+    }
+
+    Ok(())
+}
+
+/// Removes a track given as a uri/url or its number in `block list` from the
+/// blocklist.
+pub fn block_remove(track: &str) -> Result<(), Box<dyn error::Error>> {
+    let mut state = state::load()?;
+
+    let position = match track.parse::<usize>() {
+        Ok(number) => (1..=state.blocklist.len())
+            .contains(&number)
+            .then(|| number - 1),
+        Err(_) => {
+            let uri = track_uri_from_input(track)
+                .ok_or(format!("Not a track uri, url or number: {track}"))?;
+            state
+                .blocklist
+                .iter()
+                .position(|blocked| blocked.uri == uri)
+        }
+    };
+    let position = position.ok_or(format!("{track} is not on the blocklist."))?;
+
+    let removed = state.blocklist.remove(position);
+    state::save(&state)?;
+    output::info!("{}", tr!("Unblocked: {name}", name = removed.name));
+
+    Ok(())
+}
+
+async fn fetch_lyrics(
+    config: &Config,
+    query: &LyricsQuery,
+) -> Result<Option<Lyrics>, Box<dyn error::Error>> {
+    match &config.lyrics.url_template {
+        Some(template) => {
+            UrlTemplate {
+                template: template.clone(),
+            }
+            .fetch(query)
+            .await
+        }
+        None => {
+            Lrclib {
+                base_url: config
+                    .lyrics
+                    .lrclib_url
+                    .clone()
+                    .unwrap_or("https://lrclib.net".to_string()),
+            }
+            .fetch(query)
+            .await
+        }
+    }
+}
+
+/// Prints the lyrics of the current song. With `sync`, prints the lines of
+/// synced lyrics as the song progresses until the song changes.
+pub async fn lyrics_show(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    sync: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_response = get_player(auth).await?;
+    let song = &player_response.song;
+
+    let query = LyricsQuery {
+        title: song.name.clone(),
+        artist: song
+            .artists
+            .first()
+            .map(|a| a.name.clone())
+            .unwrap_or_default(),
+        album: song.album.as_ref().map(|a| a.name.clone()),
+        duration_ms: song.duration_ms,
+    };
+
+    let lyrics = fetch_lyrics(config, &query)
+        .await?
+        .ok_or(format!("Could not find lyrics for: {song}"))?;
+
+    println!("{song}\n");
+
+    let synced = match (sync, lyrics.synced) {
+        (true, Some(synced)) => synced,
+        (true, None) => {
+            println!("{}", tr!("(No synced lyrics available)\n"));
+            println!("{}", lyrics.plain.unwrap_or_default());
+            return Ok(());
+        }
+        (false, _) => {
+            println!("{}", lyrics.plain.unwrap_or_default());
+            return Ok(());
+        }
+    };
+
+    let uri = song.uri.clone();
+    let mut progress_ms = player_response.progress_ms.unwrap_or(0);
+    let mut is_playing = player_response.is_playing;
+    let mut polled_at = std::time::Instant::now();
+    let mut next_line = 0;
+
+    while next_line < synced.len() {
+        let position = match is_playing {
+            true => progress_ms + polled_at.elapsed().as_millis() as u64,
+            false => progress_ms,
+        };
+
+        while next_line < synced.len() && synced[next_line].0 <= position {
+            println!("{}", synced[next_line].1);
+            next_line += 1;
+        }
+
+        tokio::time::sleep(Duration::from_millis(200u64)).await;
+
+        // Resync with the player every few seconds to follow seeks and pauses.
+        if polled_at.elapsed() > Duration::from_secs(5) {
+            let player_response = get_player(auth).await?;
+            if player_response.song.uri != uri {
+                break;
+            }
+            progress_ms = player_response.progress_ms.unwrap_or(0);
+            is_playing = player_response.is_playing;
+            polled_at = std::time::Instant::now();
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn playback_pause(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/pause");
+
+    let client = http::client();
+    let request = client.put(url).header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+/// Pauses if something is playing and otherwise resumes, for binding play
+/// and pause to a single key. With `last_device`, a missing active device is
+/// replaced by the one playback was last seen on.
+pub async fn playback_toggle(
+    auth: &mut impl TokenProvider,
+    remember_after: Duration,
+    last_device: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_response = match get_player(auth).await {
+        Ok(player_response) => player_response,
+        Err(_) if last_device => return play_on_last_device(auth).await,
+        Err(_) => return playback_play(auth, None, None, None).await,
+    };
+    remember_device(&player_response.device);
+
+    match player_response.is_playing {
+        true => playback_pause_remembering(auth, remember_after).await,
+        false => playback_play(auth, None, None, None).await,
+    }
+}
+
+/// Saves the device for `toggle --last-device`. A failure is only a warning
+/// since toggling itself can still go on.
+fn remember_device(device: &Device) {
+    let Some(id) = &device.id else {
+        return;
+    };
+    let result = state::load().and_then(|mut state| {
+        if state
+            .last_device
+            .as_ref()
+            .is_some_and(|last| last.id == *id)
+        {
+            return Ok(());
+        }
+        state.last_device = Some(LastDevice {
+            id: id.clone(),
+            name: device.name.clone(),
+        });
+        state::save(&state)
+    });
+    if let Err(e) = result {
+        eprintln!("{}", tr!("Failed to remember the device: {e}", e = e));
+    }
+}
+
+/// Transfers playback to the remembered device and starts playing there.
+/// Without a remembered device this is a plain `play`.
+async fn play_on_last_device(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let Some(last) = state::load()?.last_device else {
+        return playback_play(auth, None, None, None).await;
+    };
+
+    // The id of some devices changes between sessions, so fall back to the name.
+    let devices = get_devices(auth).await?;
+    let device_id = find_device(&devices, &last.id)
+        .or_else(|| find_device(&devices, &last.name))
+        .and_then(|device| device.id.clone())
+        .ok_or_else(|| {
+            format!(
+                "{} isn't available. Available: {}",
+                last.name,
+                device_names(&devices)
+            )
+        })?;
+    transfer_playback(auth, &device_id).await?;
+
+    playback_play(auth, None, None, None).await
+}
+
+/// How long a track has to be, in minutes, for `pause` to remember where it
+/// was by default.
+pub const DEFAULT_REMEMBER_POSITION_MINS: u64 = 20;
+
+/// The player, read with episodes included. `PlayerResponse` only reads
+/// tracks.
+#[derive(Deserialize, Debug)]
+struct PlayingItemResponse {
+    item: Option<PlayingItem>,
+    progress_ms: Option<u64>,
+    context: Option<Context>,
+}
+
+/// A track or an episode.
+#[derive(Deserialize, Debug)]
+struct PlayingItem {
+    uri: String,
+    name: String,
+    r#type: String,
+    duration_ms: u64,
+}
+
+/// Pauses, and if an episode or a track at least `remember_after` long was
+/// playing, remembers where it was for `resume_last`.
+pub async fn playback_pause_remembering(
+    auth: &mut impl TokenProvider,
+    remember_after: Duration,
+) -> Result<(), Box<dyn error::Error>> {
+    playback_pause(auth).await?;
+
+    // The pause went through, so failing to remember only warrants a warning.
+    if let Err(e) = remember_position(auth, remember_after).await {
+        eprintln!("{}", tr!("Failed to remember the position: {e}", e = e));
+    }
+
+    Ok(())
+}
+
+async fn remember_position(
+    auth: &mut impl TokenProvider,
+    remember_after: Duration,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player");
+    let request = http::client()
+        .get(url)
+        .query(&[("additional_types", "track,episode")]);
+    let res = send(auth, request).await?;
+    if res.status() == StatusCode::NO_CONTENT {
+        return Ok(());
+    }
+    let player_response: PlayingItemResponse = parse_response(res).await?;
+
+    let Some(item) = player_response.item else {
+        return Ok(());
+    };
+    if item.r#type != "episode" && item.duration_ms < remember_after.as_millis() as u64 {
+        return Ok(());
+    }
+
+    let mut state = state::load()?;
+    state.last_position = Some(SavedPosition {
+        context_uri: player_response
+            .context
+            .filter(|ctx| ["show", "playlist", "album"].contains(&ctx.r#type.as_str()))
+            .map(|ctx| ctx.uri),
+        progress_ms: player_response.progress_ms.unwrap_or(0),
+        uri: item.uri,
+        name: item.name,
+        saved_at: current_time_secs_from_epoch()?,
+    });
+    state::save(&state)?;
+
+    Ok(())
+}
+
+/// Plays the episode or long track last paused from where it was paused.
+pub async fn resume_last(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let state = state::load()?;
+    let Some(position) = state.last_position else {
+        return Err(
+            "Nothing to resume. Pausing an episode or a long track remembers where it was.".into(),
+        );
+    };
+
+    play_track_at(
+        auth,
+        position.context_uri.as_deref(),
+        &position.uri,
+        position.progress_ms,
+    )
+    .await?;
+    output::info!(
+        "{}",
+        tr!(
+            "Resumed {name} at {position}.",
+            name = position.name,
+            position = format_duration(position.progress_ms)
+        )
+    );
+
+    Ok(())
+}
+
+/// Starts/resumes playback.
+///
+/// `uri` is a context (playlist, album, ...) to play and `index` the offset
+/// within it. Alternatively, `uris` plays an explicit list of tracks; `index`
+/// is then the offset within that list.
+pub async fn playback_play(
+    auth: &mut impl TokenProvider,
+    uri: Option<&str>,
+    uris: Option<&[String]>,
+    index: Option<u16>,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/play");
+
+    if uri.is_some() && uris.is_some() {
+        return Err("Can't play both a context and a list of tracks.".into());
+    }
+
+    let client = http::client();
+    let mut res_builder = client.put(url);
+    let mut map = serde_json::Map::new();
+    if let Some(uri) = uri {
+        map.insert(
+            "context_uri".to_string(),
+            serde_json::Value::String(uri.to_owned()),
+        );
+    }
+    if let Some(uris) = uris {
+        map.insert("uris".to_string(), serde_json::Value::from(uris));
+    }
+    if let Some(offset) = index {
+        let mut tmp = serde_json::Map::new();
+        tmp.insert(
+            "position".to_string(),
+            serde_json::Value::Number(offset.into()),
+        );
+        map.insert("offset".to_string(), serde_json::Value::Object(tmp));
+
+        if uri.is_none() && uris.is_none() {
+            let player_response = get_player(auth).await?;
+            match player_response.context {
+                Some(ctx) if ctx.r#type == "playlist" || ctx.r#type == "album" => {
+                    map.insert(
+                        "context_uri".to_string(),
+                        serde_json::Value::String(ctx.uri.to_owned()),
+                    );
+                }
+                _ => {
+                    return Err(
+                        "Not playing from a playlist or album; can't jump to an index.".into(),
+                    )
+                }
+            }
+        }
+    }
+
+    if map.is_empty() {
+        res_builder = res_builder.header("content-length", 0);
+    } else {
+        res_builder = res_builder.json(&map);
+    }
+    let res_builder = on_target_device(auth, res_builder).await?;
+    let res = send(auth, res_builder).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+pub async fn playback_next(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/next");
+
+    let client = http::client();
+    let request = client.post(url).header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+pub async fn playback_previous(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/previous");
+
+    let client = http::client();
+    let request = client.post(url).header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+/// How many seconds into a track `previous` restarts it by default.
+pub const DEFAULT_PREVIOUS_RESTART_SECS: u64 = 3;
+
+/// Goes back like most players do: more than `restart_after` into the track
+/// this restarts it, otherwise it plays the previous track. With `force`,
+/// always plays the previous track.
+pub async fn playback_back(
+    auth: &mut impl TokenProvider,
+    restart_after: Duration,
+    force: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_response = get_player(auth).await.ok();
+    if let Some(player_response) = &player_response {
+        let progress = Duration::from_millis(player_response.progress_ms.unwrap_or(0));
+        if !force && progress > restart_after {
+            playback_restart(auth).await?;
+            output::info!("{}", tr!("Restarted: {song}", song = player_response.song));
+            return Ok(());
+        }
+    }
+
+    let before = snapshot_of(player_response.as_ref());
+    playback_previous(auth).await?;
+    show_playback_change(auth, &before, ExpectedChange::Track).await
+}
+
+pub async fn playback_restart(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/seek");
+
+    let client = http::client();
+    let request = client
+        .put(url)
+        .query(&[("position_ms", 0)])
+        .header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+/// Whether the error is the API telling that there's no device to play on.
+pub fn is_no_active_device_error(e: &(dyn error::Error + 'static)) -> bool {
+    e.downcast_ref::<ApiError>()
+        .is_some_and(|e| e.is_no_active_device())
+}
+
+async fn get_devices(auth: &mut impl TokenProvider) -> Result<Vec<Device>, Box<dyn error::Error>> {
+    let url = api_url("/me/player/devices");
+
+    let client = http::client();
+    let devices_response: DevicesResponse = api_request(auth, client.get(url)).await?;
+
+    Ok(devices_response.devices)
+}
+
+/// Finds a device by id or by name (case insensitive).
+fn find_device<'a>(devices: &'a [Device], name_or_id: &str) -> Option<&'a Device> {
+    devices
+        .iter()
+        .find(|d| d.id.as_deref() == Some(name_or_id))
+        .or_else(|| {
+            devices
+                .iter()
+                .find(|d| d.name.eq_ignore_ascii_case(name_or_id))
+        })
+}
+
+/// Resolves the device to transfer playback to: the given one, or one the
+/// user picks from the available devices if running interactively.
+pub async fn choose_device(
+    auth: &mut impl TokenProvider,
+    device: Option<&str>,
+) -> Result<String, Box<dyn error::Error>> {
+    let devices: Vec<Device> = get_devices(auth)
+        .await?
+        .into_iter()
+        .filter(|d| d.id.is_some())
+        .collect();
+    if devices.is_empty() {
+        return Err("No devices available. Open Spotify on some device first.".into());
+    }
+
+    let chosen = match device {
+        Some(name_or_id) => find_device(&devices, name_or_id).ok_or_else(|| {
+            format!(
+                "No device named {name_or_id}. Available: {}",
+                device_names(&devices)
+            )
+        })?,
+        None if io::stdin().is_terminal() || prompt::preset_choice().is_some() => {
+            println!(
+                "{}",
+                tr!("No active device; choose one to transfer playback to.")
+            );
+            let ind = choose_element(&devices)?;
+            devices.get(ind).ok_or("No such device.")?
+        }
+        None => {
+            return Err(format!(
+                "No active device. Use --device to pick one of: {}",
+                device_names(&devices)
+            )
+            .into())
+        }
+    };
+
+    Ok(chosen.id.clone().unwrap())
+}
+
+fn device_names(devices: &[Device]) -> String {
+    devices
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Transfers playback to the device without starting playback.
+pub async fn transfer_playback(
+    auth: &mut impl TokenProvider,
+    device_id: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player");
+
+    let client = http::client();
+    let mut map = HashMap::new();
+    map.insert("device_ids", vec![device_id]);
+    let res = send(auth, client.put(url).json(&map)).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+static TARGET_DEVICE: OnceLock<String> = OnceLock::new();
+
+/// Cached device ids are used for a day before the devices are listed again.
+const DEVICE_CACHE_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Sets the device (name or id) playback commands act on, instead of the
+/// currently active one.
+pub fn set_target_device(name_or_id: &str) {
+    let _ = TARGET_DEVICE.set(name_or_id.to_owned());
+}
+
+/// Id of the device set with `set_target_device`, if any. Names are resolved
+/// from the cache or, failing that, from the devices endpoint.
+async fn target_device_id(
+    auth: &mut impl TokenProvider,
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let Some(name_or_id) = TARGET_DEVICE.get() else {
+        return Ok(None);
+    };
+
+    let mut cache = cache::load();
+    if let Some(devices) = cache
+        .devices
+        .as_ref()
+        .filter(|devices| devices.age_secs() < DEVICE_CACHE_MAX_AGE_SECS)
+    {
+        if let Some(device) = devices
+            .devices
+            .iter()
+            .find(|d| d.id == *name_or_id || d.name.eq_ignore_ascii_case(name_or_id))
+        {
+            return Ok(Some(device.id.clone()));
+        }
+    }
+
+    let devices = get_devices(auth).await?;
+    cache.devices = Some(cache::Devices {
+        fetched_at: current_time_secs_from_epoch()?,
+        devices: devices
+            .iter()
+            .filter_map(|d| {
+                Some(cache::CachedDevice {
+                    id: d.id.clone()?,
+                    name: d.name.clone(),
+                })
+            })
+            .collect(),
+    });
+    let _ = cache::save(&cache);
+
+    let device_id = find_device(&devices, name_or_id)
+        .and_then(|d| d.id.clone())
+        .ok_or_else(|| {
+            format!(
+                "No device named {name_or_id}. Available: {}",
+                device_names(&devices)
+            )
+        })?;
+
+    Ok(Some(device_id))
+}
+
+/// Targets the request at the device set with `set_target_device`, if any.
+async fn on_target_device(
+    auth: &mut impl TokenProvider,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::RequestBuilder, Box<dyn error::Error>> {
+    match target_device_id(auth).await? {
+        Some(device_id) => Ok(request.query(&[("device_id", device_id)])),
+        None => Ok(request),
+    }
+}
+
+async fn get_queue(
+    auth: &mut impl TokenProvider,
+) -> Result<PlayerQueueResponse, Box<dyn error::Error>> {
+    let url = api_url("/me/player/queue");
+
+    let client = http::client();
+    let player_queue_response: PlayerQueueResponse = api_request(auth, client.get(url)).await?;
+
+    Ok(player_queue_response)
+}
+
+/// Skips `count` tracks forward, waiting for each skip to take effect before
+/// the next one so that none get lost.
+async fn skip_tracks(
+    auth: &mut impl TokenProvider,
+    count: usize,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut progress = Progress::new("Skipping tracks", progress::Kind::Steps, Some(count));
+
+    for skipped in 0..count {
+        let before = get_player(auth).await?.song.uri;
+        playback_next(auth).await?;
+
+        for _ in 0..8 {
+            tokio::time::sleep(Duration::from_millis(250u64)).await;
+            if matches!(get_player(auth).await, Ok(p) if p.song.uri != before) {
+                break;
+            }
+        }
+        progress.update(skipped + 1);
+    }
+
+    Ok(())
+}
+
+/// Skips to the track at `index` in the queue, as numbered by `queue`.
+pub async fn queue_skip_to(
+    auth: &mut impl TokenProvider,
+    index: usize,
+) -> Result<(), Box<dyn error::Error>> {
+    let queue = get_queue(auth).await?;
+    if index == 0 || index > queue.queued.len() {
+        return Err(format!("No track #{index} in the queue.").into());
+    }
+
+    skip_tracks(auth, index).await?;
+
+    if output::quiet() {
+        return Ok(());
+    }
+    playback_show(auth, false, false, None).await
+}
+
+/// How many tracks at the start of the queue the user has added, as opposed
+/// to the context's own upcoming tracks.
+///
+/// Added tracks are told apart from the context's own upcoming tracks by
+/// finding where the context's next track is in the queue. This doesn't work
+/// with shuffle on; then everything queued before the next track of the
+/// context in order counts as added.
+async fn count_added_to_queue(
+    auth: &mut impl TokenProvider,
+    player_response: &PlayerResponse,
+    queue: &PlayerQueueResponse,
+) -> Result<usize, Box<dyn error::Error>> {
+    let context_tracks: Option<Vec<String>> = match &player_response.context {
+        Some(ctx) if ctx.r#type == "playlist" => Some(
+            get_playlist_tracks(auth, &ctx.uri)
+                .await?
+                .into_iter()
+                .map(|song| song.uri)
+                .collect(),
+        ),
+        Some(ctx) if ctx.r#type == "album" => {
+            let id = ctx.uri.rsplit(':').next().unwrap_or_default();
+            Some(
+                get_album(auth, id)
+                    .await?
+                    .tracks
+                    .items
+                    .into_iter()
+                    .map(|song| song.uri)
+                    .collect(),
+            )
+        }
+        _ => None,
+    };
+    let next_in_context = context_tracks.as_ref().and_then(|tracks| {
+        let current = tracks
+            .iter()
+            .position(|uri| *uri == player_response.song.uri)?;
+        tracks.get(current + 1)
+    });
+
+    Ok(match next_in_context {
+        Some(next) => queue
+            .queued
+            .iter()
+            .take_while(|song| song.uri != *next)
+            .count(),
+        None => queue.queued.len(),
+    })
+}
+
+/// Clears the tracks the user has added to the queue. The API has no way to
+/// do this, so the added tracks are skipped through and the context is then
+/// resumed from where it was. See `count_added_to_queue` for which tracks
+/// count as added.
+pub async fn queue_clear(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let player_response = get_player(auth).await?;
+    let queue = get_queue(auth).await?;
+    let added = count_added_to_queue(auth, &player_response, &queue).await?;
+
+    if added == 0 {
+        output::info!("{}", tr!("Nothing has been added to the queue."));
+        return Ok(());
+    }
+
+    output::info!(
+        "{}",
+        tr!("Skipping {added} queued track(s)...", added = added)
+    );
+    skip_tracks(auth, added).await?;
+
+    // Skipping played the queued tracks; go back to where we were.
+    restore_playback(auth, Some(&SavedPlayback::from_player(&player_response))).await?;
+
+    output::info!("{}", tr!("Cleared the queue."));
+
+    Ok(())
+}
+
+/// Adds a track or episode to the queue. The API only appends to the end of
+/// the queue, so with `next` any tracks the user added earlier are cleared
+/// like in `queue_clear` and queued again after the new one. This is best
+/// effort: the cleared tracks play for a moment while skipping through them.
+pub async fn queue_add(
+    auth: &mut impl TokenProvider,
+    link: &str,
+    next: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = SpotifyId::parse(link)?;
+    if id.kind != "track" && id.kind != "episode" {
+        return Err(format!("Only tracks and episodes can be queued, not a {}.", id.kind).into());
+    }
+    let uri = id.uri();
+
+    let requeued: Vec<String> = match next {
+        true => {
+            let player_response = get_player(auth).await?;
+            let queue = get_queue(auth).await?;
+            let added = count_added_to_queue(auth, &player_response, &queue).await?;
+            if added > 0 {
+                output::info!(
+                    "{}",
+                    tr!("Moving {added} queued track(s) after it...", added = added)
+                );
+                skip_tracks(auth, added).await?;
+                restore_playback(auth, Some(&SavedPlayback::from_player(&player_response))).await?;
+            }
+            queue
+                .queued
+                .into_iter()
+                .take(added)
+                .map(|song| song.uri)
+                .collect()
+        }
+        false => Vec::new(),
+    };
+
+    add_to_queue(auth, &uri).await?;
+    queue_tracks(auth, &requeued).await?;
+
+    match next {
+        true => output::info!("{}", tr!("Queued {uri} to play next.", uri = uri)),
+        false => output::info!("{}", tr!("Added {uri} to the queue.", uri = uri)),
+    }
+
+    Ok(())
+}
+
+/// Where the playback was, for resuming it after playing something else.
+struct SavedPlayback {
+    /// Only playlists and albums, as other contexts can't be resumed from a
+    /// given track.
+    context_uri: Option<String>,
+    song_uri: String,
+    progress_ms: u64,
+    is_playing: bool,
+}
+
+impl SavedPlayback {
+    fn from_player(player_response: &PlayerResponse) -> SavedPlayback {
+        SavedPlayback {
+            context_uri: player_response
+                .context
+                .as_ref()
+                .filter(|ctx| ctx.r#type == "playlist" || ctx.r#type == "album")
+                .map(|ctx| ctx.uri.clone()),
+            song_uri: player_response.song.uri.clone(),
+            progress_ms: player_response.progress_ms.unwrap_or(0),
+            is_playing: player_response.is_playing,
+        }
+    }
+}
+
+/// Saves the current playback. `None` if there's nothing to resume.
+async fn save_playback(auth: &mut impl TokenProvider) -> Option<SavedPlayback> {
+    get_player(auth)
+        .await
+        .ok()
+        .map(|player_response| SavedPlayback::from_player(&player_response))
+}
+
+/// Plays a track from the given position, within the context if given so
+/// that playback continues from there.
+async fn play_track_at(
+    auth: &mut impl TokenProvider,
+    context_uri: Option<&str>,
+    song_uri: &str,
+    position_ms: u64,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/play");
+    let mut map = serde_json::Map::new();
+    match context_uri {
+        Some(context_uri) => {
+            map.insert("context_uri".to_string(), Value::from(context_uri));
+            let mut offset = serde_json::Map::new();
+            offset.insert("uri".to_string(), Value::from(song_uri));
+            map.insert("offset".to_string(), Value::Object(offset));
+        }
+        None => {
+            map.insert("uris".to_string(), Value::from(vec![song_uri]));
+        }
+    }
+    map.insert("position_ms".to_string(), Value::from(position_ms));
+
+    let client = http::client();
+    let request = on_target_device(auth, client.put(url).json(&map)).await?;
+    let res = send(auth, request).await?;
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+/// Resumes saved playback where it was. With nothing saved, or if it was
+/// paused, playback is paused.
+async fn restore_playback(
+    auth: &mut impl TokenProvider,
+    saved: Option<&SavedPlayback>,
+) -> Result<(), Box<dyn error::Error>> {
+    if let Some(saved) = saved {
+        play_track_at(
+            auth,
+            saved.context_uri.as_deref(),
+            &saved.song_uri,
+            saved.progress_ms,
+        )
+        .await?;
+        if saved.is_playing {
+            return Ok(());
+        }
+    }
+
+    playback_pause(auth).await
+}
+
+/// How far into a track previews start, to get past intros.
+const PREVIEW_START_MS: u64 = 30_000;
+
+/// Plays a track until the user presses Enter and then resumes what was
+/// playing before.
+async fn preview_track(
+    auth: &mut impl TokenProvider,
+    song: &Song,
+) -> Result<(), Box<dyn error::Error>> {
+    let saved = save_playback(auth).await;
+
+    let position_ms = match song.duration_ms > 2 * PREVIEW_START_MS {
+        true => PREVIEW_START_MS,
+        false => 0,
+    };
+    play_track_at(auth, None, &song.uri, position_ms).await?;
+    println!("{}", tr!("Previewing: {track}", track = song));
+    println!("{}", tr!("Press Enter to go back to what was playing."));
+    let mut input = String::new();
+    prompt::read_line(&mut input)?;
+
+    restore_playback(auth, saved.as_ref()).await
+}
+
+pub async fn set_volume(
+    auth: &mut impl TokenProvider,
+    percent: u8,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/volume");
+
+    let client = http::client();
+    let request = client
+        .put(url)
+        .query(&[("volume_percent", percent.min(100))])
+        .header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+/// Mutes, saving the volume so that the next call unmutes back to it. If
+/// the volume has been changed elsewhere since muting, mutes again.
+pub async fn mute_toggle(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let volume = get_player(auth)
+        .await?
+        .device
+        .volume_percent
+        .ok_or("The device doesn't support setting the volume.")?;
+    let mut state = state::load()?;
+
+    match state.muted_volume {
+        Some(muted_volume) if volume == 0 => {
+            set_volume(auth, muted_volume).await?;
+            state.muted_volume = None;
+            state::save(&state)?;
+            output::info!(
+                "{}",
+                tr!(
+                    "Unmuted, volume {muted_volume}%.",
+                    muted_volume = muted_volume
+                )
+            );
+        }
+        _ if volume == 0 => return Err("The volume is already at 0.".into()),
+        _ => {
+            // Saved first so the volume can't get lost.
+            state.muted_volume = Some(volume);
+            state::save(&state)?;
+            if let Err(e) = set_volume(auth, 0).await {
+                state.muted_volume = None;
+                state::save(&state)?;
+                return Err(e);
+            }
+            output::info!(
+                "{}",
+                tr!(
+                    "Muted. Run mute again to go back to volume {volume}%.",
+                    volume = volume
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// How long before pausing the volume starts to fade out.
+const SLEEP_FADE_DURATION: Duration = Duration::from_secs(60);
+
+/// Pauses playback after `duration`. With `fade`, the volume is lowered
+/// gradually during the last minute and restored after pausing so that the
+/// next playback isn't silent.
+pub async fn sleep_timer(
+    auth: &mut impl TokenProvider,
+    duration: Duration,
+    fade: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let fade_duration = match fade {
+        true => SLEEP_FADE_DURATION.min(duration),
+        false => Duration::ZERO,
+    };
+    tokio::time::sleep(duration - fade_duration).await;
+
+    let mut original_volume = None;
+    if fade {
+        let player_response = get_player(auth).await?;
+        if let Some(volume) = player_response.device.volume_percent {
+            original_volume = Some(volume);
+
+            const STEPS: u32 = 12;
+            for step in 1..=STEPS {
+                tokio::time::sleep(fade_duration / STEPS).await;
+                let lowered = volume as u32 * (STEPS - step) / STEPS;
+                // Keep going even if a step fails; the pause matters most.
+                let _ = set_volume(auth, lowered as u8).await;
+            }
+        } else {
+            tokio::time::sleep(fade_duration).await;
+        }
+    }
+
+    playback_pause(auth).await?;
+    if let Some(volume) = original_volume {
+        set_volume(auth, volume).await?;
+    }
+    output::info!("{}", tr!("Paused playback."));
+
+    Ok(())
+}
+
+/// Starts playback at the next `time` (right away if `None`), optionally
+/// switching to `playlist` and setting the volume first thing.
+pub async fn scheduled_play(
+    auth: &mut impl TokenProvider,
+    time: Option<NaiveTime>,
+    playlist: Option<&str>,
+    volume: Option<u8>,
+) -> Result<(), Box<dyn error::Error>> {
+    // Resolve the playlist up front so a typo fails now instead of at the set time.
+    let uri = match playlist {
+        Some(playlist) => Some(format!(
+            "spotify:playlist:{}",
+            resolve_playlist_id(auth, playlist).await?
+        )),
+        None => None,
+    };
+
+    if let Some(time) = time {
+        output::info!(
+            "{}",
+            tr!("Starting playback at {time}.", time = time.format("%H:%M"))
+        );
+        schedule::wait_until(time).await;
+    }
+
+    playback_play(auth, uri.as_deref(), None, None).await?;
+    if let Some(volume) = volume {
+        set_volume(auth, volume).await?;
+    }
+    output::info!("{}", tr!("Started playback."));
+
+    Ok(())
+}
+
+/// Prints the current track and the next `number - 1` tracks in the queue,
+/// or the whole queue with `all`, with the time until each track plays.
+pub async fn queue_show(
+    auth: &mut impl TokenProvider,
+    number: usize,
+    all: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_queue_response = get_queue(auth).await?;
+
+    if player_queue_response.current.is_none() {
+        return Err("Not playing anything currently.".into());
+    }
+
+    let current = player_queue_response.current.unwrap();
+    let shown: Vec<&Song> = match all {
+        true => player_queue_response.queued.iter().collect(),
+        false => player_queue_response
+            .queued
+            .iter()
+            .take(number.saturating_sub(1))
+            .collect(),
+    };
+    let ids: Vec<&str> = std::iter::once(&current)
+        .chain(shown.iter().copied())
+        .map(|song| song.id.as_str())
+        .collect();
+    let saved = saved_tracks(auth, &ids).await;
+
+    // The queue doesn't tell the progress of the current track. Without it,
+    // the times are counted from the start of the current track.
+    let player = get_player(auth)
+        .await
+        .ok()
+        .filter(|player| player.song.uri == current.uri);
+    let progress_ms = player.as_ref().and_then(|player| player.progress_ms);
+    let sources = match &player {
+        Some(player) => queue_sources(auth, player, &shown).await,
+        None => None,
+    };
+    let mut starts_in = Vec::with_capacity(shown.len());
+    let mut total_ms = current
+        .duration_ms
+        .saturating_sub(progress_ms.unwrap_or_default());
+    for song in &shown {
+        starts_in.push(total_ms);
+        total_ms += song.duration_ms;
+    }
+
+    if output::json() {
+        let mut current_json = serde_json::to_value(&current)?;
+        current_json["progress_ms"] = progress_ms.into();
+        current_json["saved"] = saved[0].into();
+        let mut queue = Vec::with_capacity(shown.len());
+        for (ind, song) in shown.iter().enumerate() {
+            let mut song_json = serde_json::to_value(song)?;
+            song_json["position"] = (ind + 1).into();
+            song_json["starts_in_ms"] = starts_in[ind].into();
+            song_json["saved"] = saved[ind + 1].into();
+            song_json["source"] = sources.as_ref().map(|sources| sources[ind]).into();
+            queue.push(song_json);
+        }
+        println!(
+            "{}",
+            serde_json::json!({"current": current_json, "queue": queue})
+        );
+        return Ok(());
+    }
+
+    if output::tables() {
+        let mut headers: Vec<&str> = std::iter::once("#").chain(Song::headers()).collect();
+        // After the duration, where the cell is inserted below too.
+        headers.insert(5, "Starts in");
+        if sources.is_some() {
+            headers.push("Source");
+        }
+        let mut table = Table::new(&headers);
+        for (ind, song) in std::iter::once(&current).chain(shown).enumerate() {
+            let mut cells = vec![ind.to_string()];
+            cells.extend(song.cells());
+            cells[1] = format!("{}{}", saved_marker(saved[ind]), cells[1]);
+            let starts_in = match ind {
+                0 => String::new(),
+                ind => format_duration(starts_in[ind - 1]),
+            };
+            cells.insert(5, starts_in);
+            if let Some(sources) = &sources {
+                cells.push(match ind {
+                    0 => String::new(),
+                    ind => sources[ind - 1].to_string(),
+                });
+            }
+            // The first row is the current track.
+            if ind == 0 {
+                table.add_styled_row(cells, output::highlight);
+            } else {
+                table.add_row(cells);
+            }
+        }
+        table.print();
+        return Ok(());
+    }
+
+    let width = output::line_width();
+    let time = match progress_ms {
+        Some(progress_ms) => format!(
+            " [{}/{}]",
+            format_duration(progress_ms),
+            format_duration(current.duration_ms)
+        ),
+        None => format!(" [{}]", format_duration(current.duration_ms)),
+    };
+    println!(
+        "{}",
+        fit_line(
+            &tr!("Currently playing: {saved}", saved = saved_marker(saved[0])),
+            &current,
+            &time,
+            width
+        )
+    );
+    println!("{}", output::header(tr!("In queue:")));
+    let index_width = shown.len().to_string().len();
+    for (ind, song) in shown.iter().enumerate() {
+        let prefix = format!(
+            "#{:<index_width$} {}",
+            ind + 1,
+            saved_marker(saved[ind + 1])
+        );
+        let source = match &sources {
+            Some(sources) => format!(", {}", source_label(sources[ind])),
+            None => String::new(),
+        };
+        let time = format!(
+            " [{}, in {}{source}]",
+            format_duration(song.duration_ms),
+            format_duration(starts_in[ind])
+        );
+        println!("{}", fit_line(&prefix, song, &time, width));
+    }
+    if !all && player_queue_response.queued.len() > shown.len() {
+        println!(
+            "{}",
+            tr!(
+                "{count} more in the queue; see them all with --all.",
+                count = player_queue_response.queued.len() - shown.len()
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// Where each queued track comes from: `queued` for tracks added to the
+/// queue, or the type of the context, e.g. `playlist`, for the tracks that
+/// play next from it. The queue mixes the two with the added tracks first,
+/// so everything from the first upcoming track of the context on is counted
+/// as coming from it. `None` when not playing from a playlist or album, or
+/// if its tracks can't be fetched.
+async fn queue_sources<'a>(
+    auth: &mut impl TokenProvider,
+    player: &'a PlayerResponse,
+    queued: &[&Song],
+) -> Option<Vec<&'a str>> {
+    let ctx = player.context.as_ref()?;
+    if ctx.r#type != "playlist" && ctx.r#type != "album" {
+        return None;
+    }
+    let uris = context_track_uris(auth, ctx, None).await.ok()?;
+
+    // With shuffle on, any track of the context can come next.
+    let upcoming: HashSet<&str> = match player.shuffle_state {
+        true => uris.iter().map(String::as_str).collect(),
+        false => {
+            let position = position_in_context(&uris, &player.song)?;
+            uris[position + 1..].iter().map(String::as_str).collect()
+        }
+    };
+    let from_context = queued
+        .iter()
+        .position(|song| {
+            upcoming.contains(song.uri.as_str())
+                || song
+                    .linked_from
+                    .as_ref()
+                    .is_some_and(|linked| upcoming.contains(linked.uri.as_str()))
+        })
+        .unwrap_or(queued.len());
+
+    Some(
+        (0..queued.len())
+            .map(|ind| match ind < from_context {
+                true => "queued",
+                false => ctx.r#type.as_str(),
+            })
+            .collect(),
+    )
+}
+
+/// The label of a queued track's source in `queue`.
+fn source_label(source: &str) -> String {
+    match source {
+        "queued" => source.to_string(),
+        context => format!("from {context}"),
+    }
+}
+
+/// Joins the parts into a line of at most `width` characters by truncating
+/// the middle one.
+fn fit_line(prefix: &str, song: &Song, suffix: &str, width: Option<usize>) -> String {
+    let room = width.map(|width| width.saturating_sub(text::width(prefix) + text::width(suffix)));
+    let song = match room {
+        Some(room) => text::truncate(&song.fitted(Some(room)), room),
+        None => song.to_string(),
+    };
+    format!("{prefix}{song}{suffix}")
+}
+
+/// Deletes the saved tokens. Spotify can't revoke them through the API, so
+/// the app's access can only be removed on the account page.
+pub fn auth_logout(token_path: &str, revoke: bool) -> Result<(), Box<dyn error::Error>> {
+    match auth::delete_token_file(token_path)? {
+        true => println!(
+            "{}",
+            tr!(
+                "Deleted the tokens in {token_path}.",
+                token_path = token_path
+            )
+        ),
+        false => println!(
+            "{}",
+            tr!(
+                "There are no tokens saved in {token_path}.",
+                token_path = token_path
+            )
+        ),
+    }
+
+    if revoke {
+        println!(
+            "{}",
+            tr!(
+                "Remove the app's access to your account at {url}",
+                url = ACCOUNT_APPS_URL
+            )
+        );
+        if let Err(e) = desktop::open(ACCOUNT_APPS_URL) {
+            println!("{e}");
+        }
+    } else {
+        println!(
+            "{}",
+            tr!(
+                "The app can still access your account until you remove it at {url}",
+                url = ACCOUNT_APPS_URL
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// Features and the scopes they need, for `doctor`.
+fn feature_scopes() -> [(&'static str, &'static [Scope]); 6] {
+    [
+        (
+            tr!("Playback"),
+            &[
+                Scope::UserReadPlaybackState,
+                Scope::UserReadCurrentlyPlaying,
+                Scope::UserModifyPlaybackState,
+            ],
+        ),
+        (
+            tr!("Playlists"),
+            &[Scope::PlaylistReadPrivate, Scope::PlaylistModifyPrivate],
+        ),
+        (
+            tr!("Sorting public playlists"),
+            &[Scope::PlaylistModifyPublic],
+        ),
+        (tr!("Liked songs"), &[Scope::UserLibraryRead]),
+        (tr!("Top tracks"), &[Scope::UserTopRead]),
+        (tr!("Playlist covers"), &[Scope::UgcImageUpload]),
+    ]
+}
+
+/// Prints the results of the `doctor` checks and counts the failed ones.
+#[derive(Default)]
+struct Checkup {
+    failed: usize,
+}
+
+impl Checkup {
+    fn pass(&mut self, message: &str) {
+        println!("{}", tr!("ok    {message}", message = message));
+    }
+
+    fn warn(&mut self, message: &str, hint: &str) {
+        println!("{}", tr!("warn  {message}", message = message));
+        println!("      {hint}");
+    }
+
+    fn fail(&mut self, message: &str, hint: &str) {
+        self.failed += 1;
+        println!("{}", tr!("FAIL  {message}", message = message));
+        println!("      {hint}");
+    }
+}
+
+/// Checks the setup one step at a time and prints what's wrong and how to
+/// fix it. Never starts the OAuth flow; the token file is only read, unless
+/// the access token has expired and is refreshed.
+pub async fn doctor(
+    token_path: &str,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut checkup = Checkup::default();
+
+    let config_path = config::config_path()?;
+    let config = match config::load() {
+        Ok(config) if fs::exists(&config_path)? => {
+            checkup.pass(&tr!("The config {path} is valid.", path = config_path));
+            config
+        }
+        Ok(config) => {
+            checkup.pass(&tr!(
+                "There's no config at {path}; using the defaults.",
+                path = config_path
+            ));
+            config
+        }
+        Err(e) => {
+            checkup.fail(
+                &tr!(
+                    "The config {path} is broken: {e}",
+                    path = config_path,
+                    e = e
+                ),
+                tr!("Fix it or move it away; until then every command fails."),
+            );
+            Config::default()
+        }
+    };
+    http::init(&config.network, timeout)?;
+
+    if env::var("SPOTIFY_CLI_ACCESS_TOKEN").is_ok() {
+        checkup.pass(tr!(
+            "SPOTIFY_CLI_ACCESS_TOKEN is set, so the token file isn't used."
+        ));
+        let mut auth = auth::StaticToken::new(&env::var("SPOTIFY_CLI_ACCESS_TOKEN")?);
+        check_api(&mut auth, &mut checkup).await;
+        return checkup_result(checkup);
+    }
+
+    let missing_vars: Vec<&str> = ["SPOTIFY_CLI_CLIENT_ID", "SPOTIFY_CLI_CLIENT_SECRET"]
+        .into_iter()
+        .filter(|var| env::var(var).is_err())
+        .collect();
+    match missing_vars.is_empty() {
+        true => checkup.pass(tr!("SPOTIFY_CLI_CLIENT_ID and SPOTIFY_CLI_CLIENT_SECRET are set.")),
+        false => checkup.fail(
+            &tr!("{vars} not set.", vars = missing_vars.join(" and ")),
+            tr!("Create an app at https://developer.spotify.com/dashboard and set them to its client id and secret."),
+        ),
+    }
+
+    check_redirect_port(&config, &mut checkup);
+
+    let client_id = env::var("SPOTIFY_CLI_CLIENT_ID").unwrap_or_default();
+    let client_secret = env::var("SPOTIFY_CLI_CLIENT_SECRET").unwrap_or_default();
+    let mut auth = match fs::exists(token_path)? {
+        false => {
+            checkup.fail(
+                &tr!("There are no tokens saved in {path}.", path = token_path),
+                tr!("Run `spotify-cli auth login`."),
+            );
+            return checkup_result(checkup);
+        }
+        true => match SpotifyAuth::from_file(&client_id, &client_secret, token_path) {
+            Ok(auth) => {
+                checkup.pass(&tr!(
+                    "The token file {path} is readable.",
+                    path = token_path
+                ));
+                auth
+            }
+            Err(e) => {
+                checkup.fail(
+                    &tr!(
+                        "Can't read the token file {path}: {e}",
+                        path = token_path,
+                        e = e
+                    ),
+                    tr!("Run `spotify-cli auth login` to replace it."),
+                );
+                return checkup_result(checkup);
+            }
+        },
+    };
+
+    let now = current_time_secs_from_epoch()?;
+    match (auth.valid_until(), auth.has_refresh_token()) {
+        (_, false) => {
+            checkup.fail(
+                tr!("The token file has no refresh token."),
+                tr!("Run `spotify-cli auth login`."),
+            );
+            return checkup_result(checkup);
+        }
+        (Some(valid_until), true) if valid_until > now => checkup.pass(&tr!(
+            "The access token is valid for {minutes} more minutes.",
+            minutes = (valid_until - now) / 60
+        )),
+        _ => checkup.pass(tr!(
+            "The access token has expired; it's refreshed when needed."
+        )),
+    }
+
+    for (feature, scopes) in feature_scopes() {
+        let missing: Vec<&str> = scopes
+            .iter()
+            .filter(|scope| !auth.has_scope(**scope))
+            .map(|scope| scope.as_str())
+            .collect();
+        match missing.is_empty() {
+            true => checkup.pass(&tr!("{feature}: authorized.", feature = feature)),
+            false => checkup.warn(
+                &tr!(
+                    "{feature}: not authorized yet ({scopes}).",
+                    feature = feature,
+                    scopes = missing.join(", ")
+                ),
+                tr!("You're asked to authorize it the first time it's used."),
+            ),
+        }
+    }
+
+    // Refreshing the access token needs the client id and secret.
+    if missing_vars.is_empty() {
+        check_api(&mut auth, &mut checkup).await;
+    }
+
+    checkup_result(checkup)
+}
+
+fn checkup_result(checkup: Checkup) -> Result<(), Box<dyn error::Error>> {
+    match checkup.failed {
+        0 => Ok(()),
+        failed => Err(format!("{failed} check(s) failed.").into()),
+    }
+}
+
+/// Checks that the port of the login redirect is free.
+fn check_redirect_port(config: &Config, checkup: &mut Checkup) {
+    let hint = tr!("Stop whatever is using it, or set other ports with SPOTIFY_CLI_REDIRECT_PORTS or `redirect_ports` in the config.");
+    let redirect_uri = env::var("SPOTIFY_CLI_REDIRECT_URI")
+        .ok()
+        .or(config.auth.redirect_uri.clone());
+    let ports = match &redirect_uri {
+        Some(uri) => match reqwest::Url::parse(uri)
+            .ok()
+            .and_then(|url| url.port_or_known_default())
+        {
+            Some(port) => vec![port],
+            None => {
+                checkup.fail(
+                    &tr!("Can't tell the port of the redirect uri {uri}.", uri = uri),
+                    tr!("Set SPOTIFY_CLI_REDIRECT_URI or `redirect_uri` to a url like http://127.0.0.1:8888/callback."),
+                );
+                return;
+            }
+        },
+        None => match auth::redirect_ports(config.auth.redirect_ports.as_deref()) {
+            Ok(ports) => ports,
+            Err(e) => {
+                checkup.fail(&e.to_string(), hint);
+                return;
+            }
+        },
+    };
+
+    match auth::get_free_port(&ports) {
+        Ok(port) => {
+            let uri = redirect_uri.unwrap_or(format!("http://localhost:{port}"));
+            checkup.pass(&tr!(
+                "Port {port} is free for logging in; {uri} has to be a redirect URI of the app.",
+                port = port,
+                uri = uri
+            ));
+        }
+        Err(e) => checkup.fail(&format!("{e}."), hint),
+    }
+}
+
+/// Checks that the API answers with the tokens, and lists the devices.
+async fn check_api(auth: &mut impl TokenProvider, checkup: &mut Checkup) {
+    match get_user(auth).await {
+        Ok(user) => checkup.pass(&tr!(
+            "Reached the Web API as {user}.",
+            user = user.display_name.unwrap_or(user.id)
+        )),
+        Err(e) => {
+            let hint = match api::ErrorKind::of(e.as_ref()) {
+                api::ErrorKind::Network | api::ErrorKind::Timeout => {
+                    tr!("Check the connection, and the proxy under [network] in the config.")
+                }
+                api::ErrorKind::Auth => tr!("Run `spotify-cli auth login`."),
+                _ => tr!("Try again in a while; https://status.spotify.dev shows outages."),
+            };
+            checkup.fail(&tr!("Can't reach the Web API: {e}", e = e), hint);
+            return;
+        }
+    }
+
+    match get_devices(auth).await {
+        Ok(devices) if devices.is_empty() => checkup.fail(
+            tr!("No devices are available."),
+            tr!("Open Spotify on a phone, computer or speaker; it's only listed while Spotify is running there."),
+        ),
+        Ok(devices) => match devices.iter().find(|device| device.is_active) {
+            Some(active) => checkup.pass(&tr!(
+                "{count} device(s); {active} is active.",
+                count = devices.len(),
+                active = active
+            )),
+            None => {
+                let names: Vec<String> = devices.iter().map(|device| device.to_string()).collect();
+                checkup.warn(
+                    &tr!("None of the devices is active: {names}.", names = names.join(", ")),
+                    tr!("Commands starting playback transfer it to --device, or ask which device to use."),
+                )
+            }
+        },
+        Err(e) => checkup.fail(
+            &tr!("Can't list the devices: {e}", e = e),
+            tr!("The token may be missing the user-read-playback-state scope; run `spotify-cli auth login`."),
+        ),
+    }
+}
+
+/// Spotify counts calls over a rolling window of this many seconds.
+const RATE_LIMIT_WINDOW_SECS: u64 = 30;
+
+/// Prints the API calls of the last day per endpoint and when the CLI was
+/// last rate limited. Spotify doesn't publish its limit or how much of it is
+/// left, so this is only for seeing how heavy commands are.
+pub fn quota() -> Result<(), Box<dyn error::Error>> {
+    let calls = cache::load().api_calls;
+    let now = current_time_secs_from_epoch()?;
+    let within = |call: &cache::ApiCall, secs: u64| now.saturating_sub(call.at) < secs;
+
+    let count = |secs: u64| calls.iter().filter(|call| within(call, secs)).count();
+    println!(
+        "{}",
+        tr!(
+            "API calls in the last {window} seconds: {window_count}, hour: {hour}, day: {day}.",
+            window = RATE_LIMIT_WINDOW_SECS,
+            window_count = count(RATE_LIMIT_WINDOW_SECS),
+            hour = count(60 * 60),
+            day = count(24 * 60 * 60)
+        )
+    );
+
+    let limited: Vec<&cache::ApiCall> = calls
+        .iter()
+        .filter(|call| call.status == StatusCode::TOO_MANY_REQUESTS.as_u16())
+        .collect();
+    match limited.last() {
+        Some(last) => {
+            print!(
+                "{}",
+                tr!(
+                    "Rate limited {count} time(s) in the last day, last {age} ago",
+                    count = limited.len(),
+                    age = format_age(now.saturating_sub(last.at))
+                )
+            );
+            match last.retry_after {
+                Some(secs) => println!("{}", tr!(" (asked to wait {secs} seconds).", secs = secs)),
+                None => println!("."),
+            }
+        }
+        None => println!("{}", tr!("Not rate limited in the last day.")),
+    }
+    if calls.is_empty() {
+        return Ok(());
+    }
+
+    // Endpoints with their counts in the window, hour and day.
+    let mut endpoints: Vec<(&str, [usize; 3])> = Vec::new();
+    for call in &calls {
+        let ind = match endpoints.iter().position(|(e, _)| *e == call.endpoint) {
+            Some(ind) => ind,
+            None => {
+                endpoints.push((&call.endpoint, [0; 3]));
+                endpoints.len() - 1
+            }
+        };
+        for (count, secs) in
+            endpoints[ind]
+                .1
+                .iter_mut()
+                .zip([RATE_LIMIT_WINDOW_SECS, 60 * 60, 24 * 60 * 60])
+        {
+            if within(call, secs) {
+                *count += 1;
+            }
+        }
+    }
+    endpoints.sort_by(|(a_name, a), (b_name, b)| b[2].cmp(&a[2]).then(a_name.cmp(b_name)));
+
+    println!();
+    let mut table = Table::new(&["Endpoint", "30s", "Hour", "Day"]);
+    for (endpoint, counts) in endpoints {
+        let mut cells = vec![endpoint.to_string()];
+        cells.extend(counts.iter().map(|count| count.to_string()));
+        table.add_row(cells);
+    }
+    table.print();
+
+    Ok(())
+}
+
+/// With `--quota-info`, tells on stderr how many calls the command made.
+pub fn print_quota_info() {
+    let now = current_time_secs_from_epoch().unwrap_or_default();
+    let earlier = cache::load()
+        .api_calls
+        .iter()
+        .filter(|call| now.saturating_sub(call.at) < RATE_LIMIT_WINDOW_SECS)
+        .count();
+    let made = usage::calls_this_run();
+    eprintln!(
+        "{}",
+        tr!(
+            "Made {made} API call(s); {total} in the last {window} seconds with the earlier commands.",
+            made = made,
+            total = made + earlier,
+            window = RATE_LIMIT_WINDOW_SECS
+        )
+    );
+}
+
+/// E.g. `45s`, `12m` or `3h`.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h", secs / 3600),
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum PlaylistSort {
+    Name,
+    /// Most tracks first
+    Tracks,
+    /// The order of the library, most recently added or played first
+    Recent,
+}
+
+/// Which of the user's playlists `playlist list` shows.
+#[derive(Clone, Debug)]
+pub struct PlaylistFilter {
+    /// Only the ones the user owns
+    pub owned: bool,
+    /// Only the ones the user follows but doesn't own
+    pub followed: bool,
+    pub collaborative: bool,
+    /// Only the ones with this in the name, ignoring case
+    pub name: Option<String>,
+}
+
+/// Lists all of the user's playlists, filtered and sorted.
+pub async fn playlist_list(
+    auth: &mut impl TokenProvider,
+    filter: &PlaylistFilter,
+    sort: PlaylistSort,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut playlists = get_my_playlists(auth).await?;
+
+    if filter.owned || filter.followed {
+        let user = get_user(auth).await?;
+        playlists.retain(|playlist| {
+            let owned = playlist
+                .owner
+                .as_ref()
+                .is_some_and(|owner| owner.id == user.id);
+            owned == filter.owned
+        });
+    }
+    if filter.collaborative {
+        playlists.retain(|playlist| playlist.collaborative);
+    }
+    if let Some(name) = &filter.name {
+        let name = name.to_lowercase();
+        playlists.retain(|playlist| playlist.name.to_lowercase().contains(&name));
+    }
+    match sort {
+        PlaylistSort::Name => playlists.sort_by_key(|playlist| playlist.name.to_lowercase()),
+        PlaylistSort::Tracks => {
+            playlists.sort_by_key(|playlist| std::cmp::Reverse(playlist.tracks.total))
+        }
+        PlaylistSort::Recent => {}
+    }
+
+    if output::tables() {
+        let mut table = Table::new(Playlist::HEADERS);
+        for playlist in &playlists {
+            table.add_row(playlist.cells());
+        }
+        table.print();
+    } else if playlists.is_empty() {
+        println!("{}", tr!("No playlists."));
+    } else {
+        let lines: Vec<String> = playlists.iter().map(Playlist::to_string).collect();
+        println!("{}", lines.join("\n\n"));
+    }
+
+    Ok(())
+}
+
+pub async fn playlist_create(
+    auth: &mut impl TokenProvider,
+    name: &str,
+    description: Option<&str>,
+    collaborative: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let description = description.unwrap_or("Playlist created by a CLI tool.");
+    let playlist_create_response =
+        create_playlist(auth, name, description, false, collaborative).await?;
+
+    println!(
+        "{}",
+        tr!(
+            "Created playlist {name}. uri: spotify:playlist:{id}",
+            name = name,
+            id = playlist_create_response.id
+        )
+    );
+
+    Ok(())
+}
+
+/// Appends tracks given as uris/urls or searched for by name. Unless
+/// `allow_dupes`, tracks already in the playlist (or given twice) are
+/// skipped so shared playlists don't fill up with repeats.
+pub async fn playlist_add(
+    auth: &mut impl TokenProvider,
+    playlist: &str,
+    tracks: &[String],
+    allow_dupes: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, playlist).await?;
+
+    let mut uris = Vec::new();
+    for query_or_uri in tracks {
+        let uri = match track_uri_from_input(query_or_uri) {
+            Some(uri) => uri,
+            None => {
+                let track = find(auth, Some(query_or_uri), None).await?;
+                format!("spotify:track:{}", track.id)
+            }
+        };
+        uris.push(uri);
+    }
+
+    if !allow_dupes {
+        let items = match get_playlist_from_id(auth, &id).await?.tracks {
+            Some(tracks) => tracks.get_all_items(auth).await?,
+            None => Vec::new(),
+        };
+        // Relinked tracks are in the playlist under the uri they were added
+        // with.
+        let mut existing: HashMap<String, Song> = HashMap::new();
+        for item in items {
+            if let Some(linked_from) = &item.track.linked_from {
+                existing.insert(linked_from.uri.clone(), item.track.clone());
+            }
+            existing.insert(item.track.uri.clone(), item.track);
+        }
+
+        let mut seen = HashSet::new();
+        uris.retain(|uri| {
+            if let Some(track) = existing.get(uri) {
+                output::info!(
+                    "{}",
+                    tr!("Skipped {track}: already in the playlist.", track = track)
+                );
+                return false;
+            }
+            seen.insert(uri.clone())
+        });
+    }
+
+    if uris.is_empty() {
+        output::info!("{}", tr!("Nothing to add."));
+        return Ok(());
+    }
+    add_playlist_items(auth, &id, &uris).await?;
+    output::info!(
+        "{}",
+        tr!(
+            "Added {count} track(s) to the playlist.",
+            count = uris.len()
+        )
+    );
+
+    Ok(())
+}
+
+/// Makes a playlist collaborative or not. Collaborative playlists have to be
+/// private, so turning it on also makes the playlist private.
+pub async fn playlist_set_collaborative(
+    auth: &mut impl TokenProvider,
+    playlist: &str,
+    collaborative: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, playlist).await?;
+    let url = api_url(&format!("/playlists/{id}"));
+
+    let mut body = serde_json::json!({ "collaborative": collaborative });
+    if collaborative {
+        body["public"] = Value::from(false);
+    }
+    let client = http::client();
+    let res = send(auth, client.put(url).json(&body)).await?;
+    check_for_error_and_return_text(res).await?;
+
+    match collaborative {
+        true => output::info!("{}", tr!("The playlist is now collaborative.")),
+        false => output::info!("{}", tr!("The playlist is no longer collaborative.")),
+    }
+
+    Ok(())
+}
+
+/// Max size of the base64 encoded cover image the API accepts.
+const MAX_COVER_BASE64_BYTES: usize = 256 * 1024;
+
+/// The image as a JPEG that fits the cover size limit. JPEGs that already
+/// fit are used as is; anything else is re-encoded, shrinking it until it
+/// fits.
+fn cover_jpeg(image_bytes: &[u8]) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let fits = |bytes: &[u8]| bytes.len().div_ceil(3) * 4 <= MAX_COVER_BASE64_BYTES;
+    let is_jpeg = image_bytes.starts_with(&[0xFF, 0xD8, 0xFF]);
+    if is_jpeg && fits(image_bytes) {
+        return Ok(image_bytes.to_vec());
+    }
+
+    let image = image::load_from_memory(image_bytes)
+        .map_err(|e| format!("Failed to read the image: {e}"))?
+        .to_rgb8();
+    let mut size = image.width().max(image.height()).min(1000);
+    loop {
+        let resized = image::imageops::thumbnail(
+            &image,
+            size * image.width() / image.width().max(image.height()),
+            size * image.height() / image.width().max(image.height()),
+        );
+        for quality in [90, 75, 60] {
+            let mut jpeg = Vec::new();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+                .encode_image(&resized)?;
+            if fits(&jpeg) {
+                return Ok(jpeg);
+            }
+        }
+        if size <= 100 {
+            return Err("Couldn't shrink the image to the 256 KB the API allows.".into());
+        }
+        size = size * 3 / 4;
+    }
+}
+
+/// Uploads an image as the cover of a playlist.
+pub async fn playlist_set_cover(
+    auth: &mut impl TokenProvider,
+    playlist: &str,
+    image_path: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let image_bytes =
+        fs::read(image_path).map_err(|e| format!("Failed to read {image_path}: {e}"))?;
+    let jpeg = cover_jpeg(&image_bytes)?;
+
+    let id = resolve_playlist_id(auth, playlist).await?;
+    let url = api_url(&format!("/playlists/{id}/images"));
+
+    let client = http::client();
+    let request = client
+        .put(url)
+        .header("content-type", "image/jpeg")
+        .body(BASE64_STANDARD.encode(&jpeg));
+    let res = send(auth, request).await?;
+    check_for_error_and_return_text(res).await?;
+
+    output::info!("{}", tr!("Updated the playlist cover."));
+
+    Ok(())
+}
+
+pub async fn playlist_current(
+    auth: &mut impl TokenProvider,
+    max_lines: Option<u16>,
+    around: Option<u16>,
+) -> Result<(), Box<dyn error::Error>> {
+    let player_response = get_player(auth).await?;
+
+    let current_song = player_response.song.name;
+
+    let Some(ctx) = player_response.context else {
+        println!(
+            "{}",
+            tr!("Not playing from a playlist, album or artist currently.")
+        );
+        return Ok(());
+    };
+    let context_id = ctx.uri.rsplit(':').next().unwrap_or_default();
+
+    match ctx.r#type.as_str() {
+        "album" => {
+            let album = get_album(auth, context_id).await?;
+
+            println!("{}", output::header(&album.to_string()));
+            println!();
+            let tracks: Vec<(usize, Song)> = album.tracks.items.into_iter().enumerate().collect();
+            print_track_lines(&tracks, Some(&current_song), max_lines, around)?;
+        }
+        "artist" => {
+            let artist = get_artist(auth, context_id).await?;
+            let top_tracks = get_artist_top_tracks(auth, context_id).await?;
+
+            println!(
+                "{}",
+                output::header(&tr!("Playing from artist: {artist}", artist = artist))
+            );
+            println!();
+            let tracks: Vec<(usize, Song)> = top_tracks.into_iter().enumerate().collect();
+            // Artist contexts play more than the top tracks, so the current
+            // track isn't necessarily among them.
+            if tracks.iter().any(|(_, track)| track.name == current_song) {
+                print_track_lines(&tracks, Some(&current_song), max_lines, around)?;
+            } else {
+                println!("{}", output::header(tr!("Top tracks:")));
+                print_track_lines(&tracks, None, max_lines, None)?;
+                println!(
+                    "{}",
+                    tr!("\nNow playing: {current_song}", current_song = current_song)
+                );
+            }
+        }
+        _ => {
+            let playlist_description = get_playlist_from_href(auth, &ctx.href).await?;
+
+            println!("{}", output::header(&playlist_description.name));
+
+            if let Some(desc) = playlist_description.description {
+                if !desc.is_empty() {
+                    println!(" - {}", desc);
+                }
+            }
+
+            if let Some(tracks) = playlist_description.tracks {
+                println!();
+                tracks
+                    .print_tracks(auth, Some(&current_song), max_lines, around)
+                    .await?;
+            } else {
+                println!(
+                    "{}",
+                    tr!("\nNot actually playing from a playlist currently.")
+                )
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Where `jump` goes: a position in the context, or a number of tracks
+/// forward or back from the current one.
+#[derive(Clone, Copy, Debug)]
+pub enum Jump {
+    To(u16),
+    By(i32),
+}
+
+/// Parses `5` as a position and `+3` or `-2` as relative jumps.
+pub fn parse_jump(jump: &str) -> Result<Jump, String> {
+    let bad = || {
+        format!(
+            "Bad jump: {jump}. Use a position like 5, or +3 or -2 to move from the current track."
+        )
+    };
+    match jump.strip_prefix(['+', '-']) {
+        Some(_) => jump.parse().map(Jump::By).map_err(|_| bad()),
+        None => jump.parse().map(Jump::To).map_err(|_| bad()),
+    }
+}
+
+/// Jumps to a track of the current playlist or album. A relative jump finds
+/// the position of the current track in the context first; if the track is
+/// in a playlist more than once, its first position is used.
+pub async fn playback_jump(
+    auth: &mut impl TokenProvider,
+    jump: Jump,
+) -> Result<(), Box<dyn error::Error>> {
+    let offset = match jump {
+        Jump::To(position) => return playback_play(auth, None, None, Some(position)).await,
+        Jump::By(offset) => offset,
+    };
+
+    let player_response = get_player(auth).await?;
+    let Some(ctx) = player_response.context else {
+        return Err("Not playing from a playlist or album; can't jump.".into());
+    };
+    if ctx.r#type != "playlist" && ctx.r#type != "album" {
+        return Err(format!(
+            "Can't jump within a {}; only in playlists and albums.",
+            ctx.r#type
+        )
+        .into());
+    }
+    let tracks = context_track_uris(auth, &ctx, None).await?;
+
+    let position = position_in_context(&tracks, &player_response.song).ok_or(format!(
+        "Couldn't find the current track in the {}.",
+        ctx.r#type
+    ))?;
+    let target = position as i64 + offset as i64;
+    if target < 0 || target >= tracks.len() as i64 {
+        return Err(format!(
+            "Can't jump {offset:+}: the current track is #{position} of {} tracks.",
+            tracks.len()
+        )
+        .into());
+    }
+
+    playback_play(auth, Some(&ctx.uri), None, Some(target as u16)).await
+}
+
+#[derive(Deserialize, Debug)]
+struct AlbumTracks {
+    items: Vec<Song>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AlbumDescription {
+    name: String,
+    artists: Vec<Artist>,
+    release_date: Option<String>,
+    tracks: AlbumTracks,
+}
+
+impl Display for AlbumDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let artists: Vec<&str> = self.artists.iter().map(|a| a.name.as_str()).collect();
+        write!(f, "{} - {}", self.name, artists.join(", "))?;
+        if let Some(release_date) = &self.release_date {
+            write!(f, " ({release_date})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetches an album with all of its tracks, following pagination.
+async fn get_album(
+    auth: &mut impl TokenProvider,
+    id: &str,
+) -> Result<AlbumDescription, Box<dyn error::Error>> {
+    let url = api_url(&format!("/albums/{id}"));
+
+    let client = http::client();
+    let request = client.get(url).query(&[("market", market())]);
+    let mut album: AlbumDescription = api_request(auth, request).await?;
+
+    let mut next = album.tracks.next.take();
+    while let Some(url) = next {
+        let mut album_tracks: AlbumTracks = api_request(auth, client.get(url)).await?;
+
+        album.tracks.items.append(&mut album_tracks.items);
+        next = album_tracks.next;
+    }
+
+    Ok(album)
+}
+
+/// Plays an album given as a uri/url, from the track with `track` as its
+/// number on `disc` (the first disc by default), or from the first track
+/// of `disc`.
+pub async fn album_play(
+    auth: &mut impl TokenProvider,
+    album: &str,
+    disc: Option<u32>,
+    track: Option<u32>,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = SpotifyId::parse(album)?;
+    if id.kind != "album" {
+        return Err(format!("Not an album: {album}").into());
+    }
+    let uri = id.uri();
+
+    let index = match (disc, track) {
+        (None, None) => None,
+        (disc, track) => {
+            let tracks = get_album(auth, &id.id).await?.tracks.items;
+            let disc = disc.unwrap_or(1);
+            let position = tracks.iter().position(|song| {
+                song.disc_number.unwrap_or(1) == disc
+                    && track.is_none_or(|track| song.track_number == Some(track))
+            });
+            let position = match (position, track) {
+                (Some(position), _) => position,
+                (None, Some(track)) => {
+                    return Err(format!("No track {track} on disc {disc} of the album.").into())
+                }
+                (None, None) => return Err(format!("The album has no disc {disc}.").into()),
+            };
+            Some(u16::try_from(position)?)
+        }
+    };
+
+    let before = playback_snapshot(auth).await;
+    playback_play(auth, Some(&uri), None, index).await?;
+    show_playback_change(auth, &before, ExpectedChange::Context(&uri)).await
+}
+
+async fn get_artist(
+    auth: &mut impl TokenProvider,
+    id: &str,
+) -> Result<Artist, Box<dyn error::Error>> {
+    let url = api_url(&format!("/artists/{id}"));
+
+    let client = http::client();
+    let artist: Artist = api_request(auth, client.get(url)).await?;
+
+    Ok(artist)
+}
+
+#[derive(Deserialize, Debug)]
+struct ArtistTopTracksResponse {
+    tracks: Vec<Song>,
+}
+
+async fn get_artist_top_tracks(
+    auth: &mut impl TokenProvider,
+    id: &str,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let url = api_url(&format!("/artists/{id}/top-tracks"));
+
+    let client = http::client();
+    let request = client.get(url).query(&[("market", market())]);
+    let top_tracks: ArtistTopTracksResponse = api_request(auth, request).await?;
+
+    Ok(top_tracks.tracks)
+}
+
+pub fn get_managed_playlist_id() -> Result<String, Box<dyn error::Error>> {
+    env::var("SPOTIFY_CLI_MANAGED_PLAYLIST_ID")
+        .map_err(|_| "The env variable SPOTIFY_CLI_MANAGED_PLAYLIST_ID is not set. If a managed playlist has not been created yet, run 'recommendation init'; if it has been created then set the env variable with the id of the playlist.".into())
+}
+
+pub async fn recommendation_show(
+    auth: &mut impl TokenProvider,
+    max_lines: Option<u16>,
+) -> Result<(), Box<dyn error::Error>> {
+    let managed_list = get_managed_playlist_id()?;
+
+    let playlist_description = get_playlist_from_id(auth, &managed_list).await?;
+
+    println!("{}", output::header(&playlist_description.name));
+
+    if let Some(desc) = playlist_description.description {
+        if !desc.is_empty() {
+            println!(" - {}", desc);
+        }
+    }
+
+    if let Some(tracks) = playlist_description.tracks {
+        println!();
+        tracks.print_tracks(auth, None, max_lines, None).await?;
+    } else {
+        println!("{}", tr!("\nNo songs in the list."));
+    }
+
+    Ok(())
+}
+
+pub async fn recommendation_play(
+    auth: &mut impl TokenProvider,
+    index: Option<u16>,
+) -> Result<(), Box<dyn error::Error>> {
+    let managed_list = get_managed_playlist_id()?;
+    let uri = format!("spotify:playlist:{managed_list}");
+
+    let before = playback_snapshot(auth).await;
+    playback_play(auth, Some(&uri), None, index).await?;
+    show_playback_change(auth, &before, ExpectedChange::Context(&uri)).await
+}
+
+pub async fn recommendation_save(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    name: String,
+    description: Option<String>,
+) -> Result<(), Box<dyn error::Error>> {
+    let managed_list = get_managed_playlist_id()?;
+    let playlist_description = get_playlist_from_id(auth, &managed_list).await?;
+
+    if playlist_description.tracks.is_none() {
+        return Err("No tracks in the current managed playlist.".into());
+    }
+
+    let tracks = playlist_description
+        .tracks
+        .unwrap()
+        .get_tracks(auth)
+        .await?;
+    // The list may have been generated before explicit tracks were excluded.
+    let tracks = without_explicit(config, tracks);
+
+    let playlist_create_response = create_playlist(
+        auth,
+        &name,
+        &description.unwrap_or(
+            "Playlist created by a CLI tool to save a list of recommendations.".to_string(),
+        ),
+        false,
+        false,
+    )
+    .await?;
+
+    replace_playlist_items(auth, &playlist_create_response.id, &tracks).await
+}
+
+/// Adds the currently playing track as a seed.
+async fn seed_current_track(
+    auth: &mut impl TokenProvider,
+    recommendation_parameters: &mut RecommendationParameters,
+) -> Result<(), Box<dyn error::Error>> {
+    if recommendation_parameters.seeds() >= MAX_RECOMMENDATION_SEEDS {
+        return Err(tr!("Already at the max of 5 seeds.").into());
+    }
+
+    let song = get_player(auth).await?.song;
+    if !recommendation_parameters.seed_tracks.contains(&song.id) {
+        recommendation_parameters.tracks.push(song.name);
+        recommendation_parameters.seed_tracks.push(song.id);
+    }
+
+    Ok(())
+}
+
+/// Adds the artists of the currently playing track as seeds, as many as fit.
+async fn seed_current_artists(
+    auth: &mut impl TokenProvider,
+    recommendation_parameters: &mut RecommendationParameters,
+) -> Result<(), Box<dyn error::Error>> {
+    let seeds = recommendation_parameters.seeds();
+    if seeds >= MAX_RECOMMENDATION_SEEDS {
+        return Err(tr!("Already at the max of 5 seeds.").into());
+    }
+
+    let artists: Vec<Artist> = get_player(auth)
+        .await?
+        .song
+        .artists
+        .into_iter()
+        .filter(|a| !recommendation_parameters.seed_artists.contains(&a.id))
+        .take(MAX_RECOMMENDATION_SEEDS - seeds)
+        .collect();
+    for artist in artists {
+        recommendation_parameters.artists.push(artist.name);
+        recommendation_parameters.seed_artists.push(artist.id);
+    }
+
+    Ok(())
+}
+
+/// Interactive wizard for generating recommendations into the managed
+/// playlist. The seed flags prefill the seeds from what's playing now.
+pub async fn recommendation_generate(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    seed_current: bool,
+    seed_current_artist: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    // Fail before asking anything if there's nowhere to put the results.
+    get_managed_playlist_id()?;
+
+    let mut recommendation_parameters = RecommendationParameters {
+        limit: 20,
+        ..Default::default()
+    };
+    if seed_current {
+        seed_current_track(auth, &mut recommendation_parameters).await?;
+    }
+    if seed_current_artist {
+        seed_current_artists(auth, &mut recommendation_parameters).await?;
+    }
+
+    recommendation_wizard(auth, config, recommendation_parameters).await
+}
+
+/// What a radio station is seeded from.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum RadioSeed {
+    /// The currently playing track
+    Track,
+    /// The artists of the currently playing track
+    Artist,
+}
+
+/// Starts a station from what's playing now: recommendations seeded from
+/// the current track or its artists replace the managed playlist, which is
+/// then played. With `queue`, or without a managed playlist, they're added
+/// to the queue after the current track instead.
+pub async fn radio(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    seed: RadioSeed,
+    limit: u8,
+    queue: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let managed_list = match queue {
+        true => None,
+        false => match get_managed_playlist_id() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                output::info!(
+                    "{}",
+                    tr!("No managed playlist set up; queueing the tracks instead.")
+                );
+                None
+            }
+        },
+    };
+
+    let mut recommendation_parameters = RecommendationParameters {
+        limit,
+        ..Default::default()
+    };
+    match seed {
+        RadioSeed::Track => seed_current_track(auth, &mut recommendation_parameters).await?,
+        RadioSeed::Artist => seed_current_artists(auth, &mut recommendation_parameters).await?,
+    }
+    let songs = fetch_recommendations(auth, config, &recommendation_parameters).await?;
+    if songs.is_empty() {
+        return Err("Got no recommendations to play.".into());
+    }
+
+    match managed_list {
+        Some(managed_list) => {
+            snapshot_playlist(auth, &managed_list, "radio").await?;
+            replace_playlist_items(auth, &managed_list, &songs).await?;
+            output::info!(
+                "{}",
+                tr!(
+                    "Filled the managed playlist with {count} tracks.",
+                    count = songs.len()
+                )
+            );
+            recommendation_play(auth, None).await
+        }
+        None => {
+            let uris: Vec<String> = songs.into_iter().map(|song| song.uri).collect();
+            queue_tracks(auth, &uris).await?;
+            output::info!("{}", tr!("Queued {count} tracks.", count = uris.len()));
+            Ok(())
+        }
+    }
+}
+
+/// A track in the recently played history of the account.
+#[derive(Deserialize, Debug)]
+struct PlayHistoryItem {
+    track: Song,
+}
+
+/// Uris of the last 50 tracks played on the account, the most Spotify keeps.
+async fn get_recently_played_uris(
+    auth: &mut impl TokenProvider,
+) -> Result<HashSet<String>, Box<dyn error::Error>> {
+    let client = http::client();
+    let request = client
+        .get(api_url("/me/player/recently-played"))
+        .query(&[("limit", 50)]);
+    let response: TopItemsResponse<PlayHistoryItem> = api_request(auth, request).await?;
+
+    Ok(response
+        .items
+        .into_iter()
+        .map(|item| item.track.uri)
+        .collect())
+}
+
+/// Keeps the first `keep` tracks of the managed playlist that haven't been
+/// played recently and tops the list up to `size` tracks with
+/// recommendations seeded from the kept ones.
+pub async fn recommendation_rotate(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    keep: usize,
+    size: u8,
+) -> Result<(), Box<dyn error::Error>> {
+    if keep >= size as usize {
+        return Err("--keep has to be smaller than --size to leave room for new tracks.".into());
+    }
+    let managed_list = get_managed_playlist_id()?;
+
+    let tracks = match get_playlist_from_id(auth, &managed_list).await?.tracks {
+        Some(tracks) => tracks.get_tracks(auth).await?,
+        None => Vec::new(),
+    };
+    let played = get_recently_played_uris(auth).await?;
+    let unplayed: Vec<Song> = tracks
+        .into_iter()
+        .filter(|song| song.is_addable() && !played.contains(&song.uri))
+        .collect();
+    if unplayed.is_empty() {
+        return Err(
+            "No unplayed tracks in the managed playlist to seed the recommendations from.".into(),
+        );
+    }
+    let kept: Vec<Song> = unplayed.iter().take(keep).cloned().collect();
+
+    // The kept tracks can come back as recommendations, so as many extra are
+    // asked for to still fill the list. With `--keep 0` nothing is kept, and
+    // the first unplayed tracks are the seeds.
+    let needed = size as usize - kept.len();
+    let mut recommendation_parameters = RecommendationParameters {
+        limit: size,
+        ..Default::default()
+    };
+    let seeds = match kept.is_empty() {
+        true => &unplayed,
+        false => &kept,
+    };
+    for song in seeds.iter().take(MAX_RECOMMENDATION_SEEDS) {
+        recommendation_parameters.tracks.push(song.name.clone());
+        recommendation_parameters.seed_tracks.push(song.id.clone());
+    }
+    let mut seen: HashSet<String> = kept.iter().map(|song| song.uri.clone()).collect();
+    let fresh: Vec<Song> = fetch_recommendations(auth, config, &recommendation_parameters)
+        .await?
+        .into_iter()
+        .filter(|song| seen.insert(song.uri.clone()))
+        .take(needed)
+        .collect();
+
+    let songs: Vec<Song> = kept.iter().cloned().chain(fresh.iter().cloned()).collect();
+    snapshot_playlist(auth, &managed_list, "rotate").await?;
+    replace_playlist_items(auth, &managed_list, &songs).await?;
+    output::info!(
+        "{}",
+        tr!(
+            "Kept {kept} unplayed tracks and added {added} new recommendations.",
+            kept = kept.len(),
+            added = fresh.len()
+        )
+    );
+
+    Ok(())
+}
+
+/// The `rec generate` wizard, starting from the given parameters.
+async fn recommendation_wizard(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    mut recommendation_parameters: RecommendationParameters,
+) -> Result<(), Box<dyn error::Error>> {
+    let managed_list = get_managed_playlist_id()?;
+
+    let mut genres: Option<GenreSeeds> = None;
+
+    let mut user_response: String = String::new();
+    while !user_response.starts_with("q") {
+        println!("\n***********************************\n");
+        println!("{}", tr!("Current parameters:"));
+        println!("{recommendation_parameters}\n");
+        println!(
+            "{}",
+            tr!("What would you like to edit? (Enter the number of the option)")
+        );
+        println!("{}", tr!("1 - Change the limit/number of recommendations."));
+        println!("{}", tr!("2 - Add an artist."));
+        println!("{}", tr!("3 - Add a genre."));
+        println!("{}", tr!("4 - Add a track/song."));
+        println!("{}", tr!("5 - Use my top artists as seeds."));
+        println!("{}", tr!("6 - Use what's playing now as seeds."));
+        println!("{}", tr!("7 - Clear artists."));
+        println!("{}", tr!("8 - Clear genres."));
+        println!("{}", tr!("9 - Clear tracks/songs."));
+        println!("{}", tr!("g - Generate recommendations."));
+        println!("{}", tr!("q - Quit without generating recommendations."));
+        println!();
+
+        user_response = String::new();
+        prompt::read_line(&mut user_response)?;
+        user_response = user_response.trim().to_lowercase();
+
+        match user_response.as_str() {
+            // TODO: implement all optional tuning knobs somehow
+            "1" => {
+                println!("{}", tr!("New limit? (1-100)"));
+                let mut new_limit = String::new();
+                prompt::read_line(&mut new_limit)?;
+                let parsed_limit: Result<u8, _> = new_limit.trim().parse();
+                match parsed_limit {
+                    Ok(limit) => {
+                        if limit == 0 || limit > 100 {
+                            println!("{}", tr!("Limit needs to be between 1-100."));
+                        } else {
+                            recommendation_parameters.limit = limit
+                        }
+                    }
+                    Err(e) => println!("{e}"),
+                }
+            }
+            "2" => {
+                println!("{}", tr!("Artist name?"));
+                let mut new_artist = String::new();
+                prompt::read_line(&mut new_artist)?;
+                new_artist = new_artist.trim().to_lowercase();
+                println!();
+
+                match find(auth, None, Some(&new_artist)).await {
+                    Ok(artist) => {
+                        recommendation_parameters.artists.push(artist.name);
+                        recommendation_parameters.seed_artists.push(artist.id);
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
+            "3" => {
+                if genres.is_none() {
+                    genres = Some(genre_seeds(auth).await);
+                }
+                let genres = genres.as_ref().unwrap();
+                println!("{}", tr!("Available genres:"));
+                println!("{}\n", genres.genres.join(", "));
+
+                println!("{}", tr!("Genre name?"));
+                let mut new_genre = String::new();
+                prompt::read_line(&mut new_genre)?;
+                new_genre = new_genre.trim().to_lowercase();
+
+                if !genres.genres.contains(&new_genre) {
+                    if genres.up_to_date {
+                        println!("{}", tr!("Illegal genre."));
+                        continue;
+                    }
+                    println!(
+                        "{}",
+                        tr!(
+                            "{genre} isn't in the list, which may be out of date; using it anyway.",
+                            genre = new_genre
+                        )
+                    );
+                }
+
+                recommendation_parameters.genres.push(new_genre);
+            }
+            "4" => {
+                println!("{}", tr!("Song name?"));
+                let mut new_track = String::new();
+                prompt::read_line(&mut new_track)?;
+                new_track = new_track.trim().to_lowercase();
+
+                println!();
+                println!(
+                    "{}",
+                    tr!("Do you want to specify an artist? (Empty response if not)")
+                );
+                let mut by_artist = String::new();
+                prompt::read_line(&mut by_artist)?;
+                by_artist = by_artist.trim().to_lowercase();
+                println!();
+
+                let artist: Option<&str> = if !by_artist.is_empty() {
+                    Some(&by_artist)
+                } else {
+                    None
+                };
+
+                match find(auth, Some(&new_track), artist).await {
+                    Ok(track) => {
+                        recommendation_parameters.tracks.push(track.name);
+                        recommendation_parameters.seed_tracks.push(track.id);
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
+            "5" => {
+                let seeds = recommendation_parameters.seeds();
+                if seeds >= MAX_RECOMMENDATION_SEEDS {
+                    println!("{}", tr!("Already at the max of 5 seeds."));
+                    continue;
+                }
+
+                auth.require_scopes(&[Scope::UserTopRead]).await?;
+                let top_artists: Vec<Artist> = get_top_artists(auth, TimeRange::Medium, 10)
+                    .await?
+                    .into_iter()
+                    .filter(|a| !recommendation_parameters.seed_artists.contains(&a.id))
+                    .take(MAX_RECOMMENDATION_SEEDS - seeds)
+                    .collect();
+                for artist in top_artists {
+                    recommendation_parameters.artists.push(artist.name);
+                    recommendation_parameters.seed_artists.push(artist.id);
+                }
+            }
+            "6" => {
+                println!("{}", tr!("Use the track (t) or its artists (a)?"));
+                let mut seed_type = String::new();
+                prompt::read_line(&mut seed_type)?;
+
+                let result = match seed_type.trim().to_lowercase().as_str() {
+                    "t" => seed_current_track(auth, &mut recommendation_parameters).await,
+                    "a" => seed_current_artists(auth, &mut recommendation_parameters).await,
+                    other => Err(tr!("Unrecognized option: {option}", option = other).into()),
+                };
+                if let Err(e) = result {
+                    println!("{e}");
+                }
+            }
+            "7" => {
+                recommendation_parameters.artists = Vec::new();
+                recommendation_parameters.seed_artists = Vec::new();
+            }
+            "8" => {
+                recommendation_parameters.genres = Vec::new();
+            }
+            "9" => {
+                recommendation_parameters.tracks = Vec::new();
+                recommendation_parameters.seed_tracks = Vec::new();
+            }
+            "g" => {
+                let seeds = recommendation_parameters.seeds();
+                if seeds == 0 {
+                    println!(
+                        "{}",
+                        tr!("You need to specify at least one artist or genre or track.")
+                    );
+                    continue;
+                }
+                if seeds > MAX_RECOMMENDATION_SEEDS {
+                    println!(
+                        "{}",
+                        tr!(
+                            "Too many artists & genres & tracks ({count}) specified.",
+                            count = seeds
+                        )
+                    );
+                    println!("{}", tr!("Can specify at most 5 in total."));
+                    continue;
+                }
+                let mut songs =
+                    fetch_recommendations(auth, config, &recommendation_parameters).await?;
+
+                println!("{}", tr!("Got the following recommendations:"));
+                print_recommendations(&songs);
+
+                let accepted = loop {
+                    println!();
+                    println!("{}", tr!("Accept this list or keep trying? (y to accept, N to keep trying, r to review track by track, p <number> to preview a track)"));
+                    if prompt::accept_all() {
+                        println!("{}", tr!("y"));
+                        break true;
+                    }
+                    let mut user_response = String::new();
+                    prompt::read_line(&mut user_response)?;
+                    user_response = user_response.trim().to_lowercase();
+
+                    if user_response == "r" {
+                        songs =
+                            review_recommendations(auth, config, &recommendation_parameters, songs)
+                                .await?;
+                        if songs.is_empty() {
+                            println!("{}", tr!("Dropped every track."));
+                            break false;
+                        }
+                        println!("{}", tr!("The list is now:"));
+                        print_recommendations(&songs);
+                        continue;
+                    }
+
+                    let Some(number) = user_response.strip_prefix("p") else {
+                        break prompt::is_yes(&user_response);
+                    };
+                    let song = number
+                        .trim()
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|n| n.checked_sub(1))
+                        .and_then(|i| songs.get(i));
+                    match song {
+                        Some(song) => {
+                            if let Err(e) = preview_track(auth, song).await {
+                                println!("{e}");
+                            }
+                        }
+                        None => println!(
+                            "{}",
+                            tr!("No track {number} in the list.", number = number.trim())
+                        ),
+                    }
+                };
+
+                if accepted {
+                    snapshot_playlist(auth, &managed_list, "rec generate").await?;
+                    replace_playlist_items(auth, &managed_list, &songs).await?;
+
+                    println!("{}", tr!("Added recommendations to the managed playlist."));
+                    if prompt::confirm(tr!("Start playing the list?"), true)? {
+                        recommendation_play(auth, None).await?;
+                    }
+
+                    break;
+                } else {
+                    println!("{}", tr!("Ok, going again."));
+                }
+            }
+            "q" => {
+                println!(
+                    "{}",
+                    tr!("Ok, quitting without generating recommendations.")
+                );
+                break;
+            }
+            _ => println!(
+                "{}",
+                tr!("Unrecognized command: {command}", command = user_response)
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// What to do with a browse result, picked by its number in the list.
+pub enum BrowsePick {
+    Play(usize),
+    Queue(usize),
+}
+
+#[derive(Deserialize, Debug)]
+struct NewReleasesResponse {
+    albums: TopItemsResponse<BrowseAlbum>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BrowseAlbum {
+    name: String,
+    id: String,
+    uri: String,
+    artists: Vec<Artist>,
+    release_date: Option<String>,
+}
+
+impl Display for BrowseAlbum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let artists: Vec<&str> = self.artists.iter().map(|a| a.name.as_str()).collect();
+        write!(f, "{} - {}", self.name, artists.join(", "))?;
+        if let Some(release_date) = &self.release_date {
+            write!(f, " ({release_date})")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct FeaturedPlaylistsResponse {
+    message: Option<String>,
+    playlists: TopItemsResponse<Option<Playlist>>,
+}
+
+/// A page of a paged endpoint, e.g. the `categories` or `playlists` object
+/// of the browse endpoints.
+#[derive(Deserialize, Debug)]
+struct Page<T> {
+    items: Vec<T>,
+    next: Option<String>,
+    #[serde(default)]
+    total: Option<usize>,
+}
+
+/// Fetches every page of a paged endpoint whose response has the page under
+/// `key`, or is the page itself without one, following the `next` links.
+async fn get_all_pages<T: DeserializeOwned>(
+    auth: &mut impl TokenProvider,
+    request: reqwest::RequestBuilder,
+    key: Option<&str>,
+) -> Result<Vec<T>, Box<dyn error::Error>> {
+    let unwrap_page = |mut response: Value| -> Result<Page<T>, Box<dyn error::Error>> {
+        let page = match key {
+            Some(key) => response
+                .get_mut(key)
+                .map(Value::take)
+                .ok_or(format!("No {key} in the response."))?,
+            None => response,
+        };
+        Ok(serde_json::from_value(page)?)
+    };
+
+    let first_page = unwrap_page(api_request::<Value>(auth, request).await?)?;
+    let mut items = first_page.items;
+    let mut next = first_page.next;
+    let mut progress = Progress::new("Fetching results", progress::Kind::Pages, first_page.total);
+    progress.update(items.len());
+    while let Some(url) = next {
+        let client = http::client();
+        let mut page = unwrap_page(api_request::<Value>(auth, client.get(url)).await?)?;
+        items.append(&mut page.items);
+        progress.update(items.len());
+        next = page.next;
+    }
+
+    Ok(items)
+}
+
+#[derive(Deserialize, Debug)]
+struct Category {
+    id: String,
+    name: String,
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (id: {})", self.name, self.id)
+    }
+}
+
+async fn get_categories(
+    auth: &mut impl TokenProvider,
+) -> Result<Vec<Category>, Box<dyn error::Error>> {
+    let url = api_url("/browse/categories");
+
+    let client = http::client();
+    let request = client.get(url).query(&[("limit", 50)]);
+
+    get_all_pages(auth, request, Some("categories")).await
+}
+
+/// Lists Spotify's browse categories.
+pub async fn browse_categories(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let categories = get_categories(auth).await?;
+
+    print_browse_results(tr!("Categories"), &categories);
+
+    Ok(())
+}
+
+/// Lists the playlists of a category given by its id or name, or plays or
+/// queues one of them.
+pub async fn browse_category(
+    auth: &mut impl TokenProvider,
+    id_or_name: &str,
+    pick: Option<BrowsePick>,
+) -> Result<(), Box<dyn error::Error>> {
+    let categories = get_categories(auth).await?;
+    let category = categories
+        .iter()
+        .find(|c| c.id == id_or_name)
+        .or_else(|| {
+            categories
+                .iter()
+                .find(|c| c.name.eq_ignore_ascii_case(id_or_name))
+        })
+        .ok_or(format!(
+            "No category {id_or_name}. See 'browse categories' for the categories."
+        ))?;
+
+    let url = api_url(&format!("/browse/categories/{}/playlists", category.id));
+
+    let client = http::client();
+    let request = client.get(url).query(&[("limit", 50)]);
+    // Playlists that are gone are returned as nulls.
+    let playlists: Vec<Playlist> =
+        get_all_pages::<Option<Playlist>>(auth, request, Some("playlists"))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+    match pick {
+        None => print_browse_results(&category.name, &playlists),
+        Some(pick) => play_or_queue_playlist(auth, &playlists, pick).await?,
+    }
+
+    Ok(())
+}
+
+/// Adds a track to the end of the queue.
+async fn add_to_queue(
+    auth: &mut impl TokenProvider,
+    uri: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/me/player/queue");
+
+    let client = http::client();
+    let request = client
+        .post(url)
+        .query(&[("uri", uri)])
+        .header("content-length", 0);
+    let request = on_target_device(auth, request).await?;
+    let res = send(auth, request).await?;
+
+    check_for_error_and_return_text(res).await?;
+
+    Ok(())
+}
+
+/// Adds tracks to the queue in order, showing progress for long lists.
+async fn queue_tracks(
+    auth: &mut impl TokenProvider,
+    uris: &[String],
+) -> Result<(), Box<dyn error::Error>> {
+    let uris = addable_uris(uris);
+    let mut progress = Progress::new("Queueing tracks", progress::Kind::Steps, Some(uris.len()));
+    for (i, uri) in uris.iter().enumerate() {
+        add_to_queue(auth, uri).await?;
+        progress.update(i + 1);
+    }
+
+    Ok(())
+}
+
+/// Prints a numbered list of browse results.
+fn print_browse_results<T: Display>(title: &str, items: &[T]) {
+    println!("{}", output::header(title));
+    if items.is_empty() {
+        println!("{}", tr!("Nothing to show."));
+    }
+    for (i, item) in items.iter().enumerate() {
+        println!("{}. {item}", i + 1);
+    }
+}
+
+/// The item picked by its number in a list of browse results.
+fn browse_pick<T>(items: &[T], number: usize) -> Result<&T, Box<dyn error::Error>> {
+    number
+        .checked_sub(1)
+        .and_then(|i| items.get(i))
+        .ok_or(format!("No result #{number}; there are {} results.", items.len()).into())
+}
+
+/// Lists new album releases, or plays or queues one of them.
+pub async fn browse_new_releases(
+    auth: &mut impl TokenProvider,
+    limit: u8,
+    pick: Option<BrowsePick>,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/browse/new-releases");
+
+    let client = http::client();
+    let request = client.get(url).query(&[("limit", limit)]);
+    let new_releases_response: NewReleasesResponse = api_request(auth, request).await?;
+    let albums = new_releases_response.albums.items;
+
+    match pick {
+        None => print_browse_results(tr!("New releases"), &albums),
+        Some(BrowsePick::Play(number)) => {
+            let album = browse_pick(&albums, number)?;
+            let before = playback_snapshot(auth).await;
+            playback_play(auth, Some(&album.uri), None, None).await?;
+            show_playback_change(auth, &before, ExpectedChange::Context(&album.uri)).await?;
+        }
+        Some(BrowsePick::Queue(number)) => {
+            let album = browse_pick(&albums, number)?;
+            let uris: Vec<String> = get_album(auth, &album.id)
+                .await?
+                .tracks
+                .items
+                .into_iter()
+                .map(|song| song.uri)
+                .collect();
+            queue_tracks(auth, &uris).await?;
+            output::info!(
+                "{}",
+                tr!(
+                    "Queued {count} tracks of {album}.",
+                    count = uris.len(),
+                    album = album
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists Spotify's featured playlists, or plays or queues one of them.
+pub async fn browse_featured_playlists(
+    auth: &mut impl TokenProvider,
+    limit: u8,
+    pick: Option<BrowsePick>,
+) -> Result<(), Box<dyn error::Error>> {
+    let url = api_url("/browse/featured-playlists");
+
+    let client = http::client();
+    let request = client.get(url).query(&[("limit", limit)]);
+    let featured_response: FeaturedPlaylistsResponse = api_request(auth, request).await?;
+    // Playlists that are gone are returned as nulls.
+    let playlists: Vec<Playlist> = featured_response
+        .playlists
+        .items
+        .into_iter()
+        .flatten()
+        .collect();
+
+    match pick {
+        None => print_browse_results(
+            featured_response
+                .message
+                .as_deref()
+                .unwrap_or(tr!("Featured playlists")),
+            &playlists,
+        ),
+        Some(pick) => play_or_queue_playlist(auth, &playlists, pick).await?,
+    }
+
+    Ok(())
+}
+
+async fn play_or_queue_playlist(
+    auth: &mut impl TokenProvider,
+    playlists: &[Playlist],
+    pick: BrowsePick,
+) -> Result<(), Box<dyn error::Error>> {
+    match pick {
+        BrowsePick::Play(number) => {
+            let playlist = browse_pick(playlists, number)?;
+            let before = playback_snapshot(auth).await;
+            playback_play(auth, Some(&playlist.uri), None, None).await?;
+            show_playback_change(auth, &before, ExpectedChange::Context(&playlist.uri)).await
+        }
+        BrowsePick::Queue(number) => {
+            let playlist = browse_pick(playlists, number)?;
+            let uris: Vec<String> = get_playlist_tracks(auth, &playlist.uri)
+                .await?
+                .into_iter()
+                .map(|song| song.uri)
+                .collect();
+            queue_tracks(auth, &uris).await?;
+            output::info!(
+                "{}",
+                tr!(
+                    "Queued {count} tracks of {name}.",
+                    count = uris.len(),
+                    name = playlist.name
+                )
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Fetches every show saved to the user's library.
+async fn get_saved_shows(
+    auth: &mut impl TokenProvider,
+) -> Result<Vec<Show>, Box<dyn error::Error>> {
+    let url = api_url("/me/shows");
+
+    let client = http::client();
+    let request = client.get(url).query(&[("limit", 50)]);
+    let saved: Vec<SavedShow> = get_all_pages(auth, request, None).await?;
+
+    Ok(saved.into_iter().map(|saved| saved.show).collect())
+}
+
+/// Lists the shows saved to the user's library.
+pub async fn podcast_list(auth: &mut impl TokenProvider) -> Result<(), Box<dyn error::Error>> {
+    let shows = get_saved_shows(auth).await?;
+
+    if output::tables() {
+        let mut table = Table::new(Show::HEADERS);
+        for show in &shows {
+            table.add_row(show.cells());
+        }
+        table.print();
+    } else {
+        print_browse_results(tr!("Saved shows"), &shows);
+    }
+
+    Ok(())
+}
+
+/// The id of a show given as a uri/url, the name of a saved show, or else
+/// searched for by name taking the best match.
+async fn resolve_show_id(
+    auth: &mut impl TokenProvider,
+    show: &str,
+) -> Result<String, Box<dyn error::Error>> {
+    if let Ok(id) = SpotifyId::parse(show) {
+        if id.kind != "show" {
+            return Err(format!("Not a show: {show}").into());
+        }
+        return Ok(id.id);
+    }
+
+    let saved = get_saved_shows(auth).await?;
+    if let Some(saved) = saved
+        .iter()
+        .find(|s| s.name.eq_ignore_ascii_case(show))
+        .or_else(|| {
+            saved
+                .iter()
+                .find(|s| s.name.to_lowercase().contains(&show.to_lowercase()))
+        })
+    {
+        return Ok(saved.id.clone());
+    }
+
+    let url = api_url("/search");
+
+    let client = http::client();
+    let request = client.get(url).query(&[
+        ("q", show),
+        ("type", "show"),
+        ("limit", "1"),
+        ("market", market()),
+    ]);
+    let response: Value = api_request(auth, request).await?;
+    // Shows that aren't available in the market are returned as nulls.
+    let shows: Page<Option<Show>> = serde_json::from_value(response["shows"].clone())?;
+
+    shows
+        .items
+        .into_iter()
+        .flatten()
+        .next()
+        .map(|show| show.id)
+        .ok_or(format!("Didn't find a show called {show}.").into())
+}
+
+/// Lists the latest episodes of a show, with how far each has been listened
+/// to.
+pub async fn podcast_episodes(
+    auth: &mut impl TokenProvider,
+    show: &str,
+    limit: u8,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_show_id(auth, show).await?;
+    let url = api_url(&format!("/shows/{id}/episodes"));
+
+    let client = http::client();
+    let request = client
+        .get(url)
+        .query(&[("limit", limit.to_string().as_str()), ("market", market())]);
+    // Episodes that aren't available in the market are returned as nulls.
+    let episodes: Page<Option<Episode>> = api_request(auth, request).await?;
+    let episodes: Vec<Episode> = episodes.items.into_iter().flatten().collect();
+
+    if output::tables() {
+        let mut table = Table::new(Episode::HEADERS);
+        for episode in &episodes {
+            table.add_row(episode.cells());
+        }
+        table.print();
+    } else {
+        if episodes.is_empty() {
+            println!("{}", tr!("No episodes."));
+        }
+        let width = output::line_width();
+        for episode in &episodes {
+            let line = episode.to_string();
+            match width {
+                Some(width) => println!("{}", text::truncate(&line, width)),
+                None => println!("{line}"),
+            }
+            println!("  {}", episode.uri);
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays an episode given as a uri/url, from where it was left off unless
+/// `from_start`. The episode plays within its show, so playback continues
+/// with the next episode.
+pub async fn podcast_play(
+    auth: &mut impl TokenProvider,
+    episode: &str,
+    from_start: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = SpotifyId::parse(episode)?;
+    if id.kind != "episode" {
+        return Err(format!("Not an episode: {episode}").into());
+    }
+
+    let url = api_url(&format!("/episodes/{}", id.id));
+
+    let client = http::client();
+    let request = client.get(url).query(&[("market", market())]);
+    let episode: Episode = api_request(auth, request).await?;
+
+    let position_ms = match from_start {
+        true => None,
+        false => episode.resume_position_ms(),
+    };
+    let show_uri = episode.show.as_ref().map(|show| show.uri.as_str());
+    play_track_at(auth, show_uri, &episode.uri, position_ms.unwrap_or(0)).await?;
+
+    match position_ms {
+        Some(position_ms) => output::info!(
+            "{}",
+            tr!(
+                "Resumed {name} at {position} of {duration}.",
+                name = episode.name,
+                position = format_duration(position_ms),
+                duration = format_duration(episode.duration_ms)
+            )
+        ),
+        None => output::info!("{}", tr!("Playing {name}.", name = episode.name)),
+    }
+
+    Ok(())
+}
+
+/// Prints a numbered list of recommendations, cut to the terminal width.
+fn print_recommendations(songs: &[Song]) {
+    let width = output::line_width();
+    for (i, song) in songs.iter().enumerate() {
+        println!("{}", fit_line(&format!("{}. ", i + 1), song, "", width));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Verdict {
+    Keep,
+    Drop,
+}
+
+/// Goes through the recommendations one at a time asking which to keep.
+/// Dropped tracks can be replaced with new recommendations, which are then
+/// reviewed the same way. Returns the kept tracks.
+async fn review_recommendations(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    params: &RecommendationParameters,
+    songs: Vec<Song>,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let mut seen: HashSet<String> = songs.iter().map(|song| song.id.clone()).collect();
+    let mut reviewed: Vec<(Song, Verdict)> = Vec::new();
+    let mut pending = songs;
+
+    while !pending.is_empty() {
+        let mut dropped = 0;
+        for song in std::mem::take(&mut pending) {
+            let verdict = loop {
+                println!("\n{song}");
+                println!("{}", tr!("Keep this track? (Y/n, p to preview)"));
+                let mut user_response = String::new();
+                prompt::read_line(&mut user_response)?;
+                user_response = user_response.trim().to_lowercase();
+
+                match user_response.as_str() {
+                    "" => break Verdict::Keep,
+                    "p" => {
+                        if let Err(e) = preview_track(auth, &song).await {
+                            println!("{e}");
+                        }
+                    }
+                    answer if prompt::is_yes(answer) => break Verdict::Keep,
+                    answer if prompt::is_no(answer) => break Verdict::Drop,
+                    _ => println!(
+                        "{}",
+                        tr!("Unrecognized option: {option}", option = user_response)
+                    ),
+                }
+            };
+            if verdict == Verdict::Drop {
+                dropped += 1;
+            }
+            reviewed.push((song, verdict));
+        }
+
+        if dropped == 0 {
+            break;
+        }
+        println!();
+        let question = tr!(
+            "Get replacements for the {count} dropped track(s)?",
+            count = dropped
+        );
+        if !prompt::confirm(&question, false)? {
+            break;
+        }
+
+        pending = fetch_recommendations(auth, config, params)
+            .await?
+            .into_iter()
+            .filter(|song| seen.insert(song.id.clone()))
+            .take(dropped)
+            .collect();
+        if pending.is_empty() {
+            println!("{}", tr!("No new recommendations to replace them with."));
+        }
+    }
+
+    Ok(reviewed
+        .into_iter()
+        .filter(|(_, verdict)| *verdict == Verdict::Keep)
+        .map(|(song, _)| song)
+        .collect())
+}
+
+async fn get_top_items<T: serde::de::DeserializeOwned>(
+    auth: &mut impl TokenProvider,
+    top_type: TopType,
+    range: TimeRange,
+    limit: u8,
+) -> Result<Vec<T>, Box<dyn error::Error>> {
+    let url = match top_type {
+        TopType::Tracks => api_url("/me/top/tracks"),
+        TopType::Artists => api_url("/me/top/artists"),
+    };
+
+    let client = http::client();
+    let request = client
+        .get(url)
+        .query(&[("time_range", range.as_query())])
+        .query(&[("limit", limit)]);
+    let top_response: TopItemsResponse<T> = api_request(auth, request).await?;
+
+    Ok(top_response.items)
+}
+
+async fn get_top_artists(
+    auth: &mut impl TokenProvider,
+    range: TimeRange,
+    limit: u8,
+) -> Result<Vec<Artist>, Box<dyn error::Error>> {
+    get_top_items(auth, TopType::Artists, range, limit).await
+}
+
+pub async fn top_show(
+    auth: &mut impl TokenProvider,
+    top_type: TopType,
+    range: TimeRange,
+    limit: u8,
+) -> Result<(), Box<dyn error::Error>> {
+    let lines: Vec<String> = match top_type {
+        TopType::Tracks => get_top_items::<Song>(auth, top_type, range, limit)
+            .await?
+            .iter()
+            .map(|song| song.to_string())
+            .collect(),
+        TopType::Artists => get_top_artists(auth, range, limit)
+            .await?
+            .iter()
+            .map(|artist| artist.to_string())
+            .collect(),
+    };
+
+    let width = output::line_width();
+    for (ind, line) in lines.iter().enumerate() {
+        let line = format!("#{} {}", ind + 1, line);
+        match width {
+            Some(width) => println!("{}", text::truncate(&line, width)),
+            None => println!("{line}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats milliseconds as e.g. `12h 5m`.
+fn format_listening_time(ms: u64) -> String {
+    let minutes = ms / 60_000;
+    match minutes / 60 {
+        0 => format!("{minutes}m"),
+        hours => format!("{hours}h {}m", minutes % 60),
+    }
+}
+
+/// The alias name without the `@` it's used with.
+fn alias_name(name: &str) -> Result<&str, Box<dyn error::Error>> {
+    let name = name.strip_prefix('@').unwrap_or(name);
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Bad alias name {name}; use letters, digits, - and _.").into());
+    }
+
+    Ok(name)
+}
+
+pub fn alias_add(kind: AliasKind, name: &str, target: &str) -> Result<(), Box<dyn error::Error>> {
+    let name = alias_name(name)?;
+    config::set_alias(kind, name, target)?;
+    output::info!(
+        "{}",
+        tr!("@{name} now means {target}.", name = name, target = target)
+    );
+
+    Ok(())
+}
+
+/// Removes the alias of the given kind, or of either kind if not given.
+pub fn alias_remove(kind: Option<AliasKind>, name: &str) -> Result<(), Box<dyn error::Error>> {
+    let name = alias_name(name)?;
+    let kinds = match kind {
+        Some(kind) => vec![kind],
+        None => vec![AliasKind::Uri, AliasKind::Device],
+    };
+    let mut removed = false;
+    for kind in kinds {
+        removed |= config::remove_alias(kind, name)?;
+    }
+    if !removed {
+        return Err(format!("No alias named @{name}.").into());
+    }
+    output::info!("{}", tr!("Removed @{name}.", name = name));
+
+    Ok(())
+}
+
+pub fn alias_list(config: &Config) -> Result<(), Box<dyn error::Error>> {
+    if config.alias.is_empty() && config.device.is_empty() {
+        println!(
+            "{}",
+            tr!("No aliases. Add one with `spotify-cli alias add <name> <uri>`.")
+        );
+        return Ok(());
+    }
+
+    for (title, aliases) in [
+        (tr!("Uris"), &config.alias),
+        (tr!("Devices"), &config.device),
+    ] {
+        if aliases.is_empty() {
+            continue;
+        }
+        println!("{}", output::header(title));
+        for (name, target) in aliases {
+            println!("@{name} = {target}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the total listening time and the top artists of the period from
+/// the local listening history.
+pub fn history_stats(
+    config: &Config,
+    period: history::Period,
+    limit: usize,
+) -> Result<(), Box<dyn error::Error>> {
+    let filepath = history::history_path(&config.history)?;
+    if !fs::exists(&filepath)? {
+        return Err(format!(
+            "No listening history in {filepath}. Enable it with `enabled = true` under [history] in the config and run `watch`."
+        )
+        .into());
+    }
+
+    let history = History::open(&filepath)?;
+    let since = period.start();
+    let (plays, listened_ms) = history.totals(since)?;
+    println!(
+        "{}",
+        tr!(
+            "{plays} tracks played, {listened} listened.",
+            plays = plays,
+            listened = format_listening_time(listened_ms)
+        )
+    );
+
+    let artists = history.top_artists(since, limit)?;
+    if !artists.is_empty() {
+        println!();
+        println!("{}", output::header(tr!("Top artists")));
+    }
+    for (ind, artist) in artists.iter().enumerate() {
+        println!(
+            "{}",
+            tr!(
+                "#{position} {artist} ({plays} plays, {listened})",
+                position = ind + 1,
+                artist = artist.artist,
+                plays = artist.plays,
+                listened = format_listening_time(artist.listened_ms)
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// Genres seeds are refetched after this long.
+const GENRE_SEEDS_MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// The genres recommendations can be seeded with.
+struct GenreSeeds {
+    genres: Vec<String>,
+    /// False when Spotify couldn't be asked and the list is an old cached one
+    /// or the built-in one, so unknown genres can't be ruled out.
+    up_to_date: bool,
+}
+
+/// The genre seeds from the cache, or Spotify when the cache is old. When
+/// the endpoint fails, an old cached list or the built-in one is used.
+async fn genre_seeds(auth: &mut impl TokenProvider) -> GenreSeeds {
+    let mut cache = cache::load();
+    if let Some(cached) = &cache.genre_seeds {
+        if cached.age_secs() < GENRE_SEEDS_MAX_AGE_SECS {
+            return GenreSeeds {
+                genres: cached.genres.clone(),
+                up_to_date: true,
+            };
+        }
+    }
+
+    match get_available_genres(auth).await {
+        Ok(genres) => {
+            if let Ok(fetched_at) = current_time_secs_from_epoch() {
+                cache.genre_seeds = Some(cache::GenreSeeds {
+                    fetched_at,
+                    genres: genres.clone(),
+                });
+                // Only a cache; the genres were fetched either way.
+                let _ = cache::save(&cache);
+            }
+            GenreSeeds {
+                genres,
+                up_to_date: true,
+            }
+        }
+        Err(e) => {
+            println!("{}", tr!("Failed to fetch the genres: {error}", error = e));
+            let genres = match cache.genre_seeds {
+                Some(cached) => cached.genres,
+                None => FALLBACK_GENRE_SEEDS.iter().map(|g| g.to_string()).collect(),
+            };
+            GenreSeeds {
+                genres,
+                up_to_date: false,
+            }
+        }
+    }
+}
+
+async fn get_available_genres(
+    auth: &mut impl TokenProvider,
+) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let url = api_url("/recommendations/available-genre-seeds");
+
+    let client = http::client();
+    let genres_response: GenresResponse = api_request(auth, client.get(url)).await?;
+
+    Ok(genres_response.genres)
+}
+
+async fn replace_playlist_items(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    tracks: &[Song],
+) -> Result<(), Box<dyn error::Error>> {
+    let uris: Vec<String> = tracks.iter().map(|song| song.uri.to_owned()).collect();
+
+    replace_playlist_uris(auth, playlist_id, &uris).await?;
+
+    Ok(())
+}
+
+/// Replaces all items of a playlist. A replace accepts at most 100 uris, so
+/// the rest are appended afterwards in batches of 100. The uris go in the
+/// body since long lists don't fit in a url. Returns the playlist's new
+/// snapshot id.
+async fn replace_playlist_uris(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    uris: &[String],
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let url = api_url(&format!("/playlists/{playlist_id}/tracks"));
+    let uris = addable_uris(uris);
+    let (first, rest) = uris.split_at(uris.len().min(100));
+
+    let client = http::client();
+    let mut map = HashMap::new();
+    map.insert("uris", first);
+    let res = send(auth, client.put(url).json(&map)).await?;
+
+    let snapshot_id = snapshot_id_of(&check_for_error_and_return_text(res).await?);
+    if rest.is_empty() {
+        return Ok(snapshot_id);
+    }
+
+    // The replace already went through, so don't leave a cut short playlist
+    // looking like a plain failure.
+    match add_playlist_items(auth, playlist_id, rest).await {
+        Ok(appended) => Ok(appended.or(snapshot_id)),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                tr!(
+                    "The playlist was replaced with the first {first} tracks, but adding the other {rest} failed.",
+                    first = first.len(),
+                    rest = rest.len()
+                )
+            );
+            Err(e)
+        }
+    }
+}
+
+/// Stores the items of a playlist before it's changed, see `playlist undo`.
+fn record_snapshot(
+    playlist_id: &str,
+    reason: &str,
+    snapshot_id: Option<String>,
+    uris: Vec<String>,
+) -> Result<(), Box<dyn error::Error>> {
+    let snapshot = snapshots::Snapshot {
+        taken_at: current_time_secs_from_epoch()?,
+        reason: reason.to_string(),
+        snapshot_id,
+        uris,
+    };
+
+    snapshots::push(playlist_id, snapshot)
+}
+
+/// Fetches a playlist and records a snapshot of it.
+async fn snapshot_playlist(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    reason: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let playlist_description = get_playlist_from_id(auth, playlist_id).await?;
+    let uris = match playlist_description.tracks {
+        Some(tracks) => tracks
+            .get_all_items(auth)
+            .await?
+            .into_iter()
+            .map(|item| item.track.uri)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    record_snapshot(playlist_id, reason, playlist_description.snapshot_id, uris)
+}
+
+#[derive(Deserialize, Debug)]
+struct SnapshotIdResponse {
+    snapshot_id: String,
+}
+
+/// The playlist's new snapshot id from the response to a change.
+fn snapshot_id_of(response_text: &str) -> Option<String> {
+    serde_json::from_str::<SnapshotIdResponse>(response_text)
+        .ok()
+        .map(|response| response.snapshot_id)
+}
+
+/// Appends tracks to the end of a playlist. The API accepts at most 100
+/// uris per request so the uris are sent in batches. Returns the playlist's
+/// new snapshot id.
+async fn add_playlist_items(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    uris: &[String],
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let url = api_url(&format!("/playlists/{playlist_id}/tracks"));
+    let uris = addable_uris(uris);
+
+    let mut snapshot_id = None;
+    let mut done = 0;
+    for chunk in uris.chunks(100) {
+        let client = http::client();
+        let mut map = HashMap::new();
+        map.insert("uris", chunk);
+        let res = send(auth, client.post(&url).json(&map)).await?;
+
+        snapshot_id = snapshot_id_of(&check_for_error_and_return_text(res).await?);
+        done += chunk.len();
+        progress::report(&progress::Event::TracksAdded {
+            playlist: playlist_id,
+            done,
+            total: uris.len(),
+        });
+    }
+
+    Ok(snapshot_id)
+}
+
+/// The uris without the ones of local files and tracks gone from Spotify,
+/// which the API doesn't accept, telling how many were left out.
+fn addable_uris(uris: &[String]) -> Vec<String> {
+    let addable: Vec<String> = uris
+        .iter()
+        .filter(|uri| models::is_addable_uri(uri))
+        .cloned()
+        .collect();
+    if addable.len() < uris.len() {
+        output::info!(
+            "{}",
+            tr!(
+                "Skipped {count} local file(s) or unavailable track(s); they can't be added through the API.",
+                count = uris.len() - addable.len()
+            )
+        );
+    }
+
+    addable
+}
+
+/// Removes all occurrences of the given uris from a playlist. Like adding,
+/// removing is limited to 100 uris per request. With `snapshot_id`, the
+/// first batch fails if the playlist has changed since that version.
+/// Returns the playlist's new snapshot id.
+async fn remove_playlist_items(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    uris: &[String],
+    snapshot_id: Option<&str>,
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let url = api_url(&format!("/playlists/{playlist_id}/tracks"));
+
+    let mut snapshot_id = snapshot_id.map(str::to_string);
+    for chunk in uris.chunks(100) {
+        let client = http::client();
+        let tracks: Vec<HashMap<&str, &str>> = chunk
+            .iter()
+            .map(|uri| HashMap::from([("uri", uri.as_str())]))
+            .collect();
+        let mut map = serde_json::Map::new();
+        map.insert("tracks".to_string(), serde_json::to_value(tracks)?);
+        if let Some(snapshot_id) = &snapshot_id {
+            map.insert("snapshot_id".to_string(), Value::from(snapshot_id.as_str()));
+        }
+        let request = client.delete(&url).json(&map);
+        let res = send(auth, request).await?;
+
+        snapshot_id = snapshot_id_of(&check_for_error_and_return_text(res).await?);
+    }
+
+    Ok(snapshot_id)
+}
+
+/// Drops repeated tracks, keeping the first occurrence of each uri.
+fn dedup_tracks(tracks: Vec<Song>) -> Vec<Song> {
+    let mut seen = HashSet::new();
+    tracks
+        .into_iter()
+        .filter(|track| seen.insert(track.uri.clone()))
+        .collect()
+}
+
+pub async fn playlist_copy(
+    auth: &mut impl TokenProvider,
+    src: &str,
+    name: &str,
+    collaborative: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let tracks = dedup_tracks(get_playlist_tracks(auth, src).await?);
+
+    let description = format!("Playlist created by a CLI tool as a copy of {src}.");
+    let playlist_create_response =
+        create_playlist(auth, name, &description, false, collaborative).await?;
+    let uris: Vec<String> = tracks.iter().map(|song| song.uri.to_owned()).collect();
+    add_playlist_items(auth, &playlist_create_response.id, &uris).await?;
+
+    println!(
+        "{}",
+        tr!(
+            "Created playlist {name} with {count} tracks. uri: spotify:playlist:{id}",
+            name = name,
+            count = tracks.len(),
+            id = playlist_create_response.id
+        )
+    );
+
+    Ok(())
+}
+
+pub async fn playlist_merge(
+    auth: &mut impl TokenProvider,
+    a: &str,
+    b: &str,
+    into: &str,
+    collaborative: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut tracks = get_playlist_tracks(auth, a).await?;
+    tracks.append(&mut get_playlist_tracks(auth, b).await?);
+    let tracks = dedup_tracks(tracks);
+
+    let description = format!("Playlist created by a CLI tool by merging {a} and {b}.");
+    let playlist_create_response =
+        create_playlist(auth, into, &description, false, collaborative).await?;
+    let uris: Vec<String> = tracks.iter().map(|song| song.uri.to_owned()).collect();
+    add_playlist_items(auth, &playlist_create_response.id, &uris).await?;
+
+    println!(
+        "{}",
+        tr!(
+            "Created playlist {into} with {count} tracks. uri: spotify:playlist:{id}",
+            into = into,
+            count = tracks.len(),
+            id = playlist_create_response.id
+        )
+    );
+
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct FullArtist {
+    id: String,
+    #[serde(default)]
+    genres: Vec<String>,
+}
+
+/// How long genres of an artist are cached.
+const ARTIST_GENRES_MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Max number of ids the several tracks/artists endpoints take at once.
+const TRACKS_BATCH_SIZE: usize = 50;
+const ARTISTS_BATCH_SIZE: usize = 50;
+/// The audio features endpoint takes more.
+const AUDIO_FEATURES_BATCH_SIZE: usize = 100;
+
+/// Fetches objects from one of the "several" endpoints, e.g.
+/// `/tracks?ids=...`, which return `{key: [object or null]}`, in batches of
+/// `batch_size` ids. Unknown ids are skipped.
+async fn get_several<T: DeserializeOwned>(
+    auth: &mut impl TokenProvider,
+    path: &str,
+    key: &str,
+    ids: &[&str],
+    batch_size: usize,
+    label: &'static str,
+) -> Result<Vec<T>, Box<dyn error::Error>> {
+    let client = http::client();
+    let mut objects = Vec::with_capacity(ids.len());
+    let mut progress = Progress::new(label, progress::Kind::Pages, Some(ids.len()));
+    let mut done = 0;
+    for batch in ids.chunks(batch_size) {
+        let request = client.get(api_url(path)).query(&[("ids", batch.join(","))]);
+        let mut response: HashMap<String, Vec<Option<T>>> = api_request(auth, request).await?;
+        let batch_objects = response
+            .remove(key)
+            .ok_or(format!("The response of {path} has no {key}."))?;
+        objects.extend(batch_objects.into_iter().flatten());
+        // Ids that weren't found count too, so the progress reaches the end.
+        done += batch.len();
+        progress.update(done);
+    }
+
+    Ok(objects)
+}
+
+async fn get_several_tracks(
+    auth: &mut impl TokenProvider,
+    ids: &[&str],
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    get_several(
+        auth,
+        "/tracks",
+        "tracks",
+        ids,
+        TRACKS_BATCH_SIZE,
+        "Fetching tracks",
+    )
+    .await
+}
+
+async fn get_several_artists(
+    auth: &mut impl TokenProvider,
+    ids: &[&str],
+) -> Result<Vec<FullArtist>, Box<dyn error::Error>> {
+    get_several(
+        auth,
+        "/artists",
+        "artists",
+        ids,
+        ARTISTS_BATCH_SIZE,
+        "Fetching genres",
+    )
+    .await
+}
+
+#[derive(Deserialize, Debug)]
+struct AudioFeatures {
+    id: String,
+    tempo: Option<f64>,
+    energy: Option<f64>,
+    danceability: Option<f64>,
+    valence: Option<f64>,
+}
+
+async fn get_audio_features(
+    auth: &mut impl TokenProvider,
+    ids: &[&str],
+) -> Result<Vec<AudioFeatures>, Box<dyn error::Error>> {
+    get_several(
+        auth,
+        "/audio-features",
+        "audio_features",
+        ids,
+        AUDIO_FEATURES_BATCH_SIZE,
+        "Fetching audio features",
+    )
+    .await
+}
+
+/// Looks up the genres of the artists, using the cache where possible and
+/// fetching the rest in batches.
+async fn get_artist_genres(
+    auth: &mut impl TokenProvider,
+    artist_ids: &[&str],
+) -> Result<HashMap<String, Vec<String>>, Box<dyn error::Error>> {
+    let mut cache = cache::load();
+    let mut seen = HashSet::new();
+    let missing: Vec<&str> = artist_ids
+        .iter()
+        .copied()
+        .filter(|id| seen.insert(*id))
+        .filter(|id| {
+            cache
+                .artist_genres
+                .get(*id)
+                .is_none_or(|cached| cached.age_secs() > ARTIST_GENRES_MAX_AGE_SECS)
+        })
+        .collect();
+
+    let fetched_at = current_time_secs_from_epoch()?;
+    for artist in get_several_artists(auth, &missing).await? {
+        cache.artist_genres.insert(
+            artist.id,
+            ArtistGenres {
+                fetched_at,
+                genres: artist.genres,
+            },
+        );
+    }
+    if !missing.is_empty() {
+        // Failing to cache only makes the next lookup slower.
+        let _ = cache::save(&cache);
+    }
+
+    Ok(artist_ids
+        .iter()
+        .filter_map(|id| {
+            let cached = cache.artist_genres.get(*id)?;
+            Some((id.to_string(), cached.genres.clone()))
+        })
+        .collect())
+}
+
+/// Creates a playlist of the tracks of `src` by artists with a genre
+/// containing any of `genres`, e.g. `rock` also matches `indie rock`.
+pub async fn playlist_filter(
+    auth: &mut impl TokenProvider,
+    src: &str,
+    genres: &[String],
+    into: &str,
+    collaborative: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, src).await?;
+    let tracks = dedup_tracks(get_playlist_tracks(auth, &format!("spotify:playlist:{id}")).await?);
+
+    let artist_ids: Vec<&str> = tracks
+        .iter()
+        .flat_map(|song| song.artists.iter().map(|artist| artist.id.as_str()))
+        // Artists of local files have no id.
+        .filter(|id| !id.is_empty())
+        .collect();
+    let artist_genres = get_artist_genres(auth, &artist_ids).await?;
+
+    let wanted: Vec<String> = genres.iter().map(|genre| genre.to_lowercase()).collect();
+    let matches_genre = |artist: &Artist| {
+        artist_genres.get(&artist.id).is_some_and(|artist_genres| {
+            artist_genres.iter().any(|genre| {
+                wanted
+                    .iter()
+                    .any(|wanted| genre.to_lowercase().contains(wanted))
+            })
+        })
+    };
+    let uris: Vec<String> = tracks
+        .iter()
+        .filter(|song| song.artists.iter().any(matches_genre))
+        .map(|song| song.uri.to_owned())
+        .collect();
+    if uris.is_empty() {
+        return Err(format!(
+            "None of the tracks are in the genre(s) {}.",
+            genres.join(", ")
+        )
+        .into());
+    }
+
+    let description = format!(
+        "Playlist created by a CLI tool from the {} tracks of {src}.",
+        genres.join("/")
+    );
+    let playlist_create_response =
+        create_playlist(auth, into, &description, false, collaborative).await?;
+    add_playlist_items(auth, &playlist_create_response.id, &uris).await?;
+
+    println!(
+        "{}",
+        tr!(
+            "Created playlist {into} with {count} of the {total} tracks. uri: spotify:playlist:{id}",
+            into = into,
+            count = uris.len(),
+            total = tracks.len(),
+            id = playlist_create_response.id
+        )
+    );
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    M3u,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+struct ExportedTrack {
+    name: String,
+    artists: Vec<String>,
+    album: Option<String>,
+    uri: Option<String>,
+    duration_ms: Option<u64>,
+    added_at: Option<String>,
+    /// Only with `--audio-features`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tempo: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    energy: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    danceability: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    valence: Option<f64>,
+}
+
+impl From<TrackItem> for ExportedTrack {
+    fn from(item: TrackItem) -> Self {
+        ExportedTrack {
+            name: item.track.name,
+            artists: item.track.artists.into_iter().map(|a| a.name).collect(),
+            album: item.track.album.map(|a| a.name),
+            uri: Some(item.track.uri),
+            duration_ms: Some(item.track.duration_ms),
+            added_at: item.added_at,
+            tempo: None,
+            energy: None,
+            danceability: None,
+            valence: None,
+        }
+    }
+}
+
+const CSV_HEADER: &str = "name,artists,album,uri,duration_ms,added_at";
+const CSV_AUDIO_FEATURES_HEADER: &str = "tempo,energy,danceability,valence";
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_export(
+    tracks: &[ExportedTrack],
+    format: ExportFormat,
+    audio_features: bool,
+) -> Result<String, Box<dyn error::Error>> {
+    let out = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(tracks)? + "\n",
+        ExportFormat::Csv => {
+            let mut out = match audio_features {
+                true => format!("{CSV_HEADER},{CSV_AUDIO_FEATURES_HEADER}\n"),
+                false => format!("{CSV_HEADER}\n"),
+            };
+            let number = |n: Option<f64>| n.map(|n| n.to_string()).unwrap_or_default();
+            for track in tracks {
+                let mut fields = vec![
+                    track.name.clone(),
+                    track.artists.join("; "),
+                    track.album.clone().unwrap_or_default(),
+                    track.uri.clone().unwrap_or_default(),
+                    track.duration_ms.map(|d| d.to_string()).unwrap_or_default(),
+                    track.added_at.clone().unwrap_or_default(),
+                ];
+                if audio_features {
+                    fields.extend([
+                        number(track.tempo),
+                        number(track.energy),
+                        number(track.danceability),
+                        number(track.valence),
+                    ]);
+                }
+                let line: Vec<String> = fields.iter().map(|f| csv_escape(f)).collect();
+                out += &line.join(",");
+                out += "\n";
+            }
+            out
+        }
+        ExportFormat::M3u => {
+            let mut out = "#EXTM3U\n".to_string();
+            for track in tracks {
+                let secs = track.duration_ms.map(|d| (d / 1000) as i64).unwrap_or(-1);
+                out += &format!(
+                    "#EXTINF:{secs},{} - {}\n",
+                    track.artists.join(", "),
+                    track.name
+                );
+                out += track.uri.as_deref().unwrap_or_default();
+                out += "\n";
+            }
+            out
+        }
+    };
+
+    Ok(out)
+}
+
+pub async fn playlist_export(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
+    format: ExportFormat,
+    output: Option<&str>,
+    mut audio_features: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, uri_or_name).await?;
+    let playlist_description = get_playlist_from_id(auth, &id).await?;
+
+    // A backup, so unplayable tracks are kept too.
+    let items = match playlist_description.tracks {
+        Some(tracks) => tracks.get_all_items(auth).await?,
+        None => Vec::new(),
+    };
+    let mut tracks: Vec<ExportedTrack> = items.into_iter().map(ExportedTrack::from).collect();
+
+    if audio_features {
+        let ids: Vec<&str> = tracks
+            .iter()
+            .filter_map(|track| track.uri.as_deref()?.strip_prefix("spotify:track:"))
+            .collect();
+        let features: HashMap<String, AudioFeatures> = match get_audio_features(auth, &ids).await {
+            Ok(features) => features
+                .into_iter()
+                .map(|features| (features.id.clone(), features))
+                .collect(),
+            // Spotify has deprecated the endpoint for new apps.
+            Err(e) if is_endpoint_unavailable_error(e.as_ref()) => {
+                eprintln!(
+                    "{}",
+                    tr!("Audio features aren't available to this app; exporting without them.")
+                );
+                audio_features = false;
+                HashMap::new()
+            }
+            Err(e) => return Err(e),
+        };
+        for track in tracks.iter_mut() {
+            let id = track
+                .uri
+                .as_deref()
+                .and_then(|uri| uri.strip_prefix("spotify:track:"));
+            if let Some(features) = id.and_then(|id| features.get(id)) {
+                track.tempo = features.tempo;
+                track.energy = features.energy;
+                track.danceability = features.danceability;
+                track.valence = features.valence;
+            }
+        }
+    }
+
+    let out = format_export(&tracks, format, audio_features)?;
+    match output {
+        Some(filepath) => {
+            fs::write(filepath, out)?;
+            output::info!(
+                "{}",
+                tr!(
+                    "Exported {count} tracks from {name} to {filepath}.",
+                    count = tracks.len(),
+                    name = playlist_description.name,
+                    filepath = filepath
+                )
+            );
+        }
+        None => print!("{out}"),
+    }
+
+    Ok(())
+}
+
+/// Prints the total duration, explicit count, average audio features and the
+/// artist and decade distributions of a playlist.
+pub async fn playlist_stats(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
+    limit: usize,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, uri_or_name).await?;
+    let playlist_description = get_playlist_from_id(auth, &id).await?;
+    let tracks: Vec<Song> = match playlist_description.tracks {
+        Some(tracks) => tracks.get_items(auth).await?,
+        None => Vec::new(),
+    }
+    .into_iter()
+    .map(|item| item.track)
+    .collect();
+
+    let total_ms: u64 = tracks.iter().map(|track| track.duration_ms).sum();
+    println!(
+        "{}",
+        tr!(
+            "{name}: {count} tracks, {duration}.",
+            name = output::header(&playlist_description.name),
+            count = tracks.len(),
+            duration = format_listening_time(total_ms)
+        )
+    );
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    let explicit = tracks.iter().filter(|track| track.explicit).count();
+    println!(
+        "{}",
+        tr!("{explicit} explicit tracks.", explicit = explicit)
+    );
+
+    // Local files have no id.
+    let ids: Vec<&str> = tracks
+        .iter()
+        .map(|track| track.id.as_str())
+        .filter(|id| !id.is_empty())
+        .collect();
+    match get_audio_features(auth, &ids).await {
+        Ok(features) if !features.is_empty() => {
+            let average = |value: fn(&AudioFeatures) -> Option<f64>| {
+                let values: Vec<f64> = features.iter().filter_map(value).collect();
+                values.iter().sum::<f64>() / values.len().max(1) as f64
+            };
+            println!(
+                "{}",
+                tr!(
+                    "Average tempo {tempo} BPM, energy {energy}, danceability {danceability}, valence {valence}.",
+                    tempo = format!("{:.0}", average(|f| f.tempo)),
+                    energy = format!("{:.2}", average(|f| f.energy)),
+                    danceability = format!("{:.2}", average(|f| f.danceability)),
+                    valence = format!("{:.2}", average(|f| f.valence))
+                )
+            );
+        }
+        Ok(_) => {}
+        // Spotify has deprecated the endpoint for new apps.
+        Err(e) if is_endpoint_unavailable_error(e.as_ref()) => {
+            println!("{}", tr!("Audio features aren't available to this app."))
+        }
+        Err(e) => return Err(e),
+    }
+
+    let mut artists: HashMap<&str, usize> = HashMap::new();
+    for artist in tracks.iter().flat_map(|track| track.artists.iter()) {
+        *artists.entry(artist.name.as_str()).or_default() += 1;
+    }
+    let mut artists: Vec<(&str, usize)> = artists.into_iter().collect();
+    artists.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    println!();
+    println!("{}", output::header(tr!("Top artists")));
+    for (ind, (artist, count)) in artists.iter().take(limit).enumerate() {
+        println!(
+            "{}",
+            tr!(
+                "#{position} {artist} ({count} tracks)",
+                position = ind + 1,
+                artist = artist,
+                count = count
+            )
+        );
+    }
+
+    let mut decades: BTreeMap<u32, usize> = BTreeMap::new();
+    for track in tracks.iter() {
+        let year = track
+            .album
+            .as_ref()
+            .and_then(|album| album.release_date.as_deref()?.get(..4)?.parse::<u32>().ok());
+        if let Some(year) = year {
+            *decades.entry(year / 10 * 10).or_default() += 1;
+        }
+    }
+    if !decades.is_empty() {
+        println!();
+        println!("{}", output::header(tr!("Decades")));
+    }
+    for (decade, count) in decades {
+        println!(
+            "{}",
+            tr!("{decade}s: {count} tracks", decade = decade, count = count)
+        );
+    }
+
+    Ok(())
+}
+
+/// Parses CSV text into rows of fields. Handles quoted fields containing
+/// commas, escaped quotes, and newlines.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            ('"', false) => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+fn parse_csv_export(text: &str) -> Result<Vec<ExportedTrack>, Box<dyn error::Error>> {
+    let mut rows = parse_csv(text).into_iter();
+    let header = rows.next().ok_or("The CSV file is empty.")?;
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let name_col = column("name").ok_or("The CSV file is missing the column: name")?;
+    let (artists_col, album_col, uri_col, duration_col, added_col) = (
+        column("artists"),
+        column("album"),
+        column("uri"),
+        column("duration_ms"),
+        column("added_at"),
+    );
+
+    let non_empty = |row: &Vec<String>, col: Option<usize>| {
+        col.and_then(|col| row.get(col))
+            .filter(|f| !f.is_empty())
+            .cloned()
+    };
+
+    let tracks = rows
+        .filter(|row| row.iter().any(|f| !f.is_empty()))
+        .map(|row| ExportedTrack {
+            name: row.get(name_col).cloned().unwrap_or_default(),
+            artists: non_empty(&row, artists_col)
+                .map(|a| a.split("; ").map(|a| a.to_string()).collect())
+                .unwrap_or_default(),
+            album: non_empty(&row, album_col),
+            uri: non_empty(&row, uri_col),
+            duration_ms: non_empty(&row, duration_col).and_then(|d| d.parse().ok()),
+            added_at: non_empty(&row, added_col),
+            tempo: None,
+            energy: None,
+            danceability: None,
+            valence: None,
+        })
+        .collect();
+
+    Ok(tracks)
+}
+
+/// Searches for a track by name (and artist) and returns the uri of the top
+/// result, if any.
+async fn search_track_uri(
+    auth: &mut impl TokenProvider,
+    name: &str,
+    artist: Option<&str>,
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let url = api_url("/search");
+
+    let query = match artist {
+        Some(artist) => format!("track:{name} artist:{artist}"),
+        None => format!("track:{name}"),
+    };
+
+    let client = http::client();
+    let request =
+        client
+            .get(url)
+            .query(&[("q", query.as_str()), ("type", "track"), ("limit", "1")]);
+    let find_response: FindResponse = api_request(auth, request).await?;
+
+    Ok(find_response
+        .tracks
+        .and_then(|t| t.items.into_iter().next())
+        .map(|song| song.uri))
+}
+
+pub async fn playlist_import(
+    auth: &mut impl TokenProvider,
+    filepath: &str,
+    name: Option<String>,
+    collaborative: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let tracks = read_export(filepath)?;
+    let (uris, unresolved) = resolve_exported_tracks(auth, &tracks).await?;
+
+    let name = name.unwrap_or_else(|| {
+        std::path::Path::new(filepath)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or(filepath.to_string())
+    });
+    let description = format!("Playlist created by a CLI tool by importing {filepath}.");
+    let playlist_create_response =
+        create_playlist(auth, &name, &description, false, collaborative).await?;
+    add_playlist_items(auth, &playlist_create_response.id, &uris).await?;
+
+    println!(
+        "{}",
+        tr!(
+            "Created playlist {name} with {count} tracks. uri: spotify:playlist:{id}",
+            name = name,
+            count = uris.len(),
+            id = playlist_create_response.id
+        )
+    );
+
+    print_unresolved(&unresolved);
+
+    Ok(())
+}
+
+/// Reads the tracks of a JSON or CSV file written by `playlist export`. The
+/// format is told by the extension, or else by whether the file starts like
+/// JSON.
+fn read_export(filepath: &str) -> Result<Vec<ExportedTrack>, Box<dyn error::Error>> {
+    let text =
+        fs::read_to_string(filepath).map_err(|_| format!("Failed to read file {}", filepath))?;
+
+    let extension = std::path::Path::new(filepath)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    let is_json = match extension.as_deref() {
+        Some("json") => true,
+        Some("csv") => false,
+        _ => text.trim_start().starts_with(['[', '{']),
+    };
+    match is_json {
+        true => serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {filepath} as JSON: {e}").into()),
+        false => parse_csv_export(&text),
+    }
+}
+
+/// The uris of exported tracks; tracks without one are searched for by name
+/// and artist. Tracks that aren't found are returned separately.
+async fn resolve_exported_tracks<'a>(
+    auth: &mut impl TokenProvider,
+    tracks: &'a [ExportedTrack],
+) -> Result<(Vec<String>, Vec<&'a ExportedTrack>), Box<dyn error::Error>> {
+    let mut uris = Vec::new();
+    let mut unresolved = Vec::new();
+    for track in tracks.iter() {
+        if let Some(uri) = &track.uri {
+            uris.push(uri.clone());
+            continue;
+        }
+
+        match search_track_uri(auth, &track.name, track.artists.first().map(|a| a.as_str())).await?
+        {
+            Some(uri) => uris.push(uri),
+            None => unresolved.push(track),
+        }
+    }
+
+    Ok((uris, unresolved))
+}
+
+fn print_unresolved(unresolved: &[&ExportedTrack]) {
+    if !unresolved.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "\nCould not resolve the following {count} tracks:",
+                count = unresolved.len()
+            )
+        );
+        for track in unresolved {
+            println!("{} - {}", track.name, track.artists.join(", "));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum SortBy {
+    Added,
+    Name,
+    Artist,
+    Album,
+    Duration,
+    ReleaseDate,
+}
+
+fn sort_items(items: &mut [TrackItem], by: SortBy) {
+    let lowercase_artist =
+        |item: &TrackItem| item.track.artists.first().map(|a| a.name.to_lowercase());
+    let lowercase_album =
+        |item: &TrackItem| item.track.album.as_ref().map(|a| a.name.to_lowercase());
+    let release_date = |item: &TrackItem| {
+        item.track
+            .album
+            .as_ref()
+            .and_then(|a| a.release_date.clone())
+    };
+
+    match by {
+        SortBy::Added => items.sort_by(|a, b| a.added_at.cmp(&b.added_at)),
+        SortBy::Name => items.sort_by_key(|item| item.track.name.to_lowercase()),
+        SortBy::Artist => items.sort_by_key(lowercase_artist),
+        SortBy::Album => items.sort_by_key(lowercase_album),
+        SortBy::Duration => items.sort_by_key(|item| item.track.duration_ms),
+        SortBy::ReleaseDate => items.sort_by_key(release_date),
+    }
+}
+
+/// Moves the item at `range_start` to before the item at `insert_before`.
+/// With `snapshot_id`, the positions are of that version of the playlist.
+/// Returns the playlist's new snapshot id.
+async fn reorder_playlist_item(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    range_start: usize,
+    insert_before: usize,
+    snapshot_id: Option<&str>,
+) -> Result<Option<String>, Box<dyn error::Error>> {
+    let url = api_url(&format!("/playlists/{playlist_id}/tracks"));
+
+    let client = http::client();
+    let mut map = serde_json::Map::new();
+    map.insert("range_start".to_string(), Value::from(range_start));
+    map.insert("insert_before".to_string(), Value::from(insert_before));
+    map.insert("range_length".to_string(), Value::from(1));
+    if let Some(snapshot_id) = snapshot_id {
+        map.insert("snapshot_id".to_string(), Value::from(snapshot_id));
+    }
+    let res = send(auth, client.put(url).json(&map)).await?;
+
+    Ok(snapshot_id_of(&check_for_error_and_return_text(res).await?))
+}
+
+/// Moves the tracks of a playlist, currently in `current_order`, one at a
+/// time into the order of `target`, which has the same tracks.
+async fn reorder_playlist_into(
+    auth: &mut impl TokenProvider,
+    playlist_id: &str,
+    mut current_order: Vec<String>,
+    target: &[String],
+) -> Result<(), Box<dyn error::Error>> {
+    for (target_ind, uri) in target.iter().enumerate() {
+        let current_ind = target_ind
+            + current_order[target_ind..]
+                .iter()
+                .position(|current| current == uri)
+                .ok_or("Playlist changed while reordering it.")?;
+        if current_ind != target_ind {
+            reorder_playlist_item(auth, playlist_id, current_ind, target_ind, None).await?;
+            let uri = current_order.remove(current_ind);
+            current_order.insert(target_ind, uri);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn playlist_sort(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
+    by: SortBy,
+    reverse: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, uri_or_name).await?;
+    let playlist_description = get_playlist_from_id(auth, &id).await?;
+
+    let mut sorted = match playlist_description.tracks {
+        Some(tracks) => tracks.get_all_items(auth).await?,
+        None => Vec::new(),
+    };
+
+    let current_order: Vec<String> = sorted.iter().map(|item| item.track.uri.clone()).collect();
+    record_snapshot(
+        &id,
+        "sort",
+        playlist_description.snapshot_id,
+        current_order.clone(),
+    )?;
+    sort_items(&mut sorted, by);
+    if reverse {
+        sorted.reverse();
+    }
+
+    // Reordered in place one move at a time rather than replaced, which keeps
+    // the added-at dates and local files intact.
+    let sorted: Vec<String> = sorted.into_iter().map(|item| item.track.uri).collect();
+    reorder_playlist_into(auth, &id, current_order, &sorted).await?;
+
+    output::info!(
+        "{}",
+        tr!("Sorted {name}.", name = playlist_description.name)
+    );
+
+    Ok(())
+}
+
+/// The changes turning a playlist's tracks into another list of tracks.
+struct SyncPlan {
+    /// Uris to remove every occurrence of.
+    remove: Vec<String>,
+    /// Uris to append after removing.
+    add: Vec<String>,
+    /// `(range_start, insert_before)` moves after adding, in order.
+    moves: Vec<(usize, usize)>,
+}
+
+/// Plans the changes to make `remote` into `local`. A track with more
+/// copies in the playlist than in the list is removed altogether and the
+/// wanted copies added back. The tracks in the longest run already in the
+/// right relative order stay put, and the rest are moved into place.
+fn plan_sync(remote: &[String], local: &[String]) -> SyncPlan {
+    let mut local_counts: HashMap<&str, usize> = HashMap::new();
+    for uri in local {
+        *local_counts.entry(uri).or_default() += 1;
+    }
+    let mut remote_counts: HashMap<&str, usize> = HashMap::new();
+    for uri in remote {
+        *remote_counts.entry(uri).or_default() += 1;
+    }
+
+    let mut remove: Vec<String> = Vec::new();
+    for uri in remote {
+        let wanted = local_counts.get(uri.as_str()).copied().unwrap_or(0);
+        if remote_counts[uri.as_str()] > wanted && !remove.contains(uri) {
+            remove.push(uri.clone());
+        }
+    }
+
+    let mut current: Vec<&str> = remote
+        .iter()
+        .map(|uri| uri.as_str())
+        .filter(|uri| !remove.iter().any(|removed| removed == uri))
+        .collect();
+    let mut available: HashMap<&str, usize> = HashMap::new();
+    for uri in &current {
+        *available.entry(uri).or_default() += 1;
+    }
+    let mut add: Vec<String> = Vec::new();
+    for uri in local {
+        match available.get_mut(uri.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => add.push(uri.clone()),
+        }
+    }
+    current.extend(add.iter().map(|uri| uri.as_str()));
+
+    // Where each track should end up; copies of a track keep their order.
+    let mut positions: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (ind, uri) in local.iter().enumerate() {
+        positions.entry(uri).or_default().push_back(ind);
+    }
+    let mut targets: Vec<usize> = current
+        .iter()
+        .map(|uri| {
+            positions
+                .get_mut(uri)
+                .and_then(|positions| positions.pop_front())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let staying = longest_increasing_run(&targets);
+    let mut moves = Vec::new();
+    for target in 0..targets.len() {
+        if staying.contains(&target) {
+            continue;
+        }
+        let from = targets
+            .iter()
+            .position(|&t| t == target)
+            .unwrap_or_default();
+        let to = match target {
+            0 => 0,
+            _ => {
+                targets
+                    .iter()
+                    .position(|&t| t == target - 1)
+                    .unwrap_or_default()
+                    + 1
+            }
+        };
+        if from == to || from + 1 == to {
+            continue;
+        }
+
+        moves.push((from, to));
+        let moved = targets.remove(from);
+        targets.insert(if from < to { to - 1 } else { to }, moved);
+    }
+
+    SyncPlan { remove, add, moves }
+}
+
+/// The local list without the copies of local files and tracks gone from
+/// Spotify that the playlist doesn't already have, since the API can't add
+/// them, and how many were skipped. The API also only removes every copy of
+/// a uri at once, so when fewer copies are wanted than the playlist has, the
+/// rest are kept at the end instead of removing them all.
+fn skip_unaddable(remote: &[String], local: Vec<String>) -> (Vec<String>, usize) {
+    let mut in_playlist: HashMap<&str, usize> = HashMap::new();
+    for uri in remote.iter().filter(|uri| !models::is_addable_uri(uri)) {
+        *in_playlist.entry(uri).or_default() += 1;
+    }
+
+    let mut kept = Vec::new();
+    let mut skipped = 0;
+    for uri in local {
+        if models::is_addable_uri(&uri) {
+            kept.push(uri);
+            continue;
+        }
+        match in_playlist.get_mut(uri.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                kept.push(uri);
+            }
+            _ => skipped += 1,
+        }
+    }
+    for uri in remote {
+        if let Some(count) = in_playlist.get_mut(uri.as_str()) {
+            if *count > 0 && kept.contains(uri) {
+                *count -= 1;
+                kept.push(uri.clone());
+            }
+        }
+    }
+
+    (kept, skipped)
+}
+
+/// The values of the longest increasing subsequence of `values`.
+fn longest_increasing_run(values: &[usize]) -> HashSet<usize> {
+    // `tails[len]` is the index of the smallest value ending a run of
+    // length `len + 1`.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for (ind, &value) in values.iter().enumerate() {
+        let len = tails.partition_point(|&tail| values[tail] < value);
+        previous[ind] = len.checked_sub(1).map(|prev| tails[prev]);
+        match tails.get_mut(len) {
+            Some(tail) => *tail = ind,
+            None => tails.push(ind),
+        }
+    }
+
+    let mut run = HashSet::new();
+    let mut ind = tails.last().copied();
+    while let Some(i) = ind {
+        run.insert(values[i]);
+        ind = previous[i];
+    }
+    run
+}
+
+/// Makes a playlist match a local track list, e.g. an edited export, by
+/// removing, adding and moving only the tracks that differ instead of
+/// replacing everything. The changes are made against the fetched snapshot
+/// of the playlist, so they fail instead of scrambling it if someone edits
+/// it at the same time.
+pub async fn playlist_sync(
+    auth: &mut impl TokenProvider,
+    filepath: &str,
+    uri_or_name: &str,
+    dry_run: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let tracks = read_export(filepath)?;
+    let (local, unresolved) = resolve_exported_tracks(auth, &tracks).await?;
+
+    let id = resolve_playlist_id(auth, uri_or_name).await?;
+    let playlist_description = get_playlist_from_id(auth, &id).await?;
+    let remote: Vec<String> = match playlist_description.tracks {
+        Some(tracks) => tracks
+            .get_all_items(auth)
+            .await?
+            .into_iter()
+            .map(|item| item.track.uri)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let (local, skipped) = skip_unaddable(&remote, local);
+    if skipped > 0 {
+        output::info!(
+            "{}",
+            tr!(
+                "Skipped {count} local file(s) or unavailable track(s); they can't be added through the API.",
+                count = skipped
+            )
+        );
+    }
+
+    let plan = plan_sync(&remote, &local);
+    let name = &playlist_description.name;
+    if plan.remove.is_empty() && plan.add.is_empty() && plan.moves.is_empty() {
+        output::info!("{}", tr!("{name} is already in sync.", name = name));
+        print_unresolved(&unresolved);
+        return Ok(());
+    }
+    if dry_run {
+        println!(
+            "{}",
+            tr!(
+                "Would remove {remove} and add {add} track(s), and make {moves} move(s) in {name}.",
+                remove = plan.remove.len(),
+                add = plan.add.len(),
+                moves = plan.moves.len(),
+                name = name
+            )
+        );
+        print_unresolved(&unresolved);
+        return Ok(());
+    }
+
+    let mut snapshot_id = playlist_description.snapshot_id;
+    record_snapshot(&id, "sync", snapshot_id.clone(), remote)?;
+    if !plan.remove.is_empty() {
+        snapshot_id =
+            remove_playlist_items(auth, &id, &plan.remove, snapshot_id.as_deref()).await?;
+    }
+    if !plan.add.is_empty() {
+        snapshot_id = add_playlist_items(auth, &id, &plan.add).await?;
+    }
+    for &(from, to) in &plan.moves {
+        snapshot_id = reorder_playlist_item(auth, &id, from, to, snapshot_id.as_deref()).await?;
+    }
+
+    output::info!(
+        "{}",
+        tr!(
+            "Synced {name}: removed {remove} and added {add} track(s), and made {moves} move(s).",
+            name = name,
+            remove = plan.remove.len(),
+            add = plan.add.len(),
+            moves = plan.moves.len()
+        )
+    );
+    print_unresolved(&unresolved);
+
+    Ok(())
+}
+
+fn format_snapshot_time(secs: u64) -> String {
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| secs.to_string())
+}
+
+/// Lists the stored snapshots of a playlist, newest first.
+pub async fn playlist_snapshots(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, uri_or_name).await?;
+    let snapshots = snapshots::load(&id)?;
+    if snapshots.is_empty() {
+        println!("{}", tr!("No snapshots of the playlist."));
+        return Ok(());
+    }
+
+    for (ind, snapshot) in snapshots.iter().rev().enumerate() {
+        println!(
+            "{}",
+            tr!(
+                "{position}. {time} before {reason}: {count} tracks",
+                position = ind + 1,
+                time = format_snapshot_time(snapshot.taken_at),
+                reason = snapshot.reason,
+                count = snapshot.uris.len()
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// Restores the latest snapshot of a playlist and drops it, so undoing again
+/// goes further back.
+pub async fn playlist_undo(
+    auth: &mut impl TokenProvider,
+    uri_or_name: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = resolve_playlist_id(auth, uri_or_name).await?;
+    let mut snapshots = snapshots::load(&id)?;
+    let snapshot = snapshots
+        .pop()
+        .ok_or("No snapshots of the playlist to undo.")?;
+
+    // When only the order changed, e.g. after a sort, the tracks are moved
+    // back in place so that their added-at dates are kept.
+    let current_order: Vec<String> = match get_playlist_from_id(auth, &id).await?.tracks {
+        Some(tracks) => tracks
+            .get_all_items(auth)
+            .await?
+            .into_iter()
+            .map(|item| item.track.uri)
+            .collect(),
+        None => Vec::new(),
+    };
+    let (mut current_sorted, mut snapshot_sorted) = (current_order.clone(), snapshot.uris.clone());
+    current_sorted.sort();
+    snapshot_sorted.sort();
+    let (local, uris): (Vec<String>, Vec<String>) = if current_sorted == snapshot_sorted {
+        reorder_playlist_into(auth, &id, current_order, &snapshot.uris).await?;
+        (Vec::new(), snapshot.uris)
     } else {
-        println!("\nNo songs in the list.");
+        // Local files can't be added through the API.
+        let (local, uris): (Vec<String>, Vec<String>) = snapshot
+            .uris
+            .into_iter()
+            .partition(|uri| uri.starts_with("spotify:local:"));
+        replace_playlist_uris(auth, &id, &uris).await?;
+        (local, uris)
+    };
+    snapshots::save(&id, &snapshots)?;
+
+    output::info!(
+        "{}",
+        tr!(
+            "Restored the playlist to before {reason} at {time} ({count} tracks).",
+            reason = snapshot.reason,
+            time = format_snapshot_time(snapshot.taken_at),
+            count = uris.len()
+        )
+    );
+    if !local.is_empty() {
+        output::info!(
+            "{}",
+            tr!(
+                "Skipped {count} local files; add them back in Spotify.",
+                count = local.len()
+            )
+        );
+    }
+
+    Ok(())
+}
+
+async fn get_saved_tracks(
+    auth: &mut impl TokenProvider,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let url = api_url("/me/tracks");
+
+    let client = http::client();
+    let request = client.get(url).query(&[("limit", 50)]);
+    let saved_tracks: PlaylistTracks = api_request(auth, request).await?;
+
+    saved_tracks.get_tracks(auth).await
+}
+
+/// Mirrors the liked/saved tracks into a normal playlist. If a playlist with
+/// the name already exists, only the differences are applied to it.
+pub async fn liked_export_to_playlist(
+    auth: &mut impl TokenProvider,
+    name: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let liked: Vec<String> = get_saved_tracks(auth)
+        .await?
+        .into_iter()
+        .map(|song| song.uri)
+        .collect();
+
+    let existing = get_my_playlists(auth)
+        .await?
+        .into_iter()
+        .find(|p| p.name.to_lowercase() == name.to_lowercase());
+
+    match existing {
+        Some(playlist) => {
+            let id = playlist_id_from_uri(&playlist.uri)?;
+            let playlist_description = get_playlist_from_id(auth, &id).await?;
+            let items = match playlist_description.tracks {
+                Some(tracks) => tracks.get_all_items(auth).await?,
+                None => Vec::new(),
+            };
+            let current: Vec<String> = items
+                .iter()
+                .filter(|item| item.track.is_playable != Some(false))
+                .map(|item| item.track.uri.clone())
+                .collect();
+
+            let current_set: HashSet<&String> = current.iter().collect();
+            let liked_set: HashSet<&String> = liked.iter().collect();
+            let to_add: Vec<String> = liked
+                .iter()
+                .filter(|uri| !current_set.contains(uri))
+                .cloned()
+                .collect();
+            let to_remove: Vec<String> = current
+                .iter()
+                .filter(|uri| !liked_set.contains(uri))
+                .cloned()
+                .collect();
+
+            if !to_add.is_empty() || !to_remove.is_empty() {
+                record_snapshot(
+                    &id,
+                    "liked export-to-playlist",
+                    playlist_description.snapshot_id,
+                    items.into_iter().map(|item| item.track.uri).collect(),
+                )?;
+            }
+            remove_playlist_items(auth, &id, &to_remove, None).await?;
+            add_playlist_items(auth, &id, &to_add).await?;
+
+            output::info!(
+                "{}",
+                tr!(
+                    "Updated {name}: added {added} and removed {removed} tracks.",
+                    name = playlist.name,
+                    added = to_add.len(),
+                    removed = to_remove.len()
+                )
+            );
+        }
+        None => {
+            let description = "Playlist created by a CLI tool to mirror my liked songs.";
+            let playlist_create_response =
+                create_playlist(auth, name, description, false, false).await?;
+            add_playlist_items(auth, &playlist_create_response.id, &liked).await?;
+
+            println!(
+                "{}",
+                tr!(
+                    "Created playlist {name} with {count} tracks. uri: spotify:playlist:{id}",
+                    name = name,
+                    count = liked.len(),
+                    id = playlist_create_response.id
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_recommendations(
+    auth: &mut impl TokenProvider,
+    params: &RecommendationParameters,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let url = api_url("/recommendations");
+
+    let client = http::client();
+    let mut request_builder = client
+        .get(url)
+        .query(&[("limit", params.limit)])
+        .query(&[("market", market())]);
+    if !params.seed_artists.is_empty() {
+        request_builder = request_builder.query(&[("seed_artists", params.seed_artists.join(","))])
+    }
+    if !params.genres.is_empty() {
+        request_builder = request_builder.query(&[("seed_genres", params.genres.join(","))])
+    }
+    if !params.seed_tracks.is_empty() {
+        request_builder = request_builder.query(&[("seed_tracks", params.seed_tracks.join(","))])
+    }
+    let recommendation_response: RecommendationResponse =
+        api_request(auth, request_builder).await?;
+
+    Ok(recommendation_response.tracks)
+}
+
+/// Source of recommendations for `rec generate`. Spotify has deprecated the
+/// recommendations endpoint for new apps, so there's an alternative that
+/// only uses endpoints every app has access to.
+trait RecommendationBackend {
+    async fn recommend(
+        &self,
+        auth: &mut impl TokenProvider,
+        params: &RecommendationParameters,
+    ) -> Result<Vec<Song>, Box<dyn error::Error>>;
+}
+
+/// The official `/recommendations` endpoint.
+struct SpotifyRecommendations;
+
+impl RecommendationBackend for SpotifyRecommendations {
+    async fn recommend(
+        &self,
+        auth: &mut impl TokenProvider,
+        params: &RecommendationParameters,
+    ) -> Result<Vec<Song>, Box<dyn error::Error>> {
+        get_recommendations(auth, params).await
+    }
+}
+
+/// Random picks from the top tracks of the seed artists (including the
+/// artists of the seed tracks) and their related artists, plus tracks
+/// found by searching for the seed genres.
+struct ArtistSampling;
+
+/// How many related artists of each seed artist to sample from.
+const RELATED_ARTISTS_PER_SEED: usize = 3;
+
+#[derive(Deserialize, Debug)]
+struct RelatedArtistsResponse {
+    artists: Vec<Artist>,
+}
+
+impl RecommendationBackend for ArtistSampling {
+    async fn recommend(
+        &self,
+        auth: &mut impl TokenProvider,
+        params: &RecommendationParameters,
+    ) -> Result<Vec<Song>, Box<dyn error::Error>> {
+        let mut artist_ids = params.seed_artists.clone();
+        let seed_tracks: Vec<&str> = params.seed_tracks.iter().map(|id| id.as_str()).collect();
+        for track in get_several_tracks(auth, &seed_tracks).await? {
+            for artist in track.artists {
+                if !artist_ids.contains(&artist.id) {
+                    artist_ids.push(artist.id);
+                }
+            }
+        }
+
+        let mut related_ids = Vec::new();
+        for id in artist_ids.iter() {
+            // Related artists are deprecated too, so they're only a bonus.
+            match get_related_artists(auth, id).await {
+                Ok(related) => related_ids.extend(
+                    related
+                        .into_iter()
+                        .take(RELATED_ARTISTS_PER_SEED)
+                        .map(|a| a.id),
+                ),
+                Err(e) => tracing::debug!("No related artists for {id}: {e}"),
+            }
+        }
+        for id in related_ids {
+            if !artist_ids.contains(&id) {
+                artist_ids.push(id);
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for id in artist_ids.iter() {
+            candidates.extend(get_artist_top_tracks(auth, id).await?);
+        }
+        for genre in params.genres.iter() {
+            candidates.extend(search_genre_tracks(auth, genre).await?);
+        }
+
+        let mut seen: HashSet<String> = params.seed_tracks.iter().cloned().collect();
+        candidates.retain(|song| seen.insert(song.id.clone()));
+        candidates.shuffle(&mut rand::thread_rng());
+        candidates.truncate(params.limit.into());
+
+        if candidates.is_empty() {
+            return Err("Found nothing to recommend for the seeds.".into());
+        }
+
+        Ok(candidates)
+    }
+}
+
+async fn get_related_artists(
+    auth: &mut impl TokenProvider,
+    id: &str,
+) -> Result<Vec<Artist>, Box<dyn error::Error>> {
+    let url = api_url(&format!("/artists/{id}/related-artists"));
+
+    let client = http::client();
+    let related_response: RelatedArtistsResponse = api_request(auth, client.get(url)).await?;
+
+    Ok(related_response.artists)
+}
+
+async fn search_genre_tracks(
+    auth: &mut impl TokenProvider,
+    genre: &str,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let url = api_url("/search");
+
+    let client = http::client();
+    let request = client.get(url).query(&[
+        ("q", format!("genre:\"{genre}\"").as_str()),
+        ("type", "track"),
+        ("limit", "50"),
+    ]);
+    let find_response: FindResponse = api_request(auth, request).await?;
+
+    Ok(find_response.tracks.map(|t| t.items).unwrap_or_default())
+}
+
+/// Parses an artist id out of a `spotify:artist:<id>` uri or an
+/// open.spotify.com artist url, localized ones too.
+fn artist_id_from_input(input: &str) -> Option<String> {
+    SpotifyId::parse(input)
+        .ok()
+        .filter(|id| id.kind == "artist")
+        .map(|id| id.id)
+}
+
+/// Looks up an artist given as a uri/url, or searched for by name taking the
+/// best match.
+async fn resolve_artist(
+    auth: &mut impl TokenProvider,
+    name_or_uri: &str,
+) -> Result<Artist, Box<dyn error::Error>> {
+    if let Some(id) = artist_id_from_input(name_or_uri) {
+        return get_artist(auth, &id).await;
+    }
+
+    let url = api_url("/search");
+
+    let client = http::client();
+    let request = client.get(url).query(&[
+        ("q", format!("artist:{name_or_uri}").as_str()),
+        ("type", "artist"),
+        ("limit", "1"),
+    ]);
+    let find_response: FindResponse = api_request(auth, request).await?;
+
+    find_response
+        .artists
+        .and_then(|a| a.items.into_iter().next())
+        .ok_or(format!("Didn't find an artist called {name_or_uri}.").into())
+}
+
+/// Max number of artists from the fallback for related artists.
+const MAX_SIMILAR_ARTISTS: usize = 20;
+
+/// Genres of an artist searched for in the fallback for related artists.
+const SIMILAR_ARTIST_GENRES: usize = 2;
+
+struct RelatedArtists {
+    artists: Vec<Artist>,
+    /// The related artists endpoint wasn't available so these are artists
+    /// featured on the artist's top tracks and artists of the same genres.
+    fallback: bool,
+}
+
+/// Artists related to the given one. The related artists endpoint is
+/// deprecated for new apps, so without access to it similar artists are
+/// looked for among collaborators and artists of the same genres.
+async fn related_artists(
+    auth: &mut impl TokenProvider,
+    artist: &Artist,
+) -> Result<RelatedArtists, Box<dyn error::Error>> {
+    match get_related_artists(auth, &artist.id).await {
+        Ok(artists) => {
+            return Ok(RelatedArtists {
+                artists,
+                fallback: false,
+            })
+        }
+        Err(e) if is_endpoint_unavailable_error(e.as_ref()) => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut seen = HashSet::from([artist.id.clone()]);
+    let mut artists = Vec::new();
+    for song in get_artist_top_tracks(auth, &artist.id).await? {
+        for featured in song.artists {
+            if seen.insert(featured.id.clone()) {
+                artists.push(featured);
+            }
+        }
+    }
+
+    let genres = get_artist_genres(auth, &[artist.id.as_str()])
+        .await?
+        .remove(&artist.id)
+        .unwrap_or_default();
+    for genre in genres.iter().take(SIMILAR_ARTIST_GENRES) {
+        let url = api_url("/search");
+        let client = http::client();
+        let request = client.get(url).query(&[
+            ("q", format!("genre:\"{genre}\"").as_str()),
+            ("type", "artist"),
+            ("limit", "10"),
+        ]);
+        let find_response: FindResponse = api_request(auth, request).await?;
+        for similar in find_response.artists.map(|a| a.items).unwrap_or_default() {
+            if seen.insert(similar.id.clone()) {
+                artists.push(similar);
+            }
+        }
     }
+    artists.truncate(MAX_SIMILAR_ARTISTS);
 
-    Ok(())
+    Ok(RelatedArtists {
+        artists,
+        fallback: true,
+    })
 }
 
-pub async fn recommendation_play(
-    auth: &mut SpotifyAuth,
-    index: Option<u16>,
-) -> Result<(), Box<dyn error::Error>> {
-    let managed_list = get_managed_playlist_id()?;
-
-    playback_play(
-        auth,
-        Some(&format!("spotify:playlist:{managed_list}")),
-        index,
-    )
-    .await?;
-    tokio::time::sleep(Duration::from_millis(500u64)).await;
-    playback_show(auth, false).await
+fn print_related_artists(artist: &Artist, related: &RelatedArtists) {
+    match related.fallback {
+        false => println!(
+            "{}",
+            output::header(&tr!("Related to {artist}:", artist = artist))
+        ),
+        true => println!(
+            "{}",
+            output::header(&tr!(
+                "Similar to {artist} (collaborators and artists of the same genres):",
+                artist = artist
+            ))
+        ),
+    }
+    if related.artists.is_empty() {
+        println!("{}", tr!("Found no related artists."));
+    }
+    for (i, related_artist) in related.artists.iter().enumerate() {
+        println!("{}. {related_artist}", i + 1);
+    }
 }
 
-pub async fn recommendation_save(
-    auth: &mut SpotifyAuth,
-    name: String,
-    description: Option<String>,
+/// Plays an artist given as a name or uri/url. With `top_tracks`, their top
+/// 10 tracks are played as a list of tracks instead of the artist context,
+/// which also works for artists Spotify has no radio for.
+pub async fn artist_play(
+    auth: &mut impl TokenProvider,
+    name_or_uri: &str,
+    top_tracks: bool,
 ) -> Result<(), Box<dyn error::Error>> {
-    let managed_list = get_managed_playlist_id()?;
-    let playlist_description = get_playlist_from_id(auth, &managed_list).await?;
+    let artist = resolve_artist(auth, name_or_uri).await?;
+    let before = playback_snapshot(auth).await;
 
-    if playlist_description.tracks.is_none() {
-        return Err("No tracks in the current managed playlist.".into());
+    if !top_tracks {
+        let uri = format!("spotify:artist:{}", artist.id);
+        playback_play(auth, Some(&uri), None, None).await?;
+        return show_playback_change(auth, &before, ExpectedChange::Context(&uri)).await;
     }
 
-    let tracks = playlist_description
-        .tracks
-        .unwrap()
-        .get_tracks(auth)
-        .await?;
-
-    let playlist_create_response = create_playlist(
-        auth,
-        &name,
-        &description.unwrap_or(
-            "Playlist created by a CLI tool to save a list of recommendations.".to_string(),
-        ),
-        false,
-    )
-    .await?;
-
-    replace_playlist_items(auth, &playlist_create_response.id, &tracks).await
+    let uris: Vec<String> = get_artist_top_tracks(auth, &artist.id)
+        .await?
+        .into_iter()
+        .filter(|song| song.is_playable != Some(false))
+        .map(|song| song.uri)
+        .collect();
+    let Some(first) = uris.first() else {
+        return Err(format!("{} has no top tracks to play.", artist.name).into());
+    };
+    playback_play(auth, None, Some(&uris), None).await?;
+    show_playback_change(auth, &before, ExpectedChange::Song(first)).await
 }
 
-pub async fn recommendation_generate(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
-    let managed_list = get_managed_playlist_id()?;
+/// Prints the artists related to an artist given as a name or uri/url.
+pub async fn artist_related(
+    auth: &mut impl TokenProvider,
+    name_or_uri: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let artist = resolve_artist(auth, name_or_uri).await?;
+    let related = related_artists(auth, &artist).await?;
 
-    let mut genres: Option<Vec<String>> = None;
+    print_related_artists(&artist, &related);
 
-    let mut recommendation_parameters = RecommendationParameters {
-        limit: 20,
-        ..Default::default()
-    };
+    Ok(())
+}
 
-    let mut user_response: String = String::new();
-    while !user_response.starts_with("q") {
+/// Walks from an artist to related artists interactively, collecting seed
+/// artists along the way. The seeds can then be used for recommendations
+/// or for a playlist of their top tracks.
+pub async fn artist_explore(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    name_or_uri: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut artist = resolve_artist(auth, name_or_uri).await?;
+    let mut related = related_artists(auth, &artist).await?;
+    let mut previous: Vec<(Artist, RelatedArtists)> = Vec::new();
+    let mut seeds: Vec<Artist> = Vec::new();
+
+    loop {
         println!("\n***********************************\n");
-        println!("Current parameters:\n{recommendation_parameters}\n");
-        println!("What would you like to edit? (Enter the number of the option)");
-        println!("1 - Change the limit/number of recommendations.");
-        println!("2 - Add an artist.");
-        println!("3 - Add a genre.");
-        println!("4 - Add a track/song.");
-        println!("7 - Clear artists.");
-        println!("8 - Clear genres.");
-        println!("9 - Clear tracks/songs.");
-        println!("g - Generate recommendations.");
-        println!("q - Quit without generating recommendations.");
+        print_related_artists(&artist, &related);
+        let seed_names: Vec<&str> = seeds.iter().map(|a| a.name.as_str()).collect();
+        println!();
+        println!(
+            "{}\n",
+            tr!("Seeds: {seeds}", seeds = format!("{seed_names:?}"))
+        );
+        println!("{}", tr!("<number> - Go to that artist."));
+        println!(
+            "{}",
+            tr!(
+                "s [number] - Add that artist, or {artist}, as a seed.",
+                artist = artist
+            )
+        );
+        println!("{}", tr!("b - Go back."));
+        println!("{}", tr!("g - Generate recommendations from the seeds."));
+        println!(
+            "{}",
+            tr!("p <name> - Create a playlist of the seeds' top tracks.")
+        );
+        println!("{}", tr!("q - Quit."));
         println!();
 
-        user_response = String::new();
-        io::stdin().read_line(&mut user_response)?;
-        user_response = user_response.trim().to_lowercase();
+        let mut user_response = String::new();
+        prompt::read_line(&mut user_response)?;
+        let user_response = user_response.trim();
+        let (command, arg) = match user_response.split_once(' ') {
+            Some((command, arg)) => (command.to_lowercase(), arg.trim()),
+            None => (user_response.to_lowercase(), ""),
+        };
+        let pick = |related: &RelatedArtists, number: &str| {
+            number
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .filter(|&i| i < related.artists.len())
+        };
 
-        match user_response.as_str() {
-            // TODO: implement all optional tuning knobs somehow
-            "1" => {
-                println!("New limit? (1-100)");
-                let mut new_limit = String::new();
-                io::stdin().read_line(&mut new_limit)?;
-                let parsed_limit: Result<u8, _> = new_limit.trim().parse();
-                match parsed_limit {
-                    Ok(limit) => {
-                        if limit == 0 || limit > 100 {
-                            println!("Limit needs to be between 1-100.");
-                        } else {
-                            recommendation_parameters.limit = limit
+        match command.as_str() {
+            "s" => {
+                let seed = match arg {
+                    "" => artist.clone(),
+                    number => match pick(&related, number) {
+                        Some(i) => related.artists[i].clone(),
+                        None => {
+                            println!(
+                                "{}",
+                                tr!("No artist {number} in the list.", number = number)
+                            );
+                            continue;
                         }
-                    }
-                    Err(e) => println!("{e}"),
-                }
-            }
-            "2" => {
-                println!("Artist name?");
-                let mut new_artist = String::new();
-                io::stdin().read_line(&mut new_artist)?;
-                new_artist = new_artist.trim().to_lowercase();
-                println!();
-
-                match find(auth, None, Some(&new_artist)).await {
-                    Ok(artist) => {
-                        recommendation_parameters.artists.push(artist.name);
-                        recommendation_parameters.seed_artists.push(artist.id);
-                    }
-                    Err(e) => println!("{}", e),
+                    },
+                };
+                if seeds.iter().any(|a| a.id == seed.id) {
+                    println!("{}", tr!("{artist} is already a seed.", artist = seed));
+                } else if seeds.len() >= MAX_RECOMMENDATION_SEEDS {
+                    println!("{}", tr!("Already at the max of 5 seeds."));
+                } else {
+                    seeds.push(seed);
                 }
             }
-            "3" => {
-                if genres.is_none() {
-                    genres = Some(get_available_genres(auth).await?);
+            "b" => match previous.pop() {
+                Some((previous_artist, previous_related)) => {
+                    artist = previous_artist;
+                    related = previous_related;
                 }
-                println!(
-                    "Available genres:\n{}\n",
-                    genres.as_ref().unwrap().join(", ")
-                );
-
-                println!("Genre name?");
-                let mut new_genre = String::new();
-                io::stdin().read_line(&mut new_genre)?;
-                new_genre = new_genre.trim().to_lowercase();
-
-                if !genres.as_ref().unwrap().contains(&new_genre) {
-                    println!("Illegal genre.");
+                None => println!("{}", tr!("Already at the first artist.")),
+            },
+            "g" => {
+                if seeds.is_empty() {
+                    println!("{}", tr!("Add some seeds first."));
                     continue;
                 }
-
-                recommendation_parameters.genres.push(new_genre);
-            }
-            "4" => {
-                println!("Song name?");
-                let mut new_track = String::new();
-                io::stdin().read_line(&mut new_track)?;
-                new_track = new_track.trim().to_lowercase();
-
-                println!("\nDo you want to specify an artist? (Empty response if not)");
-                let mut by_artist = String::new();
-                io::stdin().read_line(&mut by_artist)?;
-                by_artist = by_artist.trim().to_lowercase();
-                println!();
-
-                let artist: Option<&str> = if !by_artist.is_empty() {
-                    Some(&by_artist)
-                } else {
-                    None
+                let recommendation_parameters = RecommendationParameters {
+                    limit: 20,
+                    artists: seeds.iter().map(|a| a.name.clone()).collect(),
+                    seed_artists: seeds.iter().map(|a| a.id.clone()).collect(),
+                    ..Default::default()
                 };
-
-                match find(auth, Some(&new_track), artist).await {
-                    Ok(track) => {
-                        recommendation_parameters.tracks.push(track.name);
-                        recommendation_parameters.seed_tracks.push(track.id);
-                    }
-                    Err(e) => println!("{}", e),
-                }
-            }
-            "7" => {
-                recommendation_parameters.artists = Vec::new();
-                recommendation_parameters.seed_artists = Vec::new();
+                return recommendation_wizard(auth, config, recommendation_parameters).await;
             }
-            "8" => {
-                recommendation_parameters.genres = Vec::new();
-            }
-            "9" => {
-                recommendation_parameters.tracks = Vec::new();
-                recommendation_parameters.seed_tracks = Vec::new();
-            }
-            "g" => {
-                let seeds = recommendation_parameters.seed_artists.len()
-                    + recommendation_parameters.genres.len()
-                    + recommendation_parameters.seed_tracks.len();
-                if seeds == 0 {
-                    println!("You need to specify at least one artist or genre or track.");
+            "p" => {
+                if seeds.is_empty() {
+                    println!("{}", tr!("Add some seeds first."));
                     continue;
                 }
-                if seeds == 0 || seeds > 5 {
-                    println!("Too many artists & genres & tracks ({seeds}) specified.");
-                    println!("Can specify at most 5 in total.");
+                if arg.is_empty() {
+                    println!("{}", tr!("Give a name for the playlist, e.g. p My Mix"));
                     continue;
                 }
-                let songs = get_recommendations(auth, &recommendation_parameters).await?;
-
-                let max_print_width = get_max_print_width();
-                println!("Got the following recommendations:");
-                for song in songs.iter() {
-                    let mut line = format!("{song}");
-                    if line.chars().count() > max_print_width {
-                        line = line.chars().take(max_print_width - 4).collect();
-                        line += " ...";
-                    }
-                    println!("{line}");
+                let mut uris = Vec::new();
+                for seed in seeds.iter() {
+                    uris.extend(
+                        get_artist_top_tracks(auth, &seed.id)
+                            .await?
+                            .into_iter()
+                            .map(|song| song.uri),
+                    );
                 }
+                let description = format!("Top tracks of {}.", seed_names.join(", "));
+                let playlist_create_response =
+                    create_playlist(auth, arg, &description, false, false).await?;
+                add_playlist_items(auth, &playlist_create_response.id, &uris).await?;
 
-                println!("\nAccept this list or keep trying? (y to accept, N to keep trying)");
-                let mut user_response = String::new();
-                io::stdin().read_line(&mut user_response)?;
-                user_response = user_response.trim().to_lowercase();
-
-                if user_response.starts_with("y") {
-                    replace_playlist_items(auth, &managed_list, &songs).await?;
-
-                    println!("Added recommendations to the managed playlist.");
-                    println!("Start playing the list? (Y/n)");
-                    let mut user_response = String::new();
-                    io::stdin().read_line(&mut user_response)?;
-                    user_response = user_response.trim().to_lowercase();
-
-                    if user_response.is_empty() || user_response.starts_with("y") {
-                        recommendation_play(auth, None).await?;
-                    }
-
-                    break;
-                } else {
-                    println!("Ok, going again.");
-                }
-            }
-            "q" => {
-                println!("Ok, quitting without generating recommendations.");
-                break;
+                println!(
+                    "{}",
+                    tr!(
+                        "Created playlist {name} with {count} tracks. uri: spotify:playlist:{id}",
+                        name = arg,
+                        count = uris.len(),
+                        id = playlist_create_response.id
+                    )
+                );
+                return Ok(());
             }
-            _ => println!("Unrecognized command: {user_response}"),
+            "q" => return Ok(()),
+            number => match pick(&related, number) {
+                Some(i) => {
+                    let next_artist = related.artists[i].clone();
+                    let next_related = related_artists(auth, &next_artist).await?;
+                    previous.push((
+                        std::mem::replace(&mut artist, next_artist),
+                        std::mem::replace(&mut related, next_related),
+                    ));
+                }
+                None => println!(
+                    "{}",
+                    tr!("Unrecognized command: {command}", command = user_response)
+                ),
+            },
         }
     }
+}
 
-    Ok(())
+/// Whether an error means the app has no access to an endpoint, like the
+/// ones Spotify has deprecated for new apps. Those answer 404, or 403 saying
+/// the endpoint is deprecated; other 403s, e.g. missing scopes, are errors.
+fn is_endpoint_unavailable_error(e: &(dyn error::Error + 'static)) -> bool {
+    e.downcast_ref::<ApiError>()
+        .is_some_and(|e| match e.status {
+            StatusCode::NOT_FOUND => true,
+            StatusCode::FORBIDDEN => e.message.to_lowercase().contains("deprecated"),
+            _ => false,
+        })
 }
 
-async fn get_available_genres(
-    auth: &mut SpotifyAuth,
-) -> Result<Vec<String>, Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/recommendations/available-genre-seeds".to_string();
+/// Gets recommendations from the backend set in the config.
+async fn fetch_recommendations(
+    auth: &mut impl TokenProvider,
+    config: &Config,
+    params: &RecommendationParameters,
+) -> Result<Vec<Song>, Box<dyn error::Error>> {
+    let songs = match config.recommendations.backend {
+        RecommendationBackendKind::Spotify => {
+            SpotifyRecommendations.recommend(auth, params).await?
+        }
+        RecommendationBackendKind::Sampling => ArtistSampling.recommend(auth, params).await?,
+        RecommendationBackendKind::Auto => {
+            match SpotifyRecommendations.recommend(auth, params).await {
+                Err(e) if is_endpoint_unavailable_error(e.as_ref()) => {
+                    output::info!("{}", tr!("The recommendations endpoint isn't available, sampling the artists' top tracks instead."));
+                    ArtistSampling.recommend(auth, params).await?
+                }
+                result => result?,
+            }
+        }
+    };
 
-    let headers = auth_header(auth).await?;
+    Ok(without_explicit(config, songs))
+}
 
-    let client = reqwest::Client::new();
-    let res = client.get(url).headers(headers).send().await?;
+/// Drops explicit tracks if the config says so, telling how many were left out.
+fn without_explicit(config: &Config, songs: Vec<Song>) -> Vec<Song> {
+    if !config.recommendations.exclude_explicit {
+        return songs;
+    }
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let genres_response: GenresResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let total = songs.len();
+    let songs: Vec<Song> = songs.into_iter().filter(|song| !song.explicit).collect();
+    if songs.len() < total {
+        output::info!(
+            "{}",
+            tr!(
+                "Left out {count} explicit track(s).",
+                count = total - songs.len()
+            )
+        );
+    }
+    songs
+}
 
-    Ok(genres_response.genres)
+/// Parses a track uri out of a `spotify:track:<id>` uri or an
+/// open.spotify.com track url.
+fn track_uri_from_input(input: &str) -> Option<String> {
+    SpotifyId::parse(input)
+        .ok()
+        .filter(|id| id.kind == "track")
+        .map(|id| id.uri())
 }
 
-async fn replace_playlist_items(
-    auth: &mut SpotifyAuth,
-    playlist_id: &str,
-    tracks: &[Song],
+/// Plays a single track given as a uri/url or searched for by name.
+pub async fn play_track(
+    auth: &mut impl TokenProvider,
+    query_or_uri: &str,
+    first: bool,
 ) -> Result<(), Box<dyn error::Error>> {
-    let url = format!("https://api.spotify.com/v1/playlists/{playlist_id}/tracks");
-
-    let headers = auth_header(auth).await?;
-
-    let client = reqwest::Client::new();
-    let uris: Vec<String> = tracks.iter().map(|song| song.uri.to_owned()).collect();
-    let mut map = HashMap::new();
-    map.insert("uris", uris);
-    let res = client.put(url).headers(headers).json(&map).send().await?;
-
-    check_for_error_and_return_text(res).await?;
+    let uri = match track_uri_from_input(query_or_uri) {
+        Some(uri) => uri,
+        None if first => search_track_uri(auth, query_or_uri, None)
+            .await?
+            .ok_or("Didn't find any tracks. Did you typo the song name?")?,
+        None => {
+            let track = find(auth, Some(query_or_uri), None).await?;
+            format!("spotify:track:{}", track.id)
+        }
+    };
 
-    Ok(())
+    let before = playback_snapshot(auth).await;
+    playback_play(auth, None, Some(std::slice::from_ref(&uri)), None).await?;
+    show_playback_change(auth, &before, ExpectedChange::Song(&uri)).await
 }
 
-async fn get_recommendations(
-    auth: &mut SpotifyAuth,
-    params: &RecommendationParameters,
-) -> Result<Vec<Song>, Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/recommendations".to_string();
-
-    let headers = auth_header(auth).await?;
-
-    let client = reqwest::Client::new();
-    let mut request_builder = client
-        .get(url)
-        .headers(headers)
-        .query(&[("limit", params.limit)])
-        .query(&[("market", "from_token")]);
-    if !params.seed_artists.is_empty() {
-        request_builder = request_builder.query(&[("seed_artists", params.seed_artists.join(","))])
-    }
-    if !params.genres.is_empty() {
-        request_builder = request_builder.query(&[("seed_genres", params.genres.join(","))])
-    }
-    if !params.seed_tracks.is_empty() {
-        request_builder = request_builder.query(&[("seed_tracks", params.seed_tracks.join(","))])
+/// Plays whatever a `spotify:` link points to; this is what the handler
+/// registered by `setup uri-handler` runs.
+pub async fn handle_uri(
+    auth: &mut impl TokenProvider,
+    link: &str,
+) -> Result<(), Box<dyn error::Error>> {
+    let id = SpotifyId::parse(link)?;
+    let uri = id.uri();
+
+    let before = playback_snapshot(auth).await;
+    match id.kind.as_str() {
+        "track" | "episode" => {
+            playback_play(auth, None, Some(std::slice::from_ref(&uri)), None).await?;
+            show_playback_change(auth, &before, ExpectedChange::Song(&uri)).await
+        }
+        "album" | "playlist" | "artist" | "show" => {
+            playback_play(auth, Some(&uri), None, None).await?;
+            show_playback_change(auth, &before, ExpectedChange::Context(&uri)).await
+        }
+        _ => Err(format!("Can't play a {}: {link}", id.kind).into()),
     }
-    let res = request_builder.send().await?;
+}
 
-    let response_text = check_for_error_and_return_text(res).await?;
-    let recommendation_response: RecommendationResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+/// Registers this binary as the handler of `spotify:` links, passing `device`
+/// on so that clicked links play there.
+pub fn setup_uri_handler(device: Option<&str>) -> Result<(), Box<dyn error::Error>> {
+    let exe = env::current_exe()?;
+    let mut command = vec![exe
+        .to_str()
+        .ok_or("The path of the binary isn't UTF-8.")?
+        .to_string()];
+    if let Some(device) = device {
+        command.extend(["--device".to_string(), device.to_string()]);
+    }
+    command.push("handle-uri".to_string());
+
+    let registered = desktop::register_uri_handler(&command)?;
+    output::info!(
+        "{}",
+        tr!(
+            "Registered spotify-cli as the handler of spotify: links in {registered}.",
+            registered = registered
+        )
+    );
 
-    Ok(recommendation_response.tracks)
+    Ok(())
 }
 
 async fn find(
-    auth: &mut SpotifyAuth,
+    auth: &mut impl TokenProvider,
     track: Option<&str>,
     artist: Option<&str>,
 ) -> Result<TrackOrArtist, Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/search".to_string();
-
-    let headers = auth_header(auth).await?;
+    let url = api_url("/search");
 
-    let client = reqwest::Client::new();
-    let mut request_builder = client.get(url).headers(headers).query(&[("limit", 5)]);
+    let client = http::client();
+    let mut request_builder = client.get(url).query(&[("limit", 5)]);
 
     if let Some(track) = track {
         if let Some(artist) = artist {
@@ -1081,11 +7703,7 @@ async fn find(
                 .into(),
         );
     }
-    let res = request_builder.send().await?;
-
-    let response_text = check_for_error_and_return_text(res).await?;
-    let find_response: FindResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let find_response: FindResponse = api_request(auth, request_builder).await?;
 
     if track.is_some() {
         match find_response.tracks {
@@ -1094,7 +7712,7 @@ async fn find(
                     return Err("Didn't find any tracks. Did you typo the song name?".into());
                 }
                 let ind = choose_element(&t.items)?;
-                let found_track = t.items.get(ind as usize).ok_or("Index out of bounds!")?;
+                let found_track = t.items.get(ind).ok_or("Index out of bounds!")?;
                 Ok(TrackOrArtist {
                     name: found_track.name.clone(),
                     id: found_track.id.clone(),
@@ -1109,7 +7727,7 @@ async fn find(
                     return Err("Didn't find any artists. Did you typo the artists name?".into());
                 }
                 let ind = choose_element(&a.items)?;
-                let found_artist = a.items.get(ind as usize).ok_or("Index out of bounds!")?;
+                let found_artist = a.items.get(ind).ok_or("Index out of bounds!")?;
                 Ok(TrackOrArtist {
                     name: found_artist.name.clone(),
                     id: found_artist.id.clone(),
@@ -1120,83 +7738,116 @@ async fn find(
     }
 }
 
-fn choose_element<T: Display>(elems: &[T]) -> Result<u8, Box<dyn error::Error>> {
-    println!("Which one of these is the one you wanted?");
-    println!("Give the number/index of the one you want, or X if none of them.\n");
-    for (ind, e) in elems.iter().enumerate() {
-        println!("#{ind}: {e}");
+/// Asks which of the elements the user wants, with the fuzzy picker if it's
+/// enabled and otherwise by its number.
+fn choose_element<T: Display + TableRow>(elems: &[T]) -> Result<usize, Box<dyn error::Error>> {
+    if let Some(ind) = prompt::preset_choice() {
+        let elem = elems.get(ind).ok_or_else(|| {
+            format!(
+                "--choose {ind} is out of range; the choices are numbered 0-{}.",
+                elems.len().saturating_sub(1)
+            )
+        })?;
+        println!("{}", tr!("Chose #{ind}: {elem}", ind = ind, elem = elem));
+        return Ok(ind);
+    }
+    if picker::enabled()? {
+        let items: Vec<String> = elems.iter().map(|e| e.to_string()).collect();
+        return picker::pick(tr!("Which one of these is the one you wanted?"), &items, "")?
+            .ok_or_else(|| tr!("None selected.").into());
+    }
+
+    println!("{}", tr!("Which one of these is the one you wanted?"));
+    println!("{}\n", tr!("Give the number/index of the one you want, or X if none of them (--choose <number> answers this in scripts)."));
+    if output::tables() {
+        output::print_numbered_table(elems);
+    } else {
+        for (ind, e) in elems.iter().enumerate() {
+            println!("#{ind}: {e}");
+        }
     }
 
     let mut user_response = String::new();
-    io::stdin().read_line(&mut user_response)?;
+    prompt::read_line(&mut user_response)?;
     user_response = user_response.trim().to_lowercase();
 
     if !(user_response.is_empty() || user_response.starts_with("x")) {
-        let ind: u8 = user_response.parse()?;
+        let ind: usize = user_response.parse()?;
 
         Ok(ind)
     } else {
-        Err("None selected.".into())
+        Err(tr!("None selected.").into())
     }
 }
 
+/// Creates a playlist for the current user. Collaborative playlists can't
+/// be public.
 async fn create_playlist(
-    auth: &mut SpotifyAuth,
+    auth: &mut impl TokenProvider,
     name: &str,
     description: &str,
     public: bool,
+    collaborative: bool,
 ) -> Result<PlaylistCreateResponse, Box<dyn error::Error>> {
     let user = get_user(auth).await?;
 
-    #[cfg(debug_assertions)]
-    println!("Creating playlist for user with id: {}", user.id);
-
-    let url = format!("https://api.spotify.com/v1/users/{}/playlists", user.id);
+    tracing::debug!(user = %user.id, "Creating a playlist");
 
-    let headers = auth_header(auth).await?;
+    let url = api_url(&format!("/users/{}/playlists", user.id));
 
-    let client = reqwest::Client::new();
-    let mut res_builder = client.post(url).headers(headers);
+    let client = http::client();
+    let mut res_builder = client.post(url);
     let mut map = serde_json::Map::new();
     map.insert("name".to_string(), serde_json::Value::from(name));
-    map.insert("public".to_string(), serde_json::Value::from(public));
+    map.insert(
+        "public".to_string(),
+        serde_json::Value::from(public && !collaborative),
+    );
+    map.insert(
+        "collaborative".to_string(),
+        serde_json::Value::from(collaborative),
+    );
     map.insert(
         "description".to_string(),
         serde_json::Value::from(description),
     );
     res_builder = res_builder.json(&map);
-    let res = res_builder.send().await?;
-
-    let response_text = check_for_error_and_return_text(res).await?;
-    let playlist_create_response: PlaylistCreateResponse =
-        serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let playlist_create_response: PlaylistCreateResponse = api_request(auth, res_builder).await?;
 
     Ok(playlist_create_response)
 }
 
-pub async fn recommendation_init(auth: &mut SpotifyAuth) -> Result<(), Box<dyn error::Error>> {
+pub async fn recommendation_init(
+    auth: &mut impl TokenProvider,
+) -> Result<(), Box<dyn error::Error>> {
     if let Ok(id) = get_managed_playlist_id() {
-        println!("The env variable for a managed playlist is already set to: {id}");
-        println!("Do you want to create a new managed playlist anyway? (Y/n)");
-
-        let mut user_response = String::new();
-        io::stdin().read_line(&mut user_response)?;
-        user_response = user_response.trim().to_lowercase();
-
-        if !(user_response.is_empty() || user_response.starts_with("y")) {
-            println!("Ok, NOT creating a new playlist. Exiting.");
+        println!(
+            "{}",
+            tr!(
+                "The env variable for a managed playlist is already set to: {id}",
+                id = id
+            )
+        );
+        if !prompt::confirm(
+            tr!("Do you want to create a new managed playlist anyway?"),
+            true,
+        )? {
+            println!("{}", tr!("Ok, NOT creating a new playlist. Exiting."));
             return Ok(());
         }
     }
 
     let name = "CLI managed playlist";
     let description = "This playlist is created and managed by a CLI tool to hold generated recommendations. Do not touch!";
-    let playlist_create_response = create_playlist(auth, name, description, false).await?;
+    let playlist_create_response = create_playlist(auth, name, description, false, false).await?;
 
-    println!("Managed playlist created.");
-    println!("The API does not allow setting the playlist as fully private; you might want to do this from the app now.");
+    println!("{}", tr!("Managed playlist created."));
+    println!("{}", tr!("The API does not allow setting the playlist as fully private; you might want to do this from the app now."));
     println!();
-    println!("You now need to set the following environment variable:");
+    println!(
+        "{}",
+        tr!("You now need to set the following environment variable:")
+    );
     println!(
         "export SPOTIFY_CLI_MANAGED_PLAYLIST_ID={}",
         playlist_create_response.id
@@ -1205,33 +7856,58 @@ pub async fn recommendation_init(auth: &mut SpotifyAuth) -> Result<(), Box<dyn e
     Ok(())
 }
 
-async fn get_user(auth: &mut SpotifyAuth) -> Result<User, Box<dyn error::Error>> {
-    let url = "https://api.spotify.com/v1/me".to_string();
-
-    let headers = auth_header(auth).await?;
+async fn get_user(auth: &mut impl TokenProvider) -> Result<User, Box<dyn error::Error>> {
+    let url = api_url("/me");
 
-    let client = reqwest::Client::new();
-    let res = client.get(url).headers(headers).send().await?;
-
-    let response_text = check_for_error_and_return_text(res).await?;
-    let user_response: User = serde_json::from_str(&response_text).map_err(|_| response_text)?;
+    let client = http::client();
+    let user_response: User = api_request(auth, client.get(url)).await?;
 
     Ok(user_response)
 }
 
+/// The seconds to wait from a `Retry-After` header.
+fn retry_after(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
 async fn check_for_error_and_return_text(
     res: reqwest::Response,
 ) -> Result<String, Box<dyn error::Error>> {
-    if res.error_for_status_ref().is_err() {
-        let response_text = res.text().await?;
-        let response_parsed: Result<Value, serde_json::Error> =
-            serde_json::from_str(&response_text);
-        match response_parsed {
-            Ok(val) => Err(val["error"]["message"].as_str().unwrap().into()),
-            Err(_) => Err(response_text.into()),
-        }
-    } else {
-        let response_text = res.text().await?;
-        Ok(response_text)
+    let status = res.status();
+    let retry_after = retry_after(res.headers());
+    let response_text = res.text().await?;
+
+    if status.is_client_error() || status.is_server_error() {
+        tracing::debug!(body = %logging::redact_json(&response_text), "Error response");
+        return Err(ApiError::from_response(status, retry_after, &response_text).into());
     }
+
+    tracing::trace!(body = %logging::redact_json(&response_text), "Response body");
+    Ok(response_text)
+}
+
+/// Checks the response for errors and parses its JSON body.
+async fn parse_response<T: DeserializeOwned>(
+    res: reqwest::Response,
+) -> Result<T, Box<dyn error::Error>> {
+    let response_text = check_for_error_and_return_text(res).await?;
+
+    serde_json::from_str(&response_text).map_err(|e| {
+        tracing::debug!(body = %logging::redact_json(&response_text), "Unparseable response");
+        format!("Unexpected response from the Spotify API: {e}").into()
+    })
+}
+
+/// Sends the request with `send` and parses the JSON response, turning
+/// error responses into `ApiError`s.
+async fn api_request<T: DeserializeOwned>(
+    auth: &mut impl TokenProvider,
+    request: reqwest::RequestBuilder,
+) -> Result<T, Box<dyn error::Error>> {
+    let res = send(auth, request).await?;
+
+    parse_response(res).await
 }
@@ -0,0 +1,164 @@
+use super::{
+    config::HistoryConfig,
+    paths::{self, DefaultFile},
+};
+use chrono::{Datelike, Local, NaiveDate};
+use rusqlite::{params, Connection};
+use std::error;
+
+/// Local listening history recorded by `watch` when enabled in the config.
+/// Spotify's own recently played endpoint only goes back 50 tracks.
+pub struct History {
+    conn: Connection,
+}
+
+/// A track that started playing.
+pub struct Play<'a> {
+    pub played_at: u64,
+    pub track_uri: &'a str,
+    pub track: &'a str,
+    pub artists: &'a [&'a str],
+    pub album: Option<&'a str>,
+    pub context_uri: Option<&'a str>,
+    pub device: &'a str,
+}
+
+pub struct ArtistStats {
+    pub artist: String,
+    pub plays: u64,
+    pub listened_ms: u64,
+}
+
+/// Calendar periods for stats, in local time.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Period {
+    /// Since Monday
+    Week,
+    /// Since the 1st of this month
+    Month,
+    /// Since January 1st
+    Year,
+    All,
+}
+
+impl Period {
+    /// Start of the period in seconds from the epoch.
+    pub fn start(&self) -> u64 {
+        let today = Local::now().date_naive();
+        let first_day = match self {
+            Period::Week => {
+                today - chrono::Duration::days(today.weekday().num_days_from_monday().into())
+            }
+            Period::Month => today.with_day(1).unwrap_or(today),
+            Period::Year => NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap_or(today),
+            Period::All => return 0,
+        };
+
+        first_day
+            .and_hms_opt(0, 0, 0)
+            .and_then(|start| start.and_local_timezone(Local).earliest())
+            .map(|start| start.timestamp().max(0) as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// The history file set in the config, or the default one.
+pub fn history_path(config: &HistoryConfig) -> Result<String, Box<dyn error::Error>> {
+    match &config.file {
+        Some(filepath) => Ok(filepath.clone()),
+        None => paths::default_path(DefaultFile::History),
+    }
+}
+
+impl History {
+    pub fn open(filepath: &str) -> Result<History, Box<dyn error::Error>> {
+        let conn = Connection::open(filepath)
+            .map_err(|e| format!("Failed to open the history database {filepath}: {e}"))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS plays (
+                id INTEGER PRIMARY KEY,
+                played_at INTEGER NOT NULL,
+                track_uri TEXT NOT NULL,
+                track TEXT NOT NULL,
+                artist TEXT NOT NULL,
+                artists TEXT NOT NULL,
+                album TEXT,
+                context_uri TEXT,
+                device TEXT NOT NULL,
+                listened_ms INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS plays_played_at ON plays (played_at);",
+        )?;
+
+        Ok(History { conn })
+    }
+
+    /// Records a play and returns its id for `set_listened`.
+    pub fn record(&self, play: &Play) -> Result<i64, Box<dyn error::Error>> {
+        self.conn.execute(
+            "INSERT INTO plays (played_at, track_uri, track, artist, artists, album, context_uri, device)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                play.played_at as i64,
+                play.track_uri,
+                play.track,
+                play.artists.first().copied().unwrap_or("unknown artist"),
+                play.artists.join(", "),
+                play.album,
+                play.context_uri,
+                play.device,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Updates how long a play has been listened to so far.
+    pub fn set_listened(&self, id: i64, listened_ms: u64) -> Result<(), Box<dyn error::Error>> {
+        self.conn.execute(
+            "UPDATE plays SET listened_ms = MAX(listened_ms, ?1) WHERE id = ?2",
+            params![listened_ms as i64, id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Number of plays and total listening time since `since` (seconds from
+    /// the epoch).
+    pub fn totals(&self, since: u64) -> Result<(u64, u64), Box<dyn error::Error>> {
+        let totals = self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(listened_ms), 0) FROM plays WHERE played_at >= ?1",
+            params![since as i64],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )?;
+
+        Ok(totals)
+    }
+
+    /// Most played artists since `since` (seconds from the epoch). Only the
+    /// first artist of each track counts.
+    pub fn top_artists(
+        &self,
+        since: u64,
+        limit: usize,
+    ) -> Result<Vec<ArtistStats>, Box<dyn error::Error>> {
+        let mut statement = self.conn.prepare(
+            "SELECT artist, COUNT(*) AS plays, SUM(listened_ms) FROM plays
+            WHERE played_at >= ?1
+            GROUP BY artist
+            ORDER BY plays DESC, artist
+            LIMIT ?2",
+        )?;
+        let artists = statement
+            .query_map(params![since as i64, limit as i64], |row| {
+                Ok(ArtistStats {
+                    artist: row.get(0)?,
+                    plays: row.get::<_, i64>(1)? as u64,
+                    listened_ms: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<Result<Vec<ArtistStats>, _>>()?;
+
+        Ok(artists)
+    }
+}
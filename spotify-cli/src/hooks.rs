@@ -0,0 +1,64 @@
+use super::i18n::tr;
+use std::{process, thread};
+
+/// Playback events that hook commands can be attached to in the config.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    TrackChange,
+    Pause,
+    Resume,
+    DeviceChange,
+    DeviceLost,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::TrackChange => "track_change",
+            Event::Pause => "pause",
+            Event::Resume => "resume",
+            Event::DeviceChange => "device_change",
+            Event::DeviceLost => "device_lost",
+        }
+    }
+}
+
+/// Runs a hook command with the shell without waiting for it to finish.
+///
+/// The command gets the event name in `$SPOTIFY_EVENT` and the given
+/// variables, e.g. `$SPOTIFY_TRACK`, `$SPOTIFY_ARTIST`, and `$SPOTIFY_URI`.
+pub fn run(command: &str, event: Event, vars: &[(&str, String)]) {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = process::Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = process::Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    };
+
+    cmd.env("SPOTIFY_EVENT", event.name());
+    for (key, value) in vars {
+        cmd.env(key, value);
+    }
+
+    match cmd.spawn() {
+        // Reap the child in the background so long running watchers
+        // don't pile up zombie processes.
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!(
+            "{}",
+            tr!(
+                "Failed to run the {hook} hook: {e}",
+                hook = event.name(),
+                e = e
+            )
+        ),
+    }
+}
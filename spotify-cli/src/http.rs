@@ -0,0 +1,75 @@
+use super::config::NetworkConfig;
+use std::{env, error, fs, sync::OnceLock, time::Duration};
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One client shared by all requests, so the network settings apply
+/// everywhere and connections are reused.
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Builds the shared client. Has to be called before any requests are made;
+/// until then requests use a client with the default settings. `timeout`,
+/// from `--timeout`, overrides the configured request timeout.
+pub fn init(
+    config: &NetworkConfig,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn error::Error>> {
+    let connect_timeout = match config.connect_timeout_secs {
+        Some(secs) => secs_to_duration(secs, "connect_timeout_secs")?,
+        None => DEFAULT_CONNECT_TIMEOUT,
+    };
+    let timeout = match (timeout, config.timeout_secs) {
+        (Some(timeout), _) => timeout,
+        (None, Some(secs)) => secs_to_duration(secs, "timeout_secs")?,
+        (None, None) => DEFAULT_TIMEOUT,
+    };
+    let _ = TIMEOUT.set(timeout);
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .timeout(timeout);
+
+    if let Some(proxy) = env::var("SPOTIFY_CLI_PROXY").ok().or(config.proxy.clone()) {
+        let proxy =
+            reqwest::Proxy::all(&proxy).map_err(|e| format!("Invalid proxy {proxy}: {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(ca_file) = env::var("SPOTIFY_CLI_CA_FILE")
+        .ok()
+        .or(config.ca_file.clone())
+    {
+        let pem = fs::read(&ca_file).map_err(|e| format!("Failed to read {ca_file}: {e}"))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid CA file {ca_file}: {e}"))?;
+        if certificates.is_empty() {
+            return Err(format!("Invalid CA file {ca_file}: no certificates found.").into());
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    let _ = CLIENT.set(builder.build()?);
+
+    Ok(())
+}
+
+fn secs_to_duration(secs: f64, name: &str) -> Result<Duration, Box<dyn error::Error>> {
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("{name} in [network] must be a positive number of seconds.").into())
+}
+
+/// The request timeout of the shared client.
+pub fn timeout() -> Duration {
+    TIMEOUT.get().copied().unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// The shared client. Cloning it is cheap.
+pub fn client() -> reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new).clone()
+}
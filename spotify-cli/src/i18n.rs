@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Translations of the messages, keyed by the English text like in gettext.
+/// English is the baseline, so a message without a translation is printed
+/// as is.
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Looks up the translation of a message, filling in its `{name}`
+/// placeholders from the given values, e.g.
+/// `tr!("Added {count} track(s).", count = uris.len())`.
+///
+/// `locale/template.toml` lists every message for translators; keep it up to
+/// date when adding or changing messages.
+macro_rules! tr {
+    ($msgid:literal) => {
+        $crate::i18n::translate($msgid)
+    };
+    ($msgid:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::fill(
+            $crate::i18n::translate($msgid),
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+        )
+    };
+}
+pub(crate) use tr;
+
+/// Loads the catalog of the user's locale, if one is installed. Until this is
+/// called everything is in English.
+pub fn init() {
+    let catalog = match locale() {
+        Some(locale) => load_catalog(&locale),
+        None => HashMap::new(),
+    };
+    let _ = CATALOG.set(catalog);
+}
+
+pub fn translate(msgid: &'static str) -> &'static str {
+    CATALOG
+        .get()
+        .and_then(|catalog| catalog.get(msgid))
+        .map(|translation| translation.as_str())
+        .unwrap_or(msgid)
+}
+
+/// Replaces each `{name}` in the message with its value.
+pub fn fill(message: &str, values: &[(&str, &dyn Display)]) -> String {
+    let mut filled = message.to_string();
+    for (name, value) in values {
+        filled = filled.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    filled
+}
+
+/// The locale messages are shown in, from the first of `LC_ALL`,
+/// `LC_MESSAGES` and `LANG` that's set, e.g. `fi_FI.UTF-8`.
+fn locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+        .filter(|locale| locale != "C" && locale != "POSIX")
+}
+
+/// The catalog names to try for a locale, most specific first: `fi_FI.UTF-8`
+/// gives `fi_FI` and `fi`.
+fn catalog_names(locale: &str) -> Vec<String> {
+    let locale = locale.split(['.', '@']).next().unwrap_or(locale);
+    let mut names = vec![locale.to_string()];
+    if let Some((language, _)) = locale.split_once('_') {
+        names.push(language.to_string());
+    }
+    names
+}
+
+/// Where catalogs are looked for: `SPOTIFY_CLI_LOCALE_DIR` if set, else
+/// `spotify-cli/locale` under the user's data directory and then under each
+/// of `XDG_DATA_DIRS`, where packages install them.
+fn catalog_dirs() -> Vec<PathBuf> {
+    if let Ok(dir) = env::var("SPOTIFY_CLI_LOCALE_DIR") {
+        return vec![PathBuf::from(dir)];
+    }
+
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or("/usr/local/share:/usr/share".to_string());
+    dirs::data_dir()
+        .into_iter()
+        .chain(env::split_paths(&data_dirs))
+        .map(|dir| dir.join("spotify-cli").join("locale"))
+        .collect()
+}
+
+/// Reads `<name>.toml` of the first catalog name found in the catalog
+/// directories. Missing catalogs leave the messages in English; broken ones
+/// are reported but don't stop the command.
+fn load_catalog(locale: &str) -> HashMap<String, String> {
+    let dirs = catalog_dirs();
+    for name in catalog_names(locale) {
+        for dir in &dirs {
+            let path = dir.join(format!("{name}.toml"));
+            if !path.is_file() {
+                continue;
+            }
+            match read_catalog(&path) {
+                Ok(catalog) => return catalog,
+                Err(e) => eprintln!("Failed to read the translations in {}: {e}", path.display()),
+            }
+        }
+    }
+
+    HashMap::new()
+}
+
+/// A catalog is a TOML table from each English message to its translation.
+/// Empty translations are left out so that a partly filled in copy of the
+/// template works.
+fn read_catalog(path: &Path) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let catalog: HashMap<String, String> = toml::from_str(&fs::read_to_string(path)?)?;
+
+    Ok(catalog
+        .into_iter()
+        .filter(|(_, translation)| !translation.is_empty())
+        .collect())
+}
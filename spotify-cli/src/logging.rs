@@ -0,0 +1,103 @@
+use serde_json::Value;
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+/// Keys whose values are never logged.
+const SECRET_KEYS: [&str; 5] = [
+    "access_token",
+    "refresh_token",
+    "code",
+    "client_secret",
+    "authorization",
+];
+
+const REDACTED: &str = "<redacted>";
+
+/// Sets up logging to stderr. `RUST_LOG` takes precedence over the
+/// verbosity, which is the number of `-v` flags given: 1 logs the requests
+/// made and 2 also the bodies.
+pub fn init(verbosity: u8) {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::new(match verbosity {
+            0 => "warn",
+            1 => "warn,spotify_cli=debug",
+            _ => "warn,spotify_cli=trace",
+        }),
+    };
+
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .try_init();
+}
+
+fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key.to_lowercase().as_str())
+}
+
+/// Form fields with the values of secrets replaced.
+pub fn redact_form<'a>(form: &[(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+    form.iter()
+        .map(|&(key, value)| match is_secret(key) {
+            true => (key, REDACTED),
+            false => (key, value),
+        })
+        .collect()
+}
+
+/// A JSON body with the values of secrets replaced. Other bodies are
+/// returned as is.
+pub fn redact_json(body: &str) -> String {
+    fn redact(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if is_secret(key) {
+                        *value = Value::from(REDACTED);
+                    } else {
+                        redact(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(redact),
+            _ => {}
+        }
+    }
+
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => body.to_string(),
+    }
+}
+
+/// A url with the values of secret query parameters replaced.
+pub fn redact_url(url: &reqwest::Url) -> String {
+    if !url.query_pairs().any(|(key, _)| is_secret(&key)) {
+        return url.to_string();
+    }
+
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs.iter().map(|(key, value)| match is_secret(key) {
+            true => (key.as_str(), REDACTED),
+            false => (key.as_str(), value.as_str()),
+        }));
+
+    url.to_string()
+}
+
+/// Like `redact_url`, for a url that may not parse, e.g. a bare
+/// authorization code pasted by the user.
+pub fn redact_url_str(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => redact_url(&url),
+        Err(_) => REDACTED.to_string(),
+    }
+}
@@ -0,0 +1,174 @@
+use super::http;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::error;
+
+/// What we know about the track to look up lyrics for.
+pub struct LyricsQuery {
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub duration_ms: u64,
+}
+
+pub struct Lyrics {
+    pub plain: Option<String>,
+    /// Time-stamped lines sorted by time in milliseconds.
+    pub synced: Option<Vec<(u64, String)>>,
+}
+
+/// Source of lyrics. Spotify has no public lyrics API so lyrics always come
+/// from a third party.
+pub trait LyricsProvider {
+    /// Returns `None` if the provider has no lyrics for the track.
+    async fn fetch(&self, query: &LyricsQuery) -> Result<Option<Lyrics>, Box<dyn error::Error>>;
+}
+
+/// https://lrclib.net or any server implementing the same `/api/get` API.
+pub struct Lrclib {
+    pub base_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LrclibResponse {
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+impl LyricsProvider for Lrclib {
+    async fn fetch(&self, query: &LyricsQuery) -> Result<Option<Lyrics>, Box<dyn error::Error>> {
+        let url = format!("{}/api/get", self.base_url.trim_end_matches('/'));
+
+        let client = http::client();
+        let mut request_builder = client
+            .get(url)
+            .header("user-agent", "spotify-cli")
+            .query(&[
+                ("track_name", query.title.as_str()),
+                ("artist_name", query.artist.as_str()),
+            ])
+            .query(&[("duration", query.duration_ms / 1000)]);
+        if let Some(album) = &query.album {
+            request_builder = request_builder.query(&[("album_name", album)]);
+        }
+        let res = request_builder.send().await?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if res.error_for_status_ref().is_err() {
+            return Err(format!("Lyrics request failed: {}", res.status()).into());
+        }
+
+        let lrclib_response: LrclibResponse = res.json().await?;
+
+        Ok(Some(Lyrics {
+            plain: lrclib_response.plain_lyrics,
+            synced: lrclib_response.synced_lyrics.map(|lrc| parse_lrc(&lrc)),
+        }))
+    }
+}
+
+/// A user configured endpoint given as a url template with the placeholders
+/// `{artist}`, `{title}`, `{album}`, and `{duration}` (seconds). The response
+/// body is used as the lyrics; LRC formatted bodies are treated as synced.
+pub struct UrlTemplate {
+    pub template: String,
+}
+
+impl LyricsProvider for UrlTemplate {
+    async fn fetch(&self, query: &LyricsQuery) -> Result<Option<Lyrics>, Box<dyn error::Error>> {
+        let url = self
+            .template
+            .replace("{artist}", &percent_encode(&query.artist))
+            .replace("{title}", &percent_encode(&query.title))
+            .replace(
+                "{album}",
+                &percent_encode(query.album.as_deref().unwrap_or_default()),
+            )
+            .replace("{duration}", &(query.duration_ms / 1000).to_string());
+
+        let client = http::client();
+        let res = client
+            .get(url)
+            .header("user-agent", "spotify-cli")
+            .send()
+            .await?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if res.error_for_status_ref().is_err() {
+            return Err(format!("Lyrics request failed: {}", res.status()).into());
+        }
+
+        let text = res.text().await?;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let synced = parse_lrc(&text);
+        if synced.is_empty() {
+            Ok(Some(Lyrics {
+                plain: Some(text),
+                synced: None,
+            }))
+        } else {
+            let plain = synced
+                .iter()
+                .map(|(_, line)| line.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok(Some(Lyrics {
+                plain: Some(plain),
+                synced: Some(synced),
+            }))
+        }
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Parses LRC lines like `[01:23.45] some words` into (milliseconds, line)
+/// pairs. Lines without a timestamp (e.g. `[ar: ...]` tags) are skipped.
+pub fn parse_lrc(lrc: &str) -> Vec<(u64, String)> {
+    let mut lines = Vec::new();
+
+    for line in lrc.lines() {
+        let mut rest = line.trim();
+        let mut timestamps = Vec::new();
+        while let Some(stripped) = rest.strip_prefix('[') {
+            let Some((tag, after)) = stripped.split_once(']') else {
+                break;
+            };
+            match parse_lrc_timestamp(tag) {
+                Some(ms) => timestamps.push(ms),
+                None => break,
+            }
+            rest = after;
+        }
+        for ms in timestamps {
+            lines.push((ms, rest.trim().to_string()));
+        }
+    }
+
+    lines.sort_by_key(|(ms, _)| *ms);
+    lines
+}
+
+fn parse_lrc_timestamp(tag: &str) -> Option<u64> {
+    let (mins, secs) = tag.split_once(':')?;
+    let mins: u64 = mins.parse().ok()?;
+    let secs: f64 = secs.parse().ok()?;
+    Some(mins * 60_000 + (secs * 1000.0).round() as u64)
+}
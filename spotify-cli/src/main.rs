@@ -1,10 +1,40 @@
+mod api;
+mod art;
 mod auth;
+mod cache;
+mod config;
+mod desktop;
+mod duration;
+mod genres;
 mod handlers;
+mod history;
+mod hooks;
+mod http;
+mod i18n;
+mod logging;
+mod lyrics;
+mod models;
+mod output;
+mod paths;
+mod picker;
+mod progress;
+mod prompt;
+mod schedule;
+mod snapshots;
+mod state;
+mod text;
+mod usage;
 
-use auth::SpotifyAuth;
-use clap::{Args, Parser, Subcommand};
+use auth::{Scope, SpotifyAuth, StaticToken, TokenProvider};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use duration::{parse_duration, parse_seconds};
 use handlers::*;
-use std::{env, error, fs, io, time::Duration};
+use i18n::tr;
+use output::{ColorChoice, OutputMode};
+use picker::PickerChoice;
+use progress::ProgressFormat;
+use schedule::SchedulerEntry;
+use std::{env, error, fs, io, process, time::Duration};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -31,60 +61,364 @@ struct App {
 
 #[derive(Clone, Debug, Args)]
 struct Options {
-    /// Filepath for storing auth tokens; if omitted ~/.spotify_cli_token is used
+    /// Filepath for storing auth tokens; if omitted
+    /// $XDG_CONFIG_HOME/spotify-cli/token.json or SPOTIFY_CLI_TOKEN_FILE is used
     #[clap(long, short, global = true)]
     token_path: Option<String>,
+
+    /// Base url of the Web API, e.g. a mock server for testing; can also be set with SPOTIFY_CLI_API_URL
+    #[clap(long, global = true)]
+    base_url: Option<String>,
+
+    /// Device (name or id) to control instead of the active one. Also used
+    /// when starting playback while no device is active
+    #[clap(long, global = true)]
+    device: Option<String>,
+
+    /// Country code of the catalog to use, e.g. FI; defaults to the account's
+    /// country. Overrides market in the config
+    #[clap(long, global = true)]
+    market: Option<String>,
+
+    /// When to color the output
+    #[clap(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// When to choose devices, tracks, artists and playlists with a fuzzy finder
+    #[clap(long, global = true, value_enum, default_value_t = PickerChoice::Auto)]
+    picker: PickerChoice,
+
+    /// Answer "which one of these" prompts with this number instead of asking
+    #[clap(long, global = true)]
+    choose: Option<usize>,
+
+    /// Answer yes to confirmations, e.g. accepting generated recommendations
+    #[clap(long, global = true)]
+    accept: bool,
+
+    /// Log requests to stderr; twice to also log the bodies. RUST_LOG overrides this
+    #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print what was asked for and errors, leaving out messages like
+    /// confirmations and the playback shown after playback commands
+    #[clap(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// If a playlist changing command fails for lack of network, save it to
+    /// run later with `sync` instead of failing
+    #[clap(long, global = true)]
+    queue_offline: bool,
+
+    /// Keep the token, config, cache, state and history files as dotfiles in
+    /// the home directory instead of moving them to the XDG directories
+    #[clap(long, global = true)]
+    legacy_paths: bool,
+
+    /// Seconds to wait for each request before failing, e.g. 5 or 500ms.
+    /// Overrides timeout_secs in the config; defaults to 30
+    #[clap(long, global = true, value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+
+    /// How to print lists of tracks, playlists and devices; json also prints
+    /// errors on stderr as json, for scripts
+    #[clap(long, global = true, value_enum, default_value_t = OutputMode::Text)]
+    output: OutputMode,
+
+    /// Print long lines and table cells in full instead of cutting them to
+    /// the terminal width
+    #[clap(long, global = true)]
+    no_truncate: bool,
+
+    /// Print how many API calls the command made to stderr; see also `quota`
+    #[clap(long, global = true)]
+    quota_info: bool,
+
+    /// Mark explicit tracks with [E] and show the popularity (0-100) of tracks
+    #[clap(long, global = true)]
+    details: bool,
+
+    /// How to report the progress of long operations on stderr; json prints
+    /// an event per line for GUIs and wrappers
+    #[clap(long, global = true, value_enum, default_value_t = ProgressFormat::Text)]
+    progress: ProgressFormat,
 }
 
 #[derive(Clone, Debug, Subcommand)]
 enum Command {
     /// Show current playback
-    Show,
+    Show {
+        /// Print a single line using a template, e.g. '{artist} - {title} [{progress}/{duration}]'.
+        /// Placeholders: artist, title, album, device, state, progress, duration, url
+        #[arg(long)]
+        format: Option<String>,
+        /// Only print the open.spotify.com url of the current track
+        #[arg(long, conflicts_with = "format")]
+        url: bool,
+        /// With --url, print the url of the current playlist/album/artist instead
+        #[arg(long, requires = "url")]
+        context: bool,
+        /// Also draw the album cover; the protocol is picked based on the terminal unless given
+        #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "auto", conflicts_with = "url")]
+        art: Option<art::ArtProtocol>,
+        /// Also print the position of the track in the playlist or album, e.g. track 14 of 52
+        #[arg(long, conflicts_with_all = ["url", "format"])]
+        context_progress: bool,
+    },
+
+    /// Copy the open.spotify.com url of the current track to the clipboard
+    Copy {
+        /// Copy the url of the current playlist/album/artist instead
+        #[arg(long)]
+        context: bool,
+    },
+
+    /// Open the current track (default), context or a playlist in the browser
+    Open {
+        #[command(subcommand)]
+        target: Option<OpenTarget>,
+        /// Open in the Spotify app instead of the browser
+        #[arg(long)]
+        app: bool,
+    },
 
-    /// Pause playback
+    /// Pause playback. Where an episode or a long track was is remembered
+    /// for resume-last (see remember_position_mins in the config)
     #[command(visible_alias = "stop")]
     Pause,
 
+    /// Play the episode or long track last paused from where it was paused
+    ResumeLast,
+
     /// Start/resume playback
     #[command(visible_alias = "start")]
     Play,
 
+    /// Pause if playing, otherwise start/resume playback
+    Toggle {
+        /// With no active device, wake up the device playback was last on
+        /// instead of asking which one to use
+        #[arg(long)]
+        last_device: bool,
+    },
+
+    /// Play a single track given as a uri/url or searched for by name
+    PlayTrack {
+        query_or_uri: String,
+        /// Play the top search result without asking
+        #[arg(long)]
+        first: bool,
+    },
+
+    /// Play what a spotify: uri or open.spotify.com url points to; used as
+    /// the handler of spotify: links (see `setup uri-handler`)
+    HandleUri { uri: String },
+
     /// Play next track
     #[command(visible_alias = "forward")]
     Next,
 
-    /// Play previous track
+    /// Play previous track, or restart the current one if more than a few
+    /// seconds into it (see previous_restart_secs in the config)
     #[command(visible_alias = "back")]
-    Previous,
+    Previous {
+        /// Always go to the previous track
+        #[arg(long)]
+        force: bool,
+    },
 
     /// Restart current track
     #[command(visible_alias = "rewind")]
     Restart,
 
-    /// Show the current playlist's tracks
+    /// Mute, or unmute back to the volume before muting
+    Mute,
+
+    /// Show the tracks of the current playlist, album or artist
     Current {
         /// Max number of songs to print around the current track
         max_lines: Option<u16>,
+        /// Number of songs to print before and after the current track
+        #[arg(long, conflicts_with = "max_lines")]
+        around: Option<u16>,
     },
 
-    /// Jump to song in current playlist
-    Jump { offset: u16 },
+    /// Jump to a song in the current playlist or album
+    Jump {
+        /// Position of the song as shown by `current`, or +N/-N to move N songs
+        /// from the current one
+        #[arg(allow_hyphen_values = true, value_parser = parse_jump)]
+        offset: Jump,
+    },
 
-    /// Show current queue
+    /// Show current queue, telling queued tracks from the ones coming up in the
+    /// playlist or album
+    #[command(args_conflicts_with_subcommands = true)]
     Queue {
         /// Number of songs in the queue to show (including the current song).
         #[arg(default_value = "5")]
         number: usize,
+        /// Show the whole queue
+        #[arg(long, conflicts_with = "number")]
+        all: bool,
+
+        #[command(subcommand)]
+        action: Option<QueueCommand>,
+    },
+
+    /// Show how many API calls were made recently per endpoint, and rate limiting
+    Quota,
+
+    /// Check the setup and print what's wrong with hints on fixing it
+    Doctor,
+
+    /// Print a short cached now playing summary for shell prompts
+    PromptSegment {
+        /// Max age in seconds of the cached summary before refetching
+        #[arg(long, default_value = "10")]
+        max_age: u64,
+        /// Max time in milliseconds to spend on refetching
+        #[arg(long, default_value = "300")]
+        budget_ms: u64,
+    },
+
+    /// Watch playback and run the hook commands set in the config on events
+    Watch {
+        /// Seconds between polling the player
+        #[arg(long, default_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
+
+    /// Pause playback after a while, e.g. `sleep 30m`
+    Sleep {
+        /// How long to wait: e.g. 30m, 1h30m, 90s, or just minutes
+        #[arg(value_parser = parse_duration)]
+        duration: Duration,
+        /// Lower the volume gradually during the last minute
+        #[arg(long)]
+        fade: bool,
+        /// Run in the background so the terminal isn't blocked
+        #[arg(long)]
+        detach: bool,
+    },
+
+    /// Start playback at a time of day, e.g. `schedule 07:00 --playlist Mornings --volume 40`
+    Schedule {
+        /// Local time as HH:MM, or `now`
+        time: String,
+        /// Uri/url/id or name of a playlist to play; if omitted playback is resumed
+        #[arg(long)]
+        playlist: Option<String>,
+        /// Volume to set when starting (0-100)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        volume: Option<u8>,
+        /// Wait in the background so the terminal isn't blocked
+        #[arg(long)]
+        detach: bool,
+        /// Instead of waiting, print a crontab entry or systemd units that
+        /// run this every day
+        #[arg(long, value_enum, conflicts_with = "detach")]
+        print: Option<SchedulerEntry>,
+    },
+
+    /// Show my top tracks or artists
+    Top {
+        #[arg(value_enum)]
+        r#type: TopType,
+        #[arg(long, value_enum, default_value = "medium")]
+        range: TimeRange,
+        /// Number of items to show (1-50)
+        #[arg(long, default_value = "20", value_parser = clap::value_parser!(u8).range(1..=50))]
+        limit: u8,
+    },
+
+    /// Show the lyrics of the current song
+    Lyrics {
+        /// Print the lines in time with the song
+        #[arg(long)]
+        sync: bool,
+    },
+
+    /// Run several commands in one go, e.g. "pause; playlist play <uri>"
+    Exec {
+        /// Commands separated by ';'
+        commands: Option<String>,
+        /// Read the commands line by line from stdin instead
+        #[arg(long, conflicts_with = "commands")]
+        stdin: bool,
+        /// Keep running the rest of the commands after a failure
+        #[arg(long)]
+        keep_going: bool,
     },
 
+    /// Run the commands saved with --queue-offline
+    Sync,
+
     /// Control/see playlists (see subcommands)
     #[command(subcommand)]
     Playlist(PlaylistCommand),
 
+    /// Liked songs commands (see subcommands)
+    #[command(subcommand)]
+    Liked(LikedCommand),
+
+    /// Short @names for uris and devices (see subcommands)
+    #[command(subcommand)]
+    Alias(AliasCommand),
+
+    /// Integrate with the desktop (see subcommands)
+    #[command(subcommand)]
+    Setup(SetupCommand),
+
+    /// Listening history recorded by `watch` (see subcommands)
+    #[command(subcommand)]
+    History(HistoryCommand),
+
+    /// Analytics of your music (see subcommands)
+    #[command(subcommand)]
+    Stats(StatsCommand),
+
+    /// Tracks that `watch` skips automatically (see subcommands)
+    #[command(subcommand)]
+    Block(BlockCommand),
+
     /// Control authentication tokens (see subcommands)
     #[command(subcommand)]
     Auth(AuthCommand),
 
+    /// Browse new releases and featured playlists (see subcommands)
+    #[command(subcommand)]
+    Browse(BrowseCommand),
+
+    /// Track commands (see subcommands)
+    #[command(subcommand)]
+    Track(TrackCommand),
+
+    /// Album commands (see subcommands)
+    #[command(subcommand)]
+    Album(AlbumCommand),
+
+    /// Artist commands (see subcommands)
+    #[command(subcommand)]
+    Artist(ArtistCommand),
+
+    /// Podcasts and other shows (see subcommands)
+    #[command(subcommand, visible_alias = "shows")]
+    Podcast(PodcastCommand),
+
+    /// Start a station from the current track: fill the managed playlist
+    /// with recommendations seeded from it and play them
+    Radio {
+        #[arg(value_enum, default_value = "track")]
+        seed: RadioSeed,
+        /// Number of tracks, 1-100
+        #[arg(long, default_value = "50", value_parser = clap::value_parser!(u8).range(1..=100))]
+        limit: u8,
+        /// Add the tracks to the queue instead of replacing the managed playlist
+        #[arg(long)]
+        queue: bool,
+    },
+
     /// Recommendations commands (see subcommands)
     #[command(subcommand, visible_alias = "rec")]
     Recommendation(RecommendationCommand),
@@ -97,15 +431,420 @@ enum AuthCommand {
 
     /// Reset token, i.e. re-authorize & authenticate
     Reset,
+
+    /// Run the auth flow now, even if already authorized
+    Login {
+        /// Don't start a local server for the redirect; paste the redirected url
+        /// or code instead. For headless machines.
+        #[arg(long, visible_alias = "no-browser")]
+        manual: bool,
+        /// Also print the url as a QR code to authorize on a phone; implies --manual
+        #[arg(long)]
+        qr: bool,
+    },
+
+    /// Delete the saved tokens from this machine
+    Logout {
+        /// Also open the page for removing the app's access to your Spotify account
+        #[arg(long)]
+        revoke: bool,
+    },
+
+    /// Copy the current tokens to a file, e.g. to import them on a headless machine
+    Export { file: String },
+
+    /// Use tokens exported on another machine
+    Import { file: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum QueueCommand {
+    /// Remove the tracks added to the queue by skipping through them and then
+    /// resuming the current track
+    Clear,
+
+    /// Skip forward to the track with the given number in `queue`
+    SkipTo { index: usize },
+
+    /// Add a track or episode given as a uri/url to the end of the queue
+    Add {
+        uri: String,
+        /// Play it right after the current track. Tracks added to the queue
+        /// earlier are skipped through and queued again after it, so they
+        /// briefly play; best effort, especially with shuffle on
+        #[arg(long)]
+        next: bool,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum OpenTarget {
+    /// The current track
+    Track,
+    /// The current playlist/album/artist
+    Context,
+    /// A playlist by name, uri or url
+    Playlist { playlist: String },
 }
 
 #[derive(Clone, Debug, Subcommand)]
 enum PlaylistCommand {
     /// Show/list all my playlists
+    List {
+        /// Only the playlists you own
+        #[arg(long, conflicts_with = "followed")]
+        owned: bool,
+        /// Only the playlists you follow but don't own
+        #[arg(long)]
+        followed: bool,
+        /// Only collaborative playlists
+        #[arg(long)]
+        collaborative: bool,
+        /// Only the playlists with this in the name, ignoring case
+        #[arg(long)]
+        filter: Option<String>,
+        #[arg(long, value_enum, default_value = "recent")]
+        sort: PlaylistSort,
+    },
+
+    /// Start playing a playlist given as a uri/url or one of your playlists' names
+    Play {
+        uri: String,
+        index: Option<u16>,
+        /// Start from a random track
+        #[arg(long, conflicts_with = "index")]
+        random: bool,
+        /// Turn shuffle on and start from a random track
+        #[arg(long, conflicts_with = "index")]
+        shuffle: bool,
+    },
+
+    /// Create a new empty playlist
+    Create {
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+        /// Let others add tracks too; collaborative playlists are always private
+        #[arg(long)]
+        collaborative: bool,
+    },
+
+    /// Add tracks to the end of a playlist. Tracks already in it are skipped
+    Add {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+        /// Uris/urls of the tracks, or names to search for
+        #[arg(required = true)]
+        tracks: Vec<String>,
+        /// Add tracks even if they're already in the playlist
+        #[arg(long)]
+        allow_dupes: bool,
+    },
+
+    /// Copy a playlist into a new playlist (duplicate tracks are dropped)
+    Copy {
+        /// Uri/url/id of the playlist to copy
+        src: String,
+        /// Name of the new playlist
+        dst_name: String,
+        /// Make the new playlist collaborative
+        #[arg(long)]
+        collaborative: bool,
+    },
+
+    /// Upload a JPEG/PNG image as the cover of a playlist; big images are shrunk to fit
+    SetCover {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+        image: String,
+    },
+
+    /// Make a playlist collaborative (on) or not (off)
+    SetCollaborative {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+        #[arg(value_enum)]
+        state: OnOff,
+    },
+
+    /// Merge two playlists into a new playlist (duplicate tracks are dropped)
+    Merge {
+        /// Uri/url/id of the first playlist
+        a: String,
+        /// Uri/url/id of the second playlist
+        b: String,
+        /// Name of the new playlist
+        #[arg(long)]
+        into: String,
+        /// Make the new playlist collaborative
+        #[arg(long)]
+        collaborative: bool,
+    },
+
+    /// Create a new playlist from the tracks of a playlist in some genres
+    Filter {
+        /// Uri/url/id or name of the playlist
+        src: String,
+        /// Genre to keep, e.g. rock (also matches indie rock etc.); can be repeated
+        #[arg(long, required = true)]
+        genre: Vec<String>,
+        /// Name of the new playlist
+        #[arg(long)]
+        into: String,
+        /// Make the new playlist collaborative
+        #[arg(long)]
+        collaborative: bool,
+    },
+
+    /// Export a playlist's tracks to a file
+    Export {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+        #[arg(long, value_enum, default_value = "json")]
+        format: ExportFormat,
+        /// File to write to; if omitted the export is printed to stdout.
+        /// `--output` also works here
+        #[arg(long, short = 'o')]
+        file: Option<String>,
+        /// Also export the tempo, energy, danceability and valence of the tracks
+        #[arg(long)]
+        audio_features: bool,
+    },
+
+    /// Create a new playlist from an exported JSON/CSV file
+    Import {
+        file: String,
+        /// Name of the new playlist; if omitted the file name is used
+        #[arg(long)]
+        name: Option<String>,
+        /// Make the new playlist collaborative
+        #[arg(long)]
+        collaborative: bool,
+    },
+
+    /// Make a playlist match a JSON/CSV track list (e.g. an edited export) by
+    /// only removing, adding and moving the tracks that differ
+    Sync {
+        /// The track list, in the format of `playlist export`
+        file: String,
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+        /// Only print how many changes would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Sort a playlist's tracks
+    Sort {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+        #[arg(long, value_enum)]
+        by: SortBy,
+        /// Sort in descending order
+        #[arg(long)]
+        reverse: bool,
+    },
+
+    /// List the snapshots taken before the CLI changed a playlist
+    Snapshots {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+    },
+
+    /// Restore a playlist to its latest snapshot
+    Undo {
+        /// Uri/url/id or name of the playlist
+        playlist: String,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum LikedCommand {
+    /// Mirror liked songs into a playlist, creating it or updating it incrementally
+    ExportToPlaylist { name: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+enum OnOff {
+    On,
+    Off,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum HistoryCommand {
+    /// Show the total listening time and top artists
+    Stats {
+        #[arg(long, value_enum, default_value = "month")]
+        period: history::Period,
+        /// Number of top artists to show
+        #[arg(long, default_value = "10")]
+        limit: usize,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum AliasCommand {
+    /// Make @name mean the target, anywhere a uri (or with --kind device, a
+    /// device) is expected
+    Add {
+        name: String,
+        target: String,
+        #[arg(long, value_enum, default_value = "uri")]
+        kind: config::AliasKind,
+    },
+
+    /// List the aliases
+    List,
+
+    /// Remove an alias
+    Remove {
+        name: String,
+        /// Only remove the alias of this kind
+        #[arg(long, value_enum)]
+        kind: Option<config::AliasKind>,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum SetupCommand {
+    /// Make clicking spotify: links play them with spotify-cli, on the device
+    /// given with --device if any. Uses xdg-mime on Linux and the registry on
+    /// Windows
+    UriHandler,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum StatsCommand {
+    /// Show the duration, explicit tracks, average audio features, top artists and
+    /// decades of a playlist
+    Playlist {
+        playlist: String,
+        /// Number of top artists to show
+        #[arg(long, default_value = "10")]
+        limit: usize,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum BlockCommand {
+    /// Block a track given as a uri/url, or `current` to block and skip the current track
+    Add { track: String },
+
+    /// List the blocked tracks
     List,
 
-    /// Start playing a playlist
-    Play { uri: String, index: Option<u16> },
+    /// Unblock a track given as a uri/url or its number in `block list`
+    Remove { track: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum BrowseCommand {
+    /// List new album releases
+    NewReleases {
+        #[command(flatten)]
+        args: BrowseArgs,
+    },
+    /// List Spotify's featured playlists
+    FeaturedPlaylists {
+        #[command(flatten)]
+        args: BrowseArgs,
+    },
+    /// List Spotify's browse categories
+    Categories,
+    /// List the playlists of a category, given by its id or name
+    Category {
+        category: String,
+        #[command(flatten)]
+        pick: PickArgs,
+    },
+}
+
+#[derive(Args, Clone, Debug)]
+struct BrowseArgs {
+    /// Number of results to list
+    #[arg(long, default_value = "20", value_parser = clap::value_parser!(u8).range(1..=50))]
+    limit: u8,
+    #[command(flatten)]
+    pick: PickArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+struct PickArgs {
+    /// Play the result with this number in the list instead of listing
+    #[arg(long, conflicts_with = "queue")]
+    play: Option<usize>,
+    /// Add the tracks of the result with this number in the list to the queue
+    #[arg(long)]
+    queue: Option<usize>,
+}
+
+impl PickArgs {
+    fn pick(&self) -> Option<BrowsePick> {
+        match (self.play, self.queue) {
+            (Some(number), _) => Some(BrowsePick::Play(number)),
+            (None, Some(number)) => Some(BrowsePick::Queue(number)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum TrackCommand {
+    /// Show the details of a track given as a uri/url, 'current', or a name
+    /// to search for
+    Info { track: String },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum AlbumCommand {
+    /// Play an album given as a uri/url, optionally from a track by its number
+    Play {
+        album: String,
+        /// Disc of the track, for albums with more than one
+        #[arg(long)]
+        disc: Option<u32>,
+        /// Number of the track to start from, on the first disc unless --disc
+        /// is given
+        #[arg(long)]
+        track: Option<u32>,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum ArtistCommand {
+    /// List the artists related to an artist given as a name or uri/url
+    Related { artist: String },
+    /// Walk through related artists interactively, collecting seeds for
+    /// recommendations or a playlist
+    Explore { artist: String },
+    /// Play an artist given as a name or uri/url
+    Play {
+        artist: String,
+        /// Play their top 10 tracks as a list instead of the artist context
+        #[arg(long)]
+        top_tracks: bool,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum PodcastCommand {
+    /// List your saved shows
+    List,
+    /// List the latest episodes of a show given as a uri/url or a name, with
+    /// where you left off
+    Episodes {
+        show: String,
+        /// Number of episodes to list, 1-50
+        #[arg(short, long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(1..=50))]
+        limit: u8,
+    },
+    /// Play an episode given as a uri/url from where you left off
+    Play {
+        episode: String,
+        /// Play from the beginning instead
+        #[arg(long)]
+        from_start: bool,
+    },
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -126,46 +865,226 @@ enum RecommendationCommand {
     },
 
     /// Generate a new list of recommendations
-    Generate,
+    Generate {
+        /// Start with the currently playing track as a seed
+        #[arg(long)]
+        seed_current: bool,
+        /// Start with the artists of the currently playing track as seeds
+        #[arg(long)]
+        seed_current_artist: bool,
+    },
+
+    /// Keep the first unplayed tracks of the latest list and top it up with recommendations seeded from them
+    Rotate {
+        /// Number of tracks not played recently to keep from the top of the list
+        #[arg(long, default_value_t = 5)]
+        keep: usize,
+        /// Number of tracks in the list after topping it up
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(1..=100))]
+        size: u8,
+    },
 
     /// Creates a new playlist to be managed by this tool and prints the corresponding env variable
     Init,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn error::Error>> {
-    let args = App::parse();
+async fn main() {
+    i18n::init();
+    let mut argv: Vec<String> = env::args().collect();
+    // Needed before parsing, for loading the aliases.
+    paths::init(argv.iter().any(|arg| arg == "--legacy-paths"));
+    // A broken config is reported once `run` loads it.
+    if let Ok(config) = config::load() {
+        let mut args = argv.split_off(1);
+        if let Some(default_command) = &config.default_command {
+            if command_args(args.iter().cloned()).is_empty() {
+                match split_words(default_command) {
+                    Ok(words) => args.extend(words),
+                    Err(e) => eprintln!(
+                        "{}",
+                        tr!("Ignoring default_command in the config: {e}", e = e)
+                    ),
+                }
+            }
+        }
+        let expanded = expand_aliases(args, &config);
+        argv.extend(expanded);
+    }
+    let args = App::parse_from(export_output_as_file(argv));
+    let json_errors = args.options.output == OutputMode::Json;
+    let queue_offline = args.options.queue_offline && queueable(&args.command);
+    let quota_info = args.options.quota_info;
 
-    let token_path = match args.options.token_path {
-        Some(token_path) => token_path,
-        None => {
-            let default_filepath = dirs::home_dir()
-                .ok_or("Can't get home directory?")?
-                .join(".spotify_cli_token")
-                .to_str()
-                .unwrap()
-                .to_string();
-            env::var("SPOTIFY_CLI_TOKEN_FILE").unwrap_or(default_filepath)
+    let result = run(args).await;
+    if quota_info {
+        print_quota_info();
+    }
+    // The counts are only informational; failing to save them isn't an error.
+    let _ = usage::save();
+
+    if let Err(e) = result {
+        if queue_offline
+            && matches!(
+                api::ErrorKind::of(e.as_ref()),
+                api::ErrorKind::Network | api::ErrorKind::Timeout
+            )
+        {
+            match queue_command(command_args(env::args().skip(1))) {
+                Ok(()) => return,
+                Err(e) => eprintln!(
+                    "{}",
+                    tr!(
+                        "Failed to queue the command: {error}",
+                        error = format!("{e:?}")
+                    )
+                ),
+            }
         }
+        match json_errors {
+            true => eprintln!("{}", api::error_json(e.as_ref())),
+            false => eprintln!("{}", tr!("Error: {error}", error = format!("{e:?}"))),
+        }
+        process::exit(api::ErrorKind::of(e.as_ref()).exit_code());
+    }
+}
+
+async fn run(args: App) -> Result<(), Box<dyn error::Error>> {
+    logging::init(args.options.verbose);
+
+    let device = args.options.device;
+    if let Some(device) = &device {
+        set_target_device(device);
+    }
+
+    let token_path = match args
+        .options
+        .token_path
+        .or_else(|| env::var("SPOTIFY_CLI_TOKEN_FILE").ok())
+    {
+        Some(token_path) => token_path,
+        None => paths::default_path(paths::DefaultFile::Token)?,
     };
 
+    if let Some(base_url) = args
+        .options
+        .base_url
+        .or_else(|| env::var("SPOTIFY_CLI_API_URL").ok())
+    {
+        set_api_base_url(&base_url);
+    }
+
+    // Reports a broken config instead of failing on it.
+    if let Command::Doctor = args.command {
+        return doctor(&token_path, args.options.timeout).await;
+    }
+
+    let config = config::load()?;
+    http::init(&config.network, args.options.timeout)?;
+    if let Some(market) = args
+        .options
+        .market
+        .as_ref()
+        .or(config.playback.market.as_ref())
+    {
+        set_market(market)?;
+    }
+
+    if let Command::Alias(command) = args.command {
+        return match command {
+            AliasCommand::Add { name, target, kind } => alias_add(kind, &name, &target),
+            AliasCommand::List => alias_list(&config),
+            AliasCommand::Remove { name, kind } => alias_remove(kind, &name),
+        };
+    }
+
+    // Shell prompts must never block on the interactive auth flow below.
+    if let Command::PromptSegment { max_age, budget_ms } = args.command {
+        return prompt_segment(&token_path, max_age, Duration::from_millis(budget_ms)).await;
+    }
+
+    if let Command::Setup(SetupCommand::UriHandler) = args.command {
+        return setup_uri_handler(device.as_deref());
+    }
+
+    // Logging out must not offer to save new tokens.
+    if let Command::Auth(AuthCommand::Logout { revoke }) = args.command {
+        return auth_logout(&token_path, revoke);
+    }
+
+    if let Command::Sleep { detach: true, .. } = args.command {
+        return detach();
+    }
+
+    if let Command::Schedule {
+        time,
+        detach: scheduled_detach,
+        print,
+        ..
+    } = &args.command
+    {
+        let time = schedule::parse_time(time)?;
+        match (print, time) {
+            (Some(_), None) => return Err("--print needs a time of day instead of now.".into()),
+            (Some(entry), Some(time)) => {
+                let args: Vec<String> = env::args().collect();
+                return schedule::print_entry(*entry, time, &args);
+            }
+            (None, _) if *scheduled_detach => return detach(),
+            _ => {}
+        }
+    }
+
+    output::init(
+        args.options.color,
+        args.options.output,
+        args.options.quiet,
+        args.options.no_truncate,
+        &config.theme,
+    )?;
+    models::init(args.options.details);
+    picker::init(args.options.picker);
+    progress::init(args.options.progress);
+    prompt::init(args.options.choose, args.options.accept);
+
+    if let Command::Quota = args.command {
+        return quota();
+    }
+
+    // A token obtained elsewhere, e.g. in CI; no OAuth flow or token file.
+    if let Ok(access_token) = env::var("SPOTIFY_CLI_ACCESS_TOKEN") {
+        let mut auth = StaticToken::new(&access_token);
+        return run_top_level(&mut auth, &config, device.as_deref(), args.command).await;
+    }
+
     let client_id = env::var("SPOTIFY_CLI_CLIENT_ID")
         .map_err(|_| "The env variable SPOTIFY_CLI_CLIENT_ID must be set.")?;
     let client_secret = env::var("SPOTIFY_CLI_CLIENT_SECRET")
         .map_err(|_| "The env variable SPOTIFY_CLI_CLIENT_SECRET must be set.")?;
 
+    // These set up tokens themselves so there's no point in asking.
+    let setting_up_tokens = matches!(
+        args.command,
+        Command::Auth(AuthCommand::Login { .. } | AuthCommand::Import { .. })
+    );
+
     let mut auth = match fs::exists(&token_path)? {
         true => SpotifyAuth::from_file(&client_id, &client_secret, &token_path)?,
+        false if setting_up_tokens => {
+            let mut tmp = SpotifyAuth::new(&client_id, &client_secret)?;
+            tmp.with_file(&token_path)?;
+            tmp
+        }
         false => {
-            println!("There are no tokens saved in {token_path}.");
-            println!("Save new tokens there? Y/n");
-
-            let mut user_response = String::new();
-            io::stdin().read_line(&mut user_response)?;
-            user_response = user_response.trim().to_lowercase();
-
-            if !(user_response.is_empty() || user_response.starts_with("y")) {
-                println!("Ok, NOT generating and saving new tokens. Exiting.");
+            println!(
+                "{}",
+                tr!("There are no tokens saved in {path}.", path = token_path)
+            );
+            if !prompt::confirm(tr!("Save new tokens there?"), true)? {
+                println!(
+                    "{}",
+                    tr!("Ok, NOT generating and saving new tokens. Exiting.")
+                );
                 return Ok(());
             }
 
@@ -175,54 +1094,759 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
         }
     };
 
-    match args.command {
-        Command::Show => playback_show(&mut auth, true).await?,
-        Command::Pause => playback_pause(&mut auth).await?,
-        Command::Play => playback_play(&mut auth, None, None).await?,
+    auth.set_redirect_ports(auth::redirect_ports(config.auth.redirect_ports.as_deref())?);
+    if let Some(uri) = env::var("SPOTIFY_CLI_REDIRECT_URI")
+        .ok()
+        .or(config.auth.redirect_uri.clone())
+    {
+        auth.set_redirect_uri(&uri);
+    }
+    if let Some(secs) = config.auth.redirect_timeout_secs {
+        auth.set_redirect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(open_browser) = config.auth.open_browser {
+        auth.set_open_browser(open_browser);
+    }
+
+    run_top_level(&mut auth, &config, device.as_deref(), args.command).await
+}
+
+async fn run_top_level(
+    auth: &mut impl TokenProvider,
+    config: &config::Config,
+    device: Option<&str>,
+    command: Command,
+) -> Result<(), Box<dyn error::Error>> {
+    match command {
+        Command::Exec {
+            commands,
+            stdin,
+            keep_going,
+        } => exec(auth, config, device, commands, stdin, keep_going).await,
+        Command::Sync => sync(auth, config, device).await,
+        command => run_command(auth, config, device, command).await,
+    }
+}
+
+/// Commands worth running later when the network is back: ones changing
+/// playlists that don't depend on what's playing or on local files.
+fn queueable(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Playlist(
+            PlaylistCommand::Create { .. }
+                | PlaylistCommand::Add { .. }
+                | PlaylistCommand::Copy { .. }
+                | PlaylistCommand::Merge { .. }
+                | PlaylistCommand::Filter { .. }
+                | PlaylistCommand::SetCollaborative { .. }
+                | PlaylistCommand::Sort { .. }
+                | PlaylistCommand::Sync { dry_run: false, .. }
+        ) | Command::Liked(LikedCommand::ExportToPlaylist { .. })
+    )
+}
+
+/// The command line without the global options, so it can be parsed as an
+/// `ExecLine` later. The global options are read from `Options` through
+/// clap, so new ones are left out without listing them here.
+fn command_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    let app = App::command();
+    let globals: Vec<&clap::Arg> = app
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .collect();
+    let global = |name: &str| {
+        globals.iter().copied().find(|arg| {
+            match (name.strip_prefix("--"), name.strip_prefix('-')) {
+                (Some(long), _) => arg.get_long() == Some(long),
+                (None, Some(short)) => {
+                    short.chars().count() == 1 && arg.get_short() == short.chars().next()
+                }
+                _ => false,
+            }
+        })
+    };
+    // Short flags given together, e.g. `-vv`.
+    let short_flags = |arg: &str| {
+        arg.len() > 2
+            && !arg.starts_with("--")
+            && arg.strip_prefix('-').is_some_and(|shorts| {
+                shorts.chars().all(|short| {
+                    global(&format!("-{short}")).is_some_and(|arg| !arg.get_action().takes_values())
+                })
+            })
+    };
+
+    let mut command_args = Vec::new();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let name = arg.split('=').next().unwrap_or_default();
+        match global(name) {
+            Some(option) => {
+                if option.get_action().takes_values() && !arg.contains('=') {
+                    args.next();
+                }
+            }
+            None if short_flags(&arg) => {}
+            None => command_args.push(arg),
+        }
+    }
+
+    command_args
+}
+
+/// `playlist export` took the file to write to with `--output` before the
+/// global `--output` existed, so after `export` it still means the file.
+fn export_output_as_file(mut args: Vec<String>) -> Vec<String> {
+    let Some(export) = args
+        .windows(2)
+        .position(|pair| pair[0] == "playlist" && pair[1] == "export")
+    else {
+        return args;
+    };
+
+    for arg in &mut args[export + 2..] {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--output" {
+            *arg = "--file".to_string();
+        } else if let Some(file) = arg.strip_prefix("--output=") {
+            *arg = format!("--file={file}");
+        }
+    }
+
+    args
+}
+
+/// Replaces `@name` arguments with what the alias in the config means: a
+/// device alias after `--device`, otherwise a uri alias, or a device alias if
+/// there's no uri alias by that name. Unknown names are left as they are, so
+/// e.g. a playlist named `@home` still works. The `alias` command itself is
+/// left alone.
+fn expand_aliases(args: Vec<String>, config: &config::Config) -> Vec<String> {
+    if command_args(args.iter().cloned())
+        .first()
+        .map(|arg| arg.as_str())
+        == Some("alias")
+    {
+        return args;
+    }
+
+    let mut after_device = false;
+    args.into_iter()
+        .map(|arg| {
+            let (prefix, value) = match arg.strip_prefix("--device=") {
+                Some(value) => ("--device=", value),
+                None => ("", arg.as_str()),
+            };
+            let device = after_device || !prefix.is_empty();
+            after_device = arg == "--device";
+            let Some(name) = value.strip_prefix('@') else {
+                return arg;
+            };
+            let target = match device {
+                true => config.device.get(name),
+                false => config.alias.get(name).or_else(|| config.device.get(name)),
+            };
+            match target {
+                Some(target) => format!("{prefix}{target}"),
+                None => arg,
+            }
+        })
+        .collect()
+}
+
+fn queue_command(args: Vec<String>) -> Result<(), Box<dyn error::Error>> {
+    let mut state = state::load()?;
+    let line = args.join(" ");
+    state.offline_queue.push(state::QueuedCommand {
+        queued_at: auth::current_time_secs_from_epoch()?,
+        args,
+    });
+    state::save(&state)?;
+
+    output::info!(
+        "{}",
+        tr!(
+            "Offline; saved '{line}' to run later with `spotify-cli sync`.",
+            line = line
+        )
+    );
+
+    Ok(())
+}
+
+/// Runs the queued commands in order. Stops at the first one failing for
+/// lack of network and keeps it and the rest queued; other failures are
+/// reported and dropped since retrying won't help.
+async fn sync(
+    auth: &mut impl TokenProvider,
+    config: &config::Config,
+    device: Option<&str>,
+) -> Result<(), Box<dyn error::Error>> {
+    let queued = state::load()?.offline_queue;
+    if queued.is_empty() {
+        println!("{}", tr!("No queued commands."));
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for (ind, entry) in queued.iter().enumerate() {
+        let line = entry.args.join(" ");
+        let result = match ExecLine::try_parse_from(expand_aliases(entry.args.clone(), config)) {
+            Ok(exec_line) => run_command(auth, config, device, exec_line.command).await,
+            Err(e) => Err(e.to_string().into()),
+        };
+
+        match result {
+            Err(e)
+                if matches!(
+                    api::ErrorKind::of(e.as_ref()),
+                    api::ErrorKind::Network | api::ErrorKind::Timeout
+                ) =>
+            {
+                return Err(format!(
+                    "Still offline; {} command(s) left in the queue: {e}",
+                    queued.len() - ind
+                )
+                .into());
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    tr!("Command '{line}' failed: {error}", line = line, error = e)
+                );
+                failed += 1;
+            }
+            Ok(()) => {}
+        }
+
+        // The commands may change the state too, so reload before saving.
+        let mut state = state::load()?;
+        state.offline_queue = queued[ind + 1..].to_vec();
+        state::save(&state)?;
+    }
+
+    if failed > 0 {
+        Err(tr!("{count} queued command(s) failed.", count = failed).into())
+    } else {
+        output::info!(
+            "{}",
+            tr!("Ran {count} queued command(s).", count = queued.len())
+        );
+        Ok(())
+    }
+}
+
+async fn auth_command(
+    auth: &mut SpotifyAuth,
+    command: AuthCommand,
+) -> Result<(), Box<dyn error::Error>> {
+    match command {
+        AuthCommand::Refresh => auth.refresh_token().await?,
+        AuthCommand::Reset => auth.reset_auth().await?,
+        AuthCommand::Login { manual, qr } => {
+            auth.set_manual(manual);
+            auth.set_qr(qr);
+            auth.login().await?;
+            output::info!("{}", tr!("Logged in."));
+        }
+        AuthCommand::Export { file } => {
+            auth.export_tokens(&file)?;
+            output::info!("{}", tr!("Exported tokens to {file}. Keep the file secret; it grants access to your account.", file = file));
+        }
+        AuthCommand::Import { file } => {
+            auth.import_tokens(&file)?;
+            output::info!("{}", tr!("Imported tokens from {file}.", file = file));
+        }
+        AuthCommand::Logout { .. } => return Err("auth logout can't be run from exec.".into()),
+    }
+
+    Ok(())
+}
+
+/// Runs this same command again in the background, without `--detach`.
+fn detach() -> Result<(), Box<dyn error::Error>> {
+    let mut command = process::Command::new(env::current_exe()?);
+    command
+        .args(env::args().skip(1).filter(|arg| arg != "--detach"))
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    // Keep Ctrl-C in the terminal from reaching the background process.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let child = command.spawn()?;
+    println!(
+        "{}",
+        tr!("Running in the background (pid {pid}).", pid = child.id())
+    );
+
+    Ok(())
+}
+
+/// A single command given to `exec`, parsed without the binary name.
+#[derive(Debug, Parser)]
+#[clap(no_binary_name = true)]
+struct ExecLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Splits a command line into words. Supports single and double quotes and
+/// backslash escapes, roughly like a shell would.
+fn split_words(line: &str) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => word.get_or_insert_default().push(c),
+            ('\\', _) => {
+                let escaped = chars.next().ok_or("Trailing backslash in command.")?;
+                word.get_or_insert_default().push(escaped);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => word.get_or_insert_default().push(c),
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (c, None) if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            (c, None) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("Unclosed quote in command: {line}").into());
+    }
+    if let Some(word) = word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+/// Splits a script into commands on `;` and newlines outside of quotes.
+fn split_commands(script: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut command = String::new();
+    let mut quote: Option<char> = None;
+
+    for c in script.chars() {
+        match (c, quote) {
+            (c, Some(q)) if c == q => quote = None,
+            ('"' | '\'', None) => quote = Some(c),
+            (';' | '\n', None) => {
+                commands.push(std::mem::take(&mut command));
+                continue;
+            }
+            _ => {}
+        }
+        command.push(c);
+    }
+    commands.push(command);
+
+    commands
+        .into_iter()
+        .map(|command| command.trim().to_string())
+        .filter(|command| !command.is_empty() && !command.starts_with('#'))
+        .collect()
+}
+
+/// Runs several commands sharing one authenticated session, either from the
+/// given `;` separated list or line by line from stdin.
+async fn exec(
+    auth: &mut impl TokenProvider,
+    config: &config::Config,
+    device: Option<&str>,
+    commands: Option<String>,
+    stdin: bool,
+    keep_going: bool,
+) -> Result<(), Box<dyn error::Error>> {
+    let script = match (commands, stdin) {
+        (Some(commands), false) => commands,
+        (None, true) => io::read_to_string(io::stdin())?,
+        _ => return Err("Give either the commands or --stdin.".into()),
+    };
+
+    let mut failed = 0;
+    for line in split_commands(&script) {
+        let result = match ExecLine::try_parse_from(expand_aliases(split_words(&line)?, config)) {
+            Ok(exec_line) => run_command(auth, config, device, exec_line.command).await,
+            Err(e) => Err(e.to_string().into()),
+        };
+
+        if let Err(e) = result {
+            eprintln!(
+                "{}",
+                tr!("Command '{line}' failed: {error}", line = line, error = e)
+            );
+            failed += 1;
+            if !keep_going {
+                return Err(format!("Stopped at a failed command: {line}").into());
+            }
+        }
+    }
+
+    if failed > 0 {
+        Err(format!("{failed} command(s) failed.").into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Scopes a command needs on top of `auth::DEFAULT_SCOPES`.
+fn required_scopes(command: &Command) -> Vec<Scope> {
+    match command {
+        Command::Top { .. } => vec![Scope::UserTopRead],
+        Command::Liked(_) => vec![Scope::UserLibraryRead],
+        // For the saved markers.
+        Command::Show {
+            url: false,
+            format: None,
+            ..
+        }
+        | Command::Queue { action: None, .. } => vec![Scope::UserLibraryRead],
+        Command::Playlist(
+            PlaylistCommand::Add { .. }
+            | PlaylistCommand::Sort { .. }
+            | PlaylistCommand::Sync { .. }
+            | PlaylistCommand::Undo { .. },
+        ) => {
+            vec![Scope::PlaylistModifyPublic]
+        }
+        Command::Playlist(PlaylistCommand::SetCover { .. }) => vec![Scope::UgcImageUpload],
+        // To tell which tracks have been played.
+        Command::Recommendation(RecommendationCommand::Rotate { .. }) => {
+            vec![Scope::UserReadRecentlyPlayed]
+        }
+        Command::Podcast(PodcastCommand::List) => vec![Scope::UserLibraryRead],
+        // For the resume points.
+        Command::Podcast(_) => vec![Scope::UserReadPlaybackPosition],
+        _ => Vec::new(),
+    }
+}
+
+/// Runs a command. If a command starting playback fails because no device
+/// is active, playback is transferred to `device` (or one picked by the user)
+/// and the command, or just the play call at its end, is retried.
+async fn run_command(
+    auth: &mut impl TokenProvider,
+    config: &config::Config,
+    device: Option<&str>,
+    command: Command,
+) -> Result<(), Box<dyn error::Error>> {
+    auth.require_scopes(&required_scopes(&command)).await?;
+
+    let starts_playback = matches!(
+        command,
+        Command::Play
+            | Command::Toggle { .. }
+            | Command::ResumeLast
+            | Command::PlayTrack { .. }
+            | Command::HandleUri { .. }
+            | Command::Playlist(PlaylistCommand::Play { .. })
+            | Command::Recommendation(RecommendationCommand::Play { .. })
+            | Command::Podcast(PodcastCommand::Play { .. })
+            | Command::Album(AlbumCommand::Play { .. })
+            | Command::Artist(ArtistCommand::Play { .. })
+    ) || match &command {
+        Command::Browse(
+            BrowseCommand::NewReleases { args } | BrowseCommand::FeaturedPlaylists { args },
+        ) => args.pick.play.is_some(),
+        Command::Browse(BrowseCommand::Category { pick, .. }) => pick.play.is_some(),
+        _ => false,
+    };
+    let retry = match &command {
+        // Only the final play of the filled managed playlist is retried, so
+        // the recommendations aren't fetched and the playlist replaced again.
+        Command::Radio { queue: false, .. } if get_managed_playlist_id().is_ok() => {
+            Some(Command::Recommendation(RecommendationCommand::Play {
+                index: None,
+            }))
+        }
+        _ if starts_playback => Some(command.clone()),
+        _ => None,
+    };
+    match (dispatch(auth, config, command).await, retry) {
+        (Err(e), Some(retry)) if is_no_active_device_error(e.as_ref()) => {
+            let device_id = choose_device(auth, device).await?;
+            transfer_playback(auth, &device_id).await?;
+            dispatch(auth, config, retry).await
+        }
+        (result, _) => result,
+    }
+}
+
+async fn dispatch(
+    auth: &mut impl TokenProvider,
+    config: &config::Config,
+    command: Command,
+) -> Result<(), Box<dyn error::Error>> {
+    match command {
+        Command::Show {
+            url: true, context, ..
+        } => playback_show_url(auth, context).await?,
+        Command::Show {
+            format: None,
+            art,
+            context_progress,
+            ..
+        } => playback_show(auth, true, context_progress, art).await?,
+        Command::Show {
+            format: Some(template),
+            ..
+        } => playback_show_formatted(auth, &template).await?,
+        Command::Copy { context } => copy_url(auth, context).await?,
+        Command::Open { target, app } => match target {
+            None | Some(OpenTarget::Track) => open_current(auth, false, app).await?,
+            Some(OpenTarget::Context) => open_current(auth, true, app).await?,
+            Some(OpenTarget::Playlist { playlist }) => open_playlist(auth, &playlist, app).await?,
+        },
+        Command::Pause => {
+            let remember_after = config
+                .playback
+                .remember_position_mins
+                .unwrap_or(DEFAULT_REMEMBER_POSITION_MINS);
+            playback_pause_remembering(auth, Duration::from_secs(remember_after * 60)).await?
+        }
+        Command::Toggle { last_device } => {
+            let remember_after = config
+                .playback
+                .remember_position_mins
+                .unwrap_or(DEFAULT_REMEMBER_POSITION_MINS);
+            playback_toggle(auth, Duration::from_secs(remember_after * 60), last_device).await?
+        }
+        Command::ResumeLast => resume_last(auth).await?,
+        Command::Play => playback_play(auth, None, None, None).await?,
+        Command::PlayTrack {
+            query_or_uri,
+            first,
+        } => play_track(auth, &query_or_uri, first).await?,
+        Command::HandleUri { uri } => handle_uri(auth, &uri).await?,
         Command::Next => {
-            playback_next(&mut auth).await?;
-            // The API keeps returning the previously played song
-            // without a bit of a sleep here. Not happy about this
-            // but what can I do...
-            tokio::time::sleep(Duration::from_millis(500u64)).await;
-            playback_show(&mut auth, false).await?;
-        }
-        Command::Previous => {
-            playback_previous(&mut auth).await?;
-            tokio::time::sleep(Duration::from_millis(500u64)).await;
-            playback_show(&mut auth, false).await?;
-        }
-        Command::Restart => playback_restart(&mut auth).await?,
-        Command::Current { max_lines } => playlist_current(&mut auth, max_lines).await?,
+            let before = playback_snapshot(auth).await;
+            playback_next(auth).await?;
+            show_playback_change(auth, &before, ExpectedChange::Track).await?;
+        }
+        Command::Previous { force } => {
+            let restart_after = config
+                .playback
+                .previous_restart_secs
+                .unwrap_or(DEFAULT_PREVIOUS_RESTART_SECS);
+            playback_back(auth, Duration::from_secs(restart_after), force).await?
+        }
+        Command::Restart => playback_restart(auth).await?,
+        Command::Mute => mute_toggle(auth).await?,
+        Command::Current { max_lines, around } => playlist_current(auth, max_lines, around).await?,
         Command::Jump { offset } => {
-            playback_play(&mut auth, None, Some(offset)).await?;
-            tokio::time::sleep(Duration::from_millis(500u64)).await;
-            playback_show(&mut auth, false).await?;
-        }
-        Command::Queue { number } => queue_show(&mut auth, number).await?,
-        Command::Playlist(PlaylistCommand::List) => playlist_list(&mut auth).await?,
-        Command::Playlist(PlaylistCommand::Play { uri, index }) => {
-            playback_play(&mut auth, Some(&uri), index).await?;
-            tokio::time::sleep(Duration::from_millis(500u64)).await;
-            playback_show(&mut auth, false).await?;
-        }
-        Command::Auth(AuthCommand::Refresh) => auth.refresh_token().await?,
-        Command::Auth(AuthCommand::Reset) => auth.reset_auth().await?,
+            let before = playback_snapshot(auth).await;
+            playback_jump(auth, offset).await?;
+            show_playback_change(auth, &before, ExpectedChange::Track).await?;
+        }
+        Command::Queue {
+            action: Some(QueueCommand::Clear),
+            ..
+        } => queue_clear(auth).await?,
+        Command::Queue {
+            action: Some(QueueCommand::SkipTo { index }),
+            ..
+        } => queue_skip_to(auth, index).await?,
+        Command::Queue {
+            action: Some(QueueCommand::Add { uri, next }),
+            ..
+        } => queue_add(auth, &uri, next).await?,
+        Command::Queue {
+            number,
+            all,
+            action: None,
+        } => queue_show(auth, number, all).await?,
+        Command::PromptSegment { .. } => {
+            return Err("prompt-segment can't be run from exec.".into())
+        }
+        Command::Exec { .. } => return Err("exec can't be nested.".into()),
+        Command::Sync => return Err("sync can't be run from exec.".into()),
+        Command::Setup(_) => return Err("setup can't be run from exec.".into()),
+        Command::Alias(_) => return Err("alias can't be run from exec.".into()),
+        Command::Doctor => return Err("doctor can't be run from exec.".into()),
+        Command::Quota => quota()?,
+        Command::Watch { interval } => watch(auth, config, Duration::from_secs(interval)).await?,
+        Command::Sleep { detach: true, .. } => {
+            return Err("sleep --detach can't be run from exec.".into())
+        }
+        Command::Sleep { duration, fade, .. } => sleep_timer(auth, duration, fade).await?,
+        Command::Schedule { detach: true, .. } | Command::Schedule { print: Some(_), .. } => {
+            return Err("schedule --detach/--print can't be run from exec.".into())
+        }
+        Command::Schedule {
+            time,
+            playlist,
+            volume,
+            ..
+        } => {
+            let time = schedule::parse_time(&time)?;
+            scheduled_play(auth, time, playlist.as_deref(), volume).await?
+        }
+        Command::Top {
+            r#type,
+            range,
+            limit,
+        } => top_show(auth, r#type, range, limit).await?,
+        Command::Lyrics { sync } => lyrics_show(auth, config, sync).await?,
+        Command::Playlist(PlaylistCommand::List {
+            owned,
+            followed,
+            collaborative,
+            filter,
+            sort,
+        }) => {
+            let filter = PlaylistFilter {
+                owned,
+                followed,
+                collaborative,
+                name: filter,
+            };
+            playlist_list(auth, &filter, sort).await?
+        }
+        Command::Playlist(PlaylistCommand::Play {
+            uri,
+            index,
+            random,
+            shuffle,
+        }) => playlist_play(auth, &uri, index, random, shuffle).await?,
+        Command::Playlist(PlaylistCommand::Create {
+            name,
+            description,
+            collaborative,
+        }) => playlist_create(auth, &name, description.as_deref(), collaborative).await?,
+        Command::Playlist(PlaylistCommand::Add {
+            playlist,
+            tracks,
+            allow_dupes,
+        }) => playlist_add(auth, &playlist, &tracks, allow_dupes).await?,
+        Command::Playlist(PlaylistCommand::Copy {
+            src,
+            dst_name,
+            collaborative,
+        }) => playlist_copy(auth, &src, &dst_name, collaborative).await?,
+        Command::Playlist(PlaylistCommand::SetCover { playlist, image }) => {
+            playlist_set_cover(auth, &playlist, &image).await?
+        }
+        Command::Playlist(PlaylistCommand::SetCollaborative { playlist, state }) => {
+            playlist_set_collaborative(auth, &playlist, state == OnOff::On).await?
+        }
+        Command::Playlist(PlaylistCommand::Merge {
+            a,
+            b,
+            into,
+            collaborative,
+        }) => playlist_merge(auth, &a, &b, &into, collaborative).await?,
+        Command::Playlist(PlaylistCommand::Filter {
+            src,
+            genre,
+            into,
+            collaborative,
+        }) => playlist_filter(auth, &src, &genre, &into, collaborative).await?,
+        Command::Playlist(PlaylistCommand::Export {
+            playlist,
+            format,
+            file,
+            audio_features,
+        }) => playlist_export(auth, &playlist, format, file.as_deref(), audio_features).await?,
+        Command::Playlist(PlaylistCommand::Import {
+            file,
+            name,
+            collaborative,
+        }) => playlist_import(auth, &file, name, collaborative).await?,
+        Command::Playlist(PlaylistCommand::Sync {
+            file,
+            playlist,
+            dry_run,
+        }) => playlist_sync(auth, &file, &playlist, dry_run).await?,
+        Command::Playlist(PlaylistCommand::Sort {
+            playlist,
+            by,
+            reverse,
+        }) => playlist_sort(auth, &playlist, by, reverse).await?,
+        Command::Playlist(PlaylistCommand::Snapshots { playlist }) => {
+            playlist_snapshots(auth, &playlist).await?
+        }
+        Command::Playlist(PlaylistCommand::Undo { playlist }) => {
+            playlist_undo(auth, &playlist).await?
+        }
+        Command::Liked(LikedCommand::ExportToPlaylist { name }) => {
+            liked_export_to_playlist(auth, &name).await?
+        }
+        Command::Stats(StatsCommand::Playlist { playlist, limit }) => {
+            playlist_stats(auth, &playlist, limit).await?
+        }
+        Command::History(HistoryCommand::Stats { period, limit }) => {
+            history_stats(config, period, limit)?
+        }
+        Command::Block(BlockCommand::Add { track }) => block_add(auth, &track).await?,
+        Command::Block(BlockCommand::List) => block_list()?,
+        Command::Block(BlockCommand::Remove { track }) => block_remove(&track)?,
+        Command::Auth(command) => {
+            let auth = auth
+                .spotify_auth()
+                .ok_or("auth commands can't be used with SPOTIFY_CLI_ACCESS_TOKEN.")?;
+            auth_command(auth, command).await?
+        }
         Command::Recommendation(RecommendationCommand::Show { max_lines }) => {
-            recommendation_show(&mut auth, max_lines).await?
+            recommendation_show(auth, max_lines).await?
         }
         Command::Recommendation(RecommendationCommand::Play { index }) => {
-            recommendation_play(&mut auth, index).await?
+            recommendation_play(auth, index).await?
         }
         Command::Recommendation(RecommendationCommand::Save { name, description }) => {
-            recommendation_save(&mut auth, name, description).await?
+            recommendation_save(auth, config, name, description).await?
+        }
+        Command::Recommendation(RecommendationCommand::Generate {
+            seed_current,
+            seed_current_artist,
+        }) => recommendation_generate(auth, config, seed_current, seed_current_artist).await?,
+        Command::Browse(BrowseCommand::NewReleases { args }) => {
+            browse_new_releases(auth, args.limit, args.pick.pick()).await?
+        }
+        Command::Browse(BrowseCommand::FeaturedPlaylists { args }) => {
+            browse_featured_playlists(auth, args.limit, args.pick.pick()).await?
+        }
+        Command::Browse(BrowseCommand::Categories) => browse_categories(auth).await?,
+        Command::Browse(BrowseCommand::Category { category, pick }) => {
+            browse_category(auth, &category, pick.pick()).await?
+        }
+        Command::Track(TrackCommand::Info { track }) => track_info(auth, &track).await?,
+        Command::Album(AlbumCommand::Play { album, disc, track }) => {
+            album_play(auth, &album, disc, track).await?
+        }
+        Command::Artist(ArtistCommand::Related { artist }) => artist_related(auth, &artist).await?,
+        Command::Artist(ArtistCommand::Explore { artist }) => {
+            artist_explore(auth, config, &artist).await?
+        }
+        Command::Artist(ArtistCommand::Play { artist, top_tracks }) => {
+            artist_play(auth, &artist, top_tracks).await?
         }
-        Command::Recommendation(RecommendationCommand::Generate) => {
-            recommendation_generate(&mut auth).await?
+        Command::Podcast(PodcastCommand::List) => podcast_list(auth).await?,
+        Command::Podcast(PodcastCommand::Episodes { show, limit }) => {
+            podcast_episodes(auth, &show, limit).await?
         }
-        Command::Recommendation(RecommendationCommand::Init) => {
-            recommendation_init(&mut auth).await?
+        Command::Podcast(PodcastCommand::Play {
+            episode,
+            from_start,
+        }) => podcast_play(auth, &episode, from_start).await?,
+        Command::Radio { seed, limit, queue } => radio(auth, config, seed, limit, queue).await?,
+        Command::Recommendation(RecommendationCommand::Rotate { keep, size }) => {
+            recommendation_rotate(auth, config, keep, size).await?
         }
+        Command::Recommendation(RecommendationCommand::Init) => recommendation_init(auth).await?,
         #[allow(unreachable_patterns)]
         _ => unimplemented!(),
     }
@@ -0,0 +1,459 @@
+use super::duration::format_duration;
+use super::text;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{error, fmt::Display, sync::OnceLock};
+
+/// Whether tracks are shown with their explicit marker and popularity, from
+/// `--details`.
+static DETAILS: OnceLock<bool> = OnceLock::new();
+
+pub fn init(details: bool) {
+    let _ = DETAILS.set(details);
+}
+
+pub fn details() -> bool {
+    DETAILS.get().copied().unwrap_or(false)
+}
+
+/// A Spotify object by its type and id, e.g. `track` and
+/// `4uLU6hMCjMI75M1A2tKUQC`. Converts between the `spotify:<type>:<id>` uri,
+/// the open.spotify.com url and the bare id.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpotifyId {
+    pub kind: String,
+    pub id: String,
+}
+
+impl SpotifyId {
+    pub fn new(kind: &str, id: &str) -> SpotifyId {
+        SpotifyId {
+            kind: kind.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    /// Parses `spotify:<type>:<id>` uris (also the old
+    /// `spotify:user:<user>:playlist:<id>` form), `spotify://<type>/<id>`
+    /// links and open.spotify.com urls.
+    pub fn parse(link: &str) -> Result<SpotifyId, Box<dyn error::Error>> {
+        let link = link.trim();
+        let parts: Vec<&str> = if let Some(rest) = link.strip_prefix("spotify://") {
+            rest.split(['?', '#'])
+                .next()
+                .unwrap_or_default()
+                .split('/')
+                .collect()
+        } else if let Some(rest) = link.strip_prefix("spotify:") {
+            rest.split(':').collect()
+        } else if let Some(rest) = link.split("open.spotify.com/").nth(1) {
+            rest.split(['?', '#'])
+                .next()
+                .unwrap_or_default()
+                .split('/')
+                // Localized urls, e.g. `open.spotify.com/intl-fi/track/<id>`.
+                .filter(|part| !part.starts_with("intl-"))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        match parts.as_slice() {
+            [.., kind, id] if !kind.is_empty() && !id.is_empty() => Ok(SpotifyId::new(kind, id)),
+            _ => Err(format!("Not a Spotify link: {link}").into()),
+        }
+    }
+
+    pub fn uri(&self) -> String {
+        format!("spotify:{}:{}", self.kind, self.id)
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://open.spotify.com/{}/{}", self.kind, self.id)
+    }
+}
+
+impl Display for SpotifyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.uri())
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, Default, Serialize)]
+pub struct ExternalUrls {
+    pub spotify: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Image {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Artist {
+    pub name: String,
+    /// Empty for the artists of local files.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+impl Display for Artist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Album {
+    pub name: String,
+    pub id: Option<String>,
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<Artist>,
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+#[derive(Clone, Default, Deserialize, Debug, Serialize)]
+pub struct Song {
+    pub album: Option<Album>,
+    pub name: String,
+    /// Empty for local files.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    pub uri: String,
+    /// A file on the computer of whoever added it to a playlist, with a
+    /// `spotify:local:` uri. They can't be added to playlists or the queue
+    /// through the API.
+    #[serde(default)]
+    pub is_local: bool,
+    pub artists: Vec<Artist>,
+    pub is_playable: Option<bool>,
+    #[serde(default)]
+    pub duration_ms: u64,
+    pub popularity: Option<u8>,
+    #[serde(default)]
+    pub explicit: bool,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    /// Only included when the track is fetched without a market.
+    pub available_markets: Option<Vec<String>>,
+    pub external_ids: Option<ExternalIds>,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+    /// The track originally asked for, when it was relinked to another
+    /// version playable in the market.
+    pub linked_from: Option<LinkedTrack>,
+    pub restrictions: Option<Restrictions>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct ExternalIds {
+    pub isrc: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct LinkedTrack {
+    pub uri: String,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Restrictions {
+    pub reason: String,
+}
+
+impl Song {
+    /// Stands in for a playlist item whose track is gone from Spotify, which
+    /// the API returns as a null.
+    fn unavailable() -> Song {
+        Song {
+            name: "[unavailable track]".to_string(),
+            is_playable: Some(false),
+            restrictions: Some(Restrictions {
+                reason: "removed from Spotify".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the track can be added to playlists and the queue; local
+    /// files and tracks gone from Spotify can't.
+    pub fn is_addable(&self) -> bool {
+        is_addable_uri(&self.uri)
+    }
+
+    /// Why the track can't be played in the market; `None` if it can, or if
+    /// it wasn't fetched with a market.
+    pub fn unavailable_reason(&self) -> Option<String> {
+        if self.is_playable != Some(false) {
+            return None;
+        }
+
+        let reason = self.restrictions.as_ref().map(|r| r.reason.as_str());
+        Some(
+            match reason {
+                Some("market") | None => "not available in the market",
+                Some("product") => "not available with your subscription",
+                Some("explicit") => "explicit content is turned off",
+                Some(reason) => reason,
+            }
+            .to_string(),
+        )
+    }
+}
+
+impl Song {
+    /// The track as shown in track lists, with the name, artists and album
+    /// cut in proportion to fit in `width` characters if given.
+    pub fn fitted(&self, width: Option<usize>) -> String {
+        let artists_str = if !self.artists.is_empty() {
+            let tmp = self
+                .artists
+                .iter()
+                .fold("".to_string(), |acc, x| acc + ", " + &x.name);
+            tmp.strip_prefix(", ").unwrap().to_string()
+        } else {
+            "unknown artist".to_string()
+        };
+
+        let local = match self.is_local {
+            true => "[local file] ",
+            false => "",
+        };
+        let details = details();
+        let explicit = match details && self.explicit {
+            true => " [E]",
+            false => "",
+        };
+        let popularity = match (details, self.popularity) {
+            (true, Some(popularity)) => format!(" (popularity {popularity})"),
+            _ => String::new(),
+        };
+        let album = self.album.as_ref().map(|album| album.name.as_str());
+        let compose = |name: &str, artists: &str, album: Option<&str>| match album {
+            Some(album) => {
+                format!("{local}{name}{explicit} - {artists} [from the album: {album}]{popularity}")
+            }
+            None => format!("{local}{name}{explicit} - {artists}{popularity}"),
+        };
+
+        let line = compose(&self.name, &artists_str, album);
+        let Some(width) = width else {
+            return line;
+        };
+        let fields: Vec<&str> = [Some(self.name.as_str()), Some(artists_str.as_str()), album]
+            .into_iter()
+            .flatten()
+            .collect();
+        let fixed = text::width(&line) - fields.iter().map(|f| text::width(f)).sum::<usize>();
+        let fitted = text::fit_fields(&fields, width.saturating_sub(fixed));
+        compose(&fitted[0], &fitted[1], fitted.get(2).map(String::as_str))
+    }
+}
+
+impl Display for Song {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.fitted(None))
+    }
+}
+
+/// A track in a playlist or the liked songs.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct TrackItem {
+    #[serde(deserialize_with = "song_or_unavailable")]
+    pub track: Song,
+    pub added_at: Option<String>,
+}
+
+/// Whether a track uri can be added to playlists and the queue; not if it's
+/// a local file's or missing.
+pub fn is_addable_uri(uri: &str) -> bool {
+    !uri.is_empty() && !uri.starts_with("spotify:local:")
+}
+
+/// Deserializes a null as the default value, e.g. the ids of local files.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Deserializes a playlist item's track, standing in for a null one so that
+/// the positions of the other items stay right.
+fn song_or_unavailable<'de, D>(deserializer: D) -> Result<Song, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<Song>::deserialize(deserializer)?.unwrap_or_else(Song::unavailable))
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Device {
+    pub id: Option<String>,
+    pub name: String,
+    pub r#type: String,
+    pub volume_percent: Option<u8>,
+    #[serde(default)]
+    pub is_active: bool,
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.r#type)
+    }
+}
+
+/// What the playback is playing from, e.g. a playlist.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Context {
+    pub r#type: String,
+    pub href: String,
+    pub uri: String,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Playlist {
+    pub description: Option<String>,
+    pub uri: String,
+    pub name: String,
+    pub tracks: TracksLink,
+    pub public: Option<bool>,
+    #[serde(default)]
+    pub collaborative: bool,
+    pub owner: Option<User>,
+    #[serde(default)]
+    pub images: Vec<Image>,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+impl Display for Playlist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if self.collaborative {
+            write!(f, " (collaborative)")?;
+        } else if let Some(true) = &self.public {
+            write!(f, " (public)")?;
+        } else if let Some(false) = &self.public {
+            write!(f, " (private)")?;
+        }
+
+        if let Some(desc) = &self.description {
+            write!(f, ": {}", desc)?;
+        }
+
+        write!(f, "({} tracks) uri: {}", self.tracks.total, self.uri)
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct TracksLink {
+    pub total: u16,
+}
+
+/// A podcast or another show of episodes, e.g. an audiobook.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Show {
+    pub name: String,
+    pub id: String,
+    pub uri: String,
+    pub publisher: Option<String>,
+    pub total_episodes: Option<u32>,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+impl Display for Show {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(publisher) = &self.publisher {
+            write!(f, " by {publisher}")?;
+        }
+        if let Some(total) = self.total_episodes {
+            write!(f, " ({total} episodes)")?;
+        }
+        write!(f, " uri: {}", self.uri)
+    }
+}
+
+/// A show in the user's library.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct SavedShow {
+    pub show: Show,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Episode {
+    pub name: String,
+    pub id: String,
+    pub uri: String,
+    #[serde(default)]
+    pub duration_ms: u64,
+    pub release_date: Option<String>,
+    /// Where the user left off; only returned with the
+    /// `user-read-playback-position` scope.
+    pub resume_point: Option<ResumePoint>,
+    /// Included when the episode is fetched by itself.
+    pub show: Option<Show>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct ResumePoint {
+    #[serde(default)]
+    pub fully_played: bool,
+    #[serde(default)]
+    pub resume_position_ms: u64,
+}
+
+impl Episode {
+    /// Where playback of the episode would resume, if it was left unfinished.
+    pub fn resume_position_ms(&self) -> Option<u64> {
+        self.resume_point
+            .as_ref()
+            .filter(|point| !point.fully_played && point.resume_position_ms > 0)
+            .map(|point| point.resume_position_ms)
+    }
+
+    /// How much of the episode has been listened to, e.g. `played` or
+    /// `12:34 / 45:00`; empty if it hasn't been started.
+    pub fn progress(&self) -> String {
+        match (&self.resume_point, self.resume_position_ms()) {
+            (Some(point), _) if point.fully_played => "played".to_string(),
+            (_, Some(position)) => format!(
+                "{} / {}",
+                format_duration(position),
+                format_duration(self.duration_ms)
+            ),
+            _ => String::new(),
+        }
+    }
+}
+
+impl Display for Episode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(release_date) = &self.release_date {
+            write!(f, "{release_date} ")?;
+        }
+        write!(f, "{} ({})", self.name, format_duration(self.duration_ms))?;
+        match self.progress().as_str() {
+            "" => Ok(()),
+            progress => write!(f, " [{progress}]"),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct User {
+    pub id: String,
+    pub display_name: Option<String>,
+}
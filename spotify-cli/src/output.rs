@@ -0,0 +1,441 @@
+use super::config::ThemeConfig;
+use super::duration::format_duration;
+use super::models::{self, Artist, Device, Episode, Playlist, Show, Song};
+use super::text::{self, pad, truncate};
+use clap::ValueEnum;
+use std::{
+    env, error,
+    io::{self, IsTerminal},
+    sync::OnceLock,
+};
+
+/// When to color the output.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ColorChoice {
+    /// Color if stdout is a terminal and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+/// How to lay out lists of tracks, playlists and devices.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputMode {
+    /// A line per item
+    Text,
+    /// Aligned columns, truncated to the terminal width
+    Table,
+    /// A JSON array of objects keyed by column, for scripts
+    Json,
+}
+
+static MODE: OnceLock<OutputMode> = OnceLock::new();
+
+/// Set with `--quiet`.
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Set with `--no-truncate`.
+static NO_TRUNCATE: OnceLock<bool> = OnceLock::new();
+
+/// ANSI SGR codes for each kind of styled text.
+#[derive(Debug)]
+struct Theme {
+    highlight: String,
+    paused: String,
+    header: String,
+    unavailable: String,
+}
+
+/// `None` when colors are disabled.
+static THEME: OnceLock<Option<Theme>> = OnceLock::new();
+
+/// Enables or disables colors and sets up the theme. Until this is called
+/// nothing is colored.
+pub fn init(
+    choice: ColorChoice,
+    mode: OutputMode,
+    quiet: bool,
+    no_truncate: bool,
+    theme: &ThemeConfig,
+) -> Result<(), Box<dyn error::Error>> {
+    let _ = MODE.set(mode);
+    let _ = QUIET.set(quiet);
+    let _ = NO_TRUNCATE.set(no_truncate);
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        }
+    };
+
+    // Parse the theme even when colors are off so typos show up right away.
+    let theme = Theme {
+        highlight: parse_style(theme.highlight.as_deref().unwrap_or("bright-yellow"))?,
+        paused: parse_style(theme.paused.as_deref().unwrap_or("yellow"))?,
+        header: parse_style(theme.header.as_deref().unwrap_or("bold"))?,
+        unavailable: parse_style(theme.unavailable.as_deref().unwrap_or("bright-black"))?,
+    };
+    let _ = THEME.set(enabled.then_some(theme));
+
+    Ok(())
+}
+
+/// Parses a style like `bold bright-green` or raw SGR codes like `1;92` into
+/// SGR codes.
+fn parse_style(style: &str) -> Result<String, Box<dyn error::Error>> {
+    let codes = style
+        .split_whitespace()
+        .map(|word| {
+            let code = match word {
+                "bold" => "1",
+                "dim" => "2",
+                "italic" => "3",
+                "underline" => "4",
+                "black" => "30",
+                "red" => "31",
+                "green" => "32",
+                "yellow" => "33",
+                "blue" => "34",
+                "magenta" => "35",
+                "cyan" => "36",
+                "white" => "37",
+                "bright-black" => "90",
+                "bright-red" => "91",
+                "bright-green" => "92",
+                "bright-yellow" => "93",
+                "bright-blue" => "94",
+                "bright-magenta" => "95",
+                "bright-cyan" => "96",
+                "bright-white" => "97",
+                codes if codes.split(';').all(|c| c.parse::<u8>().is_ok()) => codes,
+                _ => return Err(format!("Unknown style '{word}' in theme.")),
+            };
+            Ok(code)
+        })
+        .collect::<Result<Vec<&str>, String>>()?;
+
+    Ok(codes.join(";"))
+}
+
+fn paint(text: &str, code: impl Fn(&Theme) -> &str) -> String {
+    match THEME.get() {
+        Some(Some(theme)) => format!("\x1b[{}m{text}\x1b[0m", code(theme)),
+        _ => text.to_string(),
+    }
+}
+
+/// The current track in track lists.
+pub fn highlight(text: &str) -> String {
+    paint(text, |theme| &theme.highlight)
+}
+
+pub fn paused(text: &str) -> String {
+    paint(text, |theme| &theme.paused)
+}
+
+/// Titles of playlists, albums etc. above track lists.
+pub fn header(text: &str) -> String {
+    paint(text, |theme| &theme.header)
+}
+
+/// Tracks that can't be played in the market.
+pub fn unavailable(text: &str) -> String {
+    paint(text, |theme| &theme.unavailable)
+}
+
+/// Whether lists should be printed as tables, from `--output table` or
+/// `--output json`; `Table::print` prints the json.
+pub fn tables() -> bool {
+    matches!(MODE.get(), Some(OutputMode::Table | OutputMode::Json))
+}
+
+/// Whether to print json, from `--output json`.
+pub fn json() -> bool {
+    MODE.get() == Some(&OutputMode::Json)
+}
+
+/// Whether informational messages are left out, from `--quiet`.
+pub fn quiet() -> bool {
+    QUIET.get() == Some(&true)
+}
+
+/// Prints an informational message, e.g. confirming what a command did, like
+/// `println!` unless `--quiet` was given. What the user asked to see, like
+/// track lists, is printed with `println!` as usual.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::output::quiet() {
+            println!($($arg)*)
+        }
+    };
+}
+pub(crate) use info;
+
+/// The width to fit tables in: `COLUMNS` if set, else the terminal's, else 80.
+fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .or_else(|| term_size::dimensions().map(|(width, _)| width))
+        .unwrap_or(80)
+}
+
+/// The width to fit lines and tables in, or `None` with `--no-truncate`.
+pub fn line_width() -> Option<usize> {
+    match NO_TRUNCATE.get() {
+        Some(true) => None,
+        _ => Some(terminal_width()),
+    }
+}
+
+/// Rows printed under a header with their columns aligned. When the table is
+/// wider than the terminal, the widest columns are truncated first.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Row>,
+}
+
+struct Row {
+    cells: Vec<String>,
+    style: Option<fn(&str) -> String>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Table {
+        Table {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn add_row(&mut self, cells: Vec<String>) {
+        self.rows.push(Row { cells, style: None });
+    }
+
+    /// Adds a row painted with e.g. `highlight`.
+    pub fn add_styled_row(&mut self, cells: Vec<String>, style: fn(&str) -> String) {
+        self.rows.push(Row {
+            cells,
+            style: Some(style),
+        });
+    }
+
+    pub fn print(&self) {
+        if json() {
+            println!("{}", self.to_json());
+            return;
+        }
+
+        let lines = self.render(line_width().unwrap_or(usize::MAX));
+        if let Some((first, rows)) = lines.split_first() {
+            println!("{}", header(first));
+            for (line, row) in rows.iter().zip(&self.rows) {
+                match row.style {
+                    Some(style) => println!("{}", style(line)),
+                    None => println!("{line}"),
+                }
+            }
+        }
+    }
+
+    /// The rows as objects keyed by the headers in snake case, e.g.
+    /// `Starts in` becomes `starts_in` and `#` becomes `index`.
+    fn to_json(&self) -> serde_json::Value {
+        let keys: Vec<String> = self
+            .headers
+            .iter()
+            .map(|header| match header.as_str() {
+                "#" => "index".to_string(),
+                header => header.to_lowercase().replace(' ', "_"),
+            })
+            .collect();
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                keys.iter()
+                    .cloned()
+                    .zip(row.cells.iter().map(|cell| cell.clone().into()))
+                    .collect::<serde_json::Map<_, _>>()
+                    .into()
+            })
+            .collect();
+
+        serde_json::Value::Array(rows)
+    }
+
+    fn render(&self, max_width: usize) -> Vec<String> {
+        const GAP: usize = 2;
+        // Truncating further than this would leave nothing readable.
+        const MIN_COLUMN: usize = 5;
+
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| text::width(h)).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(&row.cells) {
+                *width = (*width).max(text::width(cell));
+            }
+        }
+
+        let gaps = GAP * widths.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + gaps > max_width {
+            let (widest, width) = widths
+                .iter_mut()
+                .enumerate()
+                .max_by_key(|(_, width)| **width)
+                .map(|(ind, width)| (ind, *width))
+                .unwrap_or((0, 0));
+            if width <= MIN_COLUMN {
+                break;
+            }
+            widths[widest] -= 1;
+        }
+
+        std::iter::once(&self.headers)
+            .chain(self.rows.iter().map(|row| &row.cells))
+            .map(|cells| {
+                let line = cells
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| pad(&truncate(cell, width), width))
+                    .collect::<Vec<_>>()
+                    .join(&" ".repeat(GAP));
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+/// Things listed as tables, one per row.
+pub trait TableRow {
+    const HEADERS: &'static [&'static str];
+
+    fn cells(&self) -> Vec<String>;
+
+    /// The headers of the cells; `HEADERS` unless some columns are optional.
+    fn headers() -> Vec<&'static str> {
+        Self::HEADERS.to_vec()
+    }
+}
+
+impl TableRow for Song {
+    const HEADERS: &'static [&'static str] = &["Name", "Artists", "Album", "Duration", "URI"];
+
+    fn cells(&self) -> Vec<String> {
+        let artists: Vec<&str> = self.artists.iter().map(|a| a.name.as_str()).collect();
+        let mut cells = vec![
+            self.name.clone(),
+            artists.join(", "),
+            self.album
+                .as_ref()
+                .map(|a| a.name.clone())
+                .unwrap_or_default(),
+            format_duration(self.duration_ms),
+            self.uri.clone(),
+        ];
+        if models::details() {
+            if self.explicit {
+                cells[0] += " [E]";
+            }
+            let popularity = self.popularity.map(|p| p.to_string()).unwrap_or_default();
+            cells.insert(4, popularity);
+        }
+        cells
+    }
+
+    fn headers() -> Vec<&'static str> {
+        let mut headers = Self::HEADERS.to_vec();
+        if models::details() {
+            headers.insert(4, "Popularity");
+        }
+        headers
+    }
+}
+
+impl TableRow for Artist {
+    const HEADERS: &'static [&'static str] = &["Name", "URI"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.uri
+                .clone()
+                .unwrap_or_else(|| format!("spotify:artist:{}", self.id)),
+        ]
+    }
+}
+
+impl TableRow for Playlist {
+    const HEADERS: &'static [&'static str] = &["Name", "Tracks", "Access", "URI"];
+
+    fn cells(&self) -> Vec<String> {
+        let access = match (self.collaborative, self.public) {
+            (true, _) => "collaborative",
+            (false, Some(true)) => "public",
+            (false, Some(false)) => "private",
+            (false, None) => "",
+        };
+        vec![
+            self.name.clone(),
+            self.tracks.total.to_string(),
+            access.to_string(),
+            self.uri.clone(),
+        ]
+    }
+}
+
+impl TableRow for Show {
+    const HEADERS: &'static [&'static str] = &["Name", "Publisher", "Episodes", "URI"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.publisher.clone().unwrap_or_default(),
+            self.total_episodes
+                .map(|total| total.to_string())
+                .unwrap_or_default(),
+            self.uri.clone(),
+        ]
+    }
+}
+
+impl TableRow for Episode {
+    const HEADERS: &'static [&'static str] = &["Released", "Name", "Duration", "Progress", "URI"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.release_date.clone().unwrap_or_default(),
+            self.name.clone(),
+            format_duration(self.duration_ms),
+            self.progress(),
+            self.uri.clone(),
+        ]
+    }
+}
+
+impl TableRow for Device {
+    const HEADERS: &'static [&'static str] = &["Name", "Type", "Volume", "Active"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.r#type.clone(),
+            self.volume_percent
+                .map(|volume| format!("{volume}%"))
+                .unwrap_or_default(),
+            if self.is_active { "yes" } else { "" }.to_string(),
+        ]
+    }
+}
+
+/// Prints the items as a table with their indices in the first column.
+pub fn print_numbered_table<T: TableRow>(items: &[T]) {
+    let headers: Vec<&str> = std::iter::once("#").chain(T::headers()).collect();
+    let mut table = Table::new(&headers);
+    for (ind, item) in items.iter().enumerate() {
+        let mut cells = vec![ind.to_string()];
+        cells.extend(item.cells());
+        table.add_row(cells);
+    }
+    table.print();
+}
@@ -0,0 +1,121 @@
+use super::i18n::tr;
+use std::{error, fs, io, path::PathBuf, sync::OnceLock};
+
+/// Whether to use the old dotfiles in the home directory, from
+/// `--legacy-paths`.
+static LEGACY: OnceLock<bool> = OnceLock::new();
+
+pub fn init(legacy: bool) {
+    let _ = LEGACY.set(legacy);
+}
+
+fn legacy() -> bool {
+    LEGACY.get().copied().unwrap_or(false)
+}
+
+/// The files the CLI keeps by default, each under its XDG base directory
+/// (`$XDG_CONFIG_HOME/spotify-cli/` etc.; the platform's equivalents on
+/// macOS and Windows). They used to be dotfiles in the home directory.
+#[derive(Clone, Copy, Debug)]
+pub enum DefaultFile {
+    Token,
+    Config,
+    Cache,
+    State,
+    History,
+}
+
+impl DefaultFile {
+    fn legacy_name(&self) -> &'static str {
+        match self {
+            DefaultFile::Token => ".spotify_cli_token",
+            DefaultFile::Config => ".spotify_cli_config.toml",
+            DefaultFile::Cache => ".spotify_cli_cache",
+            DefaultFile::State => ".spotify_cli_state",
+            DefaultFile::History => ".spotify_cli_history.sqlite",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DefaultFile::Token => "token.json",
+            DefaultFile::Config => "config.toml",
+            DefaultFile::Cache => "cache.json",
+            DefaultFile::State => "state.json",
+            DefaultFile::History => "history.sqlite",
+        }
+    }
+
+    fn base_dir(&self) -> Option<PathBuf> {
+        match self {
+            DefaultFile::Token | DefaultFile::Config => dirs::config_dir(),
+            DefaultFile::Cache => dirs::cache_dir(),
+            // There's no state directory outside Linux.
+            DefaultFile::State => dirs::state_dir().or_else(dirs::data_local_dir),
+            DefaultFile::History => dirs::data_local_dir(),
+        }
+    }
+}
+
+/// The path of a file when no env variable or option points elsewhere. A file
+/// still at its legacy path is moved to the new one the first time it's
+/// needed; if that fails, it's used where it is.
+pub fn default_path(file: DefaultFile) -> Result<String, Box<dyn error::Error>> {
+    let legacy_path = dirs::home_dir()
+        .ok_or("Can't get home directory?")?
+        .join(file.legacy_name());
+    if legacy() {
+        return path_string(legacy_path);
+    }
+
+    let dir = file
+        .base_dir()
+        .ok_or("Can't get the config/cache/data directory?")?
+        .join("spotify-cli");
+    let path = dir.join(file.name());
+    if !fs::exists(&path)? && fs::exists(&legacy_path)? {
+        match fs::create_dir_all(&dir).and_then(|_| move_file(&legacy_path, &path)) {
+            Ok(()) => eprintln!(
+                "{}",
+                tr!(
+                    "Moved {from} to {to}.",
+                    from = legacy_path.display(),
+                    to = path.display()
+                )
+            ),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "Failed to move {from} to {to}: {e}",
+                        from = legacy_path.display(),
+                        to = path.display(),
+                        e = e
+                    )
+                );
+                return path_string(legacy_path);
+            }
+        }
+    }
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create the directory {}: {e}", dir.display()))?;
+
+    path_string(path)
+}
+
+/// Renames, or copies and removes when the paths are on different
+/// filesystems.
+fn move_file(from: &PathBuf, to: &PathBuf) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+fn path_string(path: PathBuf) -> Result<String, Box<dyn error::Error>> {
+    Ok(path
+        .to_str()
+        .ok_or(format!("The path {} isn't UTF-8.", path.display()))?
+        .to_string())
+}
@@ -0,0 +1,183 @@
+use super::text;
+use clap::ValueEnum;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+use std::{
+    error,
+    io::{self, IsTerminal, Write},
+    sync::OnceLock,
+};
+
+/// When to choose from lists with the fuzzy finder instead of typing a number.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PickerChoice {
+    /// Use the picker if stdin and stderr are terminals
+    Auto,
+    Always,
+    Never,
+}
+
+static CHOICE: OnceLock<PickerChoice> = OnceLock::new();
+
+pub fn init(choice: PickerChoice) {
+    let _ = CHOICE.set(choice);
+}
+
+/// Whether choices should go through `pick`. Errors if the picker was asked
+/// for but there's no terminal to draw it on.
+pub fn enabled() -> Result<bool, Box<dyn error::Error>> {
+    let terminal = io::stdin().is_terminal() && io::stderr().is_terminal();
+    match CHOICE.get().copied().unwrap_or(PickerChoice::Auto) {
+        PickerChoice::Auto => Ok(terminal),
+        PickerChoice::Never => Ok(false),
+        PickerChoice::Always if terminal => Ok(true),
+        PickerChoice::Always => Err("--picker always needs a terminal.".into()),
+    }
+}
+
+/// Scores how well `candidate` matches `query`: all characters of the query
+/// have to appear in order, ignoring case. Consecutive matches and matches
+/// at word starts score higher. `None` if it doesn't match.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut start = 0;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let ind = start + candidate[start..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == ind) {
+            score += 5;
+        }
+        if ind == 0 || !candidate[ind - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(ind);
+        start = ind + 1;
+    }
+    // Prefer shorter candidates among equally good matches.
+    Some(score * 1000 - candidate.len() as i64)
+}
+
+/// Indices of the items matching the query, best first.
+fn matches(items: &[String], query: &str) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(ind, item)| Some((fuzzy_score(query, item)?, ind)))
+        .collect();
+    if !query.trim().is_empty() {
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    }
+    scored.into_iter().map(|(_, ind)| ind).collect()
+}
+
+/// Lets the user narrow down `items` by typing and pick one with the arrow
+/// keys and Enter. Returns the index of the picked item, or `None` if the
+/// user pressed Esc or Ctrl-C.
+pub fn pick(
+    prompt: &str,
+    items: &[String],
+    query: &str,
+) -> Result<Option<usize>, Box<dyn error::Error>> {
+    let mut stderr = io::stderr();
+    terminal::enable_raw_mode()?;
+    execute!(stderr, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let picked = run(&mut stderr, prompt, items, query.to_string());
+    let _ = execute!(stderr, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+
+    picked
+}
+
+fn run(
+    out: &mut impl Write,
+    prompt: &str,
+    items: &[String],
+    mut query: String,
+) -> Result<Option<usize>, Box<dyn error::Error>> {
+    let mut selected = 0;
+    loop {
+        let matching = matches(items, &query);
+        selected = selected.min(matching.len().saturating_sub(1));
+        draw(out, prompt, items, &query, &matching, selected)?;
+
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if ctrl => return Ok(None),
+            KeyCode::Enter => return Ok(matching.get(selected).copied()),
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => selected += 1,
+            KeyCode::Char('n') if ctrl => selected += 1,
+            KeyCode::Char('u') if ctrl => query.clear(),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(
+    out: &mut impl Write,
+    prompt: &str,
+    items: &[String],
+    query: &str,
+    matching: &[usize],
+    selected: usize,
+) -> Result<(), Box<dyn error::Error>> {
+    let (width, height) = terminal::size()?;
+    let width = width as usize;
+    // The prompt, query and count lines take three rows.
+    let rows = (height as usize).saturating_sub(3).max(1);
+    let first = selected.saturating_sub(rows - 1);
+
+    queue!(
+        out,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        Print(text::cut(prompt, width)),
+        cursor::MoveTo(0, 1),
+        Print(text::cut(&format!("> {query}"), width).to_string()),
+        cursor::MoveTo(0, 2),
+        Print(format!("  {}/{}", matching.len(), items.len())),
+    )?;
+    for (row, &ind) in matching.iter().skip(first).take(rows).enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16 + 3))?;
+        let line = format!("  {}", items[ind]);
+        let line = text::cut(&line, width);
+        if first + row == selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(line),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(line))?;
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::OnceLock,
+};
+
+/// How the progress of long operations is reported on stderr.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ProgressFormat {
+    /// A progress line, when stderr is a terminal
+    Text,
+    /// A JSON object per line for each event, for GUIs and wrappers
+    Json,
+}
+
+static FORMAT: OnceLock<ProgressFormat> = OnceLock::new();
+
+pub fn init(format: ProgressFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn json() -> bool {
+    FORMAT.get() == Some(&ProgressFormat::Json)
+}
+
+/// Something that happened during a long operation. Serialized with the
+/// kind of the event under `event`, e.g.
+/// `{"event":"page_fetched","label":"Fetching tracks","done":100,"total":250}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A page of a paged list was fetched; `done` items so far.
+    PageFetched {
+        label: &'a str,
+        done: usize,
+        total: Option<usize>,
+    },
+    /// A batch of tracks was added to a playlist.
+    TracksAdded {
+        playlist: &'a str,
+        done: usize,
+        total: usize,
+    },
+    /// A step of some other long operation, e.g. queueing tracks one by one.
+    Step {
+        label: &'a str,
+        done: usize,
+        total: Option<usize>,
+    },
+    /// Rate limited by Spotify; the request is retried after waiting.
+    Retrying { after_secs: u64, attempt: u32 },
+}
+
+/// Reports an event. With `--progress json` it's printed on stderr as a line
+/// of JSON; otherwise events are only shown through `Progress`.
+pub fn report(event: &Event) {
+    if !json() {
+        return;
+    }
+    if let Ok(line) = serde_json::to_string(event) {
+        eprintln!("{line}");
+    }
+}
+
+/// What a `Progress` counts, which decides the events it reports.
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    Pages,
+    Steps,
+}
+
+/// The sink long operations report their progress to: a progress line on
+/// stderr if it's a terminal, cleared when dropped, or events with
+/// `--progress json`.
+pub struct Progress {
+    label: &'static str,
+    kind: Kind,
+    total: Option<usize>,
+    ticks: usize,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, kind: Kind, total: Option<usize>) -> Progress {
+        Progress {
+            label,
+            kind,
+            total,
+            ticks: 0,
+            visible: !json() && io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn update(&mut self, done: usize) {
+        let (label, total) = (self.label, self.total);
+        report(&match self.kind {
+            Kind::Pages => Event::PageFetched { label, done, total },
+            Kind::Steps => Event::Step { label, done, total },
+        });
+        if !self.visible {
+            return;
+        }
+
+        const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+        let spinner = SPINNER[self.ticks % SPINNER.len()];
+        self.ticks += 1;
+        match self.total {
+            Some(total) => eprint!("\r{spinner} {} {done}/{total}", self.label),
+            None => eprint!("\r{spinner} {} {done}", self.label),
+        }
+        let _ = io::stderr().flush();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.visible && self.ticks > 0 {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
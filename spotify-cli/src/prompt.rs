@@ -0,0 +1,71 @@
+use super::i18n::tr;
+use std::{error, io, sync::OnceLock};
+
+/// Answers to prompts given up front with `--choose` and `--accept`, so
+/// scripts don't need to feed stdin.
+#[derive(Debug, Default)]
+struct Answers {
+    choose: Option<usize>,
+    accept: bool,
+}
+
+static ANSWERS: OnceLock<Answers> = OnceLock::new();
+
+pub fn init(choose: Option<usize>, accept: bool) {
+    let _ = ANSWERS.set(Answers { choose, accept });
+}
+
+/// The number to answer "which one of these" prompts with, from `--choose`.
+pub fn preset_choice() -> Option<usize> {
+    ANSWERS.get().and_then(|answers| answers.choose)
+}
+
+/// Whether to answer yes to confirmations, from `--accept`.
+pub fn accept_all() -> bool {
+    ANSWERS.get().is_some_and(|answers| answers.accept)
+}
+
+/// Reads the answer to a prompt like `io::stdin().read_line`, except that a
+/// closed stdin is an error. Otherwise a script piping in too few answers
+/// would have every prompt after that answered with an empty line, and the
+/// wizards would loop forever.
+pub fn read_line(buf: &mut String) -> Result<usize, Box<dyn error::Error>> {
+    match io::stdin().read_line(buf)? {
+        0 => Err(tr!("No answer to the prompt; stdin is closed. Use --choose <number> or --accept to answer prompts in scripts.").into()),
+        read => Ok(read),
+    }
+}
+
+/// Whether a lowercased answer means yes. The answer key is translated along
+/// with the questions, e.g. "k" for "kyllä", and "y" works in every language.
+pub fn is_yes(answer: &str) -> bool {
+    answer.starts_with(&tr!("y").to_lowercase()) || answer.starts_with("y")
+}
+
+/// Whether a lowercased answer means no, like [`is_yes`].
+pub fn is_no(answer: &str) -> bool {
+    answer.starts_with(&tr!("n").to_lowercase()) || answer.starts_with("n")
+}
+
+/// Asks a yes/no question; an empty answer means `default`. `--accept`
+/// answers yes without reading stdin.
+pub fn confirm(question: &str, default: bool) -> Result<bool, Box<dyn error::Error>> {
+    let options = match default {
+        true => tr!("(Y/n)"),
+        false => tr!("(y/N)"),
+    };
+    println!("{question} {options}");
+    if accept_all() {
+        println!("{}", tr!("y"));
+        return Ok(true);
+    }
+
+    let mut user_response = String::new();
+    read_line(&mut user_response)?;
+    let user_response = user_response.trim().to_lowercase();
+
+    Ok(match user_response.is_empty() {
+        true => default,
+        false => is_yes(&user_response),
+    })
+}
@@ -0,0 +1,131 @@
+use chrono::{Local, NaiveTime, Timelike};
+use clap::ValueEnum;
+use std::{env, error, time::Duration};
+
+/// Alternatives to waiting in a running process: entries for the system
+/// scheduler that run the command at the time.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SchedulerEntry {
+    Cron,
+    Systemd,
+}
+
+/// Parses a local time like `07:00`, or `now`.
+pub fn parse_time(time: &str) -> Result<Option<NaiveTime>, String> {
+    if time == "now" {
+        return Ok(None);
+    }
+
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map(Some)
+        .map_err(|_| format!("Bad time: {time}. Use e.g. 07:00, or now."))
+}
+
+/// Waits until the next time the local clock shows `time`.
+///
+/// Sleeps in short steps and checks the wall clock in between, since a
+/// single long sleep doesn't account for the machine being suspended.
+pub async fn wait_until(time: NaiveTime) {
+    let now = Local::now();
+    let mut target = now.date_naive().and_time(time);
+    if target <= now.naive_local() {
+        target += chrono::Duration::days(1);
+    }
+
+    loop {
+        let left = target - Local::now().naive_local();
+        if left <= chrono::Duration::zero() {
+            return;
+        }
+        let step = left
+            .to_std()
+            .unwrap_or_default()
+            .min(Duration::from_secs(30));
+        tokio::time::sleep(step).await;
+    }
+}
+
+/// Quotes a word for sh if needed.
+fn shell_quote(word: &str) -> String {
+    let plain = word
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    match plain && !word.is_empty() {
+        true => word.to_string(),
+        false => format!("'{}'", word.replace('\'', r"'\''")),
+    }
+}
+
+/// The command line that starts the playback right away, i.e. this one with
+/// the time replaced by `now` and without scheduling flags.
+fn command_line(args: &[String]) -> Result<String, Box<dyn error::Error>> {
+    let exe = env::current_exe()?;
+    let mut words = vec![shell_quote(&exe.to_string_lossy())];
+
+    let mut args = args.iter().skip(1);
+    let mut replaced_time = false;
+    while let Some(arg) = args.next() {
+        if arg == "--print" {
+            args.next();
+            continue;
+        }
+        if arg.starts_with("--print=") || arg == "--detach" {
+            continue;
+        }
+        if !replaced_time && parse_time(arg).is_ok_and(|time| time.is_some()) {
+            words.push("now".to_string());
+            replaced_time = true;
+            continue;
+        }
+        words.push(shell_quote(arg));
+    }
+
+    Ok(words.join(" "))
+}
+
+/// Prints a crontab line or systemd user units that start the playback at
+/// `time` every day.
+pub fn print_entry(
+    entry: SchedulerEntry,
+    time: NaiveTime,
+    args: &[String],
+) -> Result<(), Box<dyn error::Error>> {
+    let command = command_line(args)?;
+
+    match entry {
+        SchedulerEntry::Cron => {
+            println!(
+                "# Add with `crontab -e`. SPOTIFY_CLI_CLIENT_ID and SPOTIFY_CLI_CLIENT_SECRET"
+            );
+            println!("# must be set for cron too, e.g. on lines of their own above this one.");
+            println!("{} {} * * * {command}", time.minute(), time.hour());
+        }
+        SchedulerEntry::Systemd => {
+            println!("# ~/.config/systemd/user/spotify-cli-alarm.service");
+            println!("[Unit]");
+            println!("Description=Start Spotify playback");
+            println!();
+            println!("[Service]");
+            println!("Type=oneshot");
+            println!("Environment=SPOTIFY_CLI_CLIENT_ID=<client id>");
+            println!("Environment=SPOTIFY_CLI_CLIENT_SECRET=<client secret>");
+            println!("ExecStart={command}");
+            println!();
+            println!("# ~/.config/systemd/user/spotify-cli-alarm.timer");
+            println!("# Enable with `systemctl --user enable --now spotify-cli-alarm.timer`.");
+            println!("[Unit]");
+            println!(
+                "Description=Start Spotify playback at {}",
+                time.format("%H:%M")
+            );
+            println!();
+            println!("[Timer]");
+            println!("OnCalendar=*-*-* {}:00", time.format("%H:%M"));
+            println!();
+            println!("[Install]");
+            println!("WantedBy=timers.target");
+        }
+    }
+
+    Ok(())
+}
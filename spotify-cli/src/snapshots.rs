@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::{env, error, fs, io, path::PathBuf};
+
+/// Snapshots kept per playlist; older ones are dropped.
+const MAX_SNAPSHOTS: usize = 20;
+
+/// The tracks of a playlist before the CLI changed it, so the change can be
+/// rolled back with `playlist undo`.
+#[derive(Deserialize, Debug, Serialize)]
+pub struct Snapshot {
+    /// Seconds from the epoch.
+    pub taken_at: u64,
+    /// What was about to change the playlist, e.g. `sort`.
+    pub reason: String,
+    /// Spotify's version id of the playlist at the time.
+    pub snapshot_id: Option<String>,
+    pub uris: Vec<String>,
+}
+
+fn snapshot_dir() -> Result<PathBuf, Box<dyn error::Error>> {
+    match env::var("SPOTIFY_CLI_SNAPSHOT_DIR") {
+        Ok(dir) => Ok(PathBuf::from(dir)),
+        Err(_) => Ok(dirs::data_local_dir()
+            .ok_or("Can't get local data directory?")?
+            .join("spotify-cli")
+            .join("snapshots")),
+    }
+}
+
+fn snapshot_path(playlist_id: &str) -> Result<PathBuf, Box<dyn error::Error>> {
+    Ok(snapshot_dir()?.join(format!("{playlist_id}.json")))
+}
+
+/// Snapshots of a playlist, oldest first. No file means no snapshots.
+pub fn load(playlist_id: &str) -> Result<Vec<Snapshot>, Box<dyn error::Error>> {
+    let path = snapshot_path(playlist_id)?;
+    let snapshots_str = match fs::read_to_string(&path) {
+        Ok(snapshots_str) => snapshots_str,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display()).into()),
+    };
+
+    serde_json::from_str(&snapshots_str)
+        .map_err(|e| format!("Invalid snapshot file {}: {e}", path.display()).into())
+}
+
+pub fn save(playlist_id: &str, snapshots: &[Snapshot]) -> Result<(), Box<dyn error::Error>> {
+    let path = snapshot_path(playlist_id)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let snapshots_str = serde_json::to_string_pretty(snapshots)?;
+
+    // Same as the state file: an interrupted save must not lose the snapshots.
+    let tmp_path = path.with_extension(format!("json.tmp-{}", std::process::id()));
+    fs::write(&tmp_path, snapshots_str)?;
+    fs::rename(&tmp_path, &path)?;
+
+    Ok(())
+}
+
+/// Adds a snapshot of a playlist, dropping the oldest ones over the limit.
+pub fn push(playlist_id: &str, snapshot: Snapshot) -> Result<(), Box<dyn error::Error>> {
+    let mut snapshots = load(playlist_id)?;
+    snapshots.push(snapshot);
+    let excess = snapshots.len().saturating_sub(MAX_SNAPSHOTS);
+    snapshots.drain(..excess);
+
+    save(playlist_id, &snapshots)
+}
@@ -0,0 +1,96 @@
+use super::paths::{self, DefaultFile};
+use serde::{Deserialize, Serialize};
+use std::{env, error, fs, io};
+
+/// Data the user builds up through commands, e.g. the blocklist. Unlike the
+/// cache this can't be refetched, so a broken file is an error instead of
+/// being silently replaced.
+#[derive(Deserialize, Debug, Default, Serialize)]
+pub struct StateFile {
+    #[serde(default)]
+    pub blocklist: Vec<BlockedTrack>,
+    /// Volume before `mute`, restored by the next `mute`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted_volume: Option<u8>,
+    /// Commands that failed for lack of network with `--queue-offline`,
+    /// oldest first. Replayed by `sync`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub offline_queue: Vec<QueuedCommand>,
+    /// Where an episode or a long track was last paused, for `resume-last`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_position: Option<SavedPosition>,
+    /// The device `toggle` last saw playback on, for `toggle --last-device`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_device: Option<LastDevice>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct LastDevice {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct SavedPosition {
+    pub uri: String,
+    pub name: String,
+    /// The show, playlist or album it was played from.
+    pub context_uri: Option<String>,
+    pub progress_ms: u64,
+    /// Seconds from the epoch.
+    pub saved_at: u64,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct QueuedCommand {
+    /// Seconds from the epoch.
+    pub queued_at: u64,
+    /// The command line without the binary name and global options, as for
+    /// `exec`.
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct BlockedTrack {
+    pub uri: String,
+    /// `track - artists`, for listing.
+    pub name: String,
+}
+
+impl StateFile {
+    pub fn is_blocked(&self, uri: &str) -> bool {
+        self.blocklist.iter().any(|track| track.uri == uri)
+    }
+}
+
+fn state_path() -> Result<String, Box<dyn error::Error>> {
+    match env::var("SPOTIFY_CLI_STATE_FILE") {
+        Ok(filepath) => Ok(filepath),
+        Err(_) => paths::default_path(DefaultFile::State),
+    }
+}
+
+/// Loads the state. A missing file is treated as empty.
+pub fn load() -> Result<StateFile, Box<dyn error::Error>> {
+    let filepath = state_path()?;
+    let state_str = match fs::read_to_string(&filepath) {
+        Ok(state_str) => state_str,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(StateFile::default()),
+        Err(e) => return Err(format!("Failed to read {filepath}: {e}").into()),
+    };
+
+    serde_json::from_str(&state_str)
+        .map_err(|e| format!("Invalid state file {filepath}: {e}").into())
+}
+
+pub fn save(state: &StateFile) -> Result<(), Box<dyn error::Error>> {
+    let filepath = state_path()?;
+    let state_str = serde_json::to_string_pretty(state)?;
+
+    // Write to a temporary file first so an interrupted save can't lose the state.
+    let tmp_path = format!("{filepath}.tmp-{}", std::process::id());
+    fs::write(&tmp_path, state_str)?;
+    fs::rename(&tmp_path, &filepath)?;
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use unicode_width::UnicodeWidthStr;
+
+/// How many columns the text takes in a terminal; CJK characters and most
+/// emoji take two, combining marks none.
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// The longest start of the text that fits in `width` columns.
+pub fn cut(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (ind, c) in text.char_indices() {
+        used += self::width(c.encode_utf8(&mut [0; 4]));
+        if used > width {
+            return &text[..ind];
+        }
+    }
+    text
+}
+
+/// Cuts the text to at most `width` columns, ending with "…" if cut.
+pub fn truncate(text: &str, width: usize) -> String {
+    if self::width(text) <= width {
+        return text.to_string();
+    }
+    cut(text, width.saturating_sub(1)).to_string() + "…"
+}
+
+/// Fills the text with spaces up to `width` columns.
+pub fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(self::width(text));
+    format!("{text}{}", " ".repeat(padding))
+}
+
+/// Cuts fields sharing `room` columns, e.g. the name, artists and album
+/// of a track, so that one long field doesn't push out the others. Each
+/// field keeps its first `MIN_FIELD` columns and the rest of the room is
+/// shared in proportion to how much longer than that the fields are. If
+/// even that doesn't fit, the result is wider than `room`.
+pub fn fit_fields(fields: &[&str], room: usize) -> Vec<String> {
+    // Cutting further than this would leave nothing readable.
+    const MIN_FIELD: usize = 10;
+
+    let lens: Vec<usize> = fields.iter().map(|field| width(field)).collect();
+    let floors: Vec<usize> = lens.iter().map(|&len| len.min(MIN_FIELD)).collect();
+    let extra_total: usize = lens
+        .iter()
+        .zip(&floors)
+        .map(|(len, floor)| len - floor)
+        .sum();
+    if lens.iter().sum::<usize>() <= room || extra_total == 0 {
+        return fields.iter().map(|field| field.to_string()).collect();
+    }
+
+    let extra_room = room.saturating_sub(floors.iter().sum());
+    fields
+        .iter()
+        .zip(lens.iter().zip(&floors))
+        .map(|(field, (&len, &floor))| {
+            truncate(field, floor + (len - floor) * extra_room / extra_total)
+        })
+        .collect()
+}
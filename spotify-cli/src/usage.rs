@@ -0,0 +1,92 @@
+use super::{
+    auth::current_time_secs_from_epoch,
+    cache::{self, ApiCall},
+};
+use reqwest::{StatusCode, Url};
+use std::{error, sync::Mutex};
+
+/// Calls older than this are dropped from the cache.
+const KEEP_SECS: u64 = 24 * 60 * 60;
+
+/// At most this many calls are kept, so a busy day can't bloat the cache.
+const MAX_CALLS: usize = 10_000;
+
+/// Path segments after which the next segment is an id, e.g. `playlists` in
+/// `/playlists/<id>/tracks`.
+const ID_COLLECTIONS: [&str; 10] = [
+    "albums",
+    "artists",
+    "audio-analysis",
+    "audio-features",
+    "categories",
+    "episodes",
+    "playlists",
+    "shows",
+    "tracks",
+    "users",
+];
+
+/// The calls made by this run, saved into the cache by `save`.
+static CALLS: Mutex<Vec<ApiCall>> = Mutex::new(Vec::new());
+
+/// Records a call to the Web API and its response status.
+pub fn record(method: &str, url: &Url, status: StatusCode, retry_after: Option<u64>) {
+    let call = ApiCall {
+        at: current_time_secs_from_epoch().unwrap_or_default(),
+        endpoint: endpoint(method, url),
+        status: status.as_u16(),
+        retry_after,
+    };
+    if let Ok(mut calls) = CALLS.lock() {
+        if calls.len() < MAX_CALLS {
+            calls.push(call);
+        }
+    }
+}
+
+/// How many calls this run has made so far.
+pub fn calls_this_run() -> usize {
+    CALLS.lock().map(|calls| calls.len()).unwrap_or_default()
+}
+
+/// The method and path of a call with the ids left out, so that calls to
+/// the same endpoint count together, e.g. `GET /playlists/{id}/tracks`.
+pub fn endpoint(method: &str, url: &Url) -> String {
+    let path = url.path();
+    let path = path.strip_prefix("/v1").unwrap_or(path);
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    let mut parts = Vec::with_capacity(segments.len());
+    for (ind, segment) in segments.iter().enumerate() {
+        let is_id = ind > 0
+            && ID_COLLECTIONS.contains(&segments[ind - 1])
+            // E.g. `/me/tracks/contains`.
+            && (ind < 2 || segments[ind - 2] != "me");
+        parts.push(if is_id { "{id}" } else { segment });
+    }
+
+    format!("{method} /{}", parts.join("/"))
+}
+
+/// Adds the calls of this run to the ones in the cache, dropping the ones
+/// older than a day.
+pub fn save() -> Result<(), Box<dyn error::Error>> {
+    let calls = match CALLS.lock() {
+        Ok(mut calls) => std::mem::take(&mut *calls),
+        Err(_) => return Ok(()),
+    };
+    if calls.is_empty() {
+        return Ok(());
+    }
+
+    let now = current_time_secs_from_epoch()?;
+    let mut cache = cache::load();
+    cache.api_calls.extend(calls);
+    cache
+        .api_calls
+        .retain(|call| now.saturating_sub(call.at) < KEEP_SECS);
+    let excess = cache.api_calls.len().saturating_sub(MAX_CALLS);
+    cache.api_calls.drain(..excess);
+
+    cache::save(&cache)
+}
@@ -0,0 +1,232 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use wiremock::{
+    matchers::{body_json, method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+fn artist_json(id: &str) -> serde_json::Value {
+    json!({"name": format!("Artist {id}"), "id": id})
+}
+
+async fn mount_search(env: &TestEnv) {
+    Mock::given(method("GET"))
+        .and(path("/search"))
+        .and(query_param("q", "artist:Some Band"))
+        .and(query_param("type", "artist"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "artists": {"items": [artist_json("band")]}
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+}
+
+async fn mount_related(env: &TestEnv, id: &str, related: &[&str]) {
+    let artists: Vec<_> = related.iter().map(|id| artist_json(id)).collect();
+    Mock::given(method("GET"))
+        .and(path(format!("/artists/{id}/related-artists")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"artists": artists})))
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn related_lists_related_artists() {
+    let env = TestEnv::new().await;
+    mount_search(&env).await;
+    mount_related(&env, "band", &["a", "b"]).await;
+
+    let output = env.run(&["artist", "related", "Some Band"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Related to Artist band:\n1. Artist a\n2. Artist b\n"
+    );
+}
+
+#[tokio::test]
+async fn related_falls_back_to_collaborators_and_genres() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/artists/band"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(artist_json("band")))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/artists/band/related-artists"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "error": {"status": 404, "message": "Not Found"}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/artists/band/top-tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [{
+                "name": "A Song",
+                "id": "track-1",
+                "uri": "spotify:track:track-1",
+                "artists": [artist_json("band"), artist_json("feat")]
+            }]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/artists"))
+        .and(query_param("ids", "band"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "artists": [{"id": "band", "genres": ["shoegaze"]}]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/search"))
+        .and(query_param("q", "genre:\"shoegaze\""))
+        .and(query_param("type", "artist"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "artists": {"items": [artist_json("band"), artist_json("gaze")]}
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["artist", "related", "spotify:artist:band"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Similar to Artist band (collaborators and artists of the same genres):\n1. Artist feat\n2. Artist gaze\n"
+    );
+}
+
+#[tokio::test]
+async fn related_takes_localized_artist_urls() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/artists/band"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(artist_json("band")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    mount_related(&env, "band", &["a"]).await;
+
+    let output = env
+        .run(&[
+            "artist",
+            "related",
+            "https://open.spotify.com/intl-fi/artist/band?si=abc",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Related to Artist band:\n1. Artist a\n");
+}
+
+#[tokio::test]
+async fn explore_collects_seeds_into_a_playlist() {
+    let env = TestEnv::new().await;
+    mount_search(&env).await;
+    mount_related(&env, "band", &["a", "b"]).await;
+    mount_related(&env, "b", &["c"]).await;
+    for id in ["band", "c"] {
+        Mock::given(method("GET"))
+            .and(path(format!("/artists/{id}/top-tracks")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "tracks": [{
+                    "name": format!("Hit of {id}"),
+                    "id": format!("hit-{id}"),
+                    "uri": format!("spotify:track:hit-{id}"),
+                    "artists": [artist_json(id)]
+                }]
+            })))
+            .mount(&env.server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "me"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/me/playlists"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "mix"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/mix/tracks"))
+        .and(body_json(json!({
+            "uris": ["spotify:track:hit-band", "spotify:track:hit-c"]
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    // Seed the band, go to b, seed its related artist c, go back and make
+    // the playlist.
+    let mut child = env
+        .command(&["artist", "explore", "Some Band"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"s\n2\ns 1\nb\np My Mix\n")
+        .await
+        .unwrap();
+    let output = child.wait_with_output().await.unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains(r#"Seeds: ["Artist band", "Artist c"]"#),
+        "{out}"
+    );
+    assert!(
+        out.contains("Created playlist My Mix with 2 tracks. uri: spotify:playlist:mix"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn play_top_tracks_plays_them_as_a_list_of_tracks() {
+    let env = TestEnv::new().await;
+    mount_search(&env).await;
+    let track = |n: usize| {
+        json!({"name": format!("Hit {n}"), "id": format!("hit-{n}"),
+            "uri": format!("spotify:track:hit-{n}"), "artists": [artist_json("band")]})
+    };
+    Mock::given(method("GET"))
+        .and(path("/artists/band/top-tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track(1), track(2), track(3)]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({"uris": [
+            "spotify:track:hit-1", "spotify:track:hit-2", "spotify:track:hit-3"
+        ]})))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["artist", "play", "Some Band", "--top-tracks", "-q"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
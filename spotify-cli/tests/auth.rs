@@ -0,0 +1,377 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use std::{fs, process::Stdio};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn exported_tokens_can_be_imported() {
+    let env = TestEnv::new().await;
+    let export_path = env.path("exported");
+
+    let output = env.run(&["auth", "export", &export_path]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let exported = fs::read_to_string(&export_path).unwrap();
+    assert!(exported.contains("test-refresh"));
+
+    fs::write(env.path("token"), "{}").unwrap();
+    let output = env.run(&["auth", "import", &export_path]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Imported tokens"));
+    let imported = fs::read_to_string(env.path("token")).unwrap();
+    assert!(imported.contains("test-refresh"));
+}
+
+#[tokio::test]
+async fn importing_a_file_without_tokens_fails() {
+    let env = TestEnv::new().await;
+    let bogus_path = env.path("bogus");
+    fs::write(&bogus_path, "{}").unwrap();
+
+    let output = env.run(&["auth", "import", &bogus_path]).await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("has no refresh token"));
+}
+
+#[tokio::test]
+async fn refreshed_tokens_replace_the_token_file() {
+    let env = TestEnv::new().await;
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"access_token": "fresh-token", "expires_in": 3600})),
+        )
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["auth", "refresh"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let saved = fs::read_to_string(env.path("token")).unwrap();
+    assert!(saved.contains("fresh-token"));
+    assert!(saved.contains("test-refresh"));
+    let leftovers: Vec<_> = fs::read_dir(env.path(""))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.contains(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+#[tokio::test]
+async fn login_qr_prints_a_qr_code_and_takes_the_pasted_code() {
+    let env = TestEnv::new().await;
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "new-token",
+            "refresh_token": "new-refresh",
+            "expires_in": 3600
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let mut child = env
+        .command(&["auth", "login", "--qr"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"the-code\n")
+        .await
+        .unwrap();
+    let output = child.wait_with_output().await.unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Go to this url for the auth flow:"), "{out}");
+    // Half blocks of the QR code.
+    assert!(
+        out.contains('\u{2580}') || out.contains('\u{2584}'),
+        "{out}"
+    );
+    assert!(out.contains("Logged in."), "{out}");
+    assert!(fs::read_to_string(env.path("token"))
+        .unwrap()
+        .contains("new-refresh"));
+}
+
+#[tokio::test]
+async fn login_ignores_other_paths_and_gives_up_after_the_timeout() {
+    let env = TestEnv::new().await;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    fs::write(
+        env.path("config.toml"),
+        format!(
+            "[auth]\nredirect_ports = [{port}]\nredirect_timeout_secs = 2\nopen_browser = false\n"
+        ),
+    )
+    .unwrap();
+
+    let mut child = env
+        .command(&["auth", "login"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"n\n").await.unwrap();
+
+    let favicon = format!("http://127.0.0.1:{port}/favicon.ico");
+    let mut status = None;
+    for _ in 0..20 {
+        if let Ok(response) = reqwest::get(&favicon).await {
+            status = Some(response.status());
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let output = child.wait_with_output().await.unwrap();
+
+    assert_eq!(status, Some(reqwest::StatusCode::NOT_FOUND));
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Timed out waiting for the redirect. Keep waiting?"));
+    assert!(stderr(&output).contains("Timed out waiting for the authorization redirect."));
+}
+
+#[tokio::test]
+async fn login_rejects_redirects_with_the_wrong_state() {
+    let env = TestEnv::new().await;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    fs::write(
+        env.path("config.toml"),
+        format!("[auth]\nredirect_ports = [{port}]\nopen_browser = false\n"),
+    )
+    .unwrap();
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "new-token",
+            "refresh_token": "new-refresh",
+            "expires_in": 3600
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let mut child = env
+        .command(&["auth", "login"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let auth_url = lines.next_line().await.unwrap().unwrap();
+    let state = auth_url
+        .split("state=")
+        .nth(1)
+        .unwrap()
+        .split('&')
+        .next()
+        .unwrap()
+        .to_string();
+
+    let redirect = format!("http://127.0.0.1:{port}/?code=the-code&state=");
+    let forged = reqwest::get(format!("{redirect}forged")).await.unwrap();
+    assert_eq!(forged.status(), reqwest::StatusCode::BAD_REQUEST);
+    let real = reqwest::get(format!("{redirect}{state}")).await.unwrap();
+    assert_eq!(real.status(), reqwest::StatusCode::OK);
+    assert!(real.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    let output = child.wait_with_output().await.unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut rest = String::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        rest.push_str(&line);
+    }
+    assert!(rest.contains("Logged in."), "{rest}");
+}
+
+#[tokio::test]
+async fn logout_deletes_the_tokens_without_asking_for_new_ones() {
+    let env = TestEnv::new().await;
+
+    let output = env.run(&["auth", "logout"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Deleted the tokens"));
+    assert!(!fs::exists(env.path("token")).unwrap());
+
+    let output = env.run(&["auth", "logout"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("There are no tokens saved"));
+}
+
+#[tokio::test]
+async fn a_fixed_access_token_is_used_without_a_token_file() {
+    let env = TestEnv::new().await;
+    fs::remove_file(env.path("token")).unwrap();
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(header("authorization", "Bearer env-token"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["pause"])
+        .env("SPOTIFY_CLI_ACCESS_TOKEN", "env-token")
+        .env_remove("SPOTIFY_CLI_CLIENT_ID")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fs::exists(env.path("token")).unwrap());
+
+    let output = env
+        .command(&["auth", "refresh"])
+        .env("SPOTIFY_CLI_ACCESS_TOKEN", "env-token")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("can't be used with SPOTIFY_CLI_ACCESS_TOKEN"));
+}
+
+#[tokio::test]
+async fn doctor_passes_with_a_working_setup() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"id": "user-1", "display_name": "Lumi"})),
+        )
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"devices": [
+            {"id": "device-1", "name": "Kitchen", "type": "Speaker", "is_active": true}
+        ]})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["doctor"])
+        .env("SPOTIFY_CLI_REDIRECT_PORTS", "47811,47812")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(!out.contains("FAIL"), "{out}");
+    assert!(out.contains("ok    There's no config at"), "{out}");
+    assert!(
+        out.contains("ok    Port 47811 is free for logging in"),
+        "{out}"
+    );
+    assert!(out.contains("ok    Top tracks: authorized."), "{out}");
+    assert!(out.contains("ok    Reached the Web API as Lumi."), "{out}");
+    assert!(
+        out.contains("ok    1 device(s); Kitchen (Speaker) is active."),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn doctor_reports_a_broken_config_and_missing_tokens_with_hints() {
+    let env = TestEnv::new().await;
+    fs::write(env.path("config.toml"), "[playback]\nbogus = 1\n").unwrap();
+    fs::remove_file(env.path("token")).unwrap();
+
+    let output = env
+        .command(&["doctor"])
+        .env_remove("SPOTIFY_CLI_CLIENT_SECRET")
+        .env("SPOTIFY_CLI_REDIRECT_PORTS", "47813")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("FAIL  The config"), "{out}");
+    assert!(
+        out.contains("FAIL  SPOTIFY_CLI_CLIENT_SECRET not set."),
+        "{out}"
+    );
+    assert!(out.contains("There are no tokens saved"), "{out}");
+    assert!(out.contains("      Run `spotify-cli auth login`."), "{out}");
+    assert!(stderr(&output).contains("3 check(s) failed."));
+    // Doctor only looks; it doesn't offer to create a token file.
+    assert!(!fs::exists(env.path("token")).unwrap());
+}
+
+#[tokio::test]
+async fn legacy_dotfiles_move_to_the_xdg_directories_once() {
+    let env = TestEnv::new().await;
+    let home = env.path("home");
+    fs::create_dir_all(&home).unwrap();
+    let legacy_config = format!("{home}/.spotify_cli_config.toml");
+    fs::write(
+        &legacy_config,
+        "# Mine.\n[alias]\nmix = \"spotify:playlist:p1\"\n",
+    )
+    .unwrap();
+    let run = |legacy: bool| {
+        let mut command = env.command(&["alias", "list"]);
+        if legacy {
+            command.arg("--legacy-paths");
+        }
+        command
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", env.path("xdg-config"))
+            .env_remove("SPOTIFY_CLI_CONFIG_FILE")
+            .output()
+    };
+
+    let output = run(true).await.unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("@mix = spotify:playlist:p1"));
+    assert!(fs::exists(&legacy_config).unwrap());
+
+    let output = run(false).await.unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("@mix = spotify:playlist:p1"));
+    assert!(stderr(&output).contains("Moved"), "{}", stderr(&output));
+    assert!(!fs::exists(&legacy_config).unwrap());
+    let moved = fs::read_to_string(env.path("xdg-config/spotify-cli/config.toml")).unwrap();
+    assert!(moved.starts_with("# Mine."), "{moved}");
+
+    let output = run(false).await.unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("@mix = spotify:playlist:p1"));
+    assert!(!stderr(&output).contains("Moved"), "{}", stderr(&output));
+}
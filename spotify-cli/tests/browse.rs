@@ -0,0 +1,175 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+fn album_json(n: usize) -> serde_json::Value {
+    json!({
+        "name": format!("Album {n}"),
+        "id": format!("album-{n}"),
+        "uri": format!("spotify:album:album-{n}"),
+        "artists": [{"name": "An Artist", "id": "artist-1"}],
+        "release_date": "2026-10-09"
+    })
+}
+
+#[tokio::test]
+async fn new_releases_are_listed_with_numbers() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/browse/new-releases"))
+        .and(query_param("limit", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "albums": {"items": [album_json(1), album_json(2)]}
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["browse", "new-releases", "--limit", "2"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "New releases\n1. Album 1 - An Artist (2026-10-09)\n2. Album 2 - An Artist (2026-10-09)\n"
+    );
+}
+
+fn track_json(n: usize) -> serde_json::Value {
+    json!({"track": {
+        "name": format!("Song {n}"),
+        "id": format!("track-{n}"),
+        "uri": format!("spotify:track:track-{n}"),
+        "artists": []
+    }})
+}
+
+#[tokio::test]
+async fn featured_playlist_can_be_queued_by_number() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/browse/featured-playlists"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "message": "Monday picks",
+            "playlists": {"items": [
+                null,
+                {
+                    "name": "Focus",
+                    "description": null,
+                    "uri": "spotify:playlist:focus",
+                    "tracks": {"total": 2}
+                }
+            ]}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/focus"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Focus",
+            "description": "",
+            "tracks": {
+                "items": [track_json(1), track_json(2)],
+                "next": null,
+                "total": 2
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    for n in [1, 2] {
+        Mock::given(method("POST"))
+            .and(path("/me/player/queue"))
+            .and(query_param("uri", format!("spotify:track:track-{n}")))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&env.server)
+            .await;
+    }
+
+    let listed = env.run(&["browse", "featured-playlists"]).await;
+    let queued = env
+        .run(&["browse", "featured-playlists", "--queue", "1"])
+        .await;
+
+    assert!(listed.status.success(), "{}", stderr(&listed));
+    assert_eq!(
+        stdout(&listed),
+        "Monday picks\n1. Focus(2 tracks) uri: spotify:playlist:focus\n"
+    );
+    assert!(queued.status.success(), "{}", stderr(&queued));
+    assert!(stdout(&queued).contains("Queued 2 tracks of Focus."));
+}
+
+/// Mocks the categories served in two pages.
+async fn mount_categories(env: &TestEnv) {
+    Mock::given(method("GET"))
+        .and(path("/browse/categories"))
+        .and(query_param("limit", "50"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "categories": {
+                "items": [{"id": "cat-jazz", "name": "Jazz"}],
+                "next": format!("{}/browse/categories?offset=1&limit=1", env.server.uri())
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/browse/categories"))
+        .and(query_param("offset", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "categories": {
+                "items": [{"id": "cat-focus", "name": "Focus"}],
+                "next": null
+            }
+        })))
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn categories_are_listed_from_every_page() {
+    let env = TestEnv::new().await;
+    mount_categories(&env).await;
+
+    let output = env.run(&["browse", "categories"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Categories\n1. Jazz (id: cat-jazz)\n2. Focus (id: cat-focus)\n"
+    );
+}
+
+#[tokio::test]
+async fn category_playlists_are_found_by_category_name() {
+    let env = TestEnv::new().await;
+    mount_categories(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/browse/categories/cat-focus/playlists"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "playlists": {
+                "items": [{
+                    "name": "Deep Focus",
+                    "description": null,
+                    "uri": "spotify:playlist:deep",
+                    "tracks": {"total": 100}
+                }],
+                "next": null
+            }
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["browse", "category", "focus"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Focus\n1. Deep Focus(100 tracks) uri: spotify:playlist:deep\n"
+    );
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use std::time::Duration;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+fn enable_history(env: &TestEnv) {
+    std::fs::write(
+        env.path("config.toml"),
+        format!(
+            "[history]\nenabled = true\nfile = \"{}\"\n",
+            env.path("history.sqlite")
+        ),
+    )
+    .unwrap();
+}
+
+#[tokio::test]
+async fn watch_records_plays_for_stats() {
+    let env = TestEnv::new().await;
+    enable_history(&env);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device": {"id": "device-1", "name": "Kitchen", "type": "Speaker"},
+            "item": {
+                "name": "A Song",
+                "id": "track-1",
+                "uri": "spotify:track:track-1",
+                "artists": [{"name": "An Artist", "id": "artist-1"}],
+                "duration_ms": 185000
+            },
+            "is_playing": true,
+            "progress_ms": 125000,
+            "context": null
+        })))
+        .mount(&env.server)
+        .await;
+
+    let mut watch = env.command(&["watch", "--interval", "1"]).spawn().unwrap();
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    watch.kill().await.unwrap();
+
+    let output = env.run(&["history", "stats", "--period", "all"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("1 tracks played, 2m listened."), "{stdout}");
+    assert!(stdout.contains("#1 An Artist (1 plays, 2m)"), "{stdout}");
+}
+
+#[tokio::test]
+async fn stats_without_history_explains_how_to_enable_it() {
+    let env = TestEnv::new().await;
+    enable_history(&env);
+
+    let output = env.run(&["history", "stats"]).await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("[history]"), "{}", stderr(&output));
+}
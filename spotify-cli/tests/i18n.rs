@@ -0,0 +1,97 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use std::{collections::BTreeSet, fs, path::Path};
+
+/// The messages passed to `tr!` in the sources, unescaped.
+fn messages_in_sources() -> BTreeSet<String> {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut messages = BTreeSet::new();
+    for entry in fs::read_dir(src).unwrap() {
+        let path = entry.unwrap().path();
+        // The module itself only has an example in a doc comment.
+        if path.file_name().unwrap() == "i18n.rs" {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        for (_, rest) in source
+            .match_indices("tr!(")
+            .map(|(ind, m)| source.split_at(ind + m.len()))
+        {
+            let Some(literal) = rest.trim_start().strip_prefix('"') else {
+                continue;
+            };
+            let mut message = String::new();
+            let mut chars = literal.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next().unwrap() {
+                        'n' => message.push('\n'),
+                        escaped => message.push(escaped),
+                    },
+                    c => message.push(c),
+                }
+            }
+            messages.insert(message);
+        }
+    }
+    messages
+}
+
+#[test]
+fn the_template_lists_every_message() {
+    let template = Path::new(env!("CARGO_MANIFEST_DIR")).join("locale/template.toml");
+    let template: toml::Table = toml::from_str(&fs::read_to_string(template).unwrap()).unwrap();
+    let in_template: BTreeSet<String> = template.keys().cloned().collect();
+    let in_sources = messages_in_sources();
+
+    let missing: Vec<&String> = in_sources.difference(&in_template).collect();
+    assert!(missing.is_empty(), "Not in the template: {missing:?}");
+    let unused: Vec<&String> = in_template.difference(&in_sources).collect();
+    assert!(unused.is_empty(), "No longer used: {unused:?}");
+}
+
+#[tokio::test]
+async fn messages_are_shown_in_the_language_of_the_locale() {
+    let env = TestEnv::new().await;
+    fs::create_dir_all(env.path("locale")).unwrap();
+    fs::write(
+        env.path("locale/fi.toml"),
+        "\"No queued commands.\" = \"Ei jonossa olevia komentoja.\"\n\"Error: {error}\" = \"Virhe: {error}\"\n\"Logged in.\" = \"\"\n",
+    )
+    .unwrap();
+
+    // fi_FI falls back to the catalog of the language.
+    let output = env
+        .command(&["sync"])
+        .env("LANG", "fi_FI.UTF-8")
+        .env_remove("LC_ALL")
+        .env_remove("LC_MESSAGES")
+        .env("SPOTIFY_CLI_LOCALE_DIR", env.path("locale"))
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Ei jonossa olevia komentoja.\n");
+
+    // Placeholders are filled into the translation.
+    let output = env
+        .command(&["rec", "show"])
+        .env("LC_ALL", "fi_FI.UTF-8")
+        .env_remove("SPOTIFY_CLI_MANAGED_PLAYLIST_ID")
+        .env("SPOTIFY_CLI_LOCALE_DIR", env.path("locale"))
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).starts_with("Virhe: \"The env variable SPOTIFY_CLI_MANAGED_PLAYLIST_ID"),
+        "{}",
+        stderr(&output)
+    );
+
+    // Without a catalog for the locale everything stays in English.
+    let output = env.run(&["sync"]).await;
+    assert_eq!(stdout(&output), "No queued commands.\n");
+}
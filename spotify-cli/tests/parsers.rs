@@ -0,0 +1,131 @@
+// The parsers are self-contained modules of the binary, so they're compiled
+// into this test directly instead of being driven through the CLI.
+#[allow(dead_code)]
+#[path = "../src/duration.rs"]
+mod duration;
+#[allow(dead_code)]
+#[path = "../src/models.rs"]
+mod models;
+#[allow(dead_code)]
+#[path = "../src/text.rs"]
+mod text;
+
+use duration::{format_duration, parse_duration};
+use models::SpotifyId;
+use proptest::prelude::*;
+
+fn kind() -> impl Strategy<Value = String> {
+    prop::sample::select(vec![
+        "track", "album", "artist", "playlist", "episode", "show",
+    ])
+    .prop_map(str::to_string)
+}
+
+fn id() -> impl Strategy<Value = String> {
+    "[0-9A-Za-z]{1,22}"
+}
+
+proptest! {
+    #[test]
+    fn uri_round_trips(kind in kind(), id in id()) {
+        let parsed = SpotifyId::parse(&format!("spotify:{kind}:{id}")).unwrap();
+
+        prop_assert_eq!(&parsed, &SpotifyId::new(&kind, &id));
+        prop_assert_eq!(SpotifyId::parse(&parsed.uri()).unwrap(), parsed);
+    }
+
+    #[test]
+    fn url_round_trips(kind in kind(), id in id()) {
+        let parsed = SpotifyId::parse(&format!("https://open.spotify.com/{kind}/{id}")).unwrap();
+
+        prop_assert_eq!(&parsed, &SpotifyId::new(&kind, &id));
+        prop_assert_eq!(SpotifyId::parse(&parsed.url()).unwrap(), parsed);
+    }
+
+    #[test]
+    fn every_link_form_parses_to_the_same_id(
+        kind in kind(),
+        id in id(),
+        locale in "[a-z]{2}",
+        si in "[0-9a-f]{16}",
+    ) {
+        let expected = SpotifyId::new(&kind, &id);
+        for link in [
+            format!("spotify://{kind}/{id}"),
+            format!("https://open.spotify.com/{kind}/{id}?si={si}"),
+            format!("https://open.spotify.com/intl-{locale}/{kind}/{id}"),
+            format!("  spotify:{kind}:{id}\n"),
+        ] {
+            prop_assert_eq!(SpotifyId::parse(&link).unwrap(), expected.clone(), "{}", link);
+        }
+    }
+
+    #[test]
+    fn old_user_playlist_uris_parse_as_playlists(user in "[a-z0-9]{1,12}", id in id()) {
+        let parsed = SpotifyId::parse(&format!("spotify:user:{user}:playlist:{id}")).unwrap();
+
+        prop_assert_eq!(parsed, SpotifyId::new("playlist", &id));
+    }
+
+    #[test]
+    fn text_that_isnt_a_link_errors(text in "[^:/]*") {
+        prop_assert!(SpotifyId::parse(&text).is_err());
+    }
+
+    #[test]
+    fn bare_numbers_are_minutes(minutes in 1u64..100_000) {
+        prop_assert_eq!(
+            parse_duration(&minutes.to_string()).unwrap().as_secs(),
+            minutes * 60
+        );
+    }
+
+    #[test]
+    fn units_add_up(h in 0u64..100, m in 0u64..1000, s in 0u64..10_000) {
+        prop_assume!(h + m + s > 0);
+
+        prop_assert_eq!(
+            parse_duration(&format!("{h}h{m}m{s}s")).unwrap().as_secs(),
+            h * 3600 + m * 60 + s
+        );
+        if s > 0 {
+            prop_assert_eq!(parse_duration(&format!("{s}s")).unwrap().as_secs(), s);
+        }
+    }
+
+    #[test]
+    fn durations_with_a_missing_unit_or_other_characters_error(
+        number in 1u64..1000,
+        other in "[^0-9hms]",
+    ) {
+        let missing_unit = format!("1h{number}");
+        let other_character = format!("{number}{other}");
+
+        prop_assert!(parse_duration(&missing_unit).is_err());
+        prop_assert!(parse_duration(&other_character).is_err());
+    }
+
+    #[test]
+    fn formatted_durations_read_back_to_the_same_seconds(ms in 0u64..100_000_000) {
+        let formatted = format_duration(ms);
+        let parts: Vec<u64> = formatted.split(':').map(|part| part.parse().unwrap()).collect();
+
+        prop_assert!(parts.len() == 2 || (parts.len() == 3 && parts[0] > 0), "{}", formatted);
+        prop_assert!(parts[1..].iter().all(|&part| part < 60), "{}", formatted);
+        prop_assert_eq!(parts.iter().fold(0, |acc, part| acc * 60 + part), ms / 1000);
+    }
+}
+
+#[test]
+fn zero_durations_error() {
+    assert!(parse_duration("0").is_err());
+    assert!(parse_duration("0s").is_err());
+    assert!(parse_duration("").is_err());
+}
+
+#[test]
+fn overflowing_durations_error() {
+    assert!(parse_duration("307445734561825861").is_err());
+    assert!(parse_duration("5124095576030432h").is_err());
+    assert!(parse_duration("18446744073709551615s1s").is_err());
+}
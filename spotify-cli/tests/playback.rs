@@ -0,0 +1,2079 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, header, method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+fn player_json() -> serde_json::Value {
+    json!({
+        "device": {"id": "device-1", "name": "Kitchen", "type": "Speaker"},
+        "item": {
+            "album": {"name": "An Album", "release_date": "2020-01-01"},
+            "name": "A Song",
+            "id": "track-1",
+            "uri": "spotify:track:track-1",
+            "artists": [{"name": "An Artist", "id": "artist-1"}],
+            "duration_ms": 185000
+        },
+        "is_playing": true,
+        "progress_ms": 65000,
+        "context": null
+    })
+}
+
+#[tokio::test]
+async fn show_prints_current_song() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .and(header("authorization", "Bearer test-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["show"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Current song: A Song - An Artist [from the album: An Album]"));
+    assert!(out.contains("Running on:   Kitchen (Speaker)"));
+}
+
+#[tokio::test]
+async fn show_cuts_long_fields_to_the_terminal_width() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["item"]["name"] = json!("An Extremely Long Epic Doom Metal Title Part I");
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["show"])
+        .env("COLUMNS", "80")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let line = stdout(&output).lines().next().unwrap().to_string();
+    assert_eq!(
+        line,
+        "Current song: An Extremely Long Epic Doo… - An Artist [from the album: An Album]"
+    );
+    assert_eq!(line.chars().count(), 80);
+
+    let output = env
+        .command(&["show", "--no-truncate"])
+        .env("COLUMNS", "80")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("An Extremely Long Epic Doom Metal Title Part I - An Artist"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn show_format_renders_template() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "show",
+            "--format",
+            "{artist} - {title} [{progress}/{duration}]",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "An Artist - A Song [1:05/3:05]\n");
+}
+
+#[tokio::test]
+async fn show_without_active_device_fails() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["show"]).await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No active devices."));
+}
+
+#[tokio::test]
+async fn pause_calls_pause_endpoint() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn unauthorized_request_is_retried_with_refreshed_token() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(header("authorization", "Bearer test-token"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(
+            json!({"error": {"status": 401, "message": "The access token expired"}}),
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"access_token": "fresh-token", "expires_in": 3600})),
+        )
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(header("authorization", "Bearer fresh-token"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn verbose_logs_requests_without_tokens() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(header("authorization", "Bearer test-token"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(
+            json!({"access_token": "fresh-token", "refresh_token": "new-refresh", "expires_in": 3600}),
+        ))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(header("authorization", "Bearer fresh-token"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause", "-vv"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("/me/player/pause"), "{stderr}");
+    assert!(stderr.contains("Refreshing the access token"), "{stderr}");
+    for secret in ["test-token", "test-refresh", "fresh-token", "new-refresh"] {
+        assert!(!stderr.contains(secret), "{secret} in {stderr}");
+    }
+}
+
+fn devices_json() -> serde_json::Value {
+    json!({"devices": [
+        {"id": "device-1", "name": "Kitchen", "type": "Speaker", "is_active": false},
+        {"id": "device-2", "name": "Laptop", "type": "Computer", "is_active": false},
+    ]})
+}
+
+fn no_active_device_json() -> serde_json::Value {
+    json!({"error": {"status": 404, "message": "Player command failed: No active device found", "reason": "NO_ACTIVE_DEVICE"}})
+}
+
+#[tokio::test]
+async fn play_without_active_device_transfers_to_given_device() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(no_active_device_json()))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(devices_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player"))
+        .and(body_json(json!({"device_ids": ["device-2"]})))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["play", "--device", "laptop"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn play_without_active_device_lists_devices_when_not_interactive() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(no_active_device_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(devices_json()))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["play"]).await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Kitchen, Laptop"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn play_without_active_device_transfers_to_the_chosen_device() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(no_active_device_json()))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(devices_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player"))
+        .and(body_json(json!({"device_ids": ["device-2"]})))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["play", "--choose", "1"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Chose #1: "),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn choose_out_of_range_errors() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(no_active_device_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(devices_json()))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["play", "--choose", "2"]).await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("--choose 2 is out of range"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn device_option_targets_requests_and_caches_the_lookup() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(devices_json()))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(query_param("device_id", "device-1"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    for _ in 0..2 {
+        let output = env.run(&["pause", "--device", "kitchen"]).await;
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+}
+
+#[tokio::test]
+async fn current_lists_album_tracks_when_playing_an_album() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["context"] = json!({
+        "type": "album",
+        "href": format!("{}/albums/album-1", env.server.uri()),
+        "uri": "spotify:album:album-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    let track = |n: u32, name: &str| {
+        json!({"name": name, "id": format!("track-{n}"), "uri": format!("spotify:track:track-{n}"),
+               "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 1000})
+    };
+    Mock::given(method("GET"))
+        .and(path("/albums/album-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "An Album",
+            "artists": [{"name": "An Artist", "id": "artist-1"}],
+            "release_date": "2020-01-01",
+            "tracks": {"items": [track(0, "Intro"), track(1, "A Song")], "next": null}
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["current"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("An Album - An Artist (2020-01-01)"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("#0 Intro"), "{stdout}");
+    assert!(stdout.contains("#1 A Song"), "{stdout}");
+    assert!(!stdout.contains('\x1b'), "no colors when not a terminal");
+}
+
+#[tokio::test]
+async fn color_always_uses_the_configured_theme() {
+    let env = TestEnv::new().await;
+    std::fs::write(env.path("config.toml"), "[theme]\npaused = \"bold red\"\n").unwrap();
+    let mut player = player_json();
+    player["is_playing"] = json!(false);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["show", "--color", "always"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("\x1b[1;31m(paused)\x1b[0m"));
+}
+
+fn track_json(n: usize) -> serde_json::Value {
+    json!({"track": {"name": format!("Song {n}"), "id": format!("track-{n}"),
+        "uri": format!("spotify:track:track-{n}"),
+        "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 1000}})
+}
+
+/// Mocks a player playing "Song 4" from a playlist of 5 tracks served in
+/// pages of 2.
+async fn mount_paged_playlist(env: &TestEnv) {
+    let mut player = player_json();
+    player["item"]["name"] = json!("Song 4");
+    player["context"] = json!({
+        "type": "playlist",
+        "href": format!("{}/playlists/playlist-1", env.server.uri()),
+        "uri": "spotify:playlist:playlist-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Big Playlist",
+            "description": "",
+            "tracks": {
+                "items": [track_json(0), track_json(1)],
+                "next": format!("{}/playlists/playlist-1/tracks?offset=2&limit=2", env.server.uri()),
+                "total": 5
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(query_param("offset", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [track_json(2), track_json(3)],
+            "next": format!("{}/playlists/playlist-1/tracks?offset=4&limit=2", env.server.uri()),
+            "total": 5
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(query_param("offset", "4"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [track_json(4)],
+            "next": null,
+            "total": 5
+        })))
+        .mount(&env.server)
+        .await;
+}
+
+/// Mocks a player playing track 2 of a playlist of 5 tracks.
+async fn mount_playlist_at_track_2(env: &TestEnv) {
+    let mut player = player_json();
+    player["item"] = track_json(2)["track"].clone();
+    player["context"] = json!({
+        "type": "playlist",
+        "href": format!("{}/playlists/playlist-1", env.server.uri()),
+        "uri": "spotify:playlist:playlist-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "A Playlist",
+            "description": "",
+            "tracks": {"items": (0..5).map(track_json).collect::<Vec<_>>(), "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn show_context_progress_fetches_the_playlist_tracks_once() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["item"] = track_json(3)["track"].clone();
+    player["context"] = json!({
+        "type": "playlist",
+        "href": format!("{}/playlists/playlist-1", env.server.uri()),
+        "uri": "spotify:playlist:playlist-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "A Playlist",
+            "description": "",
+            "snapshot_id": "v1",
+            "tracks": {
+                "items": (0..2).map(track_json).collect::<Vec<_>>(),
+                "next": format!("{}/playlists/playlist-1/tracks?offset=2", env.server.uri())
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": (2..5).map(track_json).collect::<Vec<_>>(),
+            "next": null
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    for _ in 0..2 {
+        let output = env.run(&["show", "--context-progress"]).await;
+
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(
+            stdout(&output).contains("Position:     track 4 of 5 in A Playlist"),
+            "{}",
+            stdout(&output)
+        );
+    }
+}
+
+#[tokio::test]
+async fn jump_moves_relative_to_the_current_track() {
+    let env = TestEnv::new().await;
+    mount_playlist_at_track_2(&env).await;
+    for position in [4, 1] {
+        Mock::given(method("PUT"))
+            .and(path("/me/player/play"))
+            .and(body_json(json!({
+                "context_uri": "spotify:playlist:playlist-1",
+                "offset": {"position": position}
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&env.server)
+            .await;
+    }
+
+    for jump in ["+2", "-1"] {
+        let output = env.run(&["jump", jump]).await;
+
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+}
+
+#[tokio::test]
+async fn jump_works_in_albums() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["context"] = json!({
+        "type": "album",
+        "href": format!("{}/albums/album-1", env.server.uri()),
+        "uri": "spotify:album:album-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/albums/album-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "An Album",
+            "artists": [{"name": "An Artist", "id": "artist-1"}],
+            "tracks": {"items": (0..5).map(|n| track_json(n)["track"].clone()).collect::<Vec<_>>(), "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    // track-1 is playing, so +2 goes to position 3.
+    for position in [4, 3] {
+        Mock::given(method("PUT"))
+            .and(path("/me/player/play"))
+            .and(body_json(json!({
+                "context_uri": "spotify:album:album-1",
+                "offset": {"position": position}
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&env.server)
+            .await;
+    }
+
+    for jump in ["4", "+2"] {
+        let output = env.run(&["jump", jump]).await;
+
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+}
+
+#[tokio::test]
+async fn jump_past_the_ends_of_the_playlist_errors() {
+    let env = TestEnv::new().await;
+    mount_playlist_at_track_2(&env).await;
+
+    let output = env.run(&["jump", "-3"]).await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Can't jump -3: the current track is #2 of 5 tracks."),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = env.run(&["jump", "+x"]).await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Bad jump: +x."),
+        "{}",
+        stderr(&output)
+    );
+}
+
+fn assert_all_songs_listed(output: &std::process::Output) {
+    assert!(output.status.success(), "{}", stderr(output));
+    let stdout = stdout(output);
+    for n in 0..5 {
+        assert!(stdout.contains(&format!("#{n} Song {n}")), "{stdout}");
+    }
+}
+
+#[tokio::test]
+async fn current_fetches_every_page_of_the_playlist() {
+    let env = TestEnv::new().await;
+    mount_paged_playlist(&env).await;
+
+    let output = env.run(&["current"]).await;
+
+    assert_all_songs_listed(&output);
+}
+
+#[tokio::test]
+async fn failed_pages_are_fetched_again() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(query_param("offset", "2"))
+        .respond_with(ResponseTemplate::new(502))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    mount_paged_playlist(&env).await;
+
+    let output = env.run(&["current"]).await;
+
+    assert_all_songs_listed(&output);
+}
+
+#[tokio::test]
+async fn show_url_prints_track_and_context_urls() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["context"] = json!({
+        "type": "playlist",
+        "href": format!("{}/playlists/playlist-1", env.server.uri()),
+        "uri": "spotify:playlist:playlist-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["show", "--url"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "https://open.spotify.com/track/track-1\n");
+
+    let output = env.run(&["show", "--url", "--context"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "https://open.spotify.com/playlist/playlist-1\n"
+    );
+}
+
+#[tokio::test]
+async fn queue_skip_to_skips_to_the_given_track() {
+    let env = TestEnv::new().await;
+    let song = |n: usize| {
+        json!({"name": format!("Song {n}"), "id": format!("track-{n}"),
+            "uri": format!("spotify:track:track-{n}"),
+            "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 1000})
+    };
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": song(0),
+            "queue": [song(1), song(2), song(3)]
+        })))
+        .mount(&env.server)
+        .await;
+    // Each skip reads the player before skipping and once after it.
+    for (n, times) in [(0, 1), (1, 2), (2, 10)] {
+        let mut player = player_json();
+        player["item"] = song(n);
+        Mock::given(method("GET"))
+            .and(path("/me/player"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(player))
+            .up_to_n_times(times)
+            .mount(&env.server)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["queue", "skip-to", "2"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Song 2"), "{}", stdout(&output));
+}
+
+#[tokio::test]
+async fn sleep_fades_out_pauses_and_restores_volume() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["device"]["volume_percent"] = json!(60);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/volume"))
+        .and(query_param("volume_percent", "0"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/volume"))
+        .and(query_param("volume_percent", "60"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/volume"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["sleep", "1s", "--fade"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn sleep_rejects_bad_durations() {
+    let env = TestEnv::new().await;
+
+    let output = env.run(&["sleep", "10x"]).await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Bad duration"));
+}
+
+#[tokio::test]
+async fn schedule_now_plays_the_playlist_at_the_given_volume() {
+    let env = TestEnv::new().await;
+    let playlist = "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M";
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({ "context_uri": playlist })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/volume"))
+        .and(query_param("volume_percent", "40"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["schedule", "now", "--playlist", playlist, "--volume", "40"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn schedule_prints_a_crontab_entry() {
+    let env = TestEnv::new().await;
+
+    let output = env
+        .run(&["schedule", "07:30", "--volume", "40", "--print", "cron"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let line = stdout(&output)
+        .lines()
+        .find(|line| !line.starts_with('#'))
+        .unwrap()
+        .to_string();
+    assert!(line.starts_with("30 7 * * * "), "{line}");
+    assert!(line.contains(" schedule now --volume 40"), "{line}");
+    assert!(!line.contains("--print"), "{line}");
+}
+
+#[tokio::test]
+async fn block_current_skips_it_and_lists_it_until_removed() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["block", "add", "current"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Blocked: A Song - An Artist"));
+
+    let output = env.run(&["block", "list"]).await;
+    assert!(
+        stdout(&output).contains("1. A Song - An Artist (spotify:track:track-1)"),
+        "{}",
+        stdout(&output)
+    );
+
+    let output = env.run(&["block", "remove", "1"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = env.run(&["block", "list"]).await;
+    assert!(stdout(&output).contains("The blocklist is empty."));
+}
+
+#[tokio::test]
+async fn block_add_looks_up_the_track_name() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/tracks/track-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&track_json(2)["track"]))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "block",
+            "add",
+            "https://open.spotify.com/track/track-2?si=x",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Blocked: Song 2 - An Artist"));
+}
+
+#[tokio::test]
+async fn block_add_takes_localized_urls_and_spotify_links() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/tracks/track-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&track_json(2)["track"]))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "block",
+            "add",
+            "https://open.spotify.com/intl-fi/track/track-2?si=x",
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Blocked: Song 2 - An Artist"));
+
+    let output = env.run(&["block", "add", "spotify://track/track-2"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Already blocked: Song 2 - An Artist"));
+}
+
+#[tokio::test]
+async fn show_art_draws_the_album_cover_with_half_blocks() {
+    let env = TestEnv::new().await;
+    let mut cover = Vec::new();
+    image::RgbImage::from_pixel(64, 64, image::Rgb([255, 0, 0]))
+        .write_to(
+            &mut std::io::Cursor::new(&mut cover),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    let mut player = player_json();
+    player["item"]["album"]["images"] = json!([
+        {"url": format!("{}/covers/640", env.server.uri()), "width": 640, "height": 640},
+        {"url": format!("{}/covers/64", env.server.uri()), "width": 64, "height": 64}
+    ]);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/covers/64"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(cover))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["show", "--art", "blocks"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(
+        stdout.starts_with("\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m▀"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Current song: A Song"), "{stdout}");
+}
+
+#[tokio::test]
+async fn html_error_bodies_are_reported_instead_of_panicking() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(
+            ResponseTemplate::new(502).set_body_string("<html><body>Bad Gateway</body></html>"),
+        )
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause"]).await;
+
+    assert!(!output.status.success());
+    let stderr = stderr(&output);
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    assert!(stderr.contains("Bad Gateway"), "{stderr}");
+}
+
+#[tokio::test]
+async fn known_error_reasons_get_friendly_messages() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({"error": {
+            "status": 403,
+            "message": "Player command failed: Premium required",
+            "reason": "PREMIUM_REQUIRED"
+        }})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("retry-after", "7")
+                .set_body_json(
+                    json!({"error": {"status": 429, "message": "API rate limit exceeded"}}),
+                ),
+        )
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause"]).await;
+    assert!(
+        stderr(&output).contains("This needs Spotify Premium."),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = env.run(&["next"]).await;
+    assert!(
+        stderr(&output).contains("Rate limited by Spotify. Try again in 7 seconds."),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn json_errors_have_a_kind_and_an_exit_code() {
+    let env = TestEnv::new().await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("retry-after", "7")
+                .set_body_json(
+                    json!({"error": {"status": 429, "message": "API rate limit exceeded"}}),
+                ),
+        )
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["next", "--output", "json"]).await;
+
+    assert_eq!(output.status.code(), Some(4));
+    let error: serde_json::Value = serde_json::from_str(stderr(&output).trim()).unwrap();
+    assert_eq!(
+        error,
+        json!({"error": {
+            "kind": "rate_limited",
+            "message": "Rate limited by Spotify. Try again in 7 seconds.",
+            "status": 429,
+            "retry_after": 7
+        }})
+    );
+
+    let output = env.run(&["block", "add", "not a uri"]).await;
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).starts_with("Error: "),
+        "{}",
+        stderr(&output)
+    );
+}
+
+async fn mount_paused_item(env: &TestEnv, item: serde_json::Value) {
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .and(query_param("additional_types", "track,episode"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device": {"id": "device-1", "name": "Speaker", "type": "Speaker"},
+            "is_playing": false,
+            "progress_ms": 4_321_000,
+            "context": {"type": "show", "href": "", "uri": "spotify:show:show-1"},
+            "item": item
+        })))
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn resume_last_continues_the_paused_episode() {
+    let env = TestEnv::new().await;
+    mount_paused_item(
+        &env,
+        json!({
+            "type": "episode",
+            "uri": "spotify:episode:episode-1",
+            "name": "Episode 12",
+            "duration_ms": 5_400_000
+        }),
+    )
+    .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:show:show-1",
+            "offset": {"uri": "spotify:episode:episode-1"},
+            "position_ms": 4_321_000
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = env.run(&["resume-last"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Resumed Episode 12 at 1:12:01.\n");
+}
+
+#[tokio::test]
+async fn pausing_a_short_track_remembers_nothing() {
+    let env = TestEnv::new().await;
+    mount_paused_item(
+        &env,
+        json!({
+            "type": "track",
+            "uri": "spotify:track:track-1",
+            "name": "A Song",
+            "duration_ms": 185_000
+        }),
+    )
+    .await;
+
+    let output = env.run(&["pause"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = env.run(&["resume-last"]).await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Nothing to resume"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn quota_counts_calls_per_endpoint_and_rate_limits() {
+    let env = TestEnv::new().await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "7"))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "A Playlist",
+            "description": "",
+            "tracks": {"items": [], "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+
+    // Each `next` reads the player first, so they make four calls.
+    for _ in 0..2 {
+        let output = env.run(&["next"]).await;
+        assert_eq!(output.status.code(), Some(4));
+    }
+    let output = env
+        .run(&[
+            "stats",
+            "playlist",
+            "spotify:playlist:playlist-1",
+            "--quota-info",
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("Made 1 API call(s); 5 in the last 30 seconds"),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = env.run(&["quota"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("API calls in the last 30 seconds: 5, hour: 5, day: 5."),
+        "{out}"
+    );
+    assert!(
+        out.contains("Rate limited 2 time(s) in the last day, last "),
+        "{out}"
+    );
+    assert!(out.contains("ago (asked to wait 7 seconds)."), "{out}");
+    assert!(out.contains("POST /me/player/next  2    2     2"), "{out}");
+    assert!(out.contains("GET /playlists/{id}   1    1     1"), "{out}");
+}
+
+#[tokio::test]
+async fn slow_responses_time_out_with_their_own_exit_code() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(204).set_delay(std::time::Duration::from_secs(3)))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["pause", "--timeout", "500ms"]).await;
+
+    assert_eq!(output.status.code(), Some(10));
+    assert!(
+        stderr(&output).contains("No response from") && stderr(&output).contains("--timeout"),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = env
+        .run(&["pause", "--timeout", "0.5", "--output", "json"])
+        .await;
+
+    assert_eq!(output.status.code(), Some(10));
+    let error: serde_json::Value = serde_json::from_str(stderr(&output).trim()).unwrap();
+    assert_eq!(error["error"]["kind"], "timeout");
+
+    let output = env.run(&["pause", "--timeout", "0"]).await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Bad number of seconds"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn track_info_prints_the_track_details() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/tracks/track-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "album": {"name": "An Album", "release_date": "2020-01-01"},
+            "name": "A Song",
+            "id": "track-1",
+            "uri": "spotify:track:track-1",
+            "artists": [{"name": "An Artist", "id": "artist-1"}, {"name": "Guest", "id": "artist-2"}],
+            "duration_ms": 185000,
+            "popularity": 57,
+            "explicit": true,
+            "track_number": 3,
+            "disc_number": 2,
+            "available_markets": ["FI", "SE", "US"],
+            "external_ids": {"isrc": "FIABC2000001"}
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "track",
+            "info",
+            "https://open.spotify.com/track/track-1?si=abc",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Title:      A Song
+Artists:    An Artist, Guest
+Album:      An Album (2020-01-01)
+Track:      3 (disc 2)
+Duration:   3:05
+Popularity: 57/100
+Explicit:   yes
+Markets:    3
+ISRC:       FIABC2000001
+URL:        https://open.spotify.com/track/track-1
+"
+    );
+}
+
+#[tokio::test]
+async fn queue_marks_saved_tracks() {
+    let env = TestEnv::new().await;
+    let song = |n: usize| {
+        json!({"name": format!("Song {n}"), "id": format!("track-{n}"),
+            "uri": format!("spotify:track:track-{n}"),
+            "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 1000})
+    };
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": song(0),
+            "queue": [song(1), song(2), song(3)]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/tracks/contains"))
+        .and(query_param("ids", "track-0,track-1,track-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([true, false, true])))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["queue", "3"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Currently playing: ♥ Song 0 - An Artist [0:01]\nIn queue:\n#1 Song 1 - An Artist [0:01, in 0:01]\n#2 ♥ Song 2 - An Artist [0:01, in 0:02]\n1 more in the queue; see them all with --all.\n"
+    );
+}
+
+#[tokio::test]
+async fn previous_restarts_the_track_when_past_the_start() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/seek"))
+        .and(query_param("position_ms", "0"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/previous"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(0)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["previous"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Restarted: A Song - An Artist"));
+}
+
+#[tokio::test]
+async fn previous_goes_back_when_forced_or_below_the_configured_threshold() {
+    let env = TestEnv::new().await;
+    std::fs::write(
+        env.path("config.toml"),
+        "[playback]\nprevious_restart_secs = 120\n",
+    )
+    .unwrap();
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/previous"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/seek"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(0)
+        .mount(&env.server)
+        .await;
+
+    let below_threshold = env.run(&["previous"]).await;
+    let forced = env.run(&["previous", "--force"]).await;
+
+    assert!(
+        below_threshold.status.success(),
+        "{}",
+        stderr(&below_threshold)
+    );
+    assert!(forced.status.success(), "{}", stderr(&forced));
+}
+
+#[tokio::test]
+async fn mute_saves_the_volume_and_the_next_mute_restores_it() {
+    let env = TestEnv::new().await;
+    for volume in [40, 0] {
+        let mut player = player_json();
+        player["device"]["volume_percent"] = json!(volume);
+        Mock::given(method("GET"))
+            .and(path("/me/player"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(player))
+            .up_to_n_times(1)
+            .mount(&env.server)
+            .await;
+    }
+    for volume in ["0", "40"] {
+        Mock::given(method("PUT"))
+            .and(path("/me/player/volume"))
+            .and(query_param("volume_percent", volume))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&env.server)
+            .await;
+    }
+
+    let muted = env.run(&["mute"]).await;
+    let state = std::fs::read_to_string(env.path("state")).unwrap();
+    let unmuted = env.run(&["mute"]).await;
+
+    assert!(muted.status.success(), "{}", stderr(&muted));
+    assert!(stdout(&muted).contains("Muted. Run mute again to go back to volume 40%."));
+    assert!(state.contains(r#""muted_volume": 40"#), "{state}");
+    assert!(unmuted.status.success(), "{}", stderr(&unmuted));
+    assert!(stdout(&unmuted).contains("Unmuted, volume 40%."));
+    let state = std::fs::read_to_string(env.path("state")).unwrap();
+    assert!(!state.contains("muted_volume"), "{state}");
+}
+
+#[tokio::test]
+async fn requests_go_through_the_configured_proxy() {
+    let env = TestEnv::new().await;
+    std::fs::write(
+        env.path("config.toml"),
+        format!("[network]\nproxy = \"{}\"\n", env.server.uri()),
+    )
+    .unwrap();
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"access_token": "fresh-token", "expires_in": 3600})),
+        )
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    // The host doesn't resolve, so this only works through the proxy.
+    let output = env
+        .command(&["auth", "refresh"])
+        .env(
+            "SPOTIFY_CLI_ACCOUNTS_URL",
+            "http://accounts.spotify.invalid",
+        )
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn an_invalid_ca_file_is_an_error() {
+    let env = TestEnv::new().await;
+    std::fs::write(env.path("ca.pem"), "not a certificate").unwrap();
+
+    let output = env
+        .command(&["pause"])
+        .env("SPOTIFY_CLI_CA_FILE", env.path("ca.pem"))
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Invalid CA file"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn handle_uri_plays_links_in_any_form() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({ "uris": ["spotify:track:track-1"] })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(3)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(
+            json!({ "context_uri": "spotify:playlist:list-1" }),
+        ))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    for link in [
+        "spotify:track:track-1",
+        "spotify://track/track-1",
+        "https://open.spotify.com/intl-fi/track/track-1?si=abc",
+        "spotify:user:someone:playlist:list-1",
+        "https://open.spotify.com/playlist/list-1",
+    ] {
+        let output = env.run(&["handle-uri", link]).await;
+        assert!(output.status.success(), "{link}: {}", stderr(&output));
+    }
+
+    let output = env.run(&["handle-uri", "https://example.com"]).await;
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Not a Spotify link"));
+}
+
+#[tokio::test]
+async fn queue_add_next_requeues_earlier_tracks_after_it() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": player_json()["item"],
+            "queue": [{"name": "Queued", "id": "track-2", "uri": "spotify:track:track-2",
+                "artists": [{"name": "An Artist", "id": "artist-1"}]}]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "uris": ["spotify:track:track-1"], "position_ms": 65000
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "queue",
+            "add",
+            "https://open.spotify.com/track/track-9",
+            "--next",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Queued spotify:track:track-9 to play next."));
+    let queued: Vec<String> = env
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|request| {
+            request.url.path() == "/me/player/queue" && request.method.as_str() == "POST"
+        })
+        .map(|request| request.url.query().unwrap_or_default().to_string())
+        .collect();
+    assert_eq!(
+        queued,
+        [
+            "uri=spotify%3Atrack%3Atrack-9",
+            "uri=spotify%3Atrack%3Atrack-2"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn aliases_stand_in_for_uris_and_devices() {
+    let env = TestEnv::new().await;
+    std::fs::write(env.path("config.toml"), "# My config\n[alias]\n").unwrap();
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(
+            json!({ "context_uri": "spotify:playlist:list-1" }),
+        ))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(devices_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .and(query_param("device_id", "device-1"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    for args in [
+        &["alias", "add", "chill", "spotify:playlist:list-1"][..],
+        &["alias", "add", "@kitchen", "Kitchen", "--kind", "device"],
+    ] {
+        let output = env.run(args).await;
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+    let config = std::fs::read_to_string(env.path("config.toml")).unwrap();
+    assert!(config.starts_with("# My config\n"), "{config}");
+
+    let output = env.run(&["alias", "list"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("@chill = spotify:playlist:list-1"));
+    assert!(stdout(&output).contains("@kitchen = Kitchen"));
+
+    let output = env.run(&["playlist", "play", "@chill"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = env.run(&["pause", "--device", "@kitchen"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = env.run(&["alias", "remove", "@chill"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = env.run(&["alias", "list"]).await;
+    assert!(!stdout(&output).contains("@chill"), "{}", stdout(&output));
+}
+
+#[tokio::test]
+async fn watchdog_pauses_when_a_watched_device_disappears() {
+    let env = TestEnv::new().await;
+    std::fs::write(
+        env.path("config.toml"),
+        format!(
+            "[device]\nphone = \"Phone\"\n\n[hooks]\ndevice_lost = \"echo \\\"$SPOTIFY_LOST_DEVICE $SPOTIFY_WATCHDOG_ACTION\\\" > {}\"\n\n[watchdog]\nenabled = true\ndevices = [\"@phone\"]\n",
+            env.path("lost.txt")
+        ),
+    )
+    .unwrap();
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"devices": [
+            {"id": "device-1", "name": "Kitchen", "type": "Speaker"},
+            {"id": "device-2", "name": "Phone", "type": "Smartphone"}
+        ]})))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"devices": [
+            {"id": "device-1", "name": "Kitchen", "type": "Speaker"}
+        ]})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let mut watch = env
+        .command(&["watch", "--interval", "1"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    watch.kill().await.unwrap();
+    let output = watch.wait_with_output().await.unwrap();
+
+    assert!(
+        stdout(&output).contains("Phone disappeared; paused playback."),
+        "{}",
+        stdout(&output)
+    );
+    let lost = std::fs::read_to_string(env.path("lost.txt")).unwrap();
+    assert_eq!(lost.trim(), "Phone paused");
+}
+
+#[tokio::test]
+async fn queue_all_prints_the_whole_queue_as_json_with_start_times() {
+    let env = TestEnv::new().await;
+    let song = |n: usize| {
+        json!({"name": format!("Song {n}"), "id": format!("track-{n}"),
+            "uri": format!("spotify:track:track-{n}"),
+            "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 60000})
+    };
+    let queue: Vec<serde_json::Value> = (1..=12).map(song).collect();
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": song(0),
+            "queue": queue
+        })))
+        .mount(&env.server)
+        .await;
+    let mut player = player_json();
+    player["item"] = song(0);
+    player["progress_ms"] = json!(20000);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["queue", "--all", "--output", "json"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let queue: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(queue["current"]["progress_ms"], 20000);
+    assert_eq!(queue["queue"].as_array().unwrap().len(), 12);
+    assert_eq!(queue["queue"][0]["starts_in_ms"], 40000);
+    assert_eq!(queue["queue"][11]["position"], 12);
+    assert_eq!(queue["queue"][11]["starts_in_ms"], 40000 + 11 * 60000);
+
+    // Two digit positions keep the names lined up.
+    let output = env.run(&["queue", "--all"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("Currently playing: Song 0 - An Artist [0:20/1:00]\n"),
+        "{out}"
+    );
+    assert!(
+        out.contains("#1  Song 1 - An Artist [1:00, in 0:40]\n"),
+        "{out}"
+    );
+    assert!(
+        out.contains("#12 Song 12 - An Artist [1:00, in 11:40]\n"),
+        "{out}"
+    );
+    assert!(!out.contains("--all"), "{out}");
+}
+
+#[tokio::test]
+async fn queue_table_lines_up_double_width_names() {
+    let env = TestEnv::new().await;
+    let song = |n: usize, name: &str| {
+        json!({"name": name, "id": format!("track-{n}"),
+            "uri": format!("spotify:track:track-{n}"),
+            "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 60000})
+    };
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": song(0, "Song 0"),
+            "queue": [song(1, "夜に駆ける夜に駆ける"), song(2, "Party 🎉🎉"), song(3, "Plain")]
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["queue", "--output", "table"])
+        .env("COLUMNS", "200")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    // The artist column starts at the same terminal column on every line.
+    let columns: Vec<usize> = out
+        .lines()
+        .skip(1)
+        .map(|line| unicode_width::UnicodeWidthStr::width(&line[..line.find("An Artist").unwrap()]))
+        .collect();
+    assert_eq!(columns.len(), 4, "{out}");
+    assert!(columns.iter().all(|&column| column == columns[0]), "{out}");
+
+    // Cutting never splits a double-width character.
+    let output = env
+        .command(&["queue"])
+        .env("COLUMNS", "40")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("#1 夜に駆け… - An Artist [1:00, in 1:00]"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn queue_labels_queued_tracks_and_the_ones_from_the_playlist() {
+    let env = TestEnv::new().await;
+    mount_playlist_at_track_2(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": track_json(2)["track"],
+            "queue": [track_json(9)["track"], track_json(3)["track"], track_json(4)["track"]]
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["queue"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Currently playing: Song 2 - An Artist [1:05/0:01]\nIn queue:\n\
+         #1 Song 9 - An Artist [0:01, in 0:00, queued]\n\
+         #2 Song 3 - An Artist [0:01, in 0:01, from playlist]\n\
+         #3 Song 4 - An Artist [0:01, in 0:02, from playlist]\n"
+    );
+
+    let output = env.run(&["queue", "--output", "json"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let queue: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(queue["queue"][0]["source"], "queued");
+    assert_eq!(queue["queue"][1]["source"], "playlist");
+}
+
+#[tokio::test]
+async fn toggle_pauses_when_playing_and_plays_when_paused() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .up_to_n_times(2)
+        .mount(&env.server)
+        .await;
+    let mut paused = player_json();
+    paused["is_playing"] = json!(false);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(paused))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/pause"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    // Pausing also reads the player to remember the position.
+    let output = env.run(&["toggle"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // With no command given, the default command from the config runs, also
+    // when global options are given.
+    std::fs::write(env.path("config.toml"), "default_command = \"toggle\"\n").unwrap();
+    let output = env
+        .run(&["--timeout", "5", "--color=never", "-vv", "--no-truncate"])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn toggle_with_last_device_wakes_up_the_device_it_last_saw() {
+    let env = TestEnv::new().await;
+    let mut paused = player_json();
+    paused["is_playing"] = json!(false);
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(paused))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"devices": [
+            {"id": "device-2", "name": "Phone", "type": "Smartphone"},
+            {"id": "device-1", "name": "Kitchen", "type": "Speaker"}
+        ]})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player"))
+        .and(body_json(json!({"device_ids": ["device-1"]})))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    // Resuming on Kitchen remembers it.
+    let output = env.run(&["toggle"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // Nothing is active anymore, so Kitchen is woken up.
+    let output = env.run(&["toggle", "--last-device"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[tokio::test]
+async fn details_marks_explicit_tracks_and_shows_popularity() {
+    let env = TestEnv::new().await;
+    let song = |n: usize, explicit: bool| {
+        json!({"name": format!("Song {n}"), "id": format!("track-{n}"),
+            "uri": format!("spotify:track:track-{n}"),
+            "artists": [{"name": "An Artist", "id": "artist-1"}], "duration_ms": 60000,
+            "explicit": explicit, "popularity": 40 + n})
+    };
+    Mock::given(method("GET"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "currently_playing": song(0, false),
+            "queue": [song(1, true)]
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["queue", "--details"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("Currently playing: Song 0 - An Artist (popularity 40) [1:00]"),
+        "{out}"
+    );
+    assert!(
+        out.contains("#1 Song 1 [E] - An Artist (popularity 41) [1:00, in 1:00]"),
+        "{out}"
+    );
+
+    let output = env
+        .command(&["queue", "--details", "--output", "table"])
+        .env("COLUMNS", "200")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(
+        lines[0].contains("Duration  Starts in  Popularity  URI"),
+        "{out}"
+    );
+    assert!(lines[2].starts_with("1  Song 1 [E]"), "{out}");
+    assert!(lines[2].contains("1:00      1:00       41"), "{out}");
+
+    // Without the flag neither is shown.
+    let output = env.run(&["queue"]).await;
+    assert!(!stdout(&output).contains("[E]"), "{}", stdout(&output));
+    assert!(
+        !stdout(&output).contains("popularity"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn quiet_leaves_out_confirmations_and_the_playback_after_commands() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/next"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["next", "--quiet"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+
+    let output = env
+        .run(&["-q", "queue", "add", "spotify:track:track-2"])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+
+    // Without it, the new playback is shown.
+    let output = env.run(&["next"]).await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("A Song"), "{}", stdout(&output));
+
+    let output = env.run(&["next", "--quiet", "--verbose"]).await;
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn album_play_starts_from_a_track_by_its_disc_and_number() {
+    let env = TestEnv::new().await;
+    let track = |disc: u32, number: u32| {
+        json!({"name": format!("Song {disc}-{number}"), "id": format!("track-{disc}-{number}"),
+            "uri": format!("spotify:track:track-{disc}-{number}"),
+            "artists": [{"name": "An Artist", "id": "artist-1"}],
+            "disc_number": disc, "track_number": number})
+    };
+    Mock::given(method("GET"))
+        .and(path("/albums/album-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "An Album",
+            "artists": [{"name": "An Artist", "id": "artist-1"}],
+            "tracks": {"items": [track(1, 1), track(1, 2), track(2, 1), track(2, 2)], "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:album:album-1",
+            "offset": {"position": 2}
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:album:album-1",
+            "offset": {"position": 1}
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "album",
+            "play",
+            "https://open.spotify.com/album/album-1",
+            "--disc",
+            "2",
+            "--track",
+            "1",
+            "--quiet",
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = env
+        .run(&["album", "play", "spotify:album:album-1", "--track", "2", "-q"])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = env
+        .run(&["album", "play", "spotify:album:album-1", "--disc", "3"])
+        .await;
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("The album has no disc 3."),
+        "{}",
+        stderr(&output)
+    );
+}
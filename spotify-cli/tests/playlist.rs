@@ -0,0 +1,1217 @@
+mod common;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use std::io::Cursor;
+use wiremock::{
+    matchers::{body_json, method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+fn track_json(n: usize, artist: &str) -> serde_json::Value {
+    json!({"track": {"name": format!("Song {n}"), "id": format!("track-{n}"),
+        "uri": format!("spotify:track:track-{n}"),
+        "artists": [{"name": artist, "id": artist}], "duration_ms": 1000}})
+}
+
+#[tokio::test]
+async fn filter_keeps_tracks_by_artists_in_the_genre_and_caches_genres() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mixed",
+            "description": "",
+            "tracks": {
+                "items": [track_json(1, "rocker"), track_json(2, "popper"), track_json(3, "rocker")],
+                "next": null,
+                "total": 3
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/artists"))
+        .and(query_param("ids", "rocker,popper"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"artists": [
+            {"id": "rocker", "name": "Rocker", "genres": ["indie rock"]},
+            {"id": "popper", "name": "Popper", "genres": ["pop"]}
+        ]})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "user-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/user-1/playlists"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "new-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/new-1/tracks"))
+        .and(body_json(
+            json!({"uris": ["spotify:track:track-1", "spotify:track:track-3"]}),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    for _ in 0..2 {
+        let output = env
+            .run(&[
+                "playlist",
+                "filter",
+                "spotify:playlist:playlist-1",
+                "--genre",
+                "Rock",
+                "--into",
+                "Rock only",
+            ])
+            .await;
+
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(
+            stdout(&output).contains("Created playlist Rock only with 2 of the 3 tracks."),
+            "{}",
+            stdout(&output)
+        );
+    }
+}
+
+#[tokio::test]
+async fn create_collaborative_playlist_is_private() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "user-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/user-1/playlists"))
+        .and(body_json(json!({
+            "name": "Party",
+            "public": false,
+            "collaborative": true,
+            "description": "Playlist created by a CLI tool."
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "new-1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["playlist", "create", "Party", "--collaborative"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("uri: spotify:playlist:new-1"));
+}
+
+#[tokio::test]
+async fn set_collaborative_updates_the_playlist_details() {
+    let env = TestEnv::new().await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1"))
+        .and(body_json(json!({"collaborative": true, "public": false})))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1"))
+        .and(body_json(json!({"collaborative": false})))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    for state in ["on", "off"] {
+        let output = env
+            .run(&[
+                "playlist",
+                "set-collaborative",
+                "spotify:playlist:playlist-1",
+                state,
+            ])
+            .await;
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+}
+
+#[tokio::test]
+async fn list_filters_and_sorts_after_fetching_every_page() {
+    let env = TestEnv::new().await;
+    let playlist = |name: &str, total: u16, owner: &str| {
+        json!({"name": name, "uri": format!("spotify:playlist:{name}"), "description": null,
+            "tracks": {"total": total}, "public": true, "collaborative": false,
+            "owner": {"id": owner}})
+    };
+    Mock::given(method("GET"))
+        .and(path("/me/playlists"))
+        .and(query_param("offset", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "next": null,
+            "items": [playlist("Road Trip", 40, "me")]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/playlists"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "next": format!("{}/me/playlists?offset=2&limit=50", env.server.uri()),
+            "items": [playlist("Morning Run", 10, "me"), playlist("Top Run Hits", 99, "spotify")]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "me"})))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist", "list", "--owned", "--sort", "tracks", "--output", "table",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "\
+Name         Tracks  Access  URI
+Road Trip    40      public  spotify:playlist:Road Trip
+Morning Run  10      public  spotify:playlist:Morning Run
+"
+    );
+
+    let output = env
+        .run(&[
+            "playlist", "list", "--filter", "RUN", "--sort", "name", "--output", "table",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "\
+Name          Tracks  Access  URI
+Morning Run   10      public  spotify:playlist:Morning Run
+Top Run Hits  99      public  spotify:playlist:Top Run Hits
+"
+    );
+
+    let output = env.run(&["playlist", "list", "--followed"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("Top Run Hits (public)"));
+    assert!(!stdout(&output).contains("Road Trip"));
+}
+
+#[tokio::test]
+async fn list_shows_collaborative_playlists() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/playlists"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "next": null,
+            "items": [
+                {"name": "Party", "uri": "spotify:playlist:p1", "description": null,
+                    "tracks": {"total": 3}, "public": false, "collaborative": true},
+                {"name": "Mine", "uri": "spotify:playlist:p2", "description": null,
+                    "tracks": {"total": 1}, "public": false, "collaborative": false}
+            ]
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["playlist", "list"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Party (collaborative)"), "{stdout}");
+    assert!(stdout.contains("Mine (private)"), "{stdout}");
+
+    let output = env.run(&["playlist", "list", "--output", "table"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        common::stdout(&output),
+        "\
+Name   Tracks  Access         URI
+Party  3       collaborative  spotify:playlist:p1
+Mine   1       private        spotify:playlist:p2
+"
+    );
+}
+
+async fn upload_cover(env: &TestEnv, image: &[u8]) -> Vec<u8> {
+    std::fs::write(env.path("cover"), image).unwrap();
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1/images"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist",
+            "set-cover",
+            "spotify:playlist:playlist-1",
+            &env.path("cover"),
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let requests = env.server.received_requests().await.unwrap();
+    let request = requests.last().unwrap();
+    assert_eq!(request.headers["content-type"], "image/jpeg");
+    assert!(request.body.len() <= 256 * 1024);
+    BASE64_STANDARD.decode(&request.body).unwrap()
+}
+
+#[tokio::test]
+async fn set_cover_uploads_small_jpegs_as_is() {
+    let env = TestEnv::new().await;
+    let mut jpeg = Vec::new();
+    image::RgbImage::from_pixel(300, 300, image::Rgb([0, 128, 255]))
+        .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+        .unwrap();
+
+    assert_eq!(upload_cover(&env, &jpeg).await, jpeg);
+}
+
+#[tokio::test]
+async fn set_cover_shrinks_big_images_into_jpegs() {
+    let env = TestEnv::new().await;
+    // Noise compresses badly, so this is way over the limit as a PNG.
+    let noise = image::RgbImage::from_fn(800, 800, |x, y| {
+        let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761);
+        image::Rgb([v as u8, (v >> 8) as u8, (v >> 16) as u8])
+    });
+    let mut png = Vec::new();
+    noise
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    assert!(png.len() > 256 * 1024);
+
+    let uploaded = upload_cover(&env, &png).await;
+
+    assert_eq!(
+        image::guess_format(&uploaded).unwrap(),
+        image::ImageFormat::Jpeg
+    );
+}
+
+#[tokio::test]
+async fn play_shuffle_turns_shuffle_on_and_starts_from_a_random_track() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/big"))
+        .and(query_param("fields", "tracks.total"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"tracks": {"total": 1}})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/shuffle"))
+        .and(query_param("state", "true"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:playlist:big",
+            "offset": {"position": 0}
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device": {"id": "device-1", "name": "Kitchen", "type": "Speaker"},
+            "item": {
+                "name": "Song 0",
+                "id": "track-0",
+                "uri": "spotify:track:track-0",
+                "artists": []
+            },
+            "is_playing": true,
+            "context": {
+                "type": "playlist",
+                "href": format!("{}/playlists/big", env.server.uri()),
+                "uri": "spotify:playlist:big"
+            }
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["playlist", "play", "spotify:playlist:big", "--shuffle"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Current song: Song 0"));
+}
+
+#[tokio::test]
+async fn progress_json_reports_pages_retries_and_added_tracks_on_stderr() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Shared",
+            "description": "",
+            "tracks": {
+                "items": [track_json(1, "a")],
+                "next": format!("{}/playlists/playlist-1/tracks?offset=1&limit=1", env.server.uri()),
+                "total": 2
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [track_json(2, "b")],
+            "next": null,
+            "total": 2
+        })))
+        .mount(&env.server)
+        .await;
+    // Rate limited once, then added.
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist",
+            "add",
+            "spotify:playlist:playlist-1",
+            "spotify:track:track-3",
+            "--progress",
+            "json",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let events: Vec<serde_json::Value> = stderr(&output)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        [
+            json!({"event": "page_fetched", "label": "Fetching tracks", "done": 2, "total": 2}),
+            json!({"event": "retrying", "after_secs": 1, "attempt": 1}),
+            json!({"event": "tracks_added", "playlist": "playlist-1", "done": 1, "total": 1}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn pages_fetched_at_the_same_time_wait_out_rate_limits() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Shared",
+            "description": "",
+            "tracks": {
+                "items": [track_json(1, "a")],
+                "next": format!("{}/playlists/playlist-1/tracks?offset=1&limit=1", env.server.uri()),
+                "total": 2
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    // Rate limited once, then fetched.
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [track_json(2, "b")],
+            "next": null,
+            "total": 2
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist",
+            "add",
+            "spotify:playlist:playlist-1",
+            "spotify:track:track-3",
+            "--progress",
+            "json",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let events: Vec<serde_json::Value> = stderr(&output)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        [
+            json!({"event": "retrying", "after_secs": 1, "attempt": 1}),
+            json!({"event": "page_fetched", "label": "Fetching tracks", "done": 2, "total": 2}),
+            json!({"event": "tracks_added", "playlist": "playlist-1", "done": 1, "total": 1}),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn add_skips_tracks_already_in_the_playlist() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Shared",
+            "description": "",
+            "tracks": {
+                "items": [track_json(1, "a"), track_json(2, "b")],
+                "next": null,
+                "total": 2
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(json!({"uris": ["spotify:track:track-3"]})))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(
+            json!({"uris": ["spotify:track:track-2", "spotify:track:track-2"]}),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist",
+            "add",
+            "spotify:playlist:playlist-1",
+            "spotify:track:track-2",
+            "https://open.spotify.com/track/track-3",
+            "spotify:track:track-3",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("Skipped Song 2 - b: already in the playlist."),
+        "{out}"
+    );
+    assert!(out.contains("Added 1 track(s) to the playlist."), "{out}");
+
+    let output = env
+        .run(&[
+            "playlist",
+            "add",
+            "spotify:playlist:playlist-1",
+            "spotify:track:track-2",
+            "spotify:track:track-2",
+            "--allow-dupes",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Added 2 track(s) to the playlist."),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn sync_only_applies_the_differences() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Shared",
+            "description": "",
+            "snapshot_id": "v1",
+            "tracks": {
+                "items": (1..=4).map(|n| track_json(n, "a")).collect::<Vec<_>>(),
+                "next": null
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(json!({
+            "tracks": [{"uri": "spotify:track:track-4"}],
+            "snapshot_id": "v1"
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "v2"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(json!({"uris": ["spotify:track:track-5"]})))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "v3"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    // Track 1 moves from the start to the end; the others stay put.
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(json!({
+            "range_start": 0,
+            "insert_before": 4,
+            "range_length": 1,
+            "snapshot_id": "v3"
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "v4"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    let local: Vec<serde_json::Value> = [2, 3, 5, 1]
+        .iter()
+        .map(|n| {
+            json!({"name": format!("Song {n}"), "artists": ["a"],
+                "uri": format!("spotify:track:track-{n}")})
+        })
+        .collect();
+    std::fs::write(env.path("local.json"), json!(local).to_string()).unwrap();
+
+    let output = env
+        .run(&[
+            "playlist",
+            "sync",
+            &env.path("local.json"),
+            "spotify:playlist:playlist-1",
+            "--dry-run",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output)
+            .contains("Would remove 1 and add 1 track(s), and make 1 move(s) in Shared."),
+        "{}",
+        stdout(&output)
+    );
+
+    let output = env
+        .run(&[
+            "playlist",
+            "sync",
+            &env.path("local.json"),
+            "spotify:playlist:playlist-1",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output)
+            .contains("Synced Shared: removed 1 and added 1 track(s), and made 1 move(s)."),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn sync_reports_the_json_error_of_a_broken_export() {
+    let env = TestEnv::new().await;
+    std::fs::write(
+        env.path("broken.json"),
+        r#"[{"name": "Song", "artists": ["a"],}]"#,
+    )
+    .unwrap();
+
+    let output = env
+        .run(&[
+            "playlist",
+            "sync",
+            &env.path("broken.json"),
+            "spotify:playlist:playlist-1",
+        ])
+        .await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("as JSON: trailing comma"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn sync_skips_local_files_it_cant_add() {
+    let env = TestEnv::new().await;
+    let local_file = |name: &str| {
+        json!({"track": {"name": name, "id": null, "uri": format!("spotify:local:{name}"),
+            "artists": [{"name": "a", "id": null}], "duration_ms": 1000}})
+    };
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Shared",
+            "description": "",
+            "snapshot_id": "v1",
+            "tracks": {"items": [track_json(1, "a"), local_file("kept")], "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(json!({"uris": ["spotify:track:track-2"]})))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "v2"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    // The added track moves first; the skipped local file isn't counted.
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(json!({
+            "range_start": 2,
+            "insert_before": 0,
+            "range_length": 1,
+            "snapshot_id": "v2"
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "v3"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    let local: Vec<serde_json::Value> = [
+        "spotify:local:new",
+        "spotify:track:track-2",
+        "spotify:track:track-1",
+        "spotify:local:kept",
+    ]
+    .iter()
+    .map(|uri| json!({"name": "Song", "artists": ["a"], "uri": uri}))
+    .collect();
+    std::fs::write(env.path("local.json"), json!(local).to_string()).unwrap();
+
+    let output = env
+        .run(&[
+            "playlist",
+            "sync",
+            &env.path("local.json"),
+            "spotify:playlist:playlist-1",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("Skipped 1 local file(s) or unavailable track(s)"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Synced Shared: removed 0 and added 1 track(s), and made 1 move(s)."),
+        "{stdout}"
+    );
+}
+
+#[tokio::test]
+async fn sort_takes_a_snapshot_that_undo_restores() {
+    let env = TestEnv::new().await;
+    let playlist = |items: Vec<serde_json::Value>| {
+        json!({
+            "name": "Mixed",
+            "description": "",
+            "snapshot_id": "v1",
+            "tracks": {"items": items, "next": null, "total": 3}
+        })
+    };
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(playlist(vec![
+            track_json(2, "b"),
+            track_json(1, "a"),
+            track_json(3, "c"),
+        ])))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(playlist(vec![
+            track_json(1, "a"),
+            track_json(2, "b"),
+            track_json(3, "c"),
+        ])))
+        .mount(&env.server)
+        .await;
+    // Both sorting and undoing move the second track first, keeping the
+    // added-at dates instead of replacing the tracks.
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1/tracks"))
+        .and(body_json(
+            json!({"range_start": 1, "insert_before": 0, "range_length": 1}),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "v2"})))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist",
+            "sort",
+            "spotify:playlist:playlist-1",
+            "--by",
+            "name",
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = env
+        .run(&["playlist", "snapshots", "spotify:playlist:playlist-1"])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("before sort: 3 tracks"),
+        "{}",
+        stdout(&output)
+    );
+
+    let output = env
+        .run(&["playlist", "undo", "spotify:playlist:playlist-1"])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Restored the playlist to before sort"));
+
+    // The snapshot is used up.
+    let output = env
+        .run(&["playlist", "undo", "spotify:playlist:playlist-1"])
+        .await;
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("No snapshots of the playlist to undo."));
+}
+
+#[tokio::test]
+async fn queue_offline_saves_the_command_for_sync() {
+    let env = TestEnv::new().await;
+    let args = [
+        "playlist",
+        "set-collaborative",
+        "spotify:playlist:playlist-1",
+        "on",
+        "--queue-offline",
+    ];
+
+    // Nothing listens on the port, so this is as good as being offline.
+    let output = env
+        .command(&args)
+        .env("SPOTIFY_CLI_PROXY", "http://127.0.0.1:1")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains(
+            "saved 'playlist set-collaborative spotify:playlist:playlist-1 on' to run later"
+        ),
+        "{}",
+        stdout(&output)
+    );
+
+    Mock::given(method("PUT"))
+        .and(path("/playlists/playlist-1"))
+        .and(body_json(json!({"collaborative": true, "public": false})))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["sync"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Ran 1 queued command(s)."));
+    let output = env.run(&["sync"]).await;
+    assert!(stdout(&output).contains("No queued commands."));
+}
+
+#[tokio::test]
+async fn export_with_audio_features_fetches_them_in_batches() {
+    let env = TestEnv::new().await;
+    let items: Vec<_> = (1..=120).map(|n| track_json(n, "a")).collect();
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Big",
+            "description": "",
+            "tracks": {"items": items, "next": null, "total": 120}
+        })))
+        .mount(&env.server)
+        .await;
+    for batch in [1..=100, 101..=120] {
+        let ids: Vec<String> = batch.clone().map(|n| format!("track-{n}")).collect();
+        let features: Vec<_> = batch
+            .map(|n| {
+                json!({"id": format!("track-{n}"), "tempo": 120.5, "energy": 0.5,
+                "danceability": 0.25, "valence": 0.75})
+            })
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/audio-features"))
+            .and(query_param("ids", ids.join(",")))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"audio_features": features})),
+            )
+            .expect(1)
+            .mount(&env.server)
+            .await;
+    }
+
+    let output = env
+        .run(&[
+            "playlist",
+            "export",
+            "spotify:playlist:playlist-1",
+            "--format",
+            "csv",
+            "--audio-features",
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines.next(),
+        Some("name,artists,album,uri,duration_ms,added_at,tempo,energy,danceability,valence")
+    );
+    assert_eq!(lines.clone().count(), 120);
+    assert!(lines.all(|line| line.ends_with(",120.5,0.5,0.25,0.75")));
+}
+
+#[tokio::test]
+async fn export_without_audio_features_access_leaves_them_out() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mixed",
+            "description": "",
+            "tracks": {"items": [track_json(1, "a")], "next": null, "total": 1}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/audio-features"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "error": {"status": 404, "message": "Not Found"}
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "playlist",
+            "export",
+            "spotify:playlist:playlist-1",
+            "--format",
+            "csv",
+            "--audio-features",
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Audio features aren't available to this app"));
+    assert_eq!(
+        stdout(&output).lines().next(),
+        Some("name,artists,album,uri,duration_ms,added_at")
+    );
+}
+
+#[tokio::test]
+async fn export_still_takes_the_file_with_output() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mixed",
+            "description": "",
+            "tracks": {"items": [track_json(1, "a")], "next": null, "total": 1}
+        })))
+        .mount(&env.server)
+        .await;
+
+    for flag in ["--output", "--file", "-o"] {
+        let file = env.path(&format!("export{flag}.json"));
+        let output = env
+            .run(&[
+                "playlist",
+                "export",
+                "spotify:playlist:playlist-1",
+                flag,
+                &file,
+            ])
+            .await;
+        assert!(output.status.success(), "{}", stderr(&output));
+
+        let exported: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(exported[0]["uri"], "spotify:track:track-1");
+    }
+}
+
+#[tokio::test]
+async fn export_keeps_unplayable_tracks() {
+    let env = TestEnv::new().await;
+    let mut unplayable = track_json(2, "a");
+    unplayable["track"]["is_playable"] = json!(false);
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mixed",
+            "description": "",
+            "tracks": {"items": [track_json(1, "a"), unplayable], "next": null, "total": 2}
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["playlist", "export", "spotify:playlist:playlist-1"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let exported: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(exported[1]["uri"], "spotify:track:track-2");
+}
+
+#[tokio::test]
+async fn stats_playlist_prints_totals_artists_and_decades() {
+    let env = TestEnv::new().await;
+    let track = |n: usize, artist: &str, release_date: &str, explicit: bool| {
+        json!({"track": {"name": format!("Song {n}"), "id": format!("track-{n}"),
+            "uri": format!("spotify:track:track-{n}"), "explicit": explicit,
+            "album": {"name": "Album", "release_date": release_date},
+            "artists": [{"name": artist, "id": artist}], "duration_ms": 1_800_000}})
+    };
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mix",
+            "description": "",
+            "tracks": {
+                "items": [
+                    track(1, "a", "1994-05-01", true),
+                    track(2, "b", "1999", false),
+                    track(3, "a", "2003-01-01", false)
+                ],
+                "next": null,
+                "total": 3
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/audio-features"))
+        .and(query_param("ids", "track-1,track-2,track-3"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"audio_features": [
+            {"id": "track-1", "tempo": 100.0, "energy": 0.2, "danceability": 0.5, "valence": 0.1},
+            {"id": "track-2", "tempo": 120.0, "energy": 0.4, "danceability": 0.5, "valence": 0.2},
+            null
+        ]})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["stats", "playlist", "spotify:playlist:playlist-1"])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    for line in [
+        "Mix: 3 tracks, 1h 30m.",
+        "1 explicit tracks.",
+        "Average tempo 110 BPM, energy 0.30, danceability 0.50, valence 0.15.",
+        "#1 a (2 tracks)",
+        "#2 b (1 tracks)",
+        "1990s: 2 tracks",
+        "2000s: 1 tracks",
+    ] {
+        assert!(stdout.contains(line), "{line} not in {stdout}");
+    }
+}
+
+#[tokio::test]
+async fn stats_playlist_leaves_local_files_out_of_the_audio_features() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mix",
+            "description": "",
+            "tracks": {
+                "items": [
+                    track_json(1, "a"),
+                    {"track": {"name": "Demo", "id": null, "uri": "spotify:local:demo",
+                        "artists": [{"name": "Me", "id": null}], "duration_ms": 1000}}
+                ],
+                "next": null,
+                "total": 2
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/audio-features"))
+        .and(query_param("ids", "track-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"audio_features": [
+            {"id": "track-1", "tempo": 100.0, "energy": 0.2, "danceability": 0.5, "valence": 0.1}
+        ]})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["stats", "playlist", "spotify:playlist:playlist-1"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Average tempo 100 BPM"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn unknown_playlist_name_errors_without_a_terminal_for_the_picker() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me/playlists"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "next": null,
+            "items": [
+                {"name": "Party", "uri": "spotify:playlist:p1", "description": null,
+                    "tracks": {"total": 3}, "public": false, "collaborative": false}
+            ]
+        })))
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["playlist", "play", "Prty"]).await;
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Could not find a playlist named Prty."));
+
+    let output = env
+        .run(&["playlist", "play", "Prty", "--picker", "always"])
+        .await;
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("--picker always needs a terminal."),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn copy_leaves_out_local_files_and_unavailable_tracks() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/playlist-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Mixed",
+            "description": "",
+            "tracks": {
+                "items": [
+                    track_json(1, "a"),
+                    {"track": {"name": "Demo", "id": null, "is_local": true,
+                        "uri": "spotify:local:Band:Tape:Demo:180",
+                        "artists": [{"name": "Band", "id": null}], "duration_ms": 180000}},
+                    {"track": null}
+                ],
+                "next": null,
+                "total": 3
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "user-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/user-1/playlists"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "new-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/new-1/tracks"))
+        .and(body_json(json!({"uris": ["spotify:track:track-1"]})))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "show"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "playlist-1")
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("[local file] Demo"), "{out}");
+    assert!(out.contains("[unavailable track]"), "{out}");
+
+    let output = env
+        .run(&[
+            "playlist",
+            "copy",
+            "spotify:playlist:playlist-1",
+            "Copied",
+        ])
+        .await;
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Skipped 1 local file(s) or unavailable track(s)"),
+        "{}",
+        stdout(&output)
+    );
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+fn show_json() -> serde_json::Value {
+    json!({
+        "name": "A Podcast",
+        "id": "show-1",
+        "uri": "spotify:show:show-1",
+        "publisher": "A Publisher",
+        "total_episodes": 2
+    })
+}
+
+fn episode_json(id: &str, resume_point: serde_json::Value) -> serde_json::Value {
+    json!({
+        "name": format!("Episode {id}"),
+        "id": id,
+        "uri": format!("spotify:episode:{id}"),
+        "duration_ms": 3_600_000,
+        "release_date": "2024-05-01",
+        "resume_point": resume_point,
+        "show": show_json()
+    })
+}
+
+async fn mount_saved_shows(env: &TestEnv) {
+    Mock::given(method("GET"))
+        .and(path("/me/shows"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [{"added_at": "2024-01-01T00:00:00Z", "show": show_json()}],
+            "next": null
+        })))
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn list_prints_the_saved_shows() {
+    let env = TestEnv::new().await;
+    mount_saved_shows(&env).await;
+
+    let output = env.run(&["podcast", "list"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Saved shows\n1. A Podcast by A Publisher (2 episodes) uri: spotify:show:show-1\n"
+    );
+}
+
+#[tokio::test]
+async fn episodes_of_a_saved_show_show_where_they_were_left_off() {
+    let env = TestEnv::new().await;
+    mount_saved_shows(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/shows/show-1/episodes"))
+        .and(query_param("limit", "20"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [
+                episode_json("e2", json!({"fully_played": false, "resume_position_ms": 754_000})),
+                episode_json("e1", json!({"fully_played": true, "resume_position_ms": 0})),
+                null
+            ],
+            "next": null
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["podcast", "episodes", "a podcast"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "2024-05-01 Episode e2 (1:00:00) [12:34 / 1:00:00]\n  spotify:episode:e2\n\
+         2024-05-01 Episode e1 (1:00:00) [played]\n  spotify:episode:e1\n"
+    );
+}
+
+#[tokio::test]
+async fn play_resumes_an_episode_within_its_show() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/episodes/e2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(episode_json(
+            "e2",
+            json!({"fully_played": false, "resume_position_ms": 754_000}),
+        )))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:show:show-1",
+            "offset": {"uri": "spotify:episode:e2"},
+            "position_ms": 754_000
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["podcast", "play", "spotify:episode:e2"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Resumed Episode e2 at 12:34 of 1:00:00.\n");
+}
+
+#[tokio::test]
+async fn play_from_start_ignores_the_resume_point() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/episodes/e2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(episode_json(
+            "e2",
+            json!({"fully_played": false, "resume_position_ms": 754_000}),
+        )))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:show:show-1",
+            "offset": {"uri": "spotify:episode:e2"},
+            "position_ms": 0
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&["podcast", "play", "spotify:episode:e2", "--from-start"])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Playing Episode e2.\n");
+}
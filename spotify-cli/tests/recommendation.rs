@@ -0,0 +1,818 @@
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::json;
+use std::process::{Output, Stdio};
+use tokio::io::AsyncWriteExt;
+use wiremock::{
+    matchers::{body_json, method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+/// Runs `rec generate` answering the wizard with `input`.
+async fn generate(env: &TestEnv, args: &[&str], input: &[u8]) -> Output {
+    let mut child = env
+        .command(&[&["rec", "generate"], args].concat())
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).await.unwrap();
+
+    child.wait_with_output().await.unwrap()
+}
+
+fn player_json() -> serde_json::Value {
+    json!({
+        "device": {"id": "device-1", "name": "Kitchen", "type": "Speaker"},
+        "item": {
+            "name": "A Song",
+            "id": "track-1",
+            "uri": "spotify:track:track-1",
+            "artists": [
+                {"name": "An Artist", "id": "artist-1"},
+                {"name": "Another Artist", "id": "artist-2"}
+            ]
+        },
+        "is_playing": true,
+        "progress_ms": 0,
+        "context": null
+    })
+}
+
+fn track_json(id: &str) -> serde_json::Value {
+    json!({
+        "name": format!("Song {id}"),
+        "id": id,
+        "uri": format!("spotify:track:{id}"),
+        "artists": [{"name": "An Artist", "id": "artist-1"}]
+    })
+}
+
+async fn mount_player(env: &TestEnv) {
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player_json()))
+        .mount(&env.server)
+        .await;
+}
+
+async fn mount_recommendations_not_found(env: &TestEnv) {
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "error": {"status": 404, "message": "Not Found"}
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn generate_seeds_from_the_current_track_and_artists() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+
+    let output = generate(&env, &["--seed-current", "--seed-current-artist"], b"q\n").await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains(r#"Artists: ["An Artist", "Another Artist"]"#),
+        "{out}"
+    );
+    assert!(out.contains(r#"Tracks:  ["A Song"]"#), "{out}");
+}
+
+#[tokio::test]
+async fn generate_fails_instead_of_looping_when_stdin_runs_out() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+
+    let output = generate(&env, &["--seed-current"], b"").await;
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("stdin is closed. Use --choose <number> or --accept"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn generate_needs_the_managed_playlist() {
+    let env = TestEnv::new().await;
+
+    let output = env.run(&["rec", "generate", "--seed-current"]).await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("SPOTIFY_CLI_MANAGED_PLAYLIST_ID is not set"));
+}
+
+#[tokio::test]
+async fn generate_falls_back_to_sampling_top_tracks() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+    mount_recommendations_not_found(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/tracks"))
+        .and(query_param("ids", "track-1"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "tracks": [track_json("track-1")] })),
+        )
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/artists/artist-1/related-artists"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/artists/artist-1/top-tracks"))
+        .and(query_param("market", "from_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("track-1"), track_json("top-1"), track_json("top-2")]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = generate(&env, &["--seed-current"], b"g\nn\nq\n").await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("sampling the artists' top tracks instead"),
+        "{out}"
+    );
+    assert!(out.contains("Song top-1 - An Artist"), "{out}");
+    assert!(out.contains("Song top-2 - An Artist"), "{out}");
+    // The seed itself isn't recommended.
+    assert!(!out.contains("Song track-1"), "{out}");
+}
+
+#[tokio::test]
+async fn spotify_backend_does_not_fall_back() {
+    let env = TestEnv::new().await;
+    std::fs::write(
+        env.path("config.toml"),
+        "[recommendations]\nbackend = \"spotify\"\n",
+    )
+    .unwrap();
+    mount_player(&env).await;
+    mount_recommendations_not_found(&env).await;
+
+    let output = generate(&env, &["--seed-current"], b"g\n").await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Not Found"));
+}
+
+#[tokio::test]
+async fn preview_plays_a_track_and_resumes_the_playlist() {
+    let env = TestEnv::new().await;
+    let mut player = player_json();
+    player["progress_ms"] = json!(12000);
+    player["context"] = json!({
+        "type": "playlist",
+        "href": "https://api.spotify.com/v1/playlists/list-1",
+        "uri": "spotify:playlist:list-1"
+    });
+    Mock::given(method("GET"))
+        .and(path("/me/player"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(player))
+        .mount(&env.server)
+        .await;
+    let mut long_track = track_json("rec-2");
+    long_track["duration_ms"] = json!(200000);
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1"), long_track]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "uris": ["spotify:track:rec-2"],
+            "position_ms": 30000
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({
+            "context_uri": "spotify:playlist:list-1",
+            "offset": {"uri": "spotify:track:track-1"},
+            "position_ms": 12000
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = generate(&env, &["--seed-current"], b"g\np 2\n\nn\nq\n").await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("2. Song rec-2 - An Artist"), "{out}");
+    assert!(out.contains("Previewing: Song rec-2 - An Artist"), "{out}");
+}
+
+#[tokio::test]
+async fn review_keeps_chosen_tracks_and_replaces_dropped_ones() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1"), track_json("rec-2")]
+        })))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1"), track_json("rec-3")]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "snapshot_id": "before",
+            "tracks": {"items": [{"track": track_json("old-1")}], "next": null}
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/managed/tracks"))
+        .and(body_json(json!({
+            "uris": ["spotify:track:rec-1", "spotify:track:rec-3"]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    // Keep rec-1, drop rec-2, replace it and keep the replacement, then
+    // accept without playing.
+    let output = generate(&env, &["--seed-current"], b"g\nr\n\nn\ny\ny\ny\nn\n").await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("The list is now:\n1. Song rec-1 - An Artist\n2. Song rec-3 - An Artist\n"),
+        "{out}"
+    );
+    let snapshots = std::fs::read_to_string(env.path("snapshots/managed.json")).unwrap();
+    assert!(snapshots.contains("spotify:track:old-1"), "{snapshots}");
+}
+
+#[tokio::test]
+async fn show_with_a_market_dims_unplayable_tracks_instead_of_dropping_them() {
+    let env = TestEnv::new().await;
+    let mut unplayable = track_json("track-2");
+    unplayable["is_playable"] = json!(false);
+    unplayable["restrictions"] = json!({"reason": "market"});
+    let mut relinked = track_json("track-3");
+    relinked["linked_from"] = json!({"id": "track-0", "uri": "spotify:track:track-0"});
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .and(query_param("market", "FI"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {
+                "items": [
+                    {"track": track_json("track-1")},
+                    {"track": unplayable},
+                    {"track": relinked}
+                ],
+                "next": null,
+                "total": 3
+            }
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "show", "--market", "fi"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("#0 Song track-1"), "{out}");
+    assert!(
+        out.contains("#1 Song track-2 - An Artist (not available in the market)"),
+        "{out}"
+    );
+    assert!(
+        out.contains("#2 Song track-3 - An Artist (relinked)"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn bad_market_is_rejected() {
+    let env = TestEnv::new().await;
+
+    let output = env.run(&["rec", "show", "--market", "Finland"]).await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Bad market Finland"));
+}
+
+#[tokio::test]
+async fn save_replaces_the_first_hundred_tracks_and_appends_the_rest() {
+    let env = TestEnv::new().await;
+    let ids: Vec<String> = (0..150).map(|ind| format!("rec-{ind}")).collect();
+    let items: Vec<serde_json::Value> = ids
+        .iter()
+        .map(|id| json!({"track": track_json(id)}))
+        .collect();
+    let uris: Vec<String> = ids.iter().map(|id| format!("spotify:track:{id}")).collect();
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {"items": items, "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "user-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/user-1/playlists"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "saved"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/saved/tracks"))
+        .and(body_json(json!({"uris": uris[..100]})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/saved/tracks"))
+        .and(body_json(json!({"uris": uris[100..]})))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"snapshot_id": "s2"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "save", "Saved"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // A failed append says how far the replace got.
+    env.server.reset().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {"items": items, "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "user-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/user-1/playlists"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "saved"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/saved/tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/playlists/saved/tracks"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "save", "Saved"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains(
+            "The playlist was replaced with the first 100 tracks, but adding the other 50 failed."
+        ),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn save_leaves_out_explicit_tracks_when_configured() {
+    let env = TestEnv::new().await;
+    std::fs::write(
+        env.path("config.toml"),
+        "[recommendations]\nexclude_explicit = true\n",
+    )
+    .unwrap();
+    let mut explicit = track_json("rec-2");
+    explicit["explicit"] = json!(true);
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {"items": [{"track": track_json("rec-1")}, {"track": explicit}], "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "user-1"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/users/user-1/playlists"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"id": "saved"})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/saved/tracks"))
+        .and(body_json(json!({"uris": ["spotify:track:rec-1"]})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "save", "Family"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Left out 1 explicit track(s)."),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn genres_fall_back_to_the_built_in_list_and_accept_unknown_ones() {
+    let env = TestEnv::new().await;
+
+    let output = generate(&env, &[], b"3\nrock\n3\nrock-ish\nq\n").await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Failed to fetch the genres"), "{out}");
+    assert!(out.contains("hip-hop"), "{out}");
+    assert!(
+        out.contains("rock-ish isn't in the list, which may be out of date; using it anyway."),
+        "{out}"
+    );
+    assert!(out.contains(r#"Genres:  ["rock", "rock-ish"]"#), "{out}");
+}
+
+#[tokio::test]
+async fn genres_are_cached_between_runs() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations/available-genre-seeds"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({"genres": ["polka", "rock"]})),
+        )
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    for _ in 0..2 {
+        let output = generate(&env, &[], b"3\nrock-ish\n3\npolka\nq\n").await;
+
+        assert!(output.status.success(), "{}", stderr(&output));
+        let out = stdout(&output);
+        assert!(out.contains("polka, rock"), "{out}");
+        assert!(out.contains("Illegal genre."), "{out}");
+        assert!(out.contains(r#"Genres:  ["polka"]"#), "{out}");
+    }
+}
+
+#[tokio::test]
+async fn radio_fills_the_managed_playlist_from_the_current_track_and_plays_it() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .and(query_param("seed_tracks", "track-1"))
+        .and(query_param("limit", "50"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1"), track_json("rec-2")]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {"items": [], "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/managed/tracks"))
+        .and(body_json(
+            json!({"uris": ["spotify:track:rec-1", "spotify:track:rec-2"]}),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(json!({"context_uri": "spotify:playlist:managed"})))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["radio"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Filled the managed playlist with 2 tracks."),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn radio_without_active_device_only_retries_playing_the_list() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1")]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {"items": [], "next": null}
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/managed/tracks"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({"error": {
+            "status": 404,
+            "message": "Player command failed: No active device found",
+            "reason": "NO_ACTIVE_DEVICE"
+        }})))
+        .up_to_n_times(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player/play"))
+        .and(body_json(
+            json!({"context_uri": "spotify:playlist:managed"}),
+        ))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/devices"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"devices": [
+            {"id": "device-1", "name": "Kitchen", "type": "Speaker", "is_active": false}
+        ]})))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/me/player"))
+        .and(body_json(json!({"device_ids": ["device-1"]})))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["radio", "--device", "kitchen"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output)
+            .matches("Filled the managed playlist")
+            .count(),
+        1,
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test]
+async fn radio_queues_the_tracks_without_a_managed_playlist() {
+    let env = TestEnv::new().await;
+    mount_player(&env).await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .and(query_param("seed_artists", "artist-1,artist-2"))
+        .and(query_param("limit", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1"), track_json("rec-2")]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/me/player/queue"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["radio", "artist", "--limit", "2"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "No managed playlist set up; queueing the tracks instead.\nQueued 2 tracks.\n"
+    );
+}
+
+#[tokio::test]
+async fn rotate_keeps_the_first_unplayed_tracks_and_tops_up_the_list() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {
+                "items": [
+                    {"track": track_json("old-1")},
+                    {"track": track_json("old-2")},
+                    {"track": track_json("old-3")},
+                    {"track": track_json("old-4")}
+                ],
+                "next": null
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/recently-played"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [{"track": track_json("old-1"), "played_at": "2026-10-14T20:00:00Z"}]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .and(query_param("seed_tracks", "old-2,old-3"))
+        .and(query_param("limit", "5"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [
+                track_json("old-2"),
+                track_json("rec-1"),
+                track_json("rec-2"),
+                track_json("rec-3"),
+                track_json("rec-4")
+            ]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/managed/tracks"))
+        .and(body_json(json!({"uris": [
+            "spotify:track:old-2", "spotify:track:old-3", "spotify:track:rec-1", "spotify:track:rec-2",
+            "spotify:track:rec-3"
+        ]})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "rotate", "--keep", "2", "--size", "5"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Kept 2 unplayed tracks and added 3 new recommendations.\n"
+    );
+}
+
+#[tokio::test]
+async fn rotate_keeping_nothing_seeds_from_the_unplayed_tracks() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/playlists/managed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Managed",
+            "description": "",
+            "tracks": {
+                "items": [{"track": track_json("old-1")}, {"track": track_json("old-2")}],
+                "next": null
+            }
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me/player/recently-played"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "items": [{"track": track_json("old-1"), "played_at": "2026-10-14T20:00:00Z"}]
+        })))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/recommendations"))
+        .and(query_param("seed_tracks", "old-2"))
+        .and(query_param("limit", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tracks": [track_json("rec-1"), track_json("rec-2")]
+        })))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/playlists/managed/tracks"))
+        .and(body_json(
+            json!({"uris": ["spotify:track:rec-1", "spotify:track:rec-2"]}),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"snapshot_id": "s1"})))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .command(&["rec", "rotate", "--keep", "0", "--size", "2"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", "managed")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Kept 0 unplayed tracks and added 2 new recommendations.\n"
+    );
+}
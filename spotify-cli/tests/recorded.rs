@@ -0,0 +1,233 @@
+//! Runs commands against complete API responses in `tests/fixtures`, shaped
+//! like what Spotify actually sends, so that every field of the real API is
+//! deserialized and not just the ones the other tests bother to write out.
+mod common;
+
+use common::{stderr, stdout, TestEnv};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, ResponseTemplate,
+};
+
+const PLAYLIST_ID: &str = "37i9dQZF1DX0XUsuxWHRQd";
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{name}.json", env!("CARGO_MANIFEST_DIR"));
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+async fn mount_fixture(env: &TestEnv, endpoint: &str, name: &str) {
+    Mock::given(method("GET"))
+        .and(path(endpoint))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture(name)))
+        .mount(&env.server)
+        .await;
+}
+
+#[tokio::test]
+async fn show_reads_the_player() {
+    let env = TestEnv::new().await;
+    mount_fixture(&env, "/me/player", "player").await;
+    // The context's href points to the real API; it has to be rebased onto
+    // the mock server.
+    mount_fixture(&env, &format!("/playlists/{PLAYLIST_ID}"), "playlist").await;
+
+    let output = env.run(&["show"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Harbor Lights"), "{out}");
+    assert!(out.contains("Pale Harbor"), "{out}");
+    assert!(out.contains("Living Room"), "{out}");
+    assert!(
+        out.contains("Playing from: Coastal Evenings (playlist)"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn queue_reads_the_queue() {
+    let env = TestEnv::new().await;
+    mount_fixture(&env, "/me/player/queue", "queue").await;
+
+    let output = env
+        .command(&["queue", "3"])
+        .env("COLUMNS", "200")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Harbor Lights"), "{out}");
+    assert!(out.contains("Paper Moons"), "{out}");
+    assert!(out.contains("Night Ferry"), "{out}");
+    assert!(!out.contains("Glasshouse"), "{out}");
+}
+
+#[tokio::test]
+async fn queue_prints_a_table_that_fits_the_terminal() {
+    let env = TestEnv::new().await;
+    mount_fixture(&env, "/me/player/queue", "queue").await;
+
+    let output = env
+        .command(&["queue", "3", "--output", "table"])
+        .env("COLUMNS", "200")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 4, "{out}");
+    assert!(lines[0].starts_with("#  Name"), "{out}");
+    assert!(
+        lines[1].starts_with("0  Harbor Lights  Pale Harbor       Low Tide Letters  3:51"),
+        "{out}"
+    );
+    assert!(
+        lines[3]
+            .starts_with("2  Night Ferry    The Lantern Club  Night Ferry       4:22      7:09"),
+        "{out}"
+    );
+    // The columns line up.
+    let uri_column = lines[0].find("URI").unwrap();
+    assert!(lines[1..]
+        .iter()
+        .all(|line| line[uri_column..].starts_with("spotify:track:")));
+
+    let output = env
+        .command(&["queue", "3", "--output", "table"])
+        .env("COLUMNS", "50")
+        .output()
+        .await
+        .unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.lines().all(|line| line.chars().count() <= 50), "{out}");
+    assert!(out.contains("…"), "{out}");
+    assert!(out.contains("0  Harbor…"), "{out}");
+}
+
+#[tokio::test]
+async fn track_info_searches_and_reads_the_track() {
+    let env = TestEnv::new().await;
+    let search = fixture("search_tracks");
+    Mock::given(method("GET"))
+        .and(path("/search"))
+        .and(query_param("q", "track:Harbor Lights"))
+        .and(query_param("type", "track"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&search))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/tracks/3n3Ppam7vgaVa1iaRUc9Lp"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&search["tracks"]["items"][0]))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.run(&["track", "info", "Harbor Lights"]).await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Title:      Harbor Lights"), "{out}");
+    assert!(
+        out.contains("Album:      Low Tide Letters (2019-03-22)"),
+        "{out}"
+    );
+    assert!(out.contains("Duration:   3:51"), "{out}");
+    assert!(
+        out.contains("https://open.spotify.com/track/3n3Ppam7vgaVa1iaRUc9Lp"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn playlist_stats_reads_the_playlist() {
+    let env = TestEnv::new().await;
+    mount_fixture(&env, &format!("/playlists/{PLAYLIST_ID}"), "playlist").await;
+    Mock::given(method("GET"))
+        .and(path("/audio-features"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({"error": {
+            "status": 403,
+            "message": "This endpoint is deprecated for new apps"
+        }})))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "stats",
+            "playlist",
+            &format!("spotify:playlist:{PLAYLIST_ID}"),
+        ])
+        .await;
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("Coastal Evenings: 3 tracks, 1h 10m."), "{out}");
+    assert!(out.contains("1 explicit tracks."), "{out}");
+    assert!(out.contains("1990s: 1 tracks"), "{out}");
+}
+
+#[tokio::test]
+async fn playlist_stats_fails_on_other_forbidden_errors() {
+    let env = TestEnv::new().await;
+    mount_fixture(&env, &format!("/playlists/{PLAYLIST_ID}"), "playlist").await;
+    Mock::given(method("GET"))
+        .and(path("/audio-features"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({"error": {
+            "status": 403,
+            "message": "Insufficient client scope"
+        }})))
+        .mount(&env.server)
+        .await;
+
+    let output = env
+        .run(&[
+            "stats",
+            "playlist",
+            &format!("spotify:playlist:{PLAYLIST_ID}"),
+        ])
+        .await;
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Insufficient client scope"));
+}
+
+#[tokio::test]
+async fn generate_lists_the_recommendations() {
+    let env = TestEnv::new().await;
+    mount_fixture(&env, "/me/player", "player").await;
+    mount_fixture(&env, "/recommendations", "recommendations").await;
+
+    let mut child = env
+        .command(&["rec", "generate", "--seed-current"])
+        .env("SPOTIFY_CLI_MANAGED_PLAYLIST_ID", PLAYLIST_ID)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"g\nn\nq\n")
+        .await
+        .unwrap();
+    let output = child.wait_with_output().await.unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("1. Undertow - The Lantern Club"), "{out}");
+    assert!(out.contains("2. Salt and Cedar - Mira Solberg"), "{out}");
+    assert!(out.contains("3. Paper Moons - Pale Harbor"), "{out}");
+}